@@ -0,0 +1,49 @@
+//! How fast the scanner and parser get through a big program. Run with
+//! `cargo bench`; it prints the time per parse and the throughput, for a
+//! program that's mostly statements and one that's mostly comments, which
+//! is the scanner on its own.
+
+extern crate crenshaw;
+
+use std::time::Instant;
+
+use crenshaw::diagnostics::Diagnostics;
+use crenshaw::parser::{Newlines, Parser};
+use crenshaw::source_map::SourceMap;
+
+const RUNS: u32 = 10;
+
+fn main() {
+    let mut statements = String::new();
+    for i in 0..100_000 {
+        statements.push_str(&format!("x{0} = {0} * 2 + (y - 3) // number {0}\n", i % 100));
+    }
+    statements.push_str("return 0\n");
+    bench("statements", &statements);
+
+    let comments = format!("# {}\nreturn 0\n", "c".repeat(4_000_000));
+    bench("comments", &comments);
+}
+
+/// Parses `program` a few times, and prints how long it took on average.
+fn bench(name: &str, program: &str) {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file(name, Vec::new());
+        let mut diagnostics = Diagnostics::new(source_map);
+        let parsed = Parser::new(program.as_bytes(), file, Newlines::Significant, &mut diagnostics).parse_program();
+        assert!(parsed.is_ok());
+        assert_eq!(diagnostics.error_count(), 0);
+    }
+
+    let elapsed = start.elapsed() / RUNS;
+    let megabytes = program.len() as f64 / 1_000_000.0;
+    println!(
+        "{}: {:.1?} to parse {:.1} MB, {:.1} MB/s",
+        name,
+        elapsed,
+        megabytes,
+        megabytes / elapsed.as_secs_f64()
+    );
+}
@@ -0,0 +1,565 @@
+//! The syntax tree the parser builds. It mirrors the source closely, sugar and
+//! all. The desugaring pass turns it into the smaller language in `hir` before
+//! anything else looks at it.
+
+use std::cell::RefCell;
+
+pub use source_map::Span;
+
+/// The types a value can have. Anything without a type written out is an
+/// i32, unless it gets one from its context.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Type {
+    #[default]
+    I32,
+    I64,
+    F64,
+
+    // What comparisons give, and what conditions take. Stored as an i32 that's
+    // always 0 or 1.
+    Bool,
+
+    // A string: the address of its bytes in memory, and how many there are.
+    // Stored as an i64, with the address in the low 32 bits and the length in
+    // the high 32 bits.
+    Str,
+
+    // A function pointer, which can point to any function with this
+    // signature. Stored as an i32: the function's index in the module's
+    // table. 0 isn't any function's, so it's what a pointer starts out as.
+    Func(FuncType),
+
+    // A record: the address of its fields in memory. Stored as an i32. 0 is
+    // the null record, which is what a record starts out as.
+    Record(RecordType),
+
+    // One of an enum's variants, stored as an i32: the variant's number.
+    // Enums start out as their first variant, 0.
+    Enum(EnumType),
+
+    // A 128-bit vector, with --simd. The builtins that work on it treat it
+    // as four i32 lanes. Vectors start out with every lane 0.
+    V128,
+}
+
+impl Type {
+    /// Looks up a type by the name used for it in programs. For numbers, that's
+    /// also its name in wasm.
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "i32" => Some(Type::I32),
+            "i64" => Some(Type::I64),
+            "f64" => Some(Type::F64),
+            "bool" => Some(Type::Bool),
+            "str" => Some(Type::Str),
+            "v128" => Some(Type::V128),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Type::I32 => "i32",
+            Type::I64 => "i64",
+            Type::F64 => "f64",
+            Type::Bool => "bool",
+            Type::Str => "str",
+            Type::Func(func) => func.name(),
+            Type::Record(record) => record.name(),
+            Type::Enum(ty) => ty.name(),
+            Type::V128 => "v128",
+        }
+    }
+
+    /// The wasm type that values of this type are stored as.
+    pub fn wasm_name(self) -> &'static str {
+        match self {
+            Type::Bool => "i32",
+            Type::Str => "i64",
+            Type::Func(_) | Type::Record(_) | Type::Enum(_) => "i32",
+            ty => ty.name(),
+        }
+    }
+
+    pub fn is_integer(self) -> bool {
+        self == Type::I32 || self == Type::I64
+    }
+
+    /// Whether arithmetic works on values of this type.
+    pub fn is_number(self) -> bool {
+        self.is_integer() || self == Type::F64
+    }
+}
+
+/// What a function pointer points to, as in `func(i32, i32): i32`: the
+/// types its function takes and returns. It's a number that stands for the
+/// signature, so that types can still be copied around, and two pointers
+/// have the same type if their signatures are the same.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FuncType(usize);
+
+thread_local! {
+    // Every signature there's a FuncType for, with its name. Signatures are
+    // never forgotten, so their names can live as long as the program.
+    static SIGNATURES: RefCell<Vec<(Vec<Type>, Type, &'static str)>> = const { RefCell::new(Vec::new()) };
+}
+
+impl FuncType {
+    pub fn new(params: Vec<Type>, result: Type) -> FuncType {
+        let existing = SIGNATURES.with(|signatures| {
+            signatures
+                .borrow()
+                .iter()
+                .position(|&(ref existing, existing_result, _)| *existing == params && existing_result == result)
+        });
+        if let Some(i) = existing {
+            return FuncType(i);
+        }
+
+        // The parameters can be pointers too, so their names have to be
+        // looked up before the signature's added.
+        let names: Vec<&str> = params.iter().map(|ty| ty.name()).collect();
+        let name = format!("func({}): {}", names.join(", "), result.name());
+        SIGNATURES.with(|signatures| {
+            let mut signatures = signatures.borrow_mut();
+            signatures.push((params, result, Box::leak(name.into_boxed_str())));
+            FuncType(signatures.len() - 1)
+        })
+    }
+
+    pub fn params(self) -> Vec<Type> {
+        SIGNATURES.with(|signatures| signatures.borrow()[self.0].0.clone())
+    }
+
+    pub fn result(self) -> Type {
+        SIGNATURES.with(|signatures| signatures.borrow()[self.0].1)
+    }
+
+    fn name(self) -> &'static str {
+        SIGNATURES.with(|signatures| signatures.borrow()[self.0].2)
+    }
+}
+
+/// A record type, by name. Like a FuncType, it's a number that stands for
+/// the name, so that types can still be copied around. The parser makes one
+/// for any type name it doesn't know, since records can be defined after
+/// they're used; type checking reports the ones that aren't defined at all.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecordType(usize);
+
+thread_local! {
+    // The name of every record type there's a RecordType for.
+    static RECORDS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+impl RecordType {
+    pub fn new(name: &str) -> RecordType {
+        RECORDS.with(|records| {
+            let mut records = records.borrow_mut();
+            if let Some(i) = records.iter().position(|&existing| existing == name) {
+                return RecordType(i);
+            }
+            records.push(Box::leak(name.to_string().into_boxed_str()));
+            RecordType(records.len() - 1)
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        RECORDS.with(|records| records.borrow()[self.0])
+    }
+}
+
+/// An enum type, by name, like a RecordType. The parser can't tell enums
+/// from records, so it makes a RecordType for every name it doesn't know,
+/// and type checking turns the ones that name enums into these.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnumType(usize);
+
+thread_local! {
+    // The name of every enum type there's an EnumType for.
+    static ENUMS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+impl EnumType {
+    pub fn new(name: &str) -> EnumType {
+        ENUMS.with(|enums| {
+            let mut enums = enums.borrow_mut();
+            if let Some(i) = enums.iter().position(|&existing| existing == name) {
+                return EnumType(i);
+            }
+            enums.push(Box::leak(name.to_string().into_boxed_str()));
+            EnumType(enums.len() - 1)
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        ENUMS.with(|enums| enums.borrow()[self.0])
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+
+    /// `%`, which takes the sign of the left operand, like `rem_s`.
+    Remainder,
+
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+
+    /// `&`, `|`, and `^`, on the bits of integers, or on bools.
+    BitAnd,
+    BitOr,
+    BitXor,
+
+    /// `<<`, `>>`, which copies the sign bit in from the left, and `>>>`,
+    /// which shifts in zeros.
+    ShiftLeft,
+    ShiftRight,
+    ShiftRightUnsigned,
+
+    /// `and` and `or`, on conditions. The right operand only gets evaluated
+    /// if the left one doesn't settle the answer.
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    /// Comparisons give an i32, 1 for true and 0 for false, whatever they
+    /// compare.
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Equal
+                | BinaryOp::NotEqual
+                | BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+        )
+    }
+
+    /// Whether the operator only works on numbers.
+    pub fn is_arithmetic(self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Remainder
+        )
+    }
+
+    pub fn is_bitwise(self) -> bool {
+        matches!(self, BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor)
+    }
+
+    pub fn is_shift(self) -> bool {
+        matches!(self, BinaryOp::ShiftLeft | BinaryOp::ShiftRight | BinaryOp::ShiftRightUnsigned)
+    }
+
+    /// Logical operators take two conditions and give a bool.
+    pub fn is_logical(self) -> bool {
+        matches!(self, BinaryOp::And | BinaryOp::Or)
+    }
+}
+
+/// A whole program: the functions, event handlers, globals, and constants it
+/// defines, the statements in its `init` blocks, and the top-level statements
+/// that make up main.
+#[derive(Debug)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub handlers: Vec<Function>,
+    pub globals: Vec<Global>,
+    pub constants: Vec<Constant>,
+    pub arrays: Vec<Array>,
+    pub records: Vec<Record>,
+    pub enums: Vec<Enum>,
+    pub init: Vec<Stmt>,
+    pub main: Vec<Stmt>,
+}
+
+impl Program {
+    /// Adds everything another file defines after what this one does, for
+    /// compiling several files into one module. Their `init` blocks and
+    /// top-level statements run in the order the files are linked.
+    pub fn link(&mut self, other: Program) {
+        self.functions.extend(other.functions);
+        self.handlers.extend(other.handlers);
+        self.globals.extend(other.globals);
+        self.constants.extend(other.constants);
+        self.arrays.extend(other.arrays);
+        self.records.extend(other.records);
+        self.enums.extend(other.enums);
+        self.init.extend(other.init);
+        self.main.extend(other.main);
+    }
+}
+
+/// What the formatter needs to know about a program that the tree doesn't
+/// keep. The parser only fills this in for `parse_for_formatting`.
+#[derive(Debug, Default)]
+pub struct Layout {
+    /// Every comment, in order.
+    pub comments: Vec<Span>,
+
+    /// Where each function, handler, record, and enum is, from its first
+    /// keyword to its `end`, in the same order as the program's.
+    pub functions: Vec<Span>,
+    pub handlers: Vec<Span>,
+    pub records: Vec<Span>,
+    pub enums: Vec<Span>,
+
+    /// Where each `init` block is, and how many of the program's `init`
+    /// statements are in it.
+    pub init_blocks: Vec<(Span, usize)>,
+
+    /// Each `include`, and the path as it's written. They aren't followed.
+    pub includes: Vec<(Span, String)>,
+}
+
+/// `record Point ... end`, with a `name: type` line for each field. The
+/// fields are laid out in memory in order, each aligned to its size.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub name_span: Span,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub name_span: Span,
+    pub ty: Type,
+}
+
+/// `enum Color ... end`, with a line for each variant's name. The variants
+/// are numbered from 0, in order, and the program names them as in
+/// `Color.red`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enum {
+    pub name: String,
+    pub name_span: Span,
+    pub variants: Vec<(String, Span)>,
+}
+
+/// `global g` or `global g: i64 = 42`. Globals start out as 0 unless they
+/// have an initial value, which has to be a literal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub name_span: Span,
+    pub ty: Option<Type>,
+    pub value: Option<Expr>,
+}
+
+/// `const N = e` or `const N: i64 = e`. The value has to be something we can
+/// work out at compile time, and uses of the constant are replaced with it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Constant {
+    pub name: String,
+    pub name_span: Span,
+    pub ty: Option<Type>,
+    pub value: Expr,
+}
+
+/// `array a[10]`, a fixed number of i32s in linear memory, which start out
+/// as 0. The length has to be something we can work out at compile time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Array {
+    pub name: String,
+    pub name_span: Span,
+    pub len: Expr,
+}
+
+/// A function definition. Event handlers are functions too, named after the
+/// event they handle.
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub name_span: Span,
+    pub params: Vec<(String, Type)>,
+
+    /// Where each parameter's name is, in the same order.
+    pub param_spans: Vec<Span>,
+
+    /// The parameters declared `var`, which the caller passes a variable
+    /// to. The function gets its address, and what it assigns to the
+    /// parameter ends up in the caller's variable.
+    pub var_params: Vec<String>,
+
+    pub result: Type,
+
+    /// What else it returns, after `result`, for a function that returns
+    /// more than one value, as in `: i32, i32`.
+    pub more_results: Vec<Type>,
+
+    pub body: Vec<Stmt>,
+
+    /// Whether the module exports it, under its name.
+    pub exported: bool,
+}
+
+#[derive(Debug)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum StmtKind {
+    /// `x = e`, or `x += e` and friends when there's an operator. Plain
+    /// assignments can give the variable a type, as in `x: i64 = e`.
+    Assign {
+        name: String,
+        ty: Option<Type>,
+        op: Option<BinaryOp>,
+        value: Expr,
+    },
+
+    /// `if c ... elseif c ... else ... end`. There's always at least one
+    /// branch, and `otherwise` is empty if there's no `else`.
+    If {
+        branches: Vec<(Expr, Vec<Stmt>)>,
+        otherwise: Vec<Stmt>,
+    },
+
+    /// `while c ... end`
+    While { condition: Expr, body: Vec<Stmt> },
+
+    /// `for i = from to limit ... end`, counting up by one. Both ends are
+    /// inclusive.
+    For {
+        variable: String,
+        from: Expr,
+        to: Expr,
+        body: Vec<Stmt>,
+    },
+
+    /// `a, b = f(x)`, which assigns each value a function returns to a
+    /// variable, in order.
+    AssignValues { names: Vec<String>, value: Expr },
+
+    /// `return e`
+    Return(Expr),
+
+    /// `return a, b`, from a function that returns more than one value.
+    ReturnValues(Vec<Expr>),
+
+    /// `print e`, which hands the value to the host.
+    Print(Expr),
+
+    /// `assert e`, which stops the program if `e` is false.
+    Assert(Expr),
+
+    /// `halt e`, which stops the program with `e` as its exit status.
+    Halt(Expr),
+
+    /// `read x`, which asks the host for an i32 and assigns it to `x`.
+    Read(String),
+
+    /// `f(x)` on its own, which calls `f` and throws away whatever it
+    /// returns. The expression is always a call.
+    Call(Expr),
+
+    /// `case e when a, b ... when c ... else ... end`, which runs the first
+    /// branch with a value equal to `e`, or else the `else`. The values
+    /// have to be constants. `otherwise` is None if there's no `else`, so
+    /// type checking can tell an empty one from a missing one.
+    Case {
+        subject: Expr,
+        arms: Vec<(Vec<Expr>, Vec<Stmt>)>,
+        otherwise: Option<Vec<Stmt>>,
+    },
+
+    /// `mem[address] = e` or `a[i] = e`, or `mem[address] += e` and friends
+    /// when there's an operator.
+    Store {
+        place: Place,
+        op: Option<BinaryOp>,
+        value: Expr,
+    },
+
+    /// `begin ... end`. Variables first assigned inside it only exist until
+    /// the `end`.
+    Block(Vec<Stmt>),
+
+    /// `func f(...) ... end` inside another function, which can use that
+    /// function's variables. It can be called from anywhere in the function
+    /// it's in, including the functions nested alongside it.
+    Function(Box<Function>),
+}
+
+/// Somewhere in memory that a statement can store to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Place {
+    /// `mem[address]`
+    Memory(Expr),
+
+    /// `a[i]`, an element of an array.
+    Element(String, Expr),
+
+    /// `r.x`, a field of a record.
+    Field(Expr, String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+    /// An integer literal, exactly as written (`42` or `0x2a`), and the type
+    /// from its suffix (`42i64`) if it has one. Literals without a suffix take
+    /// whatever type is needed where they're used.
+    Int(String, Option<Type>),
+
+    /// A floating-point literal, exactly as written. These are always f64s.
+    Float(String),
+
+    /// `true` or `false`.
+    Bool(bool),
+
+    /// A string literal's bytes, with the escapes worked out.
+    Str(Vec<u8>),
+    Variable(String),
+    Call(String, Vec<Expr>),
+
+    /// `@f`, the address of function `f`, for calling it through a pointer.
+    Address(String),
+
+    /// A call through a function pointer. There's no syntax for this; type
+    /// checking turns a call to a variable that holds a pointer into one.
+    CallIndirect(Box<Expr>, Vec<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+
+    /// `mem[address]`, the i32 stored at a byte address in linear memory.
+    Load(Box<Expr>),
+
+    /// `a[i]`, an element of an array.
+    Index(String, Box<Expr>),
+
+    /// `r.x`, a field of the record `r` is the address of.
+    Field(Box<Expr>, String),
+
+    /// `Point(a)`, the record of that type at address `a`. There's no
+    /// syntax of its own for this; type checking turns a call to a record's
+    /// name into one.
+    View(RecordType, Box<Expr>),
+
+    /// `c ? a : b`. Only the value that's picked gets worked out, as far as
+    /// the program can tell.
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+
+    /// An i32 from the host. There's no syntax for this; `read x` desugars to
+    /// an assignment of one.
+    Read,
+}
@@ -0,0 +1,81 @@
+//! Support for running in a browser, with --target browser. The module is the
+//! same as for any other host; what the browser needs on top is JavaScript to
+//! load it, provide its imports, and run it, and the module assembled, since
+//! browsers don't load WAT.
+
+#[cfg(feature = "assemble")]
+use wat;
+
+/// The loader, with `__WASM__` where the module's URL goes, and `__ENTRY__`
+/// where the name main's exported as goes.
+const LOADER: &str = include_str!("loader.js");
+
+/// Returns a script that loads and runs the module at `wasm`, a URL relative
+/// to the page, by calling its `entry` export.
+pub fn loader(wasm: &str, entry: &str) -> String {
+    LOADER.replace("__WASM__", &escape(wasm)).replace("__ENTRY__", &escape(entry))
+}
+
+/// Assembles the module for the loader to load. That's the `assemble`
+/// feature; without it, the WAT has to be assembled some other way, like
+/// with `wat2wasm`.
+#[cfg(feature = "assemble")]
+pub fn assemble(text: &str) -> Result<Vec<u8>, String> {
+    wat::parse_str(text).map_err(|err| err.to_string())
+}
+
+/// Returns a URL with the module in it, so a page can load it without any
+/// other file, even from a file:// URL, which pages can't fetch from.
+pub fn data_url(binary: &[u8]) -> String {
+    format!("data:application/wasm;base64,{}", base64(binary))
+}
+
+/// The digits of base64, from 0 to 63.
+pub const BASE64_DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes in base64, with `=` padding.
+pub fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &byte)| bits | u32::from(byte) << (16 - 8 * i));
+        // Three bytes make four digits; fewer make one more digit than
+        // there are bytes, and padding to make four.
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(BASE64_DIGITS[(bits >> (18 - 6 * i) & 63) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Escapes text for a single-quoted JavaScript string.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// Returns a page that runs the module at `wasm`, with the loader inline.
+/// With a data URL for `wasm`, the page is all there is to open.
+pub fn page(title: &str, wasm: &str, entry: &str) -> String {
+    let title = title.replace('&', "&amp;").replace('<', "&lt;");
+    format!(
+        "<!DOCTYPE html>\n\
+         \n\
+         <html lang=\"en\">\n\
+         \x20 <head>\n\
+         \x20   <meta charset=\"utf-8\">\n\
+         \x20   <title>{}</title>\n\
+         \x20   <script>\n\
+         {}\
+         \x20   </script>\n\
+         \x20 </head>\n\
+         </html>\n",
+        title,
+        loader(wasm, entry)
+    )
+}
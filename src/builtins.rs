@@ -0,0 +1,404 @@
+//! Functions the compiler provides. Programs call them like their own
+//! functions, but each one compiles to a few instructions in place of the
+//! call.
+//!
+//! The math functions are each a single wasm instruction. Wasm only has
+//! `abs`, `min`, and `max` for floats, so like the rest they take f64s.
+//!
+//! `alloc`, `copy`, `fill`, and the string functions other than `len` and
+//! `ptr` are too big to repeat at every call, so their code calls a runtime
+//! function instead, which goes in the module once if anything uses it.
+//! So do `random` and `clock`, whose runtime functions ask the host.
+//!
+//! The vector functions need --simd. Each is a single i32x4 SIMD
+//! instruction, and is named after it, like `i32x4.add`.
+//!
+//! The atomic functions need --threads. They're each a single instruction
+//! too, which traps unless the address is a multiple of four.
+
+use ast::Type;
+use consteval::Value;
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub params: &'static [Type],
+    pub result: Type,
+
+    // What to emit once the arguments are on the stack.
+    pub code: &'static [&'static str],
+
+    // The runtime function the code calls, if it calls one.
+    pub runtime: Option<&'static str>,
+
+    // Whether the last argument is a lane number. That's part of the
+    // instruction, in place of the `{}` in the code, rather than a value on
+    // the stack, so it has to be a constant.
+    pub lane: bool,
+
+    // Whether the first argument is a memory address, which has to be
+    // widened for a 64-bit memory.
+    pub address: bool,
+
+    // What the code works out, for running programs without compiling them.
+    // The arguments have already been type checked. None for the ones with
+    // runtime functions, which the interpreter runs itself, since they use
+    // memory or can trap.
+    pub evaluate: Option<fn(&[Value]) -> Value>,
+}
+
+const BUILTINS: &[Builtin] = &[
+    // The number of bytes in a string, from the high half of the i64.
+    Builtin {
+        name: "len",
+        params: &[Type::Str],
+        result: Type::I32,
+        code: &["(i64.const 32)", "(i64.shr_u)", "(i32.wrap_i64)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| match arguments[0] {
+            Value::Str { len, .. } => Value::I32(len as i32),
+            _ => panic!("len takes a str"),
+        }),
+    },
+    // The address of a string's first byte, from the low half of the i64.
+    Builtin {
+        name: "ptr",
+        params: &[Type::Str],
+        result: Type::I32,
+        code: &["(i32.wrap_i64)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| match arguments[0] {
+            Value::Str { address, .. } => Value::I32(address as i32),
+            _ => panic!("ptr takes a str"),
+        }),
+    },
+    // Makes room for `n` bytes on the heap and returns their address, which
+    // is a multiple of four. `n` is unsigned. Memory grows if it has to, and
+    // if it can't, it traps.
+    Builtin {
+        name: "alloc",
+        params: &[Type::I32],
+        result: Type::I32,
+        code: &["(call $heap.alloc)"],
+        runtime: Some("heap.alloc"),
+        lane: false,
+        address: false,
+        evaluate: None,
+    },
+    // Copies `n` bytes from `src` to `dst`, even if they overlap, and returns
+    // `dst`. `n` is unsigned.
+    Builtin {
+        name: "copy",
+        params: &[Type::I32, Type::I32, Type::I32],
+        result: Type::I32,
+        code: &["(call $mem.move)"],
+        runtime: Some("mem.move"),
+        lane: false,
+        address: false,
+        evaluate: None,
+    },
+    // Sets `n` bytes from `dst` on to the low byte of `val`, and returns
+    // `dst`. `n` is unsigned.
+    Builtin {
+        name: "fill",
+        params: &[Type::I32, Type::I32, Type::I32],
+        result: Type::I32,
+        code: &["(call $mem.fill)"],
+        runtime: Some("mem.fill"),
+        lane: false,
+        address: false,
+        evaluate: None,
+    },
+    // Loads the i32 at `addr`, atomically.
+    Builtin {
+        name: "atomic_load",
+        params: &[Type::I32],
+        result: Type::I32,
+        code: &["(i32.atomic.load)"],
+        runtime: None,
+        lane: false,
+        address: true,
+        evaluate: None,
+    },
+    // Stores `val` at `addr`, atomically, and returns the i32 that was there.
+    // That makes it an exchange, since calls have to give something back.
+    Builtin {
+        name: "atomic_store",
+        params: &[Type::I32, Type::I32],
+        result: Type::I32,
+        code: &["(i32.atomic.rmw.xchg)"],
+        runtime: None,
+        lane: false,
+        address: true,
+        evaluate: None,
+    },
+    // Adds `val` to the i32 at `addr`, atomically, and returns what it was
+    // before.
+    Builtin {
+        name: "atomic_add",
+        params: &[Type::I32, Type::I32],
+        result: Type::I32,
+        code: &["(i32.atomic.rmw.add)"],
+        runtime: None,
+        lane: false,
+        address: true,
+        evaluate: None,
+    },
+    // A new string with the bytes of one and then the other, copied to the
+    // heap.
+    Builtin {
+        name: "concat",
+        params: &[Type::Str, Type::Str],
+        result: Type::Str,
+        code: &["(call $str.concat)"],
+        runtime: Some("str.concat"),
+        lane: false,
+        address: false,
+        evaluate: None,
+    },
+    // Compares the bytes of two strings, like a dictionary would, and
+    // returns -1, 0, or 1 if the first comes before, is the same as, or
+    // comes after the second.
+    Builtin {
+        name: "compare",
+        params: &[Type::Str, Type::Str],
+        result: Type::I32,
+        code: &["(call $str.compare)"],
+        runtime: Some("str.compare"),
+        lane: false,
+        address: false,
+        evaluate: None,
+    },
+    // The `len` bytes of a string starting at `start`. They aren't copied;
+    // the new string points into the old one. Traps if they aren't all in
+    // the string.
+    Builtin {
+        name: "substr",
+        params: &[Type::Str, Type::I32, Type::I32],
+        result: Type::Str,
+        code: &["(call $str.substr)"],
+        runtime: Some("str.substr"),
+        lane: false,
+        address: false,
+        evaluate: None,
+    },
+    // A random number from 0 to 2^31 - 1, from the host's `random`, or
+    // WASI's `random_get`.
+    Builtin {
+        name: "random",
+        params: &[],
+        result: Type::I32,
+        code: &["(call $host.random)"],
+        runtime: Some("host.random"),
+        lane: false,
+        address: false,
+        evaluate: None,
+    },
+    // Milliseconds since some point in the past, which doesn't change while
+    // the program runs, from the host's `now`, or WASI's monotonic clock.
+    // Only the difference between two of them means anything.
+    Builtin {
+        name: "clock",
+        params: &[],
+        result: Type::I64,
+        code: &["(call $host.clock)"],
+        runtime: Some("host.clock"),
+        lane: false,
+        address: false,
+        evaluate: None,
+    },
+    Builtin {
+        name: "abs",
+        params: &[Type::F64],
+        result: Type::F64,
+        code: &["(f64.abs)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| Value::F64(float(arguments[0]).abs())),
+    },
+    Builtin {
+        name: "min",
+        params: &[Type::F64, Type::F64],
+        result: Type::F64,
+        code: &["(f64.min)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| Value::F64(min(float(arguments[0]), float(arguments[1])))),
+    },
+    Builtin {
+        name: "max",
+        params: &[Type::F64, Type::F64],
+        result: Type::F64,
+        code: &["(f64.max)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| Value::F64(-min(-float(arguments[0]), -float(arguments[1])))),
+    },
+    Builtin {
+        name: "sqrt",
+        params: &[Type::F64],
+        result: Type::F64,
+        code: &["(f64.sqrt)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| Value::F64(float(arguments[0]).sqrt())),
+    },
+    Builtin {
+        name: "floor",
+        params: &[Type::F64],
+        result: Type::F64,
+        code: &["(f64.floor)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| Value::F64(float(arguments[0]).floor())),
+    },
+    Builtin {
+        name: "ceil",
+        params: &[Type::F64],
+        result: Type::F64,
+        code: &["(f64.ceil)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| Value::F64(float(arguments[0]).ceil())),
+    },
+    // Rounds towards zero.
+    Builtin {
+        name: "trunc",
+        params: &[Type::F64],
+        result: Type::F64,
+        code: &["(f64.trunc)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| Value::F64(float(arguments[0]).trunc())),
+    },
+    // A vector with the same i32 in every lane.
+    Builtin {
+        name: "i32x4.splat",
+        params: &[Type::I32],
+        result: Type::V128,
+        code: &["(i32x4.splat)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| match arguments[0] {
+            Value::I32(value) => Value::V128([value; 4]),
+            _ => panic!("i32x4.splat takes an i32"),
+        }),
+    },
+    // Lane by lane arithmetic, which wraps like it does on i32s.
+    Builtin {
+        name: "i32x4.add",
+        params: &[Type::V128, Type::V128],
+        result: Type::V128,
+        code: &["(i32x4.add)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| lanewise(arguments, i32::wrapping_add)),
+    },
+    Builtin {
+        name: "i32x4.sub",
+        params: &[Type::V128, Type::V128],
+        result: Type::V128,
+        code: &["(i32x4.sub)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| lanewise(arguments, i32::wrapping_sub)),
+    },
+    Builtin {
+        name: "i32x4.mul",
+        params: &[Type::V128, Type::V128],
+        result: Type::V128,
+        code: &["(i32x4.mul)"],
+        runtime: None,
+        lane: false,
+        address: false,
+        evaluate: Some(|arguments| lanewise(arguments, i32::wrapping_mul)),
+    },
+    // The i32 in one of a vector's lanes, from 0 to 3.
+    Builtin {
+        name: "i32x4.extract_lane",
+        params: &[Type::V128, Type::I32],
+        result: Type::I32,
+        code: &["(i32x4.extract_lane {})"],
+        runtime: None,
+        lane: true,
+        address: false,
+        evaluate: Some(|arguments| match (arguments[0], arguments[1]) {
+            (Value::V128(lanes), Value::I32(lane)) => Value::I32(lanes[lane as usize]),
+            _ => panic!("i32x4.extract_lane takes a v128 and an i32"),
+        }),
+    },
+];
+
+/// How many lanes the vector functions split a v128 into.
+pub const LANES: i32 = 4;
+
+impl Builtin {
+    /// Whether this is one of the vector functions, which need --simd.
+    pub fn is_simd(&self) -> bool {
+        self.result == Type::V128 || self.params.contains(&Type::V128)
+    }
+
+    /// Whether this is one of the atomic functions, which need --threads.
+    pub fn is_atomic(&self) -> bool {
+        self.name.starts_with("atomic_")
+    }
+}
+
+/// Whether `name` is what comes before the `.` in some builtins' names, like
+/// the `i32x4` of `i32x4.add`.
+pub fn is_prefix(name: &str) -> bool {
+    BUILTINS
+        .iter()
+        .any(|builtin| builtin.name.split_once('.').is_some_and(|(prefix, _)| prefix == name))
+}
+
+/// Returns the builtin called `name`, if there is one.
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// Applies `op` to each pair of lanes of two vectors.
+fn lanewise(arguments: &[Value], op: fn(i32, i32) -> i32) -> Value {
+    match (arguments[0], arguments[1]) {
+        (Value::V128(left), Value::V128(right)) => {
+            Value::V128([op(left[0], right[0]), op(left[1], right[1]), op(left[2], right[2]), op(left[3], right[3])])
+        }
+        _ => panic!("expected two v128s"),
+    }
+}
+
+fn float(value: Value) -> f64 {
+    match value {
+        Value::F64(value) => value,
+        _ => panic!("expected an f64"),
+    }
+}
+
+/// The smaller of two f64s, the way `f64.min` works it out: NaN if either is
+/// NaN, and -0 is smaller than 0. Rust's `min` does neither.
+fn min(left: f64, right: f64) -> f64 {
+    if left.is_nan() || right.is_nan() {
+        f64::NAN
+    } else if left == right {
+        if left.is_sign_negative() {
+            left
+        } else {
+            right
+        }
+    } else {
+        left.min(right)
+    }
+}
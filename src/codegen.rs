@@ -0,0 +1,2524 @@
+//! Turns the core language into a WebAssembly module in the text format.
+//! The program has to have passed type checking, which fills in the types of
+//! literals and variables that code generation relies on.
+
+use std::mem;
+
+use builtins;
+use builtins::Builtin;
+
+use consteval;
+use consteval::Value;
+use cost::CostModel;
+use debug;
+use diagnostics::Diagnostics;
+use hir::{
+    Array, BinaryOp, Constant, Expr, ExprKind, FuncType, Function, Global, Place, Program, Record, RecordType, Span,
+    Stmt, Type,
+};
+use json::Json;
+use layout;
+use layout::Layout;
+use mangle::{demangle, mangle};
+use profile::Counter;
+use runtime;
+use wasi;
+use Options;
+
+/// What array accesses do with an index that's out of bounds, picked with
+/// --bounds-check.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum BoundsCheck {
+    // Nothing. The access reads or writes whatever's there, and only traps
+    // if that's past the end of memory.
+    #[default]
+    Off,
+
+    // Trap with `unreachable`.
+    Trap,
+
+    // Use the last element instead. Negative indexes count as too big.
+    Clamp,
+}
+
+/// What runs the module, picked with --target.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Platform {
+    // Something that provides our own imports and calls `main` itself.
+    #[default]
+    Host,
+
+    // A browser. The module is the same as for any other host, but it comes
+    // with a script that loads it.
+    Browser,
+
+    // A WASI runtime, which runs `_start` and only provides WASI's functions.
+    Wasi,
+}
+
+/// A function the program defines.
+struct Signature {
+    name: String,
+    params: Vec<Type>,
+    result: Type,
+    more_results: Vec<Type>,
+
+    // Whether each parameter is `var`.
+    by_reference: Vec<bool>,
+}
+
+/// Everything we know about the function currently being compiled. Each
+/// function gets a fresh scope, so variables don't leak between functions.
+#[derive(Default)]
+struct FunctionScope {
+    // Parameters, in order.
+    params: Vec<(String, Type)>,
+
+    // The parameters that are `var`. Their locals hold an address, and the
+    // value is in memory there.
+    var_params: Vec<String>,
+
+    // Locals declared in the function, in order of first use.
+    locals: Vec<(String, Type)>,
+
+    // What the function returns. Only main can return nothing, with
+    // --no-result.
+    result: Option<Type>,
+
+    // What else it returns, if it returns more than one value.
+    more_results: Vec<Type>,
+
+    // The function's instructions. We hold on to them until the function is
+    // done, since WAT wants every local declared before the first instruction.
+    body: String,
+
+    // The file and line the last --comments comment showed, so statements
+    // on the same line share one.
+    commented_line: Option<(String, usize)>,
+
+    // With --rc, the locals that hold a reference to a string: the ones the
+    // program assigns, but not our own temporaries. The references, and the
+    // ones string parameters hold, are given up when the function ends.
+    owned: Vec<String>,
+
+    // How many locals we've used to hold on to strings until we can give
+    // them up, with --rc.
+    rc_temps: usize,
+
+    // The variables it passes to `var` parameters, other than its own `var`
+    // parameters, with their types once they're known. Each one's value is
+    // in a cell of its frame, in order.
+    homes: Vec<(String, Option<Type>)>,
+
+    // Where the frame is, for the init blocks and main, which only run once
+    // at a time. Functions take theirs off the `var` stack when they start,
+    // and `$var.frame` points at it.
+    fixed_frame: Option<u32>,
+}
+
+impl FunctionScope {
+    /// Returns true if `name` is a parameter or local of this function.
+    fn is_defined(&self, name: &str) -> bool {
+        self.variable(name).is_some()
+    }
+
+    /// Returns the type of a parameter or local.
+    fn variable(&self, name: &str) -> Option<Type> {
+        let home = || self.homes.iter().find(|&(home, _)| home == name).and_then(|&(_, ty)| ty);
+        self.params
+            .iter()
+            .chain(&self.locals)
+            .find(|&(variable, _)| variable == name)
+            .map(|&(_, ty)| ty)
+            .or_else(home)
+    }
+
+    /// The cell in the frame that a variable's value is in, if it has one.
+    fn home(&self, name: &str) -> Option<u32> {
+        let cell = self.homes.iter().position(|(home, _)| home == name)?;
+        Some(cell as u32 * layout::CELL_SIZE)
+    }
+
+    /// The instruction that leaves the frame's address on the stack.
+    fn frame_address(&self) -> String {
+        match self.fixed_frame {
+            Some(frame) => format!("(i32.const {})", frame),
+            None => String::from("(local.get $var.frame)"),
+        }
+    }
+
+    /// How much room the frame takes on the `var` stack.
+    fn frame_size(&self) -> u32 {
+        match self.fixed_frame {
+            Some(_) => 0,
+            None => self.homes.len() as u32 * layout::CELL_SIZE,
+        }
+    }
+}
+
+pub struct CodeGen<'a> {
+    options: &'a Options,
+
+    // The WAT for the whole module, put together once everything's compiled.
+    output: String,
+
+    // Every user-defined function we've seen so far.
+    functions: Vec<Signature>,
+
+    // The functions in `$funcs`, the table that function pointers index,
+    // from its second slot on. The first is left empty for the null
+    // pointer.
+    table: Vec<String>,
+
+    // Every global in the program. Globals are visible from every function,
+    // unless a parameter or local has the same name.
+    globals: Vec<(String, Type)>,
+
+    // Constants and their values. Like globals, they're visible everywhere
+    // unless a parameter or local has the same name.
+    constants: Vec<(String, Value)>,
+
+    // WAT for the functions we've finished compiling.
+    function_code: String,
+
+    // Where arrays and string literals go in memory.
+    layout: Layout,
+
+    // With --gc, each array's length. The arrays are globals instead of
+    // being in the layout.
+    gc_arrays: Vec<(String, u32)>,
+
+    // Whether the program reads or writes memory, so needs a memory even if
+    // there aren't any strings.
+    uses_memory: bool,
+
+    // The host functions the program uses: the module they're from, their
+    // name, and their type.
+    imports: Vec<(&'static str, &'static str, &'static str)>,
+
+    // The functions we've added to the module for the program to call, like
+    // the print functions under WASI.
+    runtime: Vec<&'static str>,
+
+    // With --rc, the records that have a `$rc.release.` function for giving
+    // up a reference to one.
+    released: Vec<RecordType>,
+
+    // Where the WASI print functions do their work, once they need it.
+    scratch: Option<u32>,
+
+    // The bottom of the stack that functions' frames go on, if any function
+    // has a `var` parameter. `$var.stack` points at the top.
+    stack: Option<u32>,
+
+    // The globals passed to `var` parameters. They're in memory, and their
+    // globals hold their addresses.
+    homed_globals: Vec<String>,
+
+    // The address and initial bytes of each of those, for its data segment.
+    home_data: Vec<(u32, Vec<u8>)>,
+
+    // With --coverage, where each statement with a counter is, in the order
+    // of the counters, and where the counters are, once they've been placed.
+    covered: Vec<Span>,
+    counters: Option<u32>,
+
+    // With --profile, what each of the `$profile` globals counts, in order.
+    profiled: Vec<Counter>,
+
+    // The function we're in the middle of compiling.
+    scope: FunctionScope,
+
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl<'a> CodeGen<'a> {
+    pub fn new(options: &'a Options, diagnostics: &'a mut Diagnostics) -> CodeGen<'a> {
+        CodeGen {
+            options,
+            output: String::new(),
+            functions: Vec::new(),
+            table: Vec::new(),
+            globals: Vec::new(),
+            constants: Vec::new(),
+            function_code: String::new(),
+            layout: Layout::new(),
+            gc_arrays: Vec::new(),
+            uses_memory: false,
+            imports: Vec::new(),
+            runtime: Vec::new(),
+            released: Vec::new(),
+            scratch: None,
+            stack: None,
+            homed_globals: Vec::new(),
+            home_data: Vec::new(),
+            covered: Vec::new(),
+            counters: None,
+            profiled: Vec::new(),
+            scope: FunctionScope::default(),
+            diagnostics,
+        }
+    }
+
+    /// Compiles the whole program and returns the generated WAT.
+    pub fn generate(&mut self, program: &Program) -> String {
+        for constant in &program.constants {
+            self.define_constant(constant);
+        }
+
+        for array in &program.arrays {
+            self.define_array(array);
+        }
+        for record in &program.records {
+            self.layout.add_record(record);
+        }
+        if self.options.gc {
+            self.define_structs(&program.records);
+        }
+
+        self.homed_globals.clone_from(&program.global_homes);
+        for global in &program.globals {
+            self.compile_global(global);
+        }
+
+        // The init blocks' and main's variables that are passed to `var`
+        // parameters have fixed places.
+        let init_frame = self.reserve_frame(&program.init, &program.init_homes);
+        let main_frame = self.reserve_frame(&program.main, &program.main_homes);
+
+        if let Some(function) = program.functions.iter().find(|function| !function.var_params.is_empty()) {
+            self.define_stack(function.name_span);
+        }
+
+        // Functions can call ones that come after them.
+        for function in &program.functions {
+            self.functions.push(Signature {
+                name: function.name.clone(),
+                params: function.params.iter().map(|&(_, ty)| ty).collect(),
+                result: function.result,
+                more_results: function.more_results.clone(),
+                by_reference: function
+                    .params
+                    .iter()
+                    .map(|(param, _)| function.var_params.contains(param))
+                    .collect(),
+            });
+        }
+        self.compile_table(&program.table);
+        for function in &program.functions {
+            self.compile_function(function);
+        }
+
+        self.compile_handlers(&program.handlers);
+        self.compile_init(&program.init, &program.init_homes, init_frame);
+
+        self.scope.result = if self.options.no_result { None } else { Some(Type::I32) };
+        self.scope.homes = program.main_homes.iter().map(|home| (home.clone(), None)).collect();
+        self.scope.fixed_frame = main_frame;
+        self.emit_fuel_check();
+        self.emit_profile_count(Counter::Main);
+        self.compile_block(&program.main);
+        let main = mem::take(&mut self.scope);
+        self.emit_function("main", main);
+
+        if self.options.metering {
+            self.import("env", "out_of_fuel", "");
+            self.emit_module_line(&format!("(global $meter.fuel (mut i32) (i32.const {}))", FUEL));
+            self.emit_module_line("(export \"fuel\" (global $meter.fuel))");
+        }
+        self.define_profile();
+
+        if self.options.platform == Platform::Wasi {
+            self.define_runtime("_start");
+        }
+
+        let function_code = mem::take(&mut self.function_code);
+        self.output.push_str(&function_code);
+        if self.options.coverage {
+            self.define_counters();
+        }
+        self.emit_memory();
+        self.emit_main_export();
+        if self.options.embed_source {
+            self.emit_source();
+        }
+
+        // Imports have to come first, but we only know which ones we need
+        // once everything's compiled.
+        let fields = mem::take(&mut self.output);
+        self.emit_module_start();
+        self.emit_imports();
+        self.output.push_str(&fields);
+        self.emit_module_end();
+        self.output.clone()
+    }
+
+    /// Works out a constant's value. Constants can only use constants
+    /// defined before them.
+    fn define_constant(&mut self, constant: &Constant) {
+        // Carry on with a dummy value if the constant is broken, so we don't
+        // also complain about every place it's used.
+        let ty = constant.ty.expect("constant wasn't type checked");
+        let value = self.evaluate(&constant.value).unwrap_or(Value::zero(ty));
+        self.constants.push((constant.name.clone(), value));
+    }
+
+    /// Returns the value of a constant, if there's one called `name`.
+    fn constant(&self, name: &str) -> Option<Value> {
+        self.constants
+            .iter()
+            .find(|&(constant, _)| constant == name)
+            .map(|&(_, value)| value)
+    }
+
+    /// Works out the value of a constant expression at compile time.
+    /// Reports an error and returns None if the value can't be worked out.
+    fn evaluate(&mut self, expr: &Expr) -> Option<Value> {
+        match expr.kind {
+            ExprKind::Int(..) => Some(int_value(expr)),
+            // Rust and wasm read decimals the same way, rounding to the
+            // nearest f64, so this can't fail on anything the parser accepts.
+            ExprKind::Float(ref num) => num.parse().ok().map(Value::F64),
+            ExprKind::Bool(value) => Some(Value::Bool(value)),
+            ExprKind::Str(ref bytes) => Some(self.string(bytes)),
+            ExprKind::Variable(ref name) => self.constant(name),
+            ExprKind::Call(..)
+            | ExprKind::Address(_)
+            | ExprKind::CallIndirect(..)
+            | ExprKind::Load(_)
+            | ExprKind::Index(..)
+            | ExprKind::Field(..)
+            | ExprKind::Read => None,
+            ExprKind::View(record, ref address) => match self.evaluate(address)? {
+                Value::I32(address) if self.options.gc && address != 0 => {
+                    let message = "with --gc, the only record known at compile time is the null one";
+                    self.diagnostics.error(expr.span, message);
+                    None
+                }
+                Value::I32(address) => Some(Value::Record(record, address as u32)),
+                _ => None,
+            },
+            ExprKind::Binary(op, ref left, ref right) if op.is_logical() => {
+                let left = consteval::truth(self.evaluate(left)?)?;
+                if left == (op == BinaryOp::Or) {
+                    return Some(Value::Bool(left));
+                }
+                consteval::truth(self.evaluate(right)?).map(Value::Bool)
+            }
+            ExprKind::Binary(op, ref left, ref right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                let value = consteval::binary(op, left, right);
+                if value.is_none() {
+                    self.diagnostics.error(expr.span, "this would trap at runtime");
+                }
+                value
+            }
+            ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+                let condition = consteval::truth(self.evaluate(condition)?)?;
+                self.evaluate(if condition { then_value } else { else_value })
+            }
+        }
+    }
+
+    fn compile_global(&mut self, global: &Global) {
+        let ty = global.ty.expect("global wasn't type checked");
+        self.globals.push((global.name.clone(), ty));
+
+        let value = global.value.as_ref().and_then(|value| self.evaluate(value));
+        let value = value.unwrap_or(Value::zero(ty));
+        if self.homed_globals.contains(&global.name) {
+            self.define_home(global, value);
+            return;
+        }
+        let line = format!(
+            "(global ${} (mut {}) {})",
+            mangle(&global.name),
+            self.value_type(ty),
+            self.constant_instruction(value)
+        );
+        self.emit_module_line(&line);
+    }
+
+    /// Puts a global that's passed to `var` parameters in memory, starting
+    /// out with its value, and its global holds its address instead.
+    fn define_home(&mut self, global: &Global, value: Value) {
+        let address = match self.layout.reserve(u64::from(layout::CELL_SIZE)) {
+            Some(address) => address,
+            None => {
+                let message = format!("there's no room in memory for `{}`", global.name);
+                self.diagnostics.error(global.name_span, &message);
+                return;
+            }
+        };
+        self.uses_memory = true;
+        self.home_data.push((address, value.to_le_bytes()));
+        self.emit_module_line(&format!("(global ${} i32 (i32.const {}))", mangle(&global.name), address));
+    }
+
+    /// Makes room for the frame of the init blocks or main, if `homes` has
+    /// anything in it. `block` is the one it's for, for the error if there's
+    /// no room.
+    fn reserve_frame(&mut self, block: &[Stmt], homes: &[String]) -> Option<u32> {
+        if homes.is_empty() {
+            return None;
+        }
+        let frame = self.layout.reserve(u64::from(layout::CELL_SIZE) * homes.len() as u64);
+        if frame.is_none() {
+            let span = block.iter().find_map(Stmt::span).expect("nothing's passed to `var` parameters");
+            self.diagnostics.error(span, "there's no room in memory for the variables passed to `var` parameters");
+        }
+        frame
+    }
+
+    /// Makes room for the stack that `var` arguments go on, and the global
+    /// that points at its top. `span` is a function with a `var` parameter,
+    /// for the error if there's no room.
+    fn define_stack(&mut self, span: Span) {
+        let bottom = match self.layout.reserve(layout::STACK_SIZE) {
+            Some(bottom) => bottom,
+            None => {
+                self.diagnostics.error(span, "there's no room in memory for `var` arguments");
+                return;
+            }
+        };
+        self.stack = Some(bottom);
+
+        let top = u64::from(bottom) + layout::STACK_SIZE;
+        self.emit_module_line(&format!("(global $var.stack (mut i32) (i32.const {}))", top as u32));
+    }
+
+    /// With --coverage, makes room for a counter for every statement, once
+    /// they've all been compiled. They go after everything else the layout
+    /// placed, and `$coverage.counters` points at the first.
+    fn define_counters(&mut self) {
+        let size = 4 * self.covered.len() as u64;
+        let address = match (self.layout.reserve(size), self.covered.first()) {
+            (Some(address), _) => address,
+            (None, Some(&span)) => {
+                self.diagnostics.error(span, "there's no room in memory for coverage counters");
+                return;
+            }
+            (None, None) => return,
+        };
+        self.counters = Some(address);
+        self.emit_module_line(&format!("(global $coverage.counters i32 (i32.const {}))", address));
+    }
+
+    /// With --profile, defines the globals the counters are in, and exports
+    /// each one named after what it counts.
+    fn define_profile(&mut self) {
+        let profiled = mem::take(&mut self.profiled);
+        for (index, counter) in profiled.iter().enumerate() {
+            let label = counter.label(self.diagnostics.source_map());
+            self.emit_module_line(&format!("(global $profile.{} (mut i32) (i32.const 0))", index));
+            self.emit_module_line(&format!(
+                "(export \"profile:{}\" (global $profile.{}))",
+                escape_bytes(label.as_bytes()),
+                index
+            ));
+        }
+    }
+
+    /// With --coverage, says where the counters are and which statement each
+    /// one counts, as JSON for the host: counter `i` is the i32 at `address`
+    /// plus `4 * i`. There's nothing to say until the program's been
+    /// generated.
+    pub fn coverage_map(&self) -> Option<String> {
+        let address = self.counters?;
+        let source_map = self.diagnostics.source_map();
+        let statements = self
+            .covered
+            .iter()
+            .map(|&span| {
+                let start = source_map.location(span);
+                let end = source_map.location(Span { start: span.end, end: span.end });
+                Json::Object(vec![
+                    (String::from("file"), Json::String(start.file.to_string())),
+                    (String::from("line"), Json::Number(start.line as f64)),
+                    (String::from("column"), Json::Number(start.column as f64)),
+                    (String::from("end_line"), Json::Number(end.line as f64)),
+                    (String::from("end_column"), Json::Number(end.column as f64)),
+                ])
+            })
+            .collect();
+        let map = Json::Object(vec![
+            (String::from("address"), Json::Number(address as f64)),
+            (String::from("statements"), Json::Array(statements)),
+        ]);
+        Some(map.to_string())
+    }
+
+    /// Puts a string's bytes in memory, unless they're already there, and
+    /// returns the string.
+    fn string(&mut self, bytes: &[u8]) -> Value {
+        Value::Str {
+            address: self.layout.add_string(bytes),
+            len: bytes.len() as u32,
+        }
+    }
+
+    /// With --gc, declares a struct type for each record, with a mutable
+    /// field for each of its fields. Records can refer to each other, so the
+    /// types go in one recursive group.
+    fn define_structs(&mut self, records: &[Record]) {
+        if records.is_empty() {
+            return;
+        }
+
+        self.emit_module_line("(rec");
+        for record in records {
+            let fields: Vec<String> = record
+                .fields
+                .iter()
+                .map(|field| format!(" (field ${} (mut {}))", mangle(&field.name), self.value_type(field.ty)))
+                .collect();
+            let ty = struct_name(RecordType::new(&record.name));
+            self.emit_module_line(&format!("(type {} (struct{}))", ty, fields.concat()));
+        }
+        self.emit_module_line(")");
+    }
+
+    /// Works out an array's length and makes room for it in memory. With
+    /// --gc, it's a global holding a GC array instead, made when the module
+    /// starts.
+    fn define_array(&mut self, array: &Array) {
+        let len = match self.evaluate(&array.len) {
+            Some(Value::I32(len)) if len < 0 => {
+                self.diagnostics.error(array.len.span, "array length can't be negative");
+                return;
+            }
+            Some(Value::I32(len)) => len as u32,
+            _ => return,
+        };
+
+        if self.options.gc {
+            if self.gc_arrays.is_empty() {
+                self.emit_module_line(&format!("(type {} (array (mut i32)))", GC_ARRAY));
+            }
+            self.gc_arrays.push((array.name.clone(), len));
+            self.emit_module_line(&format!(
+                "(global $array.{} (ref {}) (array.new_default {} (i32.const {})))",
+                mangle(&array.name),
+                GC_ARRAY,
+                GC_ARRAY,
+                len
+            ));
+            return;
+        }
+
+        if self.layout.add_array(&array.name, len).is_none() {
+            self.diagnostics.error(array.len.span, "array doesn't fit in memory");
+        }
+    }
+
+    /// Returns the type of a global, if there's one called `name`.
+    fn global(&self, name: &str) -> Option<Type> {
+        self.globals
+            .iter()
+            .find(|&(global, _)| global == name)
+            .map(|&(_, ty)| ty)
+    }
+
+    fn compile_function(&mut self, function: &Function) {
+        // A `var` parameter is an address, and GC records don't have one.
+        let var_record = function
+            .params
+            .iter()
+            .any(|(param, ty)| matches!(ty, Type::Record(_)) && function.var_params.contains(param));
+        if self.options.gc && var_record {
+            self.diagnostics.error(function.name_span, "with --gc, records can't be passed to `var` parameters");
+        }
+
+        self.compile_body(
+            &mangle(&function.name),
+            function.params.clone(),
+            function.var_params.clone(),
+            &function.homes,
+            function.result,
+            function.more_results.clone(),
+            &function.body,
+        );
+
+        if function.exported || self.options.export_all {
+            self.emit_module_line(&format!(
+                "(export \"{}\" (func ${}))",
+                escape_bytes(function.name.as_bytes()),
+                mangle(&function.name)
+            ));
+        }
+    }
+
+    /// Compiles a function body in a fresh scope and prints the function.
+    #[allow(clippy::too_many_arguments)]
+    fn compile_body(
+        &mut self,
+        name: &str,
+        params: Vec<(String, Type)>,
+        var_params: Vec<String>,
+        homes: &[String],
+        result: Type,
+        more_results: Vec<Type>,
+        body: &[Stmt],
+    ) {
+        let homes = homes
+            .iter()
+            .map(|home| (home.clone(), params.iter().find(|&(param, _)| param == home).map(|&(_, ty)| ty)))
+            .collect();
+        self.scope = FunctionScope {
+            params,
+            var_params,
+            result: Some(result),
+            more_results,
+            homes,
+            ..FunctionScope::default()
+        };
+        self.emit_fuel_check();
+        self.emit_profile_count(Counter::Call(demangle(name)));
+        self.enter_frame();
+        self.compile_block(body);
+        if !self.options.rc {
+            self.leave_frame();
+        }
+        let scope = mem::take(&mut self.scope);
+        self.emit_function(name, scope);
+    }
+
+    /// Puts every function the program takes the address of in `$funcs`,
+    /// after the empty slot for the null pointer. Calling through a null
+    /// pointer traps, since there's nothing in the slot.
+    fn compile_table(&mut self, table: &[String]) {
+        self.table = table.to_vec();
+        if table.is_empty() {
+            return;
+        }
+
+        let slots: Vec<String> = table.iter().map(|function| format!("${}", mangle(function))).collect();
+        self.emit_module_line(&format!("(table $funcs {} funcref)", table.len() + 1));
+        self.emit_module_line(&format!("(elem (table $funcs) (i32.const 1) func {})", slots.join(" ")));
+    }
+
+    /// Event handlers all have the same type: they take the event's payload
+    /// and return an i32. Each event gets a number, which is its handler's
+    /// slot in a table, and is exported as an `event:<name>` global so the
+    /// host can look it up. The host calls the exported `dispatch` function
+    /// with the event number and payload to run the handler.
+    fn compile_handlers(&mut self, handlers: &[Function]) {
+        if handlers.is_empty() {
+            return;
+        }
+
+        self.emit_module_line("(type $handler (func (param i32) (result i32)))");
+
+        let mut events: Vec<&str> = Vec::new();
+        for handler in handlers {
+            // Handlers that don't care about the payload still have to accept
+            // it, so they get a parameter the program can't name.
+            let mut params = handler.params.clone();
+            if params.is_empty() {
+                params.push((String::from("event.payload"), Type::I32));
+            }
+
+            let name = format!("on.{}", mangle(&handler.name));
+            self.compile_body(&name, params, Vec::new(), &handler.homes, Type::I32, Vec::new(), &handler.body);
+
+            let id = events.len();
+            self.emit_module_line(&format!("(global $event.{} i32 (i32.const {}))", mangle(&handler.name), id));
+            self.emit_module_line(&format!(
+                "(export \"event:{}\" (global $event.{}))",
+                handler.name,
+                mangle(&handler.name)
+            ));
+            events.push(&handler.name);
+        }
+
+        let slots: Vec<String> = events.iter().map(|event| format!("$on.{}", mangle(event))).collect();
+        self.emit_module_line(&format!("(table $handlers {} funcref)", events.len()));
+        self.emit_module_line(&format!("(elem (table $handlers) (i32.const 0) func {})", slots.join(" ")));
+
+        self.function_code.push_str(
+            "(func $dispatch (param $event i32) (param $payload i32) (result i32)\n\
+             (local.get $payload)\n\
+             (local.get $event)\n\
+             (call_indirect $handlers (type $handler))\n\
+             )\n",
+        );
+        self.emit_module_line("(export \"dispatch\" (func $dispatch))");
+    }
+
+    /// The init blocks go in `$init`, which the module's start section runs
+    /// when it's instantiated. Start functions can't return anything.
+    fn compile_init(&mut self, init: &[Stmt], homes: &[String], frame: Option<u32>) {
+        if init.is_empty() {
+            return;
+        }
+
+        self.scope = FunctionScope {
+            homes: homes.iter().map(|home| (home.clone(), None)).collect(),
+            fixed_frame: frame,
+            ..FunctionScope::default()
+        };
+        self.emit_fuel_check();
+        self.emit_profile_count(Counter::Init);
+        self.compile_block(init);
+        let scope = mem::take(&mut self.scope);
+        self.emit_function("init", scope);
+        self.emit_module_line("(start $init)");
+    }
+
+    fn compile_block(&mut self, block: &[Stmt]) {
+        for stmt in block {
+            self.compile_stmt(stmt);
+        }
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) {
+        if let Some(span) = stmt.span().filter(|_| self.options.comments) {
+            self.emit_comment(span);
+        }
+        if let Some(span) = stmt.span().filter(|_| self.options.debug) {
+            // A block's statements are marked on their own.
+            if !matches!(*stmt, Stmt::Block(_)) {
+                let location = self.diagnostics.source_map().location(span);
+                let mark = debug::mark(location.file, location.line, location.column);
+                self.emit_line(&mark);
+            }
+        }
+        if let Some(span) = stmt.span().filter(|_| self.options.coverage) {
+            // A block's statements are counted on their own.
+            if !matches!(*stmt, Stmt::Block(_)) {
+                self.emit_counter(span);
+            }
+        }
+
+        match *stmt {
+            Stmt::Assign { ref name, ty, ref value, .. } => self.compile_assignment(name, ty, value),
+            Stmt::If { ref condition, ref then, ref otherwise } => {
+                self.compile_if(condition, then, otherwise)
+            }
+            Stmt::While { ref condition, ref body } => self.compile_while(condition, body),
+            Stmt::Case { ref subject, ref arms, ref otherwise } => {
+                self.compile_case(subject, arms, otherwise.as_deref().unwrap_or_default())
+            }
+            Stmt::Block(ref body) => self.compile_block(body),
+            // Main still stops at a `return` with --no-result, but there's
+            // nowhere for the value to go.
+            Stmt::Return(ref value) if self.is_tail_call(value) => match value.kind {
+                ExprKind::Call(ref name, ref arguments) => {
+                    for argument in arguments {
+                        self.compile_expr(argument);
+                    }
+                    self.leave_frame();
+                    self.emit_line(&format!("(return_call ${})", mangle(name)));
+                }
+                ExprKind::CallIndirect(ref callee, ref arguments) => {
+                    self.compile_indirect_call("return_call_indirect", callee, arguments)
+                }
+                _ => unreachable!("only calls can be tail calls"),
+            },
+            Stmt::Return(ref value) => {
+                self.compile_owned(value);
+                if self.scope.result.is_none() {
+                    self.emit_line("(drop)");
+                }
+                self.emit_return();
+            }
+            Stmt::AssignValues { ref names, ref types, ref value } => self.compile_assign_values(names, types, value),
+            Stmt::ReturnValues(ref values) => {
+                for value in values {
+                    self.compile_owned(value);
+                }
+                self.emit_return();
+            }
+            Stmt::Print(ref value) => self.compile_print(value),
+            Stmt::Assert(ref condition) => self.compile_assert(condition),
+            Stmt::Halt(ref status) => self.compile_halt(status),
+            Stmt::Call(ref value) => self.compile_call_stmt(value),
+            Stmt::Store { ref place, ref value } if self.options.gc && !matches!(*place, Place::Memory(_)) => {
+                let instruction = self.compile_gc_place(place);
+                self.compile_expr(value);
+                self.emit_line(&instruction);
+            }
+            Stmt::Store { ref place, ref value } => {
+                let ty = self.type_of(value);
+                let offset = self.compile_place(place);
+                if self.is_counted(ty) {
+                    // The value that was there loses its reference, so we
+                    // need the address again.
+                    self.declare_local("rc.place", if self.options.memory64 { Type::I64 } else { Type::I32 });
+                    self.emit_line("(local.tee $rc.place)");
+                    self.compile_owned(value);
+                    self.emit_line("(local.get $rc.place)");
+                    self.emit_line(&memory_instruction(&format!("{}.load", ty.wasm_name()), offset));
+                    self.emit_release(ty);
+                } else {
+                    self.compile_expr(value);
+                }
+                let store = format!("{}.store", ty.wasm_name());
+                self.emit_line(&memory_instruction(&store, offset));
+            }
+        }
+    }
+
+    /// Leaves the function, with whatever it returns on the stack, giving
+    /// back its frame. With --rc, that's by way of the end of the block
+    /// around its body, where the references its variables hold are given
+    /// up first.
+    fn emit_return(&mut self) {
+        if self.options.rc {
+            self.emit_line("(br $rc.body)");
+        } else {
+            self.leave_frame();
+            self.emit_line("(return)");
+        }
+    }
+
+    /// Whether `return value` can be a `return_call`, with --tail-calls: the
+    /// value has to be a call to a function the program defines, or through
+    /// a pointer, and main has to return what it returns. A call with `var`
+    /// arguments can be passing the addresses of cells in our frame, which
+    /// goes before a tail call, so it can't be one.
+    fn is_tail_call(&self, value: &Expr) -> bool {
+        match value.kind {
+            ExprKind::Call(ref name, _) => {
+                self.options.tail_calls
+                    && self.scope.result.is_some()
+                    && self.signature(name).is_some()
+                    && !self.takes_var(name)
+            }
+            ExprKind::CallIndirect(..) => self.options.tail_calls && self.scope.result.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Hands a value to the print function for its type, which is the
+    /// host's, or one of ours under WASI. Bools print as 1 or 0, and strings
+    /// are passed as their address and length.
+    fn compile_print(&mut self, value: &Expr) {
+        self.compile_expr(value);
+        let import = match self.type_of(value) {
+            Type::I32 | Type::Bool | Type::Enum(_) => ("print", "(param i32)"),
+            Type::I64 => ("print_i64", "(param i64)"),
+            Type::F64 => ("print_f64", "(param f64)"),
+            Type::Func(_) | Type::Record(_) | Type::V128 => {
+                unreachable!("pointers, records, and vectors can't be printed")
+            }
+            Type::Str => {
+                self.declare_local("print.str", Type::Str);
+                self.emit_line("(local.tee $print.str)");
+                self.emit_line("(i32.wrap_i64)");
+                self.emit_line("(local.get $print.str)");
+                self.emit_line("(i64.const 32)");
+                self.emit_line("(i64.shr_u)");
+                self.emit_line("(i32.wrap_i64)");
+                ("print_str", "(param i32 i32)")
+            }
+        };
+
+        let (name, ty) = import;
+        match self.options.platform {
+            Platform::Host | Platform::Browser => self.import("env", name, ty),
+            Platform::Wasi => self.define_runtime(name),
+        }
+        self.emit_line(&format!("(call ${})", name));
+
+        // Nothing else gets the string, so its reference is given up.
+        if self.is_owned(value) {
+            self.emit_line("(local.get $print.str)");
+            self.emit_release(Type::Str);
+        }
+    }
+
+    /// Imports a host function, given the module it's from, its name, and
+    /// its type, if it hasn't been already.
+    fn import(&mut self, module: &'static str, name: &'static str, ty: &'static str) {
+        if !self.imports.contains(&(module, name, ty)) {
+            self.imports.push((module, name, ty));
+        }
+    }
+
+    /// Adds one of our own functions to the module, along with whatever it
+    /// needs, if it hasn't been already.
+    fn define_runtime(&mut self, name: &'static str) {
+        if self.runtime.contains(&name) {
+            return;
+        }
+        self.runtime.push(name);
+
+        let code = match name {
+            "_start" => {
+                self.import(wasi::MODULE, "proc_exit", wasi::PROC_EXIT);
+                wasi::start(!self.options.no_result)
+            }
+            "wasi.write" => {
+                self.import(wasi::MODULE, "fd_write", wasi::FD_WRITE);
+                wasi::write(self.scratch())
+            }
+            "print" => {
+                self.define_runtime("print_i64");
+                wasi::print_i32()
+            }
+            "print_i64" => {
+                self.define_runtime("wasi.write");
+                wasi::print_i64(self.scratch())
+            }
+            "print_str" => {
+                self.define_runtime("wasi.write");
+                wasi::print_str(self.scratch())
+            }
+            // It needs a lot more room than the others, so it gets its own.
+            "print_f64" => {
+                self.define_runtime("wasi.write");
+                let buffer = self.layout.reserve(u64::from(wasi::PRINT_F64_SIZE));
+                wasi::print_f64(buffer.expect("no room for printing f64s"))
+            }
+            "host.random" if self.options.platform == Platform::Wasi => {
+                self.import(wasi::MODULE, "random_get", wasi::RANDOM_GET);
+                wasi::random(self.scratch())
+            }
+            "host.random" => {
+                self.import("env", "random", "(result i32)");
+                runtime::random()
+            }
+            "host.clock" if self.options.platform == Platform::Wasi => {
+                self.import(wasi::MODULE, "clock_time_get", wasi::CLOCK_TIME_GET);
+                wasi::clock(self.scratch())
+            }
+            "host.clock" => {
+                self.import("env", "now", "(result i64)");
+                runtime::clock()
+            }
+            "heap.alloc" => {
+                self.uses_memory = true;
+                runtime::alloc(self.options.memory64)
+            }
+            "mem.copy" => {
+                self.uses_memory = true;
+                runtime::copy(self.options.memory64, !self.options.no_bulk_memory)
+            }
+            "mem.move" => {
+                self.uses_memory = true;
+                if self.options.no_bulk_memory {
+                    self.define_runtime("mem.copy");
+                }
+                runtime::move_bytes(self.options.memory64, !self.options.no_bulk_memory)
+            }
+            "mem.fill" => {
+                self.uses_memory = true;
+                runtime::fill(self.options.memory64, !self.options.no_bulk_memory)
+            }
+            "str.concat" => {
+                self.define_runtime(if self.options.rc { "rc.alloc" } else { "heap.alloc" });
+                self.define_runtime("mem.copy");
+                runtime::concat(self.options.rc)
+            }
+            "str.compare" => {
+                self.uses_memory = true;
+                runtime::compare(self.options.memory64)
+            }
+            "str.substr" if self.options.rc => {
+                self.define_runtime("rc.alloc");
+                self.define_runtime("mem.copy");
+                runtime::substr(true)
+            }
+            "str.substr" => runtime::substr(false),
+            "rc.alloc" => {
+                self.define_runtime("heap.alloc");
+                runtime::rc_alloc(self.options.memory64)
+            }
+            "rc.retain" => {
+                self.uses_memory = true;
+                runtime::retain(self.options.memory64)
+            }
+            "rc.release" => {
+                self.uses_memory = true;
+                runtime::release(self.options.memory64)
+            }
+            "rc.new" => {
+                self.define_runtime("rc.alloc");
+                self.define_runtime("mem.fill");
+                runtime::rc_new()
+            }
+            "rc.retain.record" => {
+                self.uses_memory = true;
+                runtime::retain_record(self.options.memory64)
+            }
+            _ => panic!("no runtime function called {}", name),
+        };
+        self.function_code.push_str(&code);
+    }
+
+    /// Returns the address of the WASI print functions' scratch area, making
+    /// room for it the first time.
+    fn scratch(&mut self) -> u32 {
+        if self.scratch.is_none() {
+            let address = self.layout.reserve(u64::from(wasi::SCRATCH_SIZE));
+            self.scratch = Some(address.expect("no room for the scratch area"));
+        }
+        self.scratch.unwrap()
+    }
+
+    /// Assigning to a global changes the global. Otherwise, the first
+    /// assignment to a name declares it as a local of the current function.
+    fn compile_assignment(&mut self, name: &str, ty: Option<Type>, value: &Expr) {
+        let ty = ty.expect("assignment wasn't type checked");
+        if self.is_counted(ty) {
+            self.compile_owned_assignment(name, ty, |codegen| codegen.compile_owned(value));
+            return;
+        }
+        self.compile_assignment_with(name, ty, |codegen| codegen.compile_expr(value));
+    }
+
+    /// With --rc, assigns to `name`, a string or record variable of type
+    /// `ty`, a value that `compile_value` leaves on the stack with a
+    /// reference for it, and gives up the reference to the value it held
+    /// before. That's only once the new one's worked out, in case it's the
+    /// same one.
+    fn compile_owned_assignment<F>(&mut self, name: &str, ty: Type, compile_value: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let is_reference = self.is_reference(name);
+        let is_var = self.scope.var_params.iter().any(|param| param == name);
+        let is_global = !is_var && !self.scope.is_defined(name) && self.global(name).is_some();
+        if !is_var && !is_global && !self.scope.owned.iter().any(|owned| owned == name) {
+            self.scope.owned.push(name.to_string());
+        }
+
+        self.compile_assignment_with(name, ty, |codegen| {
+            compile_value(codegen);
+            if is_reference {
+                let offset = codegen.compile_var_address(name);
+                codegen.emit_line(&memory_instruction(&format!("{}.load", ty.wasm_name()), offset));
+            } else if is_global {
+                codegen.emit_line(&format!("(global.get ${})", mangle(name)));
+            } else {
+                codegen.emit_line(&format!("(local.get ${})", mangle(name)));
+            }
+            codegen.emit_release(ty);
+        });
+    }
+
+    /// Assigns to `name`, a variable of type `ty`, whatever value
+    /// `compile_value` leaves on the stack.
+    fn compile_assignment_with<F>(&mut self, name: &str, ty: Type, compile_value: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        if self.is_reference(name) {
+            if let Some(&mut (_, ref mut home)) = self.scope.homes.iter_mut().find(|&&mut (ref home, _)| home == name) {
+                *home = Some(ty);
+            }
+            let offset = self.compile_var_address(name);
+            compile_value(self);
+            self.emit_line(&memory_instruction(&format!("{}.store", ty.wasm_name()), offset));
+            return;
+        }
+
+        let is_global = !self.scope.is_defined(name) && self.global(name).is_some();
+        if is_global {
+            self.emit_line(&format!("(global.set ${}", mangle(name)));
+        } else {
+            self.emit_line(&format!("(local.set ${}", mangle(name)));
+        }
+        compile_value(self);
+        self.emit_line(")");
+
+        if !is_global && !self.scope.is_defined(name) {
+            self.scope.locals.push((name.to_string(), ty));
+        }
+    }
+
+    /// `a, b = f(x)`. The call leaves its values on the stack, last on top,
+    /// so they go into locals of our own from the last one back, and then
+    /// to the variables in order. A `var` parameter needs its address under
+    /// the value, so it can't be assigned straight off the stack.
+    fn compile_assign_values(&mut self, names: &[String], types: &[Type], value: &Expr) {
+        self.compile_expr(value);
+        for (i, &ty) in types.iter().enumerate().rev() {
+            let result = format!("result.{}.{}", ty.name(), i);
+            self.declare_local(&result, ty);
+            self.emit_line(&format!("(local.set ${})", result));
+        }
+        for (i, (name, &ty)) in names.iter().zip(types).enumerate() {
+            let result = format!("result.{}.{}", ty.name(), i);
+            let compile_value = |codegen: &mut Self| codegen.emit_line(&format!("(local.get ${})", result));
+            if self.is_counted(ty) {
+                self.compile_owned_assignment(name, ty, compile_value);
+            } else {
+                self.compile_assignment_with(name, ty, compile_value);
+            }
+        }
+    }
+
+    /// Compiles a call and drops everything it returns. With --rc, a string
+    /// or a record comes with a reference, which is given up instead.
+    fn compile_call_stmt(&mut self, value: &Expr) {
+        self.compile_expr(value);
+        let mut results = vec![self.type_of(value)];
+        if let ExprKind::Call(ref name, _) = value.kind {
+            if let Some(signature) = self.signature(name) {
+                results.extend(signature.more_results.iter().cloned());
+            }
+        }
+        for ty in results.into_iter().rev() {
+            if self.is_counted(ty) {
+                self.emit_release(ty);
+            } else {
+                self.emit_line("(drop)");
+            }
+        }
+    }
+
+    /// Any non-zero i32 condition counts as true.
+    fn compile_if(&mut self, condition: &Expr, then: &[Stmt], otherwise: &[Stmt]) {
+        if let Some(target) = self.options.optimize_for {
+            if let Some((name, ty, then_value, else_value)) = select_candidate(condition, then, otherwise) {
+                // With --rc, strings and records have to be assigned the
+                // long way, so their references are counted.
+                if !self.is_counted(ty) && CostModel::new(target).prefer_select(then_value, else_value) {
+                    self.compile_select(name, ty, condition, then_value, else_value);
+                    return;
+                }
+            }
+        }
+
+        self.compile_condition(condition);
+        self.emit_line("(if");
+        self.emit_line("(then");
+        self.compile_block(then);
+        self.emit_line(")");
+
+        if !otherwise.is_empty() {
+            self.emit_line("(else");
+            self.compile_block(otherwise);
+            self.emit_line(")");
+        }
+
+        self.emit_line(")");
+    }
+
+    /// `case e when a, b ... end`, as a chain of `if`s that compare the
+    /// subject with each value in turn. The subject's only worked out once,
+    /// into a local that every `case` with the same type shares: once a
+    /// branch runs, nothing reads it again.
+    fn compile_case(&mut self, subject: &Expr, arms: &[(Vec<Expr>, Vec<Stmt>)], otherwise: &[Stmt]) {
+        let ty = self.type_of(subject).wasm_name();
+        let local = format!("case.{}", ty);
+        self.declare_local(&local, Type::from_name(ty).expect("subjects are integers or enums"));
+        self.compile_expr(subject);
+        self.emit_line(&format!("(local.set ${})", local));
+
+        for (values, body) in arms {
+            for (i, value) in values.iter().enumerate() {
+                self.emit_line(&format!("(local.get ${})", local));
+                self.compile_expr(value);
+                self.emit_line(&format!("({}.eq)", ty));
+                if i > 0 {
+                    self.emit_line("(i32.or)");
+                }
+            }
+            self.emit_line("(if");
+            self.emit_line("(then");
+            self.compile_block(body);
+            self.emit_line(")");
+            self.emit_line("(else");
+        }
+        self.compile_block(otherwise);
+        for _ in arms {
+            self.emit_line(")");
+            self.emit_line(")");
+        }
+    }
+
+    /// `if c x = a else x = b end`, without branching: both values are
+    /// worked out and `select` picks one.
+    fn compile_select(&mut self, name: &str, ty: Type, condition: &Expr, then_value: &Expr, else_value: &Expr) {
+        self.compile_assignment_with(name, ty, |codegen| {
+            codegen.emit_select(ty);
+            codegen.compile_expr(then_value);
+            codegen.compile_expr(else_value);
+            codegen.compile_condition(condition);
+            codegen.emit_line(")");
+        });
+    }
+
+    /// `c ? a : b`. If working out both values can't make a difference,
+    /// `select` picks one without branching. Otherwise, it's an `if` with a
+    /// result, which only works out the one it picks.
+    fn compile_conditional(&mut self, condition: &Expr, then_value: &Expr, else_value: &Expr) {
+        if !has_calls(condition) && can_speculate(then_value) && can_speculate(else_value) {
+            let ty = self.type_of(then_value);
+            self.emit_select(ty);
+            self.compile_expr(then_value);
+            self.compile_expr(else_value);
+            self.compile_condition(condition);
+            self.emit_line(")");
+            return;
+        }
+
+        // With --rc, if either value comes with a reference, they both have
+        // to, so whatever uses the value knows to give it up.
+        let owned = self.is_owned(then_value) || self.is_owned(else_value);
+        let ty = self.type_of(then_value);
+        self.compile_condition(condition);
+        self.emit_line(&format!("(if (result {})", self.value_type(ty)));
+        for (branch, value) in [("(then", then_value), ("(else", else_value)] {
+            self.emit_line(branch);
+            if owned {
+                self.compile_owned(value);
+            } else {
+                self.compile_expr(value);
+            }
+            self.emit_line(")");
+        }
+        self.emit_line(")");
+    }
+
+    /// Starts a `select` that picks a value of type `ty`. Picking a
+    /// reference, a record with --gc, needs its type spelled out.
+    fn emit_select(&mut self, ty: Type) {
+        match ty {
+            Type::Record(_) if self.options.gc => {
+                let line = format!("(select (result {})", self.value_type(ty));
+                self.emit_line(&line);
+            }
+            _ => self.emit_line("(select"),
+        }
+    }
+
+    /// `a and b` is `if a then b else false`, and `a or b` is `if a then true
+    /// else b`, so `b` only runs when it's needed. Chains of them nest.
+    fn compile_logical(&mut self, op: BinaryOp, left: &Expr, right: &Expr) {
+        self.compile_condition(left);
+        self.emit_line("(if (result i32)");
+        self.emit_line("(then");
+        if op == BinaryOp::And {
+            self.compile_truth(right);
+        } else {
+            self.emit_line("(i32.const 1)");
+        }
+        self.emit_line(")");
+        self.emit_line("(else");
+        if op == BinaryOp::And {
+            self.emit_line("(i32.const 0)");
+        } else {
+            self.compile_truth(right);
+        }
+        self.emit_line(")");
+        self.emit_line(")");
+    }
+
+    /// The loop checks its condition at the top, and branches out to the
+    /// enclosing block once it's false.
+    fn compile_while(&mut self, condition: &Expr, body: &[Stmt]) {
+        self.emit_line("(block");
+        self.emit_line("(loop");
+        self.compile_condition(condition);
+        self.emit_line("(i32.eqz)");
+        self.emit_line("(br_if 1)");
+        self.emit_profile_count(Counter::Loop(condition.span));
+        self.compile_block(body);
+        self.emit_fuel_check();
+        self.emit_line("(br 0)");
+        self.emit_line(")");
+        self.emit_line(")");
+    }
+
+    /// With --coverage, adds one to the counter for a statement that's
+    /// about to run.
+    fn emit_counter(&mut self, span: Span) {
+        let offset = 4 * self.covered.len() as u32;
+        self.covered.push(span);
+        self.uses_memory = true;
+        for _ in 0..2 {
+            self.emit_line("(global.get $coverage.counters)");
+            if self.options.memory64 {
+                self.emit_line("(i64.extend_i32_u)");
+            }
+        }
+        self.emit_line(&memory_instruction("i32.load", offset));
+        self.emit_line("(i32.const 1)");
+        self.emit_line("(i32.add)");
+        self.emit_line(&memory_instruction("i32.store", offset));
+    }
+
+    /// With --metering, uses up a unit of fuel, after checking there's some
+    /// left. If there isn't, the host's `out_of_fuel` gets called, and then
+    /// we trap.
+    fn emit_fuel_check(&mut self) {
+        if !self.options.metering {
+            return;
+        }
+        self.emit_line("(global.get $meter.fuel)");
+        self.emit_line("(i32.const 0)");
+        self.emit_line("(i32.le_s)");
+        self.emit_line("(if");
+        self.emit_line("(then");
+        self.emit_line("(call $out_of_fuel)");
+        self.emit_line("(unreachable)");
+        self.emit_line(")");
+        self.emit_line(")");
+        self.emit_line("(global.set $meter.fuel");
+        self.emit_line("(global.get $meter.fuel)");
+        self.emit_line("(i32.const 1)");
+        self.emit_line("(i32.sub)");
+        self.emit_line(")");
+    }
+
+    /// With --profile, adds one to a counter, giving it a global the first
+    /// time.
+    fn emit_profile_count(&mut self, counter: Counter) {
+        if !self.options.profile {
+            return;
+        }
+        let index = self.profiled.len();
+        self.profiled.push(counter);
+        self.emit_line(&format!("(global.set $profile.{}", index));
+        self.emit_line(&format!("(global.get $profile.{})", index));
+        self.emit_line("(i32.const 1)");
+        self.emit_line("(i32.add)");
+        self.emit_line(")");
+    }
+
+    /// Traps if the condition's false. The host's `assertion_failed` gets
+    /// the line and column of the condition first, except under WASI, which
+    /// only has its own imports, so the module just traps.
+    fn compile_assert(&mut self, condition: &Expr) {
+        self.compile_condition(condition);
+        self.emit_line("(i32.eqz)");
+        self.emit_line("(if");
+        self.emit_line("(then");
+        if self.options.platform != Platform::Wasi {
+            let (line, column) = {
+                let location = self.diagnostics.source_map().location(condition.span);
+                (location.line, location.column)
+            };
+            self.import("env", "assertion_failed", "(param i32 i32)");
+            self.emit_line(&format!("(i32.const {})", line));
+            self.emit_line(&format!("(i32.const {})", column));
+            self.emit_line("(call $assertion_failed)");
+        }
+        self.emit_line("(unreachable)");
+        self.emit_line(")");
+        self.emit_line(")");
+    }
+
+    /// Stops the program with a status. WASI has `proc_exit` for that, and
+    /// the browser loader gives us `exit`; neither returns. Other hosts only
+    /// get a status back from main, so there it's a `return`, and type
+    /// checking makes sure that's where it is.
+    fn compile_halt(&mut self, status: &Expr) {
+        self.compile_expr(status);
+        match self.options.platform {
+            Platform::Wasi => {
+                self.import(wasi::MODULE, "proc_exit", wasi::PROC_EXIT);
+                self.emit_line("(call $proc_exit)");
+                self.emit_line("(unreachable)");
+            }
+            Platform::Browser => {
+                self.import("env", "exit", "(param i32)");
+                self.emit_line("(call $exit)");
+                self.emit_line("(unreachable)");
+            }
+            Platform::Host => {
+                if self.scope.result.is_none() {
+                    self.emit_line("(drop)");
+                }
+                self.emit_return();
+            }
+        }
+    }
+
+    /// Leaves a condition on the stack as an i32 that's non-zero if it's true.
+    /// Bools and i32s are already there. Conditions can only be i64s with
+    /// --truthiness nonzero, and those need comparing with zero.
+    fn compile_condition(&mut self, condition: &Expr) {
+        self.compile_expr(condition);
+        if self.type_of(condition) == Type::I64 {
+            self.emit_line("(i64.const 0)");
+            self.emit_line("(i64.ne)");
+        }
+    }
+
+    /// Like `compile_condition`, but leaves a bool, which is always 0 or 1.
+    fn compile_truth(&mut self, condition: &Expr) {
+        self.compile_condition(condition);
+        if self.type_of(condition) == Type::I32 {
+            self.emit_line("(i32.const 0)");
+            self.emit_line("(i32.ne)");
+        }
+    }
+
+    /// Returns the type of an expression's value.
+    fn type_of(&self, expr: &Expr) -> Type {
+        match expr.kind {
+            ExprKind::Int(_, ty) => ty.expect("literal wasn't type checked"),
+            ExprKind::Float(_) => Type::F64,
+            ExprKind::Bool(_) => Type::Bool,
+            ExprKind::Str(_) => Type::Str,
+            ExprKind::Variable(ref name) => self
+                .scope
+                .variable(name)
+                .or_else(|| self.global(name))
+                .or_else(|| self.constant(name).map(Value::ty))
+                .expect("unknown variable after type checking"),
+            ExprKind::Call(ref name, _) => match self.signature(name) {
+                Some(signature) => signature.result,
+                None => builtins::lookup(name).expect("unknown function after type checking").result,
+            },
+            ExprKind::Address(ref name) => {
+                let signature = self.signature(name).expect("unknown function after type checking");
+                Type::Func(FuncType::new(signature.params.clone(), signature.result))
+            }
+            ExprKind::CallIndirect(ref callee, _) => match self.type_of(callee) {
+                Type::Func(func) => func.result(),
+                _ => panic!("call through something that isn't a pointer"),
+            },
+            ExprKind::Binary(op, _, _) if op.is_comparison() || op.is_logical() => Type::Bool,
+            ExprKind::Binary(_, ref left, _) => self.type_of(left),
+            ExprKind::Load(_) | ExprKind::Index(..) | ExprKind::Read => Type::I32,
+            ExprKind::Field(ref record, ref field) => self.field(record, field).1,
+            ExprKind::View(record, _) => Type::Record(record),
+            ExprKind::Conditional(_, ref then_value, _) => self.type_of(then_value),
+        }
+    }
+
+    /// The offset and type of a field of the record `record` is.
+    fn field(&self, record: &Expr, field: &str) -> (u32, Type) {
+        match self.type_of(record) {
+            Type::Record(record) => self.layout.field(record, field).expect("unknown field after type checking"),
+            _ => panic!("field of something that isn't a record"),
+        }
+    }
+
+    /// Leaves the value of an expression on the stack.
+    fn compile_expr(&mut self, expr: &Expr) {
+        match expr.kind {
+            ExprKind::Int(..) => {
+                let value = int_value(expr);
+                self.emit_line(&format!("({}.const {})", value.ty().wasm_name(), value));
+            }
+            ExprKind::Float(ref num) => self.emit_line(&format!("(f64.const {})", num)),
+            ExprKind::Bool(value) => self.emit_line(&format!("(i32.const {})", value as i32)),
+            ExprKind::Str(ref bytes) => {
+                let value = self.string(bytes);
+                self.emit_line(&format!("(i64.const {})", value));
+            }
+            ExprKind::Variable(ref name) => {
+                if self.is_reference(name) {
+                    let ty = self.type_of(expr);
+                    let offset = self.compile_var_address(name);
+                    self.emit_line(&memory_instruction(&format!("{}.load", ty.wasm_name()), offset));
+                } else if self.scope.is_defined(name) {
+                    self.emit_line(&format!("(local.get ${})", mangle(name)));
+                } else if self.global(name).is_some() {
+                    self.emit_line(&format!("(global.get ${})", mangle(name)));
+                } else {
+                    let value = self.constant(name).expect("unknown variable after type checking");
+                    let instruction = self.constant_instruction(value);
+                    self.emit_line(&instruction);
+                }
+            }
+            ExprKind::Call(ref name, ref arguments) if self.takes_var(name) => self.compile_var_call(name, arguments),
+            // Functions the program defines hide builtins of the same name.
+            ExprKind::Call(ref name, ref arguments) => match builtins::lookup(name) {
+                Some(builtin) if self.signature(name).is_none() => self.compile_builtin_call(builtin, arguments),
+                _ => {
+                    for argument in arguments {
+                        self.compile_owned(argument);
+                    }
+                    self.emit_line(&format!("(call ${})", mangle(name)));
+                }
+            },
+            ExprKind::Address(ref name) => {
+                let index = self.table.iter().position(|function| function == name);
+                let index = index.expect("function isn't in the table");
+                self.emit_line(&format!("(i32.const {})", index + 1));
+            }
+            ExprKind::CallIndirect(ref callee, ref arguments) => {
+                self.compile_indirect_call("call_indirect", callee, arguments)
+            }
+            ExprKind::Binary(op, ref left, ref right) if op.is_logical() => self.compile_logical(op, left, right),
+            ExprKind::Binary(op, ref left, ref right)
+                if self.options.gc && matches!(self.type_of(left), Type::Record(_)) =>
+            {
+                self.compile_expr(left);
+                self.compile_expr(right);
+                self.emit_line("(ref.eq)");
+                if op == BinaryOp::NotEqual {
+                    self.emit_line("(i32.eqz)");
+                }
+            }
+            ExprKind::Binary(op, ref left, ref right) => {
+                let operands = self.type_of(left);
+                self.compile_expr(left);
+                self.compile_expr(right);
+                if matches!(op, BinaryOp::Divide | BinaryOp::Remainder) && operands.is_integer() {
+                    self.check_divisor(expr.span, operands, right);
+                }
+                self.emit_line(&binary_instruction(op, operands));
+            }
+            ExprKind::Load(ref address) => {
+                self.compile_address(address);
+                self.emit_line("(i32.load)");
+            }
+            ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+                self.compile_conditional(condition, then_value, else_value)
+            }
+            ExprKind::Index(ref array, ref index) if self.options.gc => {
+                self.compile_element(array, index);
+                self.emit_line(&format!("(array.get {})", GC_ARRAY));
+            }
+            ExprKind::Field(ref record, ref field) if self.options.gc => {
+                self.compile_expr(record);
+                let line = format!("(struct.get {} ${})", struct_name(self.record_type(record)), mangle(field));
+                self.emit_line(&line);
+            }
+            ExprKind::View(record, ref address) if self.options.gc => self.compile_gc_view(expr.span, record, address),
+            ExprKind::View(record, ref address) if self.options.rc => self.compile_rc_view(expr.span, record, address),
+            ExprKind::Index(ref array, ref index) => {
+                let offset = self.compile_element(array, index);
+                self.emit_line(&memory_instruction("i32.load", offset));
+            }
+            ExprKind::Field(ref record, ref field) => {
+                let (offset, ty) = self.field(record, field);
+                self.compile_address(record);
+                self.emit_line(&memory_instruction(&format!("{}.load", ty.wasm_name()), offset));
+            }
+            // A record is its address already.
+            ExprKind::View(_, ref address) => self.compile_expr(address),
+            ExprKind::Read if self.options.platform == Platform::Wasi => {
+                self.diagnostics.error(expr.span, "`read` doesn't work with --target wasi");
+            }
+            ExprKind::Read => {
+                self.import("env", "read", "(result i32)");
+                self.emit_line("(call $read)");
+            }
+        }
+    }
+
+    /// With --gc, a record can't be made from an address. `Point(alloc(n))`
+    /// makes a new struct instead, with every field 0, and `Point(0)` is the
+    /// null reference. Anything else is an error.
+    fn compile_gc_view(&mut self, span: Span, record: RecordType, address: &Expr) {
+        match address.kind {
+            ExprKind::Call(_, ref arguments) if self.is_alloc(address) => {
+                for argument in arguments.iter().filter(|&argument| has_calls(argument)) {
+                    self.compile_expr(argument);
+                    self.emit_line("(drop)");
+                }
+                self.emit_line(&format!("(struct.new_default {})", struct_name(record)));
+            }
+            _ if self.known_value(address) == Some(Value::I32(0)) => {
+                self.emit_line(&format!("(ref.null {})", struct_name(record)));
+            }
+            _ => self.diagnostics.error(
+                span,
+                &format!(
+                    "with --gc, records aren't in memory, so this has to be `{}(alloc(...))` or `{}(0)`",
+                    record.name(),
+                    record.name()
+                ),
+            ),
+        }
+    }
+
+    /// With --rc, a record has to have a count to keep, so it's made with
+    /// `alloc`, and `Point(alloc(n))` makes room for one with a count and
+    /// every field 0. The null record, `Point(0)`, isn't counted. Anything
+    /// else is an error.
+    fn compile_rc_view(&mut self, span: Span, record: RecordType, address: &Expr) {
+        match address.kind {
+            ExprKind::Call(_, ref arguments) if self.is_alloc(address) => {
+                for argument in arguments {
+                    self.compile_expr(argument);
+                }
+                self.define_runtime("rc.new");
+                self.emit_line("(call $rc.new)");
+            }
+            _ if self.known_value(address) == Some(Value::I32(0)) => self.emit_line("(i32.const 0)"),
+            _ => self.diagnostics.error(
+                span,
+                &format!(
+                    "with --rc, records are counted, so this has to be `{}(alloc(...))` or `{}(0)`",
+                    record.name(),
+                    record.name()
+                ),
+            ),
+        }
+    }
+
+    /// Whether an expression calls the `alloc` builtin, rather than a
+    /// function of the program's that's called that.
+    fn is_alloc(&self, expr: &Expr) -> bool {
+        matches!(expr.kind, ExprKind::Call(ref name, _) if name == "alloc" && self.signature(name).is_none())
+    }
+
+    /// Builtins only look at their arguments. With --rc, values that come
+    /// with a reference are held on to until the builtin's done with them,
+    /// and then the reference is given up.
+    fn compile_builtin_call(&mut self, builtin: &Builtin, arguments: &[Expr]) {
+        // A lane number is part of the instruction rather than an argument
+        // on the stack.
+        let (arguments, lane) = match arguments.split_last() {
+            Some((last, rest)) if builtin.lane => (rest, self.lane(last)),
+            _ => (arguments, String::new()),
+        };
+
+        let mut temps = Vec::new();
+        for (i, argument) in arguments.iter().enumerate() {
+            if builtin.address && i == 0 {
+                self.compile_address(argument);
+                continue;
+            }
+            self.compile_expr(argument);
+            if self.is_owned(argument) {
+                let temp = format!("rc.{}", self.scope.rc_temps);
+                let ty = self.type_of(argument);
+                self.scope.rc_temps += 1;
+                self.declare_local(&temp, ty);
+                self.emit_line(&format!("(local.tee ${})", temp));
+                temps.push((temp, ty));
+            }
+        }
+
+        if let Some(runtime) = builtin.runtime {
+            self.define_runtime(runtime);
+        }
+        for line in builtin.code {
+            self.emit_line(&line.replace("{}", &lane));
+        }
+
+        for (temp, ty) in temps {
+            self.emit_line(&format!("(local.get ${})", temp));
+            self.emit_release(ty);
+        }
+    }
+
+    /// Works out a lane number for a vector function, which has to be a
+    /// constant that picks one of the lanes.
+    fn lane(&mut self, expr: &Expr) -> String {
+        match self.evaluate(expr) {
+            Some(Value::I32(lane)) if (0..builtins::LANES).contains(&lane) => lane.to_string(),
+            Some(_) => {
+                let message = format!("the lane has to be from 0 to {}", builtins::LANES - 1);
+                self.diagnostics.error(expr.span, &message);
+                String::from("0")
+            }
+            None => String::from("0"),
+        }
+    }
+
+    /// Leaves the value of an expression on the stack, to be kept: in a
+    /// variable or memory, or as a function's argument or result. With --rc,
+    /// a string or a record gets a reference for whatever keeps it, unless
+    /// it came with one. Literals are never freed, so they don't need one.
+    fn compile_owned(&mut self, expr: &Expr) {
+        self.compile_expr(expr);
+        let literal = matches!(expr.kind, ExprKind::Str(_));
+        let ty = self.type_of(expr);
+        if self.is_counted(ty) && !self.is_owned(expr) && !literal {
+            if ty == Type::Str {
+                self.define_runtime("rc.retain");
+                self.emit_line("(call $rc.retain)");
+            } else {
+                self.define_runtime("rc.retain.record");
+                self.emit_line("(call $rc.retain.record)");
+            }
+        }
+    }
+
+    /// Whether values of a type have their references counted, which is
+    /// strings and records with --rc.
+    fn is_counted(&self, ty: Type) -> bool {
+        self.options.rc && matches!(ty, Type::Str | Type::Record(_))
+    }
+
+    /// With --rc, whether an expression is a string or a record that comes
+    /// with a reference, which is up to whatever uses it to give up.
+    /// Functions return them with one, and a record made with `alloc`
+    /// starts with one.
+    fn is_owned(&self, expr: &Expr) -> bool {
+        if !self.is_counted(self.type_of(expr)) {
+            return false;
+        }
+        match expr.kind {
+            ExprKind::Call(..) | ExprKind::CallIndirect(..) => true,
+            ExprKind::View(_, ref address) => self.is_alloc(address),
+            ExprKind::Conditional(_, ref then_value, ref else_value) => {
+                self.is_owned(then_value) || self.is_owned(else_value)
+            }
+            _ => false,
+        }
+    }
+
+    /// Gives up the reference to the string or record of type `ty` on top of
+    /// the stack.
+    fn emit_release(&mut self, ty: Type) {
+        let call = self.release_call(ty);
+        self.emit_line(&call);
+    }
+
+    /// Returns the call that gives up a reference to a string or a record of
+    /// type `ty`, adding the function it calls to the module if it isn't
+    /// there already.
+    fn release_call(&mut self, ty: Type) -> String {
+        let record = match ty {
+            Type::Record(record) => record,
+            _ => {
+                self.define_runtime("rc.release");
+                return String::from("(call $rc.release)");
+            }
+        };
+        let call = format!("(call $rc.release.{})", mangle(record.name()));
+        if self.released.contains(&record) {
+            return call;
+        }
+        self.released.push(record);
+
+        // The fields' references go first, which can mean releasing more
+        // records, of this type too.
+        let fields: Vec<(Type, u32)> = match self.layout.record(record) {
+            Some(layout) => layout
+                .fields
+                .iter()
+                .filter(|&&(_, ty, _)| self.is_counted(ty))
+                .map(|&(_, ty, offset)| (ty, offset))
+                .collect(),
+            None => Vec::new(),
+        };
+        let mut code = String::new();
+        for (ty, offset) in fields {
+            code.push_str("(local.get $record)\n");
+            if self.options.memory64 {
+                code.push_str("(i64.extend_i32_u)\n");
+            }
+            code.push_str(&format!("{}\n", memory_instruction(&format!("{}.load", ty.wasm_name()), offset)));
+            code.push_str(&format!("{}\n", self.release_call(ty)));
+        }
+        self.uses_memory = true;
+        let release = runtime::release_record(&mangle(record.name()), &code, self.options.memory64);
+        self.function_code.push_str(&release);
+        call
+    }
+
+    /// Calls the function `callee` points to, with `instruction`, which is
+    /// `call_indirect` or `return_call_indirect`. The call checks that the
+    /// function in the slot has the type the pointer says it does, and traps
+    /// if the slot's empty.
+    fn compile_indirect_call(&mut self, instruction: &str, callee: &Expr, arguments: &[Expr]) {
+        let func = match self.type_of(callee) {
+            Type::Func(func) => func,
+            _ => panic!("call through something that isn't a pointer"),
+        };
+        for argument in arguments {
+            self.compile_owned(argument);
+        }
+        self.compile_expr(callee);
+
+        let params: Vec<String> = func.params().iter().map(|&ty| self.value_type(ty)).collect();
+        let params = if params.is_empty() { String::new() } else { format!(" (param {})", params.join(" ")) };
+        let line = format!("({} $funcs{} (result {}))", instruction, params, self.value_type(func.result()));
+        self.emit_line(&line);
+    }
+
+    /// Whether `name` is a function the program defines with a `var`
+    /// parameter.
+    fn takes_var(&self, name: &str) -> bool {
+        self.signature(name)
+            .is_some_and(|signature| signature.by_reference.contains(&true))
+    }
+
+    /// Calls a function with `var` parameters. A variable passed to one is
+    /// passed by its address, which it has because it's passed to one: a
+    /// `var` parameter's is in its local, our own variables' are cells in
+    /// our frame, and a global's is in its global. An array element is
+    /// passed by its address too.
+    fn compile_var_call(&mut self, name: &str, arguments: &[Expr]) {
+        let by_reference = self.signature(name).map_or(Vec::new(), |signature| signature.by_reference.clone());
+        for (argument, &by_reference) in arguments.iter().zip(&by_reference) {
+            match argument.kind {
+                ExprKind::Variable(ref variable) if by_reference => {
+                    let offset = self.compile_reference(variable);
+                    if offset > 0 {
+                        self.emit_line(&format!("(i32.const {})", offset));
+                        self.emit_line("(i32.add)");
+                    }
+                }
+                ExprKind::Index(ref array, ref index) if by_reference => {
+                    if self.options.gc {
+                        let message = "with --gc, array elements can't be passed to `var` parameters";
+                        self.diagnostics.error(argument.span, message);
+                    }
+                    let address = self.compile_element(array, index);
+                    if self.options.memory64 {
+                        self.emit_line("(i32.wrap_i64)");
+                    }
+                    self.emit_line(&format!("(i32.const {})", address));
+                    self.emit_line("(i32.add)");
+                }
+                _ => self.compile_owned(argument),
+            }
+        }
+        self.emit_line(&format!("(call ${})", mangle(name)));
+    }
+
+    /// Whether a variable is passed by reference: it's a `var` parameter,
+    /// or it's in memory because it's passed to one.
+    fn is_reference(&self, name: &str) -> bool {
+        self.scope.var_params.iter().any(|param| param == name)
+            || self.scope.home(name).is_some()
+            || (!self.scope.is_defined(name) && self.homed_globals.iter().any(|global| global == name))
+    }
+
+    /// Leaves the i32 address of a variable that's passed by reference on
+    /// the stack, and returns the offset to add to it.
+    fn compile_reference(&mut self, name: &str) -> u32 {
+        self.uses_memory = true;
+        if self.scope.var_params.iter().any(|param| param == name) {
+            self.emit_line(&format!("(local.get ${})", mangle(name)));
+            return 0;
+        }
+        if let Some(offset) = self.scope.home(name) {
+            let frame = self.scope.frame_address();
+            self.emit_line(&frame);
+            return offset;
+        }
+        self.emit_line(&format!("(global.get ${})", mangle(name)));
+        0
+    }
+
+    /// Leaves the address of a variable that's passed by reference on the
+    /// stack, ready for a load or store, and returns the offset to load or
+    /// store at.
+    fn compile_var_address(&mut self, name: &str) -> u32 {
+        let offset = self.compile_reference(name);
+        if self.options.memory64 {
+            self.emit_line("(i64.extend_i32_u)");
+        }
+        offset
+    }
+
+    /// Takes the function's frame off the `var` stack, if it passes any of
+    /// its variables to `var` parameters, and puts the values of the
+    /// parameters it passes in their cells. The other cells are for locals,
+    /// which start out as zero. Running out of room traps, rather than
+    /// writing over whatever comes before the stack.
+    fn enter_frame(&mut self) {
+        let size = self.scope.frame_size();
+        if size == 0 {
+            return;
+        }
+
+        self.emit_line("(global.get $var.stack)");
+        self.emit_line(&format!("(i32.const {})", self.stack.unwrap_or(0) + size));
+        self.emit_line("(i32.lt_u)");
+        self.emit_line("(if");
+        self.emit_line("(then");
+        self.emit_line("(unreachable)");
+        self.emit_line(")");
+        self.emit_line(")");
+        self.move_stack("sub", size);
+        self.declare_local("var.frame", Type::I32);
+        self.emit_line("(global.get $var.stack)");
+        self.emit_line("(local.set $var.frame)");
+
+        let homes = self.scope.homes.clone();
+        for (cell, (home, ty)) in homes.into_iter().enumerate() {
+            let offset = cell as u32 * layout::CELL_SIZE;
+            self.compile_var_address(&home);
+            match ty {
+                Some(ty) => {
+                    self.emit_line(&format!("(local.get ${})", mangle(&home)));
+                    self.emit_line(&memory_instruction(&format!("{}.store", ty.wasm_name()), offset));
+                }
+                None => {
+                    self.emit_line("(i64.const 0)");
+                    self.emit_line(&memory_instruction("i64.store", offset));
+                }
+            }
+        }
+    }
+
+    /// Gives the function's frame back to the `var` stack, if it took one.
+    fn leave_frame(&mut self) {
+        let size = self.scope.frame_size();
+        if size > 0 {
+            self.move_stack("add", size);
+        }
+    }
+
+    /// Takes `size` bytes off the top of the `var` stack with `sub`, or gives
+    /// them back with `add`.
+    fn move_stack(&mut self, instruction: &str, size: u32) {
+        self.emit_line("(global.get $var.stack)");
+        self.emit_line(&format!("(i32.const {})", size));
+        self.emit_line(&format!("(i32.{})", instruction));
+        self.emit_line("(global.set $var.stack)");
+    }
+
+    /// Leaves the address of a place on the stack, and returns the offset
+    /// to add to it.
+    fn compile_place(&mut self, place: &Place) -> u32 {
+        match *place {
+            Place::Memory(ref address) => {
+                self.compile_address(address);
+                0
+            }
+            Place::Element(ref array, ref index) => self.compile_element(array, index),
+            Place::Field(ref record, ref field) => {
+                self.compile_address(record);
+                self.field(record, field).0
+            }
+        }
+    }
+
+    /// With --gc, leaves the struct or array a place is in on the stack, and
+    /// the index if it's an element, and returns the instruction that stores
+    /// to it.
+    fn compile_gc_place(&mut self, place: &Place) -> String {
+        match *place {
+            Place::Element(ref array, ref index) => {
+                self.compile_element(array, index);
+                format!("(array.set {})", GC_ARRAY)
+            }
+            Place::Field(ref record, ref field) => {
+                self.compile_expr(record);
+                format!("(struct.set {} ${})", struct_name(self.record_type(record)), mangle(field))
+            }
+            Place::Memory(_) => panic!("memory isn't a GC place"),
+        }
+    }
+
+    /// Leaves the offset of an element from the start of its array on the
+    /// stack, and returns the array's address. The load or store adds them
+    /// up. Without bounds checks, it only traps if the index is so far out
+    /// that it's past the end of memory.
+    ///
+    /// With --gc, it leaves the array and the index instead. GC arrays always
+    /// trap on an index that's out of bounds, so only clamping needs doing.
+    fn compile_element(&mut self, array: &str, index: &Expr) -> u32 {
+        let (address, len) = if self.options.gc {
+            let len = self.gc_arrays.iter().find(|&(name, _)| name == array).map(|&(_, len)| len);
+            self.emit_line(&format!("(global.get $array.{})", mangle(array)));
+            (0, len.unwrap_or(0))
+        } else {
+            self.uses_memory = true;
+            match self.layout.array(array) {
+                Some(array) => (array.address, array.len),
+                // The array's length was broken, which has already been reported.
+                None => (0, 0),
+            }
+        };
+
+        self.compile_expr(index);
+
+        // Indexes we can work out at compile time don't need checking if
+        // they're in bounds.
+        let in_bounds = match self.known_value(index) {
+            Some(Value::I32(index)) => index >= 0 && (index as u32) < len,
+            _ => false,
+        };
+        let policy = match self.options.bounds_check {
+            _ if in_bounds => BoundsCheck::Off,
+            BoundsCheck::Trap if self.options.gc => BoundsCheck::Off,
+            policy => policy,
+        };
+        match policy {
+            BoundsCheck::Off => (),
+            // There's nothing to clamp to in an empty array.
+            BoundsCheck::Trap | BoundsCheck::Clamp if len == 0 => self.emit_line("(unreachable)"),
+            BoundsCheck::Trap => {
+                self.declare_local("bounds.index", Type::I32);
+                self.emit_line("(local.tee $bounds.index)");
+                self.emit_line(&format!("(i32.const {})", len));
+                self.emit_line("(i32.ge_u)");
+                self.emit_line("(if");
+                self.emit_line("(then");
+                self.emit_line("(unreachable)");
+                self.emit_line(")");
+                self.emit_line(")");
+                self.emit_line("(local.get $bounds.index)");
+            }
+            BoundsCheck::Clamp => {
+                self.declare_local("bounds.index", Type::I32);
+                self.emit_line("(local.tee $bounds.index)");
+                self.emit_line(&format!("(i32.const {})", len - 1));
+                self.emit_line("(local.get $bounds.index)");
+                self.emit_line(&format!("(i32.const {})", len - 1));
+                self.emit_line("(i32.lt_u)");
+                self.emit_line("(select)");
+            }
+        }
+        if self.options.gc {
+            return 0;
+        }
+
+        // With memory64, the offset is worked out in i64 so it can be past
+        // 4 GiB.
+        if self.options.memory64 {
+            self.emit_line("(i64.extend_i32_u)");
+            self.emit_line(&format!("(i64.const {})", layout::ELEMENT_SIZE));
+            self.emit_line("(i64.mul)");
+        } else {
+            self.emit_line(&format!("(i32.const {})", layout::ELEMENT_SIZE));
+            self.emit_line("(i32.mul)");
+        }
+        address
+    }
+
+    /// With --checked-div, makes sure the divisor on top of the stack isn't
+    /// zero before an integer division. If it is, the host's
+    /// `divide_by_zero` gets the line and column of the division, and then
+    /// we trap, like the division would have. Divisors we know at compile
+    /// time don't need checking.
+    fn check_divisor(&mut self, span: Span, ty: Type, divisor: &Expr) {
+        let known = self.known_value(divisor).filter(|&value| value != Value::zero(ty));
+        if !self.options.checked_div || known.is_some() {
+            return;
+        }
+
+        let local = format!("divisor.{}", ty.wasm_name());
+        let (line, column) = {
+            let location = self.diagnostics.source_map().location(span);
+            (location.line, location.column)
+        };
+
+        self.import("env", "divide_by_zero", "(param i32 i32)");
+        self.declare_local(&local, ty);
+        self.emit_line(&format!("(local.tee ${})", local));
+        self.emit_line(&format!("({}.eqz)", ty.wasm_name()));
+        self.emit_line("(if");
+        self.emit_line("(then");
+        self.emit_line(&format!("(i32.const {})", line));
+        self.emit_line(&format!("(i32.const {})", column));
+        self.emit_line("(call $divide_by_zero)");
+        self.emit_line("(unreachable)");
+        self.emit_line(")");
+        self.emit_line(")");
+        self.emit_line(&format!("(local.get ${})", local));
+    }
+
+    /// Leaves a memory address on the stack. An i32 address needs widening
+    /// for a 64-bit memory; an i64 one is already the right size.
+    fn compile_address(&mut self, address: &Expr) {
+        self.uses_memory = true;
+        self.compile_expr(address);
+        if self.options.memory64 && self.type_of(address) != Type::I64 {
+            self.emit_line("(i64.extend_i32_u)");
+        }
+    }
+
+    /// The record type of an expression that's a record.
+    fn record_type(&self, record: &Expr) -> RecordType {
+        match self.type_of(record) {
+            Type::Record(record) => record,
+            _ => panic!("field of something that isn't a record"),
+        }
+    }
+
+    /// The wasm type of a value of type `ty`. With --gc, a record is a
+    /// reference to its struct, which is null for `Point(0)`.
+    fn value_type(&self, ty: Type) -> String {
+        match ty {
+            Type::Record(record) if self.options.gc => format!("(ref null {})", struct_name(record)),
+            _ => ty.wasm_name().to_string(),
+        }
+    }
+
+    /// The instruction that leaves a value known at compile time on the
+    /// stack. With --gc, the only record that can be is the null one.
+    fn constant_instruction(&self, value: Value) -> String {
+        match value {
+            Value::Record(record, _) if self.options.gc => format!("(ref.null {})", struct_name(record)),
+            _ => format!("({}.const {})", value.ty().wasm_name(), value),
+        }
+    }
+
+    /// Declares a local for the compiler's own use, if the current function
+    /// doesn't have it yet. Each one only holds a value until the next
+    /// instruction or two are done with it, so one of each is enough, even
+    /// for `a[b[i]]`.
+    fn declare_local(&mut self, name: &str, ty: Type) {
+        if !self.scope.is_defined(name) {
+            self.scope.locals.push((name.to_string(), ty));
+        }
+    }
+
+    /// Works out the value of an expression at compile time if it's made of
+    /// literals and constants, without reporting anything if it can't be.
+    fn known_value(&self, expr: &Expr) -> Option<Value> {
+        match expr.kind {
+            ExprKind::Int(..) => Some(int_value(expr)),
+            ExprKind::Variable(ref name) if !self.scope.is_defined(name) && self.global(name).is_none() => {
+                self.constant(name)
+            }
+            ExprKind::Binary(op, ref left, ref right) => {
+                consteval::binary(op, self.known_value(left)?, self.known_value(right)?)
+            }
+            _ => None,
+        }
+    }
+
+    fn signature(&self, name: &str) -> Option<&Signature> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// Appends a line of WAT to the body of the current function.
+    fn emit_line(&mut self, line: &str) {
+        self.scope.body.push_str(line);
+        self.scope.body.push('\n');
+    }
+
+    /// Appends a comment with the line of source a span starts on to the
+    /// body of the current function, unless the last comment showed it.
+    fn emit_comment(&mut self, span: Span) {
+        let location = self.diagnostics.source_map().location(span);
+        let line = (location.file.to_string(), location.line);
+        if self.scope.commented_line.as_ref() == Some(&line) {
+            return;
+        }
+
+        let text = String::from_utf8_lossy(self.diagnostics.source_map().line(span));
+        let comment = format!(";; line {}: {}", line.1, text.trim());
+        self.emit_line(&comment);
+        self.scope.commented_line = Some(line);
+    }
+
+    /// Appends a line of WAT to the module.
+    fn emit_module_line(&mut self, line: &str) {
+        self.output.push_str(line);
+        self.output.push('\n');
+    }
+
+    /// Prints the start of a new WebAssembly module.
+    fn emit_module_start(&mut self) {
+        self.emit_module_line("(module");
+    }
+
+    /// Prints an import for each host function the program uses.
+    fn emit_imports(&mut self) {
+        let imports = mem::take(&mut self.imports);
+        for (module, name, ty) in imports {
+            let ty = if ty.is_empty() { String::new() } else { format!(" {}", ty) };
+            self.emit_module_line(&format!("(import \"{}\" \"{}\" (func ${}{}))", module, name, name, ty));
+        }
+    }
+
+    /// Prints the closing paren of a WebAssembly module.
+    fn emit_module_end(&mut self) {
+        self.emit_module_line(")");
+    }
+
+    /// Prints the linear memory declaration, big enough for everything in
+    /// the layout, and a data segment for each string literal. Programs that
+    /// don't use memory only get one under WASI, whose runtimes expect one,
+    /// or when memory64 is enabled, in which case it uses 64-bit addresses.
+    /// With --threads, it's shared. The memory is exported so the host can see what the program wrote.
+    /// The heap, if anything allocates, starts where the layout ends.
+    fn emit_memory(&mut self) {
+        let wasi = self.options.platform == Platform::Wasi;
+        if self.layout.is_empty() && !self.uses_memory && !self.options.memory64 && !wasi {
+            return;
+        }
+
+        let pages = self.layout.pages();
+        let index_type = if self.options.memory64 { "i64" } else { "i32" };
+        let shared = if self.options.threads { " 65536 shared" } else { "" };
+        if self.options.memory64 {
+            self.emit_module_line(&format!("(memory i64 {}{})", pages, shared));
+        } else {
+            self.emit_module_line(&format!("(memory {}{})", pages, shared));
+        }
+
+        let segments: Vec<String> = self
+            .layout
+            .strings()
+            .iter()
+            .filter(|&(bytes, _)| !bytes.is_empty())
+            .map(|&(ref bytes, address)| format!("(data ({}.const {}) \"{}\")", index_type, address, escape_bytes(bytes)))
+            .collect();
+        for segment in segments {
+            self.emit_module_line(&segment);
+        }
+        let homes = mem::take(&mut self.home_data);
+        for (address, value) in homes {
+            self.emit_module_line(&format!("(data ({}.const {}) \"{}\")", index_type, address, escape_bytes(&value)));
+        }
+        self.emit_module_line("(export \"memory\" (memory 0))");
+
+        // Everything's placed by now, so the heap can start after it.
+        if self.runtime.contains(&"heap.alloc") {
+            self.emit_module_line(&format!("(global $heap.top (mut i32) (i32.const {}))", self.layout.end()));
+        }
+        if self.runtime.iter().any(|name| name.starts_with("rc.")) || !self.released.is_empty() {
+            self.emit_module_line(&format!("(global $heap.base i32 (i32.const {}))", self.layout.end()));
+            self.emit_module_line("(global $rc.free (mut i32) (i32.const 0))");
+        }
+    }
+
+    /// Prints a finished function: its signature, local declarations, and
+    /// body. A function that runs off the end without a return statement
+    /// returns 0. With --rc, the body's in a block that returns leave by, and
+    /// after it, the function gives up the references its variables hold.
+    fn emit_function(&mut self, name: &str, scope: FunctionScope) {
+        let mut code = format!("(func ${}", name);
+        // A `var` parameter is the address of its value.
+        for &(ref param, ty) in &scope.params {
+            let ty = if scope.var_params.contains(param) { Type::I32 } else { ty };
+            code.push_str(&format!(" (param ${} {})", mangle(param), self.value_type(ty)));
+        }
+        let mut results = String::new();
+        if let Some(result) = scope.result {
+            results.push_str(&format!(" (result {}", self.value_type(result)));
+            for &ty in &scope.more_results {
+                results.push_str(&format!(" {}", self.value_type(ty)));
+            }
+            results.push(')');
+        }
+        code.push_str(&results);
+        code.push('\n');
+
+        for &(ref local, ty) in &scope.locals {
+            code.push_str(&format!("(local ${} {})\n", mangle(local), self.value_type(ty)));
+        }
+
+        if self.options.rc {
+            code.push_str(&format!("(block $rc.body{}\n", results));
+        }
+        code.push_str(&scope.body);
+        if let Some(result) = scope.result {
+            for &ty in Some(&result).into_iter().chain(&scope.more_results) {
+                code.push_str(&self.constant_instruction(Value::zero(ty)));
+                code.push('\n');
+            }
+            if !self.options.rc {
+                code.push_str("(return)\n");
+            }
+        }
+        if self.options.rc {
+            code.push_str(")\n");
+            let mut owned: Vec<&String> = scope
+                .params
+                .iter()
+                .filter(|&(param, ty)| self.is_counted(*ty) && !scope.var_params.contains(param))
+                .map(|(param, _)| param)
+                .collect();
+            for local in &scope.owned {
+                if !owned.contains(&local) {
+                    owned.push(local);
+                }
+            }
+            for variable in &owned {
+                let ty = scope.variable(variable).expect("unknown variable in a finished function");
+                match scope.home(variable) {
+                    Some(offset) => {
+                        code.push_str(&format!("{}\n", scope.frame_address()));
+                        if self.options.memory64 {
+                            code.push_str("(i64.extend_i32_u)\n");
+                        }
+                        let load = format!("{}.load", ty.wasm_name());
+                        code.push_str(&format!("{}\n", memory_instruction(&load, offset)));
+                    }
+                    None => code.push_str(&format!("(local.get ${})\n", mangle(variable))),
+                }
+                code.push_str(&format!("{}\n", self.release_call(ty)));
+            }
+
+            // The frame goes once nothing needs what's in it.
+            let size = scope.frame_size();
+            if size > 0 {
+                code.push_str(&format!(
+                    "(global.get $var.stack)\n(i32.const {})\n(i32.add)\n(global.set $var.stack)\n",
+                    size
+                ));
+            }
+        }
+        code.push_str(")\n");
+
+        self.function_code.push_str(&code);
+    }
+
+    /// Prints a custom section holding the source of the program, so the
+    /// module says what it was compiled from. Custom sections are written
+    /// as annotations in WAT, which assemblers only understand if they
+    /// support the annotations proposal.
+    fn emit_source(&mut self) {
+        let source = self.diagnostics.source_map().files()[0].source();
+        let section = format!("(@custom \"{}\" \"{}\")", SOURCE_SECTION, escape_bytes(source));
+        self.emit_module_line(&section);
+    }
+
+    /// Prints the export statement for the main function. WASI runs
+    /// `_start` instead, which runs `main` itself.
+    fn emit_main_export(&mut self) {
+        match self.options.platform {
+            Platform::Host | Platform::Browser => {
+                let entry = escape_bytes(self.options.entry_name().as_bytes());
+                self.emit_module_line(&format!("(export \"{}\" (func $main))", entry));
+            }
+            Platform::Wasi => self.emit_module_line("(export \"_start\" (func $_start))"),
+        }
+    }
+}
+
+/// With --metering, how much fuel there is to start with, unless the host
+/// changes it: the number of function calls and times round a loop before
+/// the program traps.
+pub const FUEL: i32 = 100_000_000;
+
+/// The name of the custom section that --embed-source puts the program in.
+pub const SOURCE_SECTION: &str = "crenshaw.source";
+
+/// With --gc, the type of arrays, which only hold i32s.
+const GC_ARRAY: &str = "$i32.array";
+
+/// With --gc, the name of the struct type for a record.
+fn struct_name(record: RecordType) -> String {
+    format!("$record.{}", mangle(record.name()))
+}
+
+/// Escapes bytes for a WAT string. Anything that isn't printable ASCII gets
+/// written as two hex digits.
+fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        if byte.is_ascii_graphic() && byte != b'"' && byte != b'\\' || byte == b' ' {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("\\{:02x}", byte));
+        }
+    }
+    escaped
+}
+
+/// A load or store instruction, with a constant offset added to the address
+/// on the stack.
+fn memory_instruction(instruction: &str, offset: u32) -> String {
+    if offset == 0 {
+        format!("({})", instruction)
+    } else {
+        format!("({} offset={})", instruction, offset)
+    }
+}
+
+/// Returns the value of an integer literal, which type checking has made
+/// sure fits in its type.
+fn int_value(expr: &Expr) -> Value {
+    match expr.kind {
+        ExprKind::Int(ref num, Some(ty)) => consteval::int_literal(num, ty).expect("literal doesn't fit in its type"),
+        _ => panic!("expected a type checked integer literal"),
+    }
+}
+
+/// Checks for an `if` that assigns one of two values to the same variable,
+/// which could be a `select` instead. `select` evaluates everything, in a
+/// different order than the `if` would, so that's only allowed when nothing
+/// has side effects and neither value can trap.
+fn select_candidate<'b>(
+    condition: &Expr,
+    then: &'b [Stmt],
+    otherwise: &'b [Stmt],
+) -> Option<(&'b str, Type, &'b Expr, &'b Expr)> {
+    match (then, otherwise) {
+        (
+            [Stmt::Assign { name: ref then_name, ty: Some(ty), value: ref then_value, .. }],
+            [Stmt::Assign { name: ref else_name, value: ref else_value, .. }],
+        ) if then_name == else_name
+            && !has_calls(condition)
+            && can_speculate(then_value)
+            && can_speculate(else_value) =>
+        {
+            Some((then_name, *ty, then_value, else_value))
+        }
+        _ => None,
+    }
+}
+
+fn has_calls(expr: &Expr) -> bool {
+    match expr.kind {
+        ExprKind::Int(..) | ExprKind::Float(_) | ExprKind::Bool(_) | ExprKind::Str(_) | ExprKind::Variable(_) => false,
+        ExprKind::Address(_) => false,
+        ExprKind::Call(..) | ExprKind::CallIndirect(..) | ExprKind::Read => true,
+        ExprKind::Binary(_, ref left, ref right) => has_calls(left) || has_calls(right),
+        ExprKind::Load(ref address) | ExprKind::View(_, ref address) => has_calls(address),
+        ExprKind::Index(_, ref index) => has_calls(index),
+        ExprKind::Field(ref record, _) => has_calls(record),
+        ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+            has_calls(condition) || has_calls(then_value) || has_calls(else_value)
+        }
+    }
+}
+
+/// Returns true if an expression can be evaluated when it wasn't going to be:
+/// it doesn't call anything, and it doesn't divide or read memory, which can
+/// trap.
+fn can_speculate(expr: &Expr) -> bool {
+    match expr.kind {
+        ExprKind::Int(..)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::Variable(_)
+        | ExprKind::Address(_) => true,
+        ExprKind::Call(..)
+        | ExprKind::CallIndirect(..)
+        | ExprKind::Load(_)
+        | ExprKind::Index(..)
+        | ExprKind::Field(..)
+        | ExprKind::Read => false,
+        ExprKind::View(_, ref address) => can_speculate(address),
+        ExprKind::Binary(BinaryOp::Divide, _, _) | ExprKind::Binary(BinaryOp::Remainder, _, _) => false,
+        ExprKind::Binary(_, ref left, ref right) => can_speculate(left) && can_speculate(right),
+        ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+            can_speculate(condition) && can_speculate(then_value) && can_speculate(else_value)
+        }
+    }
+}
+
+/// The instruction for a binary operator, applied to the two values of type
+/// `ty` on top of the stack. Comparisons leave 1 for true and 0 for false.
+/// Integers are always signed.
+fn binary_instruction(op: BinaryOp, ty: Type) -> String {
+    let instruction = match op {
+        BinaryOp::Add => "add",
+        BinaryOp::Subtract => "sub",
+        BinaryOp::Multiply => "mul",
+        BinaryOp::Divide => "div",
+        BinaryOp::Remainder => "rem",
+        BinaryOp::Equal => "eq",
+        BinaryOp::NotEqual => "ne",
+        BinaryOp::Less => "lt",
+        BinaryOp::LessEqual => "le",
+        BinaryOp::Greater => "gt",
+        BinaryOp::GreaterEqual => "ge",
+        BinaryOp::BitAnd => "and",
+        BinaryOp::BitOr => "or",
+        BinaryOp::BitXor => "xor",
+        BinaryOp::ShiftLeft => "shl",
+        BinaryOp::ShiftRight => "shr",
+        BinaryOp::ShiftRightUnsigned => return format!("({}.shr_u)", ty.wasm_name()),
+        BinaryOp::And | BinaryOp::Or => panic!("`and` and `or` compile to branches"),
+    };
+
+    // Integer division, ordering, and shifting right come in signed and
+    // unsigned versions. Nothing else does, and neither do any float
+    // instructions.
+    let signed = ty.is_integer()
+        && matches!(
+            op,
+            BinaryOp::Divide
+                | BinaryOp::Remainder
+                | BinaryOp::Less
+                | BinaryOp::LessEqual
+                | BinaryOp::Greater
+                | BinaryOp::GreaterEqual
+                | BinaryOp::ShiftRight
+        );
+    if signed {
+        format!("({}.{}_s)", ty.wasm_name(), instruction)
+    } else {
+        format!("({}.{})", ty.wasm_name(), instruction)
+    }
+}
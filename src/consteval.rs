@@ -0,0 +1,289 @@
+//! Literals, and arithmetic with the same semantics as the wasm instructions
+//! we emit, for working out values at compile time.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use ast::{BinaryOp, EnumType, FuncType, RecordType, Type};
+
+/// A value worked out at compile time.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+
+    // Where a string's bytes are in memory, and how many there are.
+    Str { address: u32, len: u32 },
+
+    // A function pointer: the function's index in the table, plus one, or 0
+    // for a pointer that doesn't point anywhere.
+    Func(FuncType, u32),
+
+    // The address of a record, or 0 for the null record.
+    Record(RecordType, u32),
+
+    // The number of one of an enum's variants.
+    Enum(EnumType, i32),
+
+    // A vector's four i32 lanes, lowest first.
+    V128([i32; 4]),
+}
+
+impl Value {
+    pub fn zero(ty: Type) -> Value {
+        match ty {
+            Type::I32 => Value::I32(0),
+            Type::I64 => Value::I64(0),
+            Type::F64 => Value::F64(0.0),
+            Type::Bool => Value::Bool(false),
+            Type::Str => Value::Str { address: 0, len: 0 },
+            Type::Func(func) => Value::Func(func, 0),
+            Type::Record(record) => Value::Record(record, 0),
+            Type::Enum(ty) => Value::Enum(ty, 0),
+            Type::V128 => Value::V128([0; 4]),
+        }
+    }
+
+    pub fn ty(self) -> Type {
+        match self {
+            Value::I32(_) => Type::I32,
+            Value::I64(_) => Type::I64,
+            Value::F64(_) => Type::F64,
+            Value::Bool(_) => Type::Bool,
+            Value::Str { .. } => Type::Str,
+            Value::Func(func, _) => Type::Func(func),
+            Value::Record(record, _) => Type::Record(record),
+            Value::Enum(ty, _) => Type::Enum(ty),
+            Value::V128(_) => Type::V128,
+        }
+    }
+    /// The bytes the value is in memory, the way the generated code loads
+    /// and stores it.
+    pub fn to_le_bytes(self) -> Vec<u8> {
+        match self {
+            Value::I32(value) | Value::Enum(_, value) => value.to_le_bytes().to_vec(),
+            Value::Bool(value) => (value as i32).to_le_bytes().to_vec(),
+            Value::I64(value) => value.to_le_bytes().to_vec(),
+            Value::F64(value) => value.to_bits().to_le_bytes().to_vec(),
+            Value::Str { address, len } => (u64::from(len) << 32 | u64::from(address)).to_le_bytes().to_vec(),
+            Value::Func(_, index) | Value::Record(_, index) => index.to_le_bytes().to_vec(),
+            Value::V128(lanes) => lanes.iter().flat_map(|lane| lane.to_le_bytes()).collect(),
+        }
+    }
+}
+
+/// Prints just the number, the way it goes in a `const` instruction. Bools
+/// are 1 or 0, and strings are packed into an i64.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::I32(value) => write!(f, "{}", value),
+            Value::I64(value) => write!(f, "{}", value),
+            // WAT spells these differently from Rust.
+            Value::F64(value) if value.is_nan() => write!(f, "nan"),
+            Value::F64(value) if value.is_infinite() && value < 0.0 => write!(f, "-inf"),
+            Value::F64(value) if value.is_infinite() => write!(f, "inf"),
+            Value::F64(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value as i32),
+            Value::Str { address, len } => write!(f, "{}", ((len as u64) << 32 | address as u64) as i64),
+            Value::Func(_, index) => write!(f, "{}", index),
+            Value::Record(_, address) => write!(f, "{}", address),
+            Value::Enum(_, value) => write!(f, "{}", value),
+            // `v128.const` needs the lanes' shape first.
+            Value::V128([a, b, c, d]) => write!(f, "i32x4 {} {} {} {}", a, b, c, d),
+        }
+    }
+}
+
+/// Works out the value of an integer literal, as written in the program, if
+/// it's used as a `ty`. Returns None if it's out of range.
+///
+/// Decimal literals have to be in the type's signed range, -2147483648 to
+/// 2147483647 for an i32. Hex, octal, and binary literals are bit patterns,
+/// so they can use all the bits: `0xffffffff` is an i32 with every bit set,
+/// which is -1. With a minus sign, they're negated magnitudes, and have to be
+/// in the signed range like decimals.
+pub fn int_literal(text: &str, ty: Type) -> Option<Value> {
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned),
+        None => (false, text),
+    };
+    let radix = match unsigned.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+
+    if radix == 10 {
+        // Going through f64's parser rounds big decimals correctly, and lets
+        // them be as big as an f64 can be.
+        return match ty {
+            Type::I32 => text.parse().ok().map(Value::I32),
+            Type::I64 => text.parse().ok().map(Value::I64),
+            Type::F64 => text.parse().ok().map(Value::F64),
+            // Programs can't write pointers or records, but passes write
+            // the null ones as a 0.
+            Type::Func(func) => text.parse().ok().map(|index| Value::Func(func, index)),
+            Type::Record(record) => text.parse().ok().map(|address| Value::Record(record, address)),
+            // Type checking turns variants into literals like this.
+            Type::Enum(ty) => text.parse().ok().map(|value| Value::Enum(ty, value)),
+            Type::Bool | Type::Str | Type::V128 => None,
+        };
+    }
+
+    let magnitude = u64::from_str_radix(&unsigned[2..], radix).ok()?;
+    match ty {
+        Type::I32 if negative => i32::try_from(-i64::try_from(magnitude).ok()?).ok().map(Value::I32),
+        Type::I32 => u32::try_from(magnitude).ok().map(|bits| Value::I32(bits as i32)),
+        Type::I64 if negative => i64::try_from(-i128::from(magnitude)).ok().map(Value::I64),
+        Type::I64 => Some(Value::I64(magnitude as i64)),
+        Type::F64 if negative => Some(Value::F64(-(magnitude as f64))),
+        Type::F64 => Some(Value::F64(magnitude as f64)),
+        Type::Bool | Type::Str | Type::Func(_) | Type::Record(_) | Type::Enum(_) | Type::V128 => None,
+    }
+}
+
+/// Whether a condition's value counts as true. Integers only show up in
+/// conditions with --truthiness nonzero.
+pub fn truth(value: Value) -> Option<bool> {
+    match value {
+        Value::Bool(value) => Some(value),
+        Value::I32(value) => Some(value != 0),
+        Value::I64(value) => Some(value != 0),
+        Value::F64(_) | Value::Str { .. } | Value::Func(..) | Value::Record(..) | Value::Enum(..) | Value::V128(_) => {
+            None
+        }
+    }
+}
+
+/// Applies a binary operator the way the generated code would at runtime.
+/// Returns None where the wasm instruction would trap. Both operands have to
+/// have the same type.
+pub fn binary(op: BinaryOp, left: Value, right: Value) -> Option<Value> {
+    // The operands of `and` and `or` are conditions, which don't have to be
+    // the same type.
+    if op.is_logical() {
+        let (left, right) = (truth(left)?, truth(right)?);
+        let value = if op == BinaryOp::And { left && right } else { left || right };
+        return Some(Value::Bool(value));
+    }
+
+    match (left, right) {
+        (Value::I32(left), Value::I32(right)) => binary_i32(op, left, right),
+        (Value::I64(left), Value::I64(right)) => binary_i64(op, left, right),
+        (Value::F64(left), Value::F64(right)) => Some(binary_f64(op, left, right)),
+        (Value::Bool(left), Value::Bool(right)) => Some(binary_bool(op, left, right)),
+        (Value::Str { .. }, Value::Str { .. }) => panic!("operators don't work on strings"),
+        (Value::V128(_), Value::V128(_)) => panic!("operators don't work on vectors"),
+        // Pointers can only be compared for equality.
+        (Value::Func(_, left), Value::Func(_, right)) => match op {
+            BinaryOp::Equal => Some(Value::Bool(left == right)),
+            BinaryOp::NotEqual => Some(Value::Bool(left != right)),
+            _ => panic!("only == and != work on function pointers"),
+        },
+        (Value::Record(_, left), Value::Record(_, right)) => match op {
+            BinaryOp::Equal => Some(Value::Bool(left == right)),
+            BinaryOp::NotEqual => Some(Value::Bool(left != right)),
+            _ => panic!("only == and != work on records"),
+        },
+        (Value::Enum(_, left), Value::Enum(_, right)) => match op {
+            BinaryOp::Equal => Some(Value::Bool(left == right)),
+            BinaryOp::NotEqual => Some(Value::Bool(left != right)),
+            _ => panic!("only == and != work on enums"),
+        },
+        _ => panic!("operands of a binary operator have different types"),
+    }
+}
+
+fn binary_i32(op: BinaryOp, left: i32, right: i32) -> Option<Value> {
+    let value = match op {
+        BinaryOp::Add => left.wrapping_add(right),
+        BinaryOp::Subtract => left.wrapping_sub(right),
+        BinaryOp::Multiply => left.wrapping_mul(right),
+        // i32.div_s traps on division by zero, and on the one division that
+        // overflows. checked_div fails in exactly the same cases.
+        BinaryOp::Divide => left.checked_div(right)?,
+        // i32.rem_s only traps on zero. The remainder of the division that
+        // overflows is 0.
+        BinaryOp::Remainder if right == 0 => return None,
+        BinaryOp::Remainder => left.wrapping_rem(right),
+        BinaryOp::Equal => return Some(Value::Bool(left == right)),
+        BinaryOp::NotEqual => return Some(Value::Bool(left != right)),
+        BinaryOp::Less => return Some(Value::Bool(left < right)),
+        BinaryOp::LessEqual => return Some(Value::Bool(left <= right)),
+        BinaryOp::Greater => return Some(Value::Bool(left > right)),
+        BinaryOp::GreaterEqual => return Some(Value::Bool(left >= right)),
+        BinaryOp::BitAnd => left & right,
+        BinaryOp::BitOr => left | right,
+        BinaryOp::BitXor => left ^ right,
+        // Shifts only use the low five bits of the count, like the wrapping
+        // shifts in Rust.
+        BinaryOp::ShiftLeft => left.wrapping_shl(right as u32),
+        BinaryOp::ShiftRight => left.wrapping_shr(right as u32),
+        BinaryOp::ShiftRightUnsigned => (left as u32).wrapping_shr(right as u32) as i32,
+        BinaryOp::And | BinaryOp::Or => panic!("`and` and `or` are worked out in `binary`"),
+    };
+
+    Some(Value::I32(value))
+}
+
+/// Like `binary_i32`, for i64s.
+fn binary_i64(op: BinaryOp, left: i64, right: i64) -> Option<Value> {
+    let value = match op {
+        BinaryOp::Add => left.wrapping_add(right),
+        BinaryOp::Subtract => left.wrapping_sub(right),
+        BinaryOp::Multiply => left.wrapping_mul(right),
+        BinaryOp::Divide => left.checked_div(right)?,
+        BinaryOp::Remainder if right == 0 => return None,
+        BinaryOp::Remainder => left.wrapping_rem(right),
+        BinaryOp::Equal => return Some(Value::Bool(left == right)),
+        BinaryOp::NotEqual => return Some(Value::Bool(left != right)),
+        BinaryOp::Less => return Some(Value::Bool(left < right)),
+        BinaryOp::LessEqual => return Some(Value::Bool(left <= right)),
+        BinaryOp::Greater => return Some(Value::Bool(left > right)),
+        BinaryOp::GreaterEqual => return Some(Value::Bool(left >= right)),
+        BinaryOp::BitAnd => left & right,
+        BinaryOp::BitOr => left | right,
+        BinaryOp::BitXor => left ^ right,
+        BinaryOp::ShiftLeft => left.wrapping_shl(right as u32),
+        BinaryOp::ShiftRight => left.wrapping_shr(right as u32),
+        BinaryOp::ShiftRightUnsigned => (left as u64).wrapping_shr(right as u32) as i64,
+        BinaryOp::And | BinaryOp::Or => panic!("`and` and `or` are worked out in `binary`"),
+    };
+
+    Some(Value::I64(value))
+}
+
+/// Float arithmetic never traps. Dividing by zero gives an infinity or NaN.
+/// There's no `%` for floats, since wasm doesn't have one.
+fn binary_f64(op: BinaryOp, left: f64, right: f64) -> Value {
+    match op {
+        BinaryOp::Add => Value::F64(left + right),
+        BinaryOp::Subtract => Value::F64(left - right),
+        BinaryOp::Multiply => Value::F64(left * right),
+        BinaryOp::Divide => Value::F64(left / right),
+        BinaryOp::Equal => Value::Bool(left == right),
+        BinaryOp::NotEqual => Value::Bool(left != right),
+        BinaryOp::Less => Value::Bool(left < right),
+        BinaryOp::LessEqual => Value::Bool(left <= right),
+        BinaryOp::Greater => Value::Bool(left > right),
+        BinaryOp::GreaterEqual => Value::Bool(left >= right),
+        _ => panic!("`%`, bitwise operators, and shifts don't work on f64s"),
+    }
+}
+
+/// Bools can only be compared for equality, or combined with `&`, `|`, and
+/// `^`.
+fn binary_bool(op: BinaryOp, left: bool, right: bool) -> Value {
+    match op {
+        BinaryOp::Equal => Value::Bool(left == right),
+        BinaryOp::NotEqual => Value::Bool(left != right),
+        BinaryOp::BitAnd => Value::Bool(left & right),
+        BinaryOp::BitOr => Value::Bool(left | right),
+        BinaryOp::BitXor => Value::Bool(left ^ right),
+        _ => panic!("only ==, !=, &, |, and ^ work on bools"),
+    }
+}
@@ -0,0 +1,211 @@
+//! A rough model of what instructions cost, for optimizations that have to
+//! weigh one code shape against another. What "cost" means depends on what
+//! we're optimizing for: bytes of code, or time spent running it.
+//!
+//! Code generation asks it whether to pick between two values with `select`
+//! or a branch, the `inline` pass whether to replace a call with the body
+//! of the function, and the `unroll` pass whether to unroll a loop. Those
+//! last two always make the code faster, so what they ask is whether it's
+//! worth the code getting bigger: for size, it never is, and for speed, a
+//! little is.
+
+use hir::{BinaryOp, Expr, ExprKind, Place, Stmt};
+
+/// What to optimize for, picked with --optimize-for.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Target {
+    // Smaller modules. Every instruction counts, whether it runs or not.
+    Size,
+
+    // Faster code. Only the instructions that actually run count.
+    Speed,
+}
+
+/// The cost of each kind of instruction.
+struct Costs {
+    get: u32,
+    constant: u32,
+    arithmetic: u32,
+    multiply: u32,
+    divide: u32,
+    call: u32,
+    load: u32,
+    set: u32,
+    select: u32,
+
+    // The extra cost of an `if`/`else` over straight-line code: the block
+    // instructions and the second `local.set` for size, and the chance of a
+    // mispredicted branch for speed.
+    branch: u32,
+
+    // How many bytes bigger inlining a call or unrolling a loop can make
+    // the code.
+    growth: u32,
+}
+
+// Bytes in the binary encoding, assuming small constants and indexes.
+const SIZE: Costs = Costs {
+    get: 2,
+    constant: 2,
+    arithmetic: 1,
+    multiply: 1,
+    divide: 1,
+    call: 2,
+    load: 3,
+    set: 2,
+    select: 1,
+    branch: 6,
+    growth: 0,
+};
+
+// Roughly what a typical JIT turns each instruction into.
+const SPEED: Costs = Costs {
+    get: 1,
+    constant: 1,
+    arithmetic: 1,
+    multiply: 3,
+    divide: 20,
+    call: 20,
+    load: 3,
+    set: 1,
+    select: 1,
+    branch: 4,
+    growth: 64,
+};
+
+pub struct CostModel {
+    target: Target,
+}
+
+impl CostModel {
+    pub fn new(target: Target) -> CostModel {
+        CostModel { target }
+    }
+
+    fn costs(&self) -> &'static Costs {
+        match self.target {
+            Target::Size => &SIZE,
+            Target::Speed => &SPEED,
+        }
+    }
+
+    /// The cost of evaluating an expression.
+    pub fn expr(&self, expr: &Expr) -> u32 {
+        expr_cost(self.costs(), expr)
+    }
+
+    /// Whether to pick between two values with `select`, which always
+    /// evaluates both, instead of branching and evaluating only one.
+    pub fn prefer_select(&self, then_value: &Expr, else_value: &Expr) -> bool {
+        let costs = self.costs();
+        let then_cost = self.expr(then_value);
+        let else_cost = self.expr(else_value);
+
+        let branch_arms = match self.target {
+            Target::Size => then_cost + else_cost,
+            Target::Speed => then_cost.max(else_cost),
+        };
+
+        then_cost + else_cost + costs.select <= branch_arms + costs.branch
+    }
+
+    /// Whether to replace a call with `inlined`, the function's body with
+    /// the arguments in place of its parameters. The function's still
+    /// there, for the other calls to it.
+    pub fn prefer_inline(&self, call: &Expr, inlined: &Expr) -> bool {
+        expr_cost(&SIZE, inlined) <= expr_cost(&SIZE, call) + self.costs().growth
+    }
+
+    /// Whether to unroll a loop that runs `trips` times into a copy of
+    /// `body` for each, which sets the counter to its value first. The
+    /// loop would check the counter and add one to it every time round.
+    pub fn prefer_unroll(&self, trips: u64, body: &[Stmt]) -> bool {
+        let body = u64::from(block_cost(&SIZE, body));
+        let check = 2 * SIZE.get + SIZE.arithmetic;
+        let step = SIZE.get + SIZE.constant + SIZE.arithmetic + SIZE.set;
+        let looped = u64::from(check + SIZE.branch + step) + body;
+
+        let unrolled = trips.saturating_mul(u64::from(SIZE.constant + SIZE.set) + body);
+        unrolled <= looped + u64::from(self.costs().growth)
+    }
+}
+
+fn expr_cost(costs: &Costs, expr: &Expr) -> u32 {
+    let cost = |expr: &Expr| expr_cost(costs, expr);
+    match expr.kind {
+        ExprKind::Int(..) | ExprKind::Float(_) | ExprKind::Bool(_) | ExprKind::Str(_) => costs.constant,
+        // An address is its index in the table.
+        ExprKind::Address(_) => costs.constant,
+        ExprKind::Variable(_) => costs.get,
+        ExprKind::Read => costs.call,
+        ExprKind::Call(_, ref arguments) => costs.call + arguments.iter().map(cost).sum::<u32>(),
+        ExprKind::CallIndirect(ref callee, ref arguments) => {
+            costs.call + cost(callee) + arguments.iter().map(cost).sum::<u32>()
+        }
+        ExprKind::Binary(op, ref left, ref right) => {
+            let instruction = match op {
+                BinaryOp::Multiply => costs.multiply,
+                BinaryOp::Divide | BinaryOp::Remainder => costs.divide,
+                BinaryOp::And | BinaryOp::Or => costs.branch,
+                _ => costs.arithmetic,
+            };
+            instruction + cost(left) + cost(right)
+        }
+        ExprKind::Load(ref address) => costs.load + cost(address),
+        // The index gets multiplied by the size of an element.
+        ExprKind::Index(_, ref index) => costs.load + costs.constant + costs.multiply + cost(index),
+        // The field's offset goes in the load.
+        ExprKind::Field(ref record, _) => costs.load + cost(record),
+        // It's just the address, with another type.
+        ExprKind::View(_, ref address) => cost(address),
+        // Whichever way code generation picks, it's at least a select.
+        ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+            costs.select + cost(condition) + cost(then_value) + cost(else_value)
+        }
+    }
+}
+
+/// The cost of running a block once. Loops count as going round once too,
+/// since we can't tell how many times they will.
+fn block_cost(costs: &Costs, block: &[Stmt]) -> u32 {
+    block.iter().map(|stmt| stmt_cost(costs, stmt)).sum()
+}
+
+fn stmt_cost(costs: &Costs, stmt: &Stmt) -> u32 {
+    let cost = |expr: &Expr| expr_cost(costs, expr);
+    let block = |block: &[Stmt]| block_cost(costs, block);
+    match *stmt {
+        Stmt::Assign { ref value, .. } => cost(value) + costs.set,
+        Stmt::AssignValues { ref names, ref value, .. } => cost(value) + costs.set * names.len() as u32,
+        // The call's in the value, and its result gets dropped.
+        Stmt::Return(ref value) | Stmt::Halt(ref value) | Stmt::Call(ref value) => cost(value) + costs.arithmetic,
+        Stmt::ReturnValues(ref values) => values.iter().map(cost).sum::<u32>() + costs.arithmetic,
+        Stmt::Print(ref value) => cost(value) + costs.call,
+        Stmt::Assert(ref condition) => cost(condition) + costs.branch,
+        Stmt::If { ref condition, ref then, ref otherwise } => {
+            cost(condition) + costs.branch + block(then) + block(otherwise)
+        }
+        Stmt::While { ref condition, ref body } => cost(condition) + costs.branch + block(body),
+        // Each value gets compared with the subject.
+        Stmt::Case { ref subject, ref arms, ref otherwise } => {
+            let arms: u32 = arms
+                .iter()
+                .map(|(values, body)| {
+                    let compare = costs.get + costs.arithmetic;
+                    values.iter().map(|value| cost(value) + compare).sum::<u32>() + costs.branch + block(body)
+                })
+                .sum();
+            cost(subject) + costs.set + arms + otherwise.as_ref().map_or(0, |otherwise| block(otherwise))
+        }
+        // Stores cost what loads do.
+        Stmt::Store { ref place, ref value } => {
+            let address = match *place {
+                Place::Memory(ref address) => cost(address),
+                Place::Element(_, ref index) => costs.constant + costs.multiply + cost(index),
+                Place::Field(ref record, _) => cost(record),
+            };
+            costs.load + address + cost(value)
+        }
+        Stmt::Block(ref body) => block(body),
+    }
+}
@@ -0,0 +1,143 @@
+//! Crash reports. With --save-crash, an internal compiler error writes out
+//! everything needed to reproduce it to a `.crash` file, and `crenshaw replay`
+//! runs the compiler on it again.
+//!
+//! There's no separate token stream to save: the parser reads characters
+//! straight out of the source, so saving the source covers it. The files it
+//! includes, and the ones linked with it, are saved too, so replaying reads
+//! them from the report instead of from disk, where they may have changed.
+//!
+//! The file is a list of sections, each a header line `<name> <length>`
+//! followed by that many bytes and a newline. Lengths are in bytes, so the
+//! sections can hold anything, including source that isn't valid UTF-8.
+
+use std::fs;
+use std::path::Path;
+
+/// How far compilation got. Filled in as each pass runs, so a crash report
+/// can say where things went wrong.
+#[derive(Default)]
+pub struct Progress {
+    // The pass that's running, or the last one that ran.
+    pub pass: &'static str,
+
+    // Every file read so far: the input, the files it includes, and the
+    // files linked with it.
+    pub files: Vec<String>,
+
+    // With --save-crash or --debug, what was in each of those, once they've
+    // all been parsed.
+    pub sources: Vec<(String, Vec<u8>)>,
+
+    // With --coverage, the JSON that goes in the file next to the output,
+    // once code generation has made it.
+    pub coverage: Option<String>,
+
+    // Debug dumps of the syntax tree and the core language, once they exist.
+    // These are only for reading; replaying starts again from the source.
+    pub ast: Option<String>,
+    pub hir: Option<String>,
+}
+
+/// Everything in a crash report.
+pub struct Snapshot {
+    // The command line arguments, without the program name.
+    pub args: Vec<String>,
+    pub source: Vec<u8>,
+
+    // The other files, by the name they were read as, and what was in them.
+    pub files: Vec<(String, Vec<u8>)>,
+
+    pub pass: String,
+    pub message: String,
+    pub ast: Option<String>,
+    pub hir: Option<String>,
+}
+
+const MAGIC: &str = "crenshaw crash 1\n";
+
+impl Snapshot {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut out = MAGIC.as_bytes().to_vec();
+
+        section(&mut out, "args", self.args.join("\0").as_bytes());
+        section(&mut out, "source", &self.source);
+        // The name can't have a NUL in it, so that's where it ends.
+        for (name, source) in &self.files {
+            let mut body = name.as_bytes().to_vec();
+            body.push(0);
+            body.extend_from_slice(source);
+            section(&mut out, "file", &body);
+        }
+        section(&mut out, "pass", self.pass.as_bytes());
+        section(&mut out, "message", self.message.as_bytes());
+        if let Some(ref ast) = self.ast {
+            section(&mut out, "ast", ast.as_bytes());
+        }
+        if let Some(ref hir) = self.hir {
+            section(&mut out, "hir", hir.as_bytes());
+        }
+
+        fs::write(path, out).map_err(|err| format!("could not write {}: {}", path.display(), err))
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot, String> {
+        let bytes = fs::read(path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+        let malformed = || format!("{} is not a crash report", path.display());
+
+        let mut rest = bytes.strip_prefix(MAGIC.as_bytes()).ok_or_else(malformed)?;
+        let mut snapshot = Snapshot {
+            args: Vec::new(),
+            source: Vec::new(),
+            files: Vec::new(),
+            pass: String::new(),
+            message: String::new(),
+            ast: None,
+            hir: None,
+        };
+
+        while !rest.is_empty() {
+            let header_end = rest.iter().position(|&b| b == b'\n').ok_or_else(malformed)?;
+            let header = String::from_utf8_lossy(&rest[..header_end]).into_owned();
+            let mut header = header.split(' ');
+            let name = header.next().unwrap_or("");
+            let length: usize = header.next().and_then(|n| n.parse().ok()).ok_or_else(malformed)?;
+
+            let body_start = header_end + 1;
+            let body_end = body_start + length;
+            if rest.len() <= body_end || rest[body_end] != b'\n' {
+                return Err(malformed());
+            }
+
+            let body = &rest[body_start..body_end];
+            let text = String::from_utf8_lossy(body).into_owned();
+            match name {
+                "args" if text.is_empty() => {}
+                "args" => snapshot.args = text.split('\0').map(String::from).collect(),
+                "source" => snapshot.source = body.to_vec(),
+                "file" => {
+                    let end = body.iter().position(|&b| b == 0).ok_or_else(malformed)?;
+                    let name = String::from_utf8_lossy(&body[..end]).into_owned();
+                    snapshot.files.push((name, body[end + 1..].to_vec()));
+                }
+                "pass" => snapshot.pass = text,
+                "message" => snapshot.message = text,
+                "ast" => snapshot.ast = Some(text),
+                "hir" => snapshot.hir = Some(text),
+                // Skip sections we don't know about, so newer reports can
+                // still be replayed.
+                _ => {}
+            }
+
+            rest = &rest[body_end + 1..];
+        }
+
+        Ok(snapshot)
+    }
+}
+
+fn section(out: &mut Vec<u8>, name: &str, body: &[u8]) {
+    out.extend_from_slice(format!("{} {}\n", name, body.len()).as_bytes());
+    out.extend_from_slice(body);
+    out.push(b'\n');
+}
@@ -0,0 +1,377 @@
+//! Dead code elimination. With -O1, after constant folding, an `if` whose
+//! condition folded to a constant is replaced with the branch it always
+//! takes, a `case` whose subject did is replaced with the one it always
+//! takes, a `while` whose condition is always false goes away, and so does
+//! an `assert` whose condition is always true. With -O2, statements after
+//! one that always returns go away too.
+//!
+//! Code generation declares a local where it's first assigned. If that
+//! assignment was in code we dropped, and the variable's read before it's
+//! assigned again, it gets assigned 0 at the start of the function instead. Wasm locals start
+//! out as 0 anyway, so that doesn't change what the program does. (Except
+//! for strings: those get the empty string, which has the same length as
+//! the string that's all zeros, but its own address.)
+
+use std::mem;
+
+use consteval;
+use consteval::Value;
+use hir::{Expr, ExprKind, Place, Program, Span, Stmt, Type};
+
+/// Removes the dead branches and loops from every function body in a
+/// program, and main.
+pub fn eliminate(program: &mut Program) {
+    remove_in_program(program, eliminate_in_block);
+}
+
+/// Removes the statements that can never run because of a `return` before
+/// them from every function body in a program, and main.
+pub fn remove_unreachable(program: &mut Program) {
+    remove_in_program(program, remove_unreachable_in_block);
+}
+
+/// Removes code from every function body with `remove`, which takes a
+/// block, then declares whatever locals that leaves undeclared.
+fn remove_in_program(program: &mut Program, remove: fn(&mut Vec<Stmt>)) {
+    let globals: Vec<String> = program.globals.iter().map(|global| global.name.clone()).collect();
+
+    for function in program.functions.iter_mut().chain(&mut program.handlers) {
+        let params: Vec<String> = function.params.iter().map(|(name, _)| name.clone()).collect();
+        remove_in_body(&params, &globals, &mut function.body, remove);
+    }
+    remove_in_body(&[], &globals, &mut program.init, remove);
+    remove_in_body(&[], &globals, &mut program.main, remove);
+}
+
+fn remove_in_body(params: &[String], globals: &[String], body: &mut Vec<Stmt>, remove: fn(&mut Vec<Stmt>)) {
+    let mut locals = Vec::new();
+    declared_locals(params, globals, body, &mut locals);
+
+    remove(body);
+
+    let mut undeclared = Vec::new();
+    find_undeclared(body, &locals, &mut Vec::new(), &mut undeclared);
+
+    let declarations: Vec<Stmt> = locals
+        .into_iter()
+        .filter(|(name, _, _)| undeclared.contains(name))
+        .map(|(name, ty, span)| Stmt::Assign {
+            name,
+            name_span: span,
+            ty: Some(ty),
+            value: Expr { kind: zero(ty, span), span },
+        })
+        .collect();
+    body.splice(0..0, declarations);
+}
+
+fn eliminate_in_block(block: &mut Vec<Stmt>) {
+    for stmt in mem::take(block) {
+        match stmt {
+            Stmt::If { condition, mut then, mut otherwise } => {
+                eliminate_in_block(&mut then);
+                eliminate_in_block(&mut otherwise);
+                match constant_condition(&condition) {
+                    Some(true) => block.extend(then),
+                    Some(false) => block.extend(otherwise),
+                    None => block.push(Stmt::If { condition, then, otherwise }),
+                }
+            }
+            Stmt::While { condition, mut body } => {
+                eliminate_in_block(&mut body);
+                if constant_condition(&condition) != Some(false) {
+                    block.push(Stmt::While { condition, body });
+                }
+            }
+            Stmt::Case { subject, mut arms, mut otherwise } => {
+                for arm in &mut arms {
+                    eliminate_in_block(&mut arm.1);
+                }
+                if let Some(ref mut otherwise) = otherwise {
+                    eliminate_in_block(otherwise);
+                }
+                match constant_arm(&subject, &arms) {
+                    Some(Some(i)) => block.extend(arms.swap_remove(i).1),
+                    Some(None) => block.extend(otherwise.unwrap_or_default()),
+                    None => block.push(Stmt::Case { subject, arms, otherwise }),
+                }
+            }
+            Stmt::Block(mut body) => {
+                eliminate_in_block(&mut body);
+                block.extend(body);
+            }
+            Stmt::Assert(condition) => {
+                if constant_condition(&condition) != Some(true) {
+                    block.push(Stmt::Assert(condition));
+                }
+            }
+            stmt => block.push(stmt),
+        }
+    }
+}
+
+fn remove_unreachable_in_block(block: &mut Vec<Stmt>) {
+    for stmt in block.iter_mut() {
+        match *stmt {
+            Stmt::If { ref mut then, ref mut otherwise, .. } => {
+                remove_unreachable_in_block(then);
+                remove_unreachable_in_block(otherwise);
+            }
+            Stmt::While { ref mut body, .. } | Stmt::Block(ref mut body) => remove_unreachable_in_block(body),
+            Stmt::Case { ref mut arms, ref mut otherwise, .. } => {
+                for arm in arms {
+                    remove_unreachable_in_block(&mut arm.1);
+                }
+                if let Some(ref mut otherwise) = *otherwise {
+                    remove_unreachable_in_block(otherwise);
+                }
+            }
+            Stmt::Assign { .. }
+            | Stmt::AssignValues { .. }
+            | Stmt::Return(_)
+            | Stmt::ReturnValues(_)
+            | Stmt::Print(_)
+            | Stmt::Assert(_)
+            | Stmt::Halt(_)
+            | Stmt::Call(_)
+            | Stmt::Store { .. } => (),
+        }
+    }
+
+    if let Some(last) = block.iter().position(always_returns) {
+        block.truncate(last + 1);
+    }
+}
+
+/// Returns true if running a statement always ends with a `return`, or a
+/// `halt`, which doesn't come back either. A loop might not run at all, so
+/// it never does, and neither does a `case` without an `else`.
+pub fn always_returns(stmt: &Stmt) -> bool {
+    match *stmt {
+        Stmt::Return(_) | Stmt::ReturnValues(_) | Stmt::Halt(_) => true,
+        Stmt::If { ref then, ref otherwise, .. } => then.iter().any(always_returns) && otherwise.iter().any(always_returns),
+        Stmt::Case { ref arms, otherwise: Some(ref otherwise), .. } => {
+            arms.iter().all(|arm| arm.1.iter().any(always_returns)) && otherwise.iter().any(always_returns)
+        }
+        Stmt::Case { otherwise: None, .. } => false,
+        Stmt::Block(ref body) => body.iter().any(always_returns),
+        Stmt::Assign { .. }
+        | Stmt::AssignValues { .. }
+        | Stmt::While { .. }
+        | Stmt::Print(_)
+        | Stmt::Assert(_)
+        | Stmt::Call(_)
+        | Stmt::Store { .. } => false,
+    }
+}
+
+/// Returns whether a condition is always true or always false, if it's a
+/// literal. Integers only show up here with --truthiness nonzero.
+fn constant_condition(condition: &Expr) -> Option<bool> {
+    match condition.kind {
+        ExprKind::Bool(value) => Some(value),
+        ExprKind::Int(ref num, Some(ty)) => match consteval::int_literal(num, ty)? {
+            Value::I32(value) => Some(value != 0),
+            Value::I64(value) => Some(value != 0),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Works out which arm of a `case` runs, if its subject and all its values
+/// are literals: the first one with a value equal to the subject, or None
+/// for the `else`.
+fn constant_arm(subject: &Expr, arms: &[(Vec<Expr>, Vec<Stmt>)]) -> Option<Option<usize>> {
+    let subject = literal(subject)?;
+    let mut found = None;
+    for (i, (values, _)) in arms.iter().enumerate() {
+        for value in values {
+            if literal(value)? == subject && found.is_none() {
+                found = Some(i);
+            }
+        }
+    }
+    Some(found)
+}
+
+fn literal(expr: &Expr) -> Option<Value> {
+    match expr.kind {
+        ExprKind::Int(ref num, Some(ty)) => consteval::int_literal(num, ty),
+        _ => None,
+    }
+}
+
+/// Adds the locals a block declares to `locals`, with their types and the
+/// span of the value first assigned to them, the way code generation
+/// declares them: at the first assignment to anything that isn't a
+/// parameter or a global.
+fn declared_locals(params: &[String], globals: &[String], block: &[Stmt], locals: &mut Vec<(String, Type, Span)>) {
+    for stmt in block {
+        match *stmt {
+            Stmt::Assign { ref name, ty, ref value, .. } => {
+                let declared = params.contains(name) || globals.contains(name);
+                if !declared && !locals.iter().any(|(local, _, _)| local == name) {
+                    let ty = ty.expect("assignment wasn't type checked");
+                    locals.push((name.clone(), ty, value.span));
+                }
+            }
+            Stmt::AssignValues { ref names, ref types, ref value } => {
+                for (name, &ty) in names.iter().zip(types) {
+                    let declared = params.contains(name) || globals.contains(name);
+                    if !declared && !locals.iter().any(|(local, _, _)| local == name) {
+                        locals.push((name.clone(), ty, value.span));
+                    }
+                }
+            }
+            Stmt::If { ref then, ref otherwise, .. } => {
+                declared_locals(params, globals, then, locals);
+                declared_locals(params, globals, otherwise, locals);
+            }
+            Stmt::While { ref body, .. } | Stmt::Block(ref body) => declared_locals(params, globals, body, locals),
+            Stmt::Case { ref arms, ref otherwise, .. } => {
+                for arm in arms {
+                    declared_locals(params, globals, &arm.1, locals);
+                }
+                if let Some(ref otherwise) = *otherwise {
+                    declared_locals(params, globals, otherwise, locals);
+                }
+            }
+            Stmt::Return(_)
+            | Stmt::ReturnValues(_)
+            | Stmt::Print(_)
+            | Stmt::Assert(_)
+            | Stmt::Halt(_)
+            | Stmt::Call(_)
+            | Stmt::Store { .. } => (),
+        }
+    }
+}
+
+/// Walks a block in the order code generation compiles it, and adds the
+/// `locals` that are read before anything declares them to `undeclared`.
+/// `declared` is the locals declared so far.
+fn find_undeclared(
+    block: &[Stmt],
+    locals: &[(String, Type, Span)],
+    declared: &mut Vec<String>,
+    undeclared: &mut Vec<String>,
+) {
+    for stmt in block {
+        match *stmt {
+            // The value's compiled before the variable's declared, so it
+            // can't read it yet.
+            Stmt::Assign { ref name, ref value, .. } => {
+                find_undeclared_reads(value, locals, declared, undeclared);
+                if !declared.contains(name) {
+                    declared.push(name.clone());
+                }
+            }
+            Stmt::AssignValues { ref names, ref value, .. } => {
+                find_undeclared_reads(value, locals, declared, undeclared);
+                for name in names {
+                    if !declared.contains(name) {
+                        declared.push(name.clone());
+                    }
+                }
+            }
+            Stmt::If { ref condition, ref then, ref otherwise } => {
+                find_undeclared_reads(condition, locals, declared, undeclared);
+                find_undeclared(then, locals, declared, undeclared);
+                find_undeclared(otherwise, locals, declared, undeclared);
+            }
+            Stmt::While { ref condition, ref body } => {
+                find_undeclared_reads(condition, locals, declared, undeclared);
+                find_undeclared(body, locals, declared, undeclared);
+            }
+            Stmt::Case { ref subject, ref arms, ref otherwise } => {
+                find_undeclared_reads(subject, locals, declared, undeclared);
+                for arm in arms {
+                    find_undeclared(&arm.1, locals, declared, undeclared);
+                }
+                if let Some(ref otherwise) = *otherwise {
+                    find_undeclared(otherwise, locals, declared, undeclared);
+                }
+            }
+            Stmt::Block(ref body) => find_undeclared(body, locals, declared, undeclared),
+            Stmt::Return(ref value)
+            | Stmt::Print(ref value)
+            | Stmt::Assert(ref value)
+            | Stmt::Halt(ref value)
+            | Stmt::Call(ref value) => find_undeclared_reads(value, locals, declared, undeclared),
+            Stmt::ReturnValues(ref values) => {
+                for value in values {
+                    find_undeclared_reads(value, locals, declared, undeclared);
+                }
+            }
+            Stmt::Store { ref place, ref value } => {
+                match *place {
+                    Place::Memory(ref address) => find_undeclared_reads(address, locals, declared, undeclared),
+                    Place::Element(_, ref index) => find_undeclared_reads(index, locals, declared, undeclared),
+                    Place::Field(ref record, _) => find_undeclared_reads(record, locals, declared, undeclared),
+                }
+                find_undeclared_reads(value, locals, declared, undeclared);
+            }
+        }
+    }
+}
+
+fn find_undeclared_reads(expr: &Expr, locals: &[(String, Type, Span)], declared: &[String], undeclared: &mut Vec<String>) {
+    match expr.kind {
+        ExprKind::Int(..)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::Address(_)
+        | ExprKind::Read => (),
+        ExprKind::Variable(ref name) => {
+            let is_local = locals.iter().any(|(local, _, _)| local == name);
+            if is_local && !declared.contains(name) && !undeclared.contains(name) {
+                undeclared.push(name.clone());
+            }
+        }
+        ExprKind::Call(_, ref arguments) => {
+            for argument in arguments {
+                find_undeclared_reads(argument, locals, declared, undeclared);
+            }
+        }
+        ExprKind::CallIndirect(ref callee, ref arguments) => {
+            find_undeclared_reads(callee, locals, declared, undeclared);
+            for argument in arguments {
+                find_undeclared_reads(argument, locals, declared, undeclared);
+            }
+        }
+        ExprKind::Binary(_, ref left, ref right) => {
+            find_undeclared_reads(left, locals, declared, undeclared);
+            find_undeclared_reads(right, locals, declared, undeclared);
+        }
+        ExprKind::Load(ref address) => find_undeclared_reads(address, locals, declared, undeclared),
+        ExprKind::Index(_, ref index) => find_undeclared_reads(index, locals, declared, undeclared),
+        ExprKind::Field(ref inner, _) | ExprKind::View(_, ref inner) => {
+            find_undeclared_reads(inner, locals, declared, undeclared)
+        }
+        ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+            find_undeclared_reads(condition, locals, declared, undeclared);
+            find_undeclared_reads(then_value, locals, declared, undeclared);
+            find_undeclared_reads(else_value, locals, declared, undeclared);
+        }
+    }
+}
+
+/// A literal 0 of type `ty`, or the empty string, which is as close as a
+/// string literal can get. A pointer's or record's 0 is the null one. There
+/// are no vector literals, so a vector is 0 spread across its lanes.
+fn zero(ty: Type, span: Span) -> ExprKind {
+    match ty {
+        Type::I32 | Type::I64 | Type::Func(_) | Type::Record(_) | Type::Enum(_) => {
+            ExprKind::Int(String::from("0"), Some(ty))
+        }
+        Type::F64 => ExprKind::Float(String::from("0")),
+        Type::Bool => ExprKind::Bool(false),
+        Type::Str => ExprKind::Str(Vec::new()),
+        Type::V128 => {
+            let lane = Expr { kind: ExprKind::Int(String::from("0"), Some(Type::I32)), span };
+            ExprKind::Call(String::from("i32x4.splat"), vec![lane])
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Source-level debug info, with --debug: a source map from each instruction
+//! in the module back to the statement it's part of, so a debugger that reads
+//! source maps, like the browser's, steps through the program's source
+//! instead of the module's instructions.
+//!
+//! A source map for WebAssembly maps offsets in the module to places in the
+//! source, and those offsets don't exist until the WAT's assembled. So code
+//! generation only marks each statement's code in the WAT, with a comment
+//! saying where the statement is, and `assemble` does the rest: it parses
+//! the WAT, keeping where each instruction is in it, encodes it, and reads the
+//! instructions back out of the binary. They're the same instructions in the
+//! same order, which pairs each one's offset in the binary with its place in
+//! the WAT, and the last mark before that place says where it came from.
+
+#[cfg(feature = "debug")]
+use wasmparser;
+#[cfg(feature = "debug")]
+use wasmparser::Payload;
+#[cfg(feature = "debug")]
+use wast;
+#[cfg(feature = "debug")]
+use wast::core::{Func, FuncKind, Module, ModuleField, ModuleKind};
+#[cfg(feature = "debug")]
+use wast::parser::{self, ParseBuffer};
+#[cfg(feature = "debug")]
+use wast::Wat;
+
+use browser::BASE64_DIGITS;
+use json::Json;
+
+/// What the comment that marks a statement's code starts with.
+const MARK: &str = ";; at line ";
+
+/// The comment that goes before a statement's code, saying where the
+/// statement is.
+pub fn mark(file: &str, line: usize, column: usize) -> String {
+    format!("{}{}, column {} of {}", MARK, line, column, file)
+}
+
+/// Where a statement is, as its mark says.
+#[derive(Clone, Copy, PartialEq)]
+struct Position<'a> {
+    file: &'a str,
+    line: usize,
+    column: usize,
+}
+
+/// Assembles WAT with marks in it. Returns the module, with a
+/// `sourceMappingURL` section saying the source map's at `map_url`, and the
+/// source map. `sources` are the files the program was read from and what
+/// was in them; the source map has a copy, so the debugger doesn't have to
+/// go looking for them.
+#[cfg(feature = "debug")]
+pub fn assemble(text: &str, sources: &[(String, Vec<u8>)], map_url: &str) -> Result<(Vec<u8>, String), String> {
+    let error = |mut err: wast::Error| {
+        err.set_text(text);
+        err.to_string()
+    };
+    let mut buffer = ParseBuffer::new(text).map_err(error)?;
+    buffer.track_instr_spans(true);
+    let mut wat: Wat = parser::parse(&buffer).map_err(error)?;
+
+    // Where each function starts in the WAT, and where each of its
+    // instructions is.
+    let mut functions = Vec::new();
+    if let Wat::Module(Module { kind: ModuleKind::Text(ref fields), .. }) = wat {
+        for field in fields {
+            if let ModuleField::Func(Func { span, kind: FuncKind::Inline { ref expression, .. }, .. }) = *field {
+                let spans = expression.instr_spans.as_deref().unwrap_or_default();
+                functions.push((span.offset(), spans.iter().map(|span| span.offset()).collect::<Vec<_>>()));
+            }
+        }
+    }
+    let mut binary = wat.encode().map_err(error)?;
+
+    // The binary ends each function with an `end` that isn't in the WAT, so
+    // there's one instruction left over in each, which nothing's from.
+    let mut instructions = Vec::new();
+    let mut functions = functions.into_iter();
+    for payload in wasmparser::Parser::new(0).parse_all(&binary) {
+        if let Payload::CodeSectionEntry(body) = payload.map_err(|err| err.to_string())? {
+            let (function, offsets) = functions.next().ok_or("the module has more functions than the WAT")?;
+            let mut reader = body.get_operators_reader().map_err(|err| err.to_string())?;
+            for offset in offsets {
+                let (_, instruction) = reader.read_with_offset().map_err(|err| err.to_string())?;
+                instructions.push((instruction, function, offset));
+            }
+            reader.read().map_err(|err| err.to_string())?;
+            if !reader.eof() {
+                return Err(String::from("the module has more instructions than the WAT"));
+            }
+        }
+    }
+
+    let map = source_map(text, &instructions, sources);
+    let mut url = Vec::new();
+    leb128(map_url.len(), &mut url);
+    url.extend_from_slice(map_url.as_bytes());
+    binary.extend(custom_section("sourceMappingURL", &url));
+    Ok((binary, map))
+}
+
+/// Makes the source map for WAT with marks in it, as JSON. `instructions`
+/// are the offset of each instruction in the binary, in order, with where
+/// the function it's in starts in the WAT, and where it is in the WAT.
+pub fn source_map(text: &str, instructions: &[(usize, usize, usize)], sources: &[(String, Vec<u8>)]) -> String {
+    let marks = marks(text);
+    let mut files: Vec<&str> = Vec::new();
+
+    // Each segment of the mappings starts where its instructions do, and
+    // says where they're from, or nothing if they're not from anywhere.
+    // Every field but the first is the difference from the last segment
+    // with that field, and there's only one line, since a module's all one
+    // line as far as source maps are concerned.
+    let mut mappings = String::new();
+    let mut last = [0; 4];
+    let mut current = None;
+    for &(instruction, function, offset) in instructions {
+        // Marks from before the function are another function's.
+        let before = marks.partition_point(|mark| mark.0 <= offset);
+        let position = marks[..before].last().filter(|mark| mark.0 > function).map(|mark| mark.1);
+        if position == current {
+            continue;
+        }
+
+        if !mappings.is_empty() {
+            mappings.push(',');
+        }
+        let mut fields = vec![instruction as i64];
+        if let Some(position) = position {
+            let file = match files.iter().position(|&file| file == position.file) {
+                Some(file) => file,
+                None => {
+                    files.push(position.file);
+                    files.len() - 1
+                }
+            };
+            // Lines and columns count from 0 here.
+            fields.extend([file as i64, position.line as i64 - 1, position.column as i64 - 1]);
+        }
+        for (i, &field) in fields.iter().enumerate() {
+            vlq(field - last[i], &mut mappings);
+            last[i] = field;
+        }
+        current = position;
+    }
+
+    let contents = files
+        .iter()
+        .map(|&file| match sources.iter().find(|source| source.0 == file) {
+            Some(source) => Json::String(String::from_utf8_lossy(&source.1).into_owned()),
+            None => Json::Null,
+        })
+        .collect();
+    let map = Json::object(vec![
+        ("version", Json::Number(3.0)),
+        ("sources", Json::Array(files.iter().map(|&file| Json::String(file.to_string())).collect())),
+        ("sourcesContent", Json::Array(contents)),
+        ("names", Json::Array(Vec::new())),
+        ("mappings", Json::String(mappings)),
+    ]);
+    map.to_string()
+}
+
+/// Finds the marks in WAT, with where each one is.
+fn marks(text: &str) -> Vec<(usize, Position<'_>)> {
+    let mut marks = Vec::new();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if let Some(position) = line.trim().strip_prefix(MARK).and_then(position) {
+            marks.push((offset, position));
+        }
+        offset += line.len();
+    }
+    marks
+}
+
+/// Reads the rest of a mark, after `MARK`.
+fn position(mark: &str) -> Option<Position<'_>> {
+    let (line, rest) = mark.split_once(", column ")?;
+    let (column, file) = rest.split_once(" of ")?;
+    Some(Position {
+        file,
+        line: line.parse().ok()?,
+        column: column.parse().ok()?,
+    })
+}
+
+/// Appends a number to a source map's mappings, as a base64 VLQ: five bits
+/// to a digit, the lowest first, with the sign in the lowest bit of all, and
+/// the digit's sixth bit set if there are more to come.
+fn vlq(value: i64, mappings: &mut String) {
+    let mut bits = (value.unsigned_abs() << 1) | u64::from(value < 0);
+    loop {
+        let digit = (bits & 31) as usize;
+        bits >>= 5;
+        if bits == 0 {
+            mappings.push(char::from(BASE64_DIGITS[digit]));
+            return;
+        }
+        mappings.push(char::from(BASE64_DIGITS[digit | 32]));
+    }
+}
+
+/// A custom section, encoded, for adding to the end of a module.
+#[cfg(feature = "debug")]
+fn custom_section(name: &str, payload: &[u8]) -> Vec<u8> {
+    let mut contents = Vec::new();
+    leb128(name.len(), &mut contents);
+    contents.extend_from_slice(name.as_bytes());
+    contents.extend_from_slice(payload);
+
+    let mut section = vec![0];
+    leb128(contents.len(), &mut section);
+    section.extend(contents);
+    section
+}
+
+/// Appends a number as an unsigned LEB128, seven bits to a byte, the lowest
+/// first, with the top bit set if there are more to come.
+#[cfg(feature = "debug")]
+fn leb128(mut value: usize, bytes: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 127) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 128);
+    }
+}
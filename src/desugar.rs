@@ -0,0 +1,764 @@
+//! Lowers the syntax tree to the core language in `hir`.
+//!
+//! - `x += e` becomes `x = x + e` (and the same for `-=`, `*=`, `/=`).
+//! - `mem[a] += e` becomes `mem[t] = mem[t] + e`, with the address
+//!   evaluated once into a compiler temporary `t`. Array elements work the
+//!   same way, with the index in the temporary, and so do record fields,
+//!   with the record in it.
+//! - `read x` becomes an assignment of the value the host gives us to `x`.
+//! - `elseif` chains become nested `if`s in the `else` branch.
+//! - `for i = a to b ... end` becomes a `while` loop over `i`, with the limit
+//!   evaluated once into a compiler temporary.
+//! - Functions nested in other functions are lifted out to the top level,
+//!   named `outer.inner`. The variables of the functions around them that
+//!   they use become extra `var` parameters, which every call passes. Those
+//!   are the parameters, and the variables assigned before the nested
+//!   function is, so a name it assigns that's only assigned further down
+//!   the outer function is its own.
+//!
+//! Expressions made up along the way take the span of the sugar they came
+//! from.
+//!
+//! Statements don't keep their spans past here, so this is where we warn
+//! about ones that can't run, because of a `return` before them.
+
+use std::mem;
+
+use ast;
+use ast::{BinaryOp, Expr, ExprKind, Place, Span, Type};
+use deadcode;
+use diagnostics::{Diagnostics, Warning};
+use hir;
+
+/// Lowers a whole program.
+pub fn desugar(program: ast::Program, diagnostics: &mut Diagnostics) -> hir::Program {
+    let mut desugarer = Desugarer {
+        temporaries: 0,
+        diagnostics,
+    };
+
+    hir::Program {
+        functions: program
+            .functions
+            .into_iter()
+            .flat_map(lift)
+            .map(|(function, nested_in, captured)| desugarer.lower_function(function, nested_in, captured))
+            .collect(),
+        handlers: program
+            .handlers
+            .into_iter()
+            .map(|handler| desugarer.lower_function(handler, None, 0))
+            .collect(),
+        globals: program.globals,
+        constants: program.constants,
+        arrays: program.arrays,
+        records: program.records,
+        enums: program.enums,
+        table: Vec::new(),
+        init: desugarer.lower_block(program.init),
+        main: desugarer.lower_block(program.main),
+        global_homes: Vec::new(),
+        init_homes: Vec::new(),
+        main_homes: Vec::new(),
+    }
+}
+
+struct Desugarer<'a> {
+    // How many compiler temporaries we've made up so far. Used to give each
+    // one a unique name.
+    temporaries: usize,
+
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl<'a> Desugarer<'a> {
+    fn lower_function(&mut self, function: ast::Function, nested_in: Option<String>, captured: usize) -> hir::Function {
+        hir::Function {
+            name: function.name,
+            name_span: function.name_span,
+            params: function.params,
+            var_params: function.var_params,
+            homes: Vec::new(),
+            result: function.result,
+            more_results: function.more_results,
+            body: self.lower_block(function.body),
+            exported: function.exported,
+            nested_in,
+            captured,
+        }
+    }
+
+    /// Lowers the statements in a block, and warns about the first one that
+    /// can't run because of a `return` before it.
+    fn lower_block(&mut self, block: Vec<ast::Stmt>) -> Vec<hir::Stmt> {
+        let mut lowered = Vec::new();
+        // The keyword of the statement that always returns, once there's
+        // been one.
+        let mut returned: Option<&str> = None;
+        let mut warned = false;
+        for stmt in block {
+            // Nested functions don't run where they're written, so they can
+            // go anywhere.
+            let function = matches!(stmt.kind, ast::StmtKind::Function(_));
+            if let (Some(keyword), false, false) = (returned, warned, function) {
+                let message = format!("this never runs, because of a `{}` before it", keyword);
+                self.diagnostics.warn(stmt.span, Warning::UnreachableCode, &message);
+                warned = true;
+            }
+
+            let start = lowered.len();
+            self.lower_stmt(stmt, &mut lowered);
+            if returned.is_none() {
+                if let Some(stmt) = lowered[start..].iter().find(|&stmt| deadcode::always_returns(stmt)) {
+                    returned = Some(if matches!(*stmt, hir::Stmt::Halt(_)) { "halt" } else { "return" });
+                }
+            }
+        }
+        lowered
+    }
+
+    /// Lowers a statement, appending the result to `out`. Some statements
+    /// turn into more than one.
+    fn lower_stmt(&mut self, stmt: ast::Stmt, out: &mut Vec<hir::Stmt>) {
+        let span = stmt.span;
+
+        match stmt.kind {
+            // The statement starts with the name.
+            ast::StmtKind::Assign { name, ty, op: None, value } => {
+                let name_span = Span { start: span.start, end: span.start + name.len() };
+                out.push(hir::Stmt::Assign { name, name_span, ty, value });
+            }
+            ast::StmtKind::Assign { name, op: Some(op), value, .. } => {
+                let name_span = Span { start: span.start, end: span.start + name.len() };
+                let current = variable(&name, span);
+                out.push(assign(name, name_span, binary(op, current, value, span)));
+            }
+            ast::StmtKind::If { branches, otherwise } => {
+                let otherwise = self.lower_block(otherwise);
+                out.push(self.lower_if(branches, otherwise));
+            }
+            ast::StmtKind::While { condition, body } => {
+                out.push(hir::Stmt::While {
+                    condition,
+                    body: self.lower_block(body),
+                });
+            }
+            ast::StmtKind::Case {
+                subject,
+                arms,
+                otherwise,
+            } => out.push(hir::Stmt::Case {
+                subject,
+                arms: arms
+                    .into_iter()
+                    .map(|(values, body)| (values, self.lower_block(body)))
+                    .collect(),
+                otherwise: otherwise.map(|otherwise| self.lower_block(otherwise)),
+            }),
+            ast::StmtKind::Block(body) => out.push(hir::Stmt::Block(self.lower_block(body))),
+            // The parser reported any that aren't in a function, and the
+            // rest have been lifted out.
+            ast::StmtKind::Function(_) => (),
+            ast::StmtKind::AssignValues { names, value } => out.push(hir::Stmt::AssignValues {
+                names,
+                types: Vec::new(),
+                value,
+            }),
+            ast::StmtKind::Return(value) => out.push(hir::Stmt::Return(value)),
+            ast::StmtKind::ReturnValues(values) => out.push(hir::Stmt::ReturnValues(values)),
+            ast::StmtKind::Print(value) => out.push(hir::Stmt::Print(value)),
+            ast::StmtKind::Assert(condition) => out.push(hir::Stmt::Assert(condition)),
+            ast::StmtKind::Halt(status) => out.push(hir::Stmt::Halt(status)),
+            ast::StmtKind::Call(value) => out.push(hir::Stmt::Call(value)),
+            // And this one ends with it.
+            ast::StmtKind::Read(name) => {
+                let name_span = Span { start: span.end - name.len(), end: span.end };
+                let value = Expr { kind: ExprKind::Read, span };
+                out.push(assign(name, name_span, value));
+            }
+            ast::StmtKind::Store { place, op: None, value } => {
+                out.push(hir::Stmt::Store { place, value });
+            }
+            ast::StmtKind::Store { place, op: Some(op), value } => {
+                let (place, current) = match place {
+                    Place::Memory(address) => {
+                        let temporary = self.temporary("mem.address");
+                        let address_span = address.span;
+                        out.push(assign(temporary.clone(), address_span, address));
+
+                        let current = ExprKind::Load(Box::new(variable(&temporary, address_span)));
+                        (Place::Memory(variable(&temporary, address_span)), current)
+                    }
+                    Place::Element(array, index) => {
+                        let temporary = self.temporary("index");
+                        let index_span = index.span;
+                        out.push(assign(temporary.clone(), index_span, index));
+
+                        let current = ExprKind::Index(array.clone(), Box::new(variable(&temporary, index_span)));
+                        (Place::Element(array, variable(&temporary, index_span)), current)
+                    }
+                    Place::Field(record, field) => {
+                        let temporary = self.temporary("record");
+                        let record_span = record.span;
+                        out.push(assign(temporary.clone(), record_span, record));
+
+                        let current = ExprKind::Field(Box::new(variable(&temporary, record_span)), field.clone());
+                        (Place::Field(variable(&temporary, record_span), field), current)
+                    }
+                };
+
+                let current = Expr { kind: current, span };
+                out.push(hir::Stmt::Store {
+                    place,
+                    value: binary(op, current, value, span),
+                });
+            }
+            ast::StmtKind::For { variable: counter, from, to, body } => {
+                let limit = self.temporary("for.limit");
+                let limit_span = to.span;
+
+                out.push(assign(counter.clone(), span, from));
+                out.push(assign(limit.clone(), limit_span, to));
+
+                let mut body = self.lower_block(body);
+                let next = binary(BinaryOp::Add, variable(&counter, span), int("1", span), span);
+                body.push(assign(counter.clone(), span, next));
+
+                let condition = binary(
+                    BinaryOp::LessEqual,
+                    variable(&counter, span),
+                    variable(&limit, limit_span),
+                    span,
+                );
+
+                out.push(hir::Stmt::While { condition, body });
+            }
+        }
+    }
+
+    /// Turns `if a ... elseif b ... else ... end` into
+    /// `if a ... else if b ... else ... end end`.
+    fn lower_if(&mut self, branches: Vec<(Expr, Vec<ast::Stmt>)>, otherwise: Vec<hir::Stmt>) -> hir::Stmt {
+        let mut otherwise = otherwise;
+        let mut branches = branches;
+
+        while let Some((condition, then)) = branches.pop() {
+            let lowered = hir::Stmt::If {
+                condition,
+                then: self.lower_block(then),
+                otherwise,
+            };
+
+            if branches.is_empty() {
+                return lowered;
+            }
+            otherwise = vec![lowered];
+        }
+
+        unreachable!("if statements always have at least one branch")
+    }
+
+    /// Makes up a name for a compiler temporary. The dot means it can't clash
+    /// with anything the user writes.
+    fn temporary(&mut self, purpose: &str) -> String {
+        let name = format!("{}.{}", purpose, self.temporaries);
+        self.temporaries += 1;
+        name
+    }
+}
+
+fn assign(name: String, name_span: Span, value: Expr) -> hir::Stmt {
+    hir::Stmt::Assign {
+        name,
+        name_span,
+        ty: None,
+        value,
+    }
+}
+
+fn variable(name: &str, span: Span) -> Expr {
+    Expr {
+        kind: ExprKind::Variable(name.to_string()),
+        span,
+    }
+}
+
+fn int(value: &str, span: Span) -> Expr {
+    Expr {
+        kind: ExprKind::Int(value.to_string(), None),
+        span,
+    }
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr, span: Span) -> Expr {
+    Expr {
+        kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+        span,
+    }
+}
+
+/// A function, or one nested in it, while the nested ones are being lifted
+/// out.
+struct Nested {
+    function: ast::Function,
+
+    // The name it was written with, which calls to it use.
+    name: String,
+
+    // Where the function it's nested in is, in the list of them.
+    parent: Option<usize>,
+
+    // Its parameters, and the variables it assigns, with where each is first
+    // assigned.
+    variables: Vec<(String, usize)>,
+
+    // The variables it uses at all, and the functions it calls or takes the
+    // address of.
+    used: Vec<String>,
+    calls: Vec<String>,
+
+    // The variables of the functions around it that it needs, in the order
+    // they become parameters.
+    captured: Vec<String>,
+}
+
+/// A nested function that calls can be made to from where we are.
+struct Callee {
+    name: String,
+    lifted: String,
+    captured: Vec<String>,
+}
+
+/// Lifts the functions nested in `function` out of it. Returns it and
+/// them, each after the one it was in, with the name of that one and how
+/// many variables of the functions around it it captures. Those come after
+/// its own parameters, as `var` parameters, with types for type checking
+/// to fill in.
+fn lift(function: ast::Function) -> Vec<(ast::Function, Option<String>, usize)> {
+    let mut nested = Vec::new();
+    unnest(function, None, &mut nested);
+
+    let outer: Vec<Vec<String>> = (0..nested.len())
+        .map(|i| {
+            let mut outer = Vec::new();
+            let mut inner = i;
+            while let Some(p) = nested[inner].parent {
+                let defined = nested[inner].function.name_span.start;
+                outer.extend(
+                    nested[p]
+                        .variables
+                        .iter()
+                        .filter(|&&(_, assigned)| assigned < defined)
+                        .map(|variable| variable.0.clone()),
+                );
+                inner = p;
+            }
+            outer
+        })
+        .collect();
+
+    // A function needs the outer variables it uses, and the ones the
+    // functions it calls need, and the ones the functions nested in it
+    // need, since it passes them on. Functions can call each other in
+    // circles, so this goes round until nothing changes.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in 0..nested.len() {
+            let mut needed = nested[i].used.clone();
+            for name in &nested[i].calls {
+                if let Some(callee) = resolve(&nested, i, name) {
+                    needed.extend(nested[callee].captured.iter().cloned());
+                }
+            }
+            for child in nested.iter().filter(|child| child.parent == Some(i)) {
+                needed.extend(child.captured.iter().cloned());
+            }
+
+            for name in needed {
+                let param = nested[i].function.params.iter().any(|param| param.0 == name);
+                if outer[i].contains(&name) && !param && !nested[i].captured.contains(&name) {
+                    nested[i].captured.push(name);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    for i in 0..nested.len() {
+        let callees = callees(&nested, i);
+        rewrite_block(&mut nested[i].function.body, &callees);
+    }
+
+    let names: Vec<String> = nested.iter().map(|n| n.function.name.clone()).collect();
+    nested
+        .into_iter()
+        .map(|n| {
+            let mut function = n.function;
+            for name in &n.captured {
+                function.params.push((name.clone(), Type::default()));
+                function.var_params.push(name.clone());
+            }
+            (function, n.parent.map(|p| names[p].clone()), n.captured.len())
+        })
+        .collect()
+}
+
+/// Adds `function` to `nested`, and then the functions nested in it, which
+/// get named after it.
+fn unnest(mut function: ast::Function, parent: Option<usize>, nested: &mut Vec<Nested>) {
+    let mut inner = Vec::new();
+    take_functions(&mut function.body, &mut inner);
+
+    let mut variables: Vec<(String, usize)> = function.params.iter().map(|param| (param.0.clone(), 0)).collect();
+    let mut used = Vec::new();
+    let mut calls = Vec::new();
+    names_in_block(&function.body, &mut variables, &mut used, &mut calls);
+
+    let name = function.name.clone();
+    if let Some(parent) = parent {
+        function.name = format!("{}.{}", nested[parent].function.name, name);
+    }
+
+    let index = nested.len();
+    nested.push(Nested {
+        function,
+        name,
+        parent,
+        variables,
+        used,
+        calls,
+        captured: Vec::new(),
+    });
+    for function in inner {
+        unnest(function, Some(index), nested);
+    }
+}
+
+/// Finds the nested function a call from the `i`th one to `name` is to:
+/// one nested in it, or alongside it, or alongside a function it's in,
+/// innermost first.
+fn resolve(nested: &[Nested], i: usize, name: &str) -> Option<usize> {
+    let mut scope = Some(i);
+    while let Some(j) = scope {
+        let found = nested.iter().position(|n| n.parent == Some(j) && n.name == name);
+        if found.is_some() {
+            return found;
+        }
+        scope = nested[j].parent;
+    }
+    None
+}
+
+/// The nested functions the `i`th one can call, innermost first.
+fn callees(nested: &[Nested], i: usize) -> Vec<Callee> {
+    let mut callees = Vec::new();
+    let mut scope = Some(i);
+    while let Some(j) = scope {
+        for n in nested.iter().filter(|n| n.parent == Some(j)) {
+            callees.push(Callee {
+                name: n.name.clone(),
+                lifted: n.function.name.clone(),
+                captured: n.captured.clone(),
+            });
+        }
+        scope = nested[j].parent;
+    }
+    callees
+}
+
+/// Takes the functions nested in a block out of it, including ones in the
+/// blocks inside it.
+fn take_functions(block: &mut Vec<ast::Stmt>, out: &mut Vec<ast::Function>) {
+    for mut stmt in mem::take(block) {
+        if let ast::StmtKind::Function(function) = stmt.kind {
+            out.push(*function);
+            continue;
+        }
+
+        match stmt.kind {
+            ast::StmtKind::If {
+                ref mut branches,
+                ref mut otherwise,
+            } => {
+                for branch in branches {
+                    take_functions(&mut branch.1, out);
+                }
+                take_functions(otherwise, out);
+            }
+            ast::StmtKind::Case {
+                ref mut arms,
+                ref mut otherwise,
+                ..
+            } => {
+                for arm in arms {
+                    take_functions(&mut arm.1, out);
+                }
+                if let Some(ref mut otherwise) = *otherwise {
+                    take_functions(otherwise, out);
+                }
+            }
+            ast::StmtKind::While { ref mut body, .. }
+            | ast::StmtKind::For { ref mut body, .. }
+            | ast::StmtKind::Block(ref mut body) => take_functions(body, out),
+            _ => (),
+        }
+        block.push(stmt);
+    }
+}
+
+/// Adds the variables a block assigns to `assigned`, with where, the ones
+/// it uses at all to `used`, and the functions it calls or takes the
+/// address of to `calls`. Calls through pointers count as both, since the
+/// name could be either.
+fn names_in_block(
+    block: &[ast::Stmt],
+    assigned: &mut Vec<(String, usize)>,
+    used: &mut Vec<String>,
+    calls: &mut Vec<String>,
+) {
+    for stmt in block {
+        let mut assigns = |name: &String, used: &mut Vec<String>| {
+            if !assigned.iter().any(|known| known.0 == *name) {
+                assigned.push((name.clone(), stmt.span.start));
+            }
+            add(used, name);
+        };
+        match stmt.kind {
+            ast::StmtKind::Assign { ref name, ref value, .. } => {
+                assigns(name, used);
+                names_in_expr(value, used, calls);
+            }
+            ast::StmtKind::If {
+                ref branches,
+                ref otherwise,
+            } => {
+                for branch in branches {
+                    names_in_expr(&branch.0, used, calls);
+                    names_in_block(&branch.1, assigned, used, calls);
+                }
+                names_in_block(otherwise, assigned, used, calls);
+            }
+            ast::StmtKind::Case {
+                ref subject,
+                ref arms,
+                ref otherwise,
+            } => {
+                names_in_expr(subject, used, calls);
+                for (values, body) in arms {
+                    for value in values {
+                        names_in_expr(value, used, calls);
+                    }
+                    names_in_block(body, assigned, used, calls);
+                }
+                if let Some(ref otherwise) = *otherwise {
+                    names_in_block(otherwise, assigned, used, calls);
+                }
+            }
+            ast::StmtKind::While { ref condition, ref body } => {
+                names_in_expr(condition, used, calls);
+                names_in_block(body, assigned, used, calls);
+            }
+            ast::StmtKind::For {
+                ref variable,
+                ref from,
+                ref to,
+                ref body,
+            } => {
+                assigns(variable, used);
+                names_in_expr(from, used, calls);
+                names_in_expr(to, used, calls);
+                names_in_block(body, assigned, used, calls);
+            }
+            ast::StmtKind::AssignValues { ref names, ref value } => {
+                for name in names {
+                    assigns(name, used);
+                }
+                names_in_expr(value, used, calls);
+            }
+            ast::StmtKind::Return(ref value)
+            | ast::StmtKind::Print(ref value)
+            | ast::StmtKind::Assert(ref value)
+            | ast::StmtKind::Halt(ref value)
+            | ast::StmtKind::Call(ref value) => names_in_expr(value, used, calls),
+            ast::StmtKind::ReturnValues(ref values) => {
+                for value in values {
+                    names_in_expr(value, used, calls);
+                }
+            }
+            ast::StmtKind::Read(ref name) => assigns(name, used),
+            ast::StmtKind::Store {
+                ref place, ref value, ..
+            } => {
+                match *place {
+                    Place::Memory(ref address) => names_in_expr(address, used, calls),
+                    Place::Element(_, ref index) => names_in_expr(index, used, calls),
+                    Place::Field(ref record, _) => names_in_expr(record, used, calls),
+                }
+                names_in_expr(value, used, calls);
+            }
+            ast::StmtKind::Block(ref body) => names_in_block(body, assigned, used, calls),
+            ast::StmtKind::Function(_) => (),
+        }
+    }
+}
+
+fn names_in_expr(expr: &Expr, used: &mut Vec<String>, calls: &mut Vec<String>) {
+    match expr.kind {
+        ExprKind::Variable(ref name) => add(used, name),
+        ExprKind::Call(ref name, ref arguments) => {
+            add(used, name);
+            add(calls, name);
+            for argument in arguments {
+                names_in_expr(argument, used, calls);
+            }
+        }
+        ExprKind::Address(ref name) => add(calls, name),
+        ExprKind::CallIndirect(ref callee, ref arguments) => {
+            names_in_expr(callee, used, calls);
+            for argument in arguments {
+                names_in_expr(argument, used, calls);
+            }
+        }
+        ExprKind::Binary(_, ref left, ref right) => {
+            names_in_expr(left, used, calls);
+            names_in_expr(right, used, calls);
+        }
+        ExprKind::Load(ref inner)
+        | ExprKind::Index(_, ref inner)
+        | ExprKind::Field(ref inner, _)
+        | ExprKind::View(_, ref inner) => names_in_expr(inner, used, calls),
+        ExprKind::Conditional(ref condition, ref then, ref otherwise) => {
+            names_in_expr(condition, used, calls);
+            names_in_expr(then, used, calls);
+            names_in_expr(otherwise, used, calls);
+        }
+        ExprKind::Int(..) | ExprKind::Float(_) | ExprKind::Bool(_) | ExprKind::Str(_) | ExprKind::Read => (),
+    }
+}
+
+fn add(names: &mut Vec<String>, name: &str) {
+    if !names.iter().any(|known| known == name) {
+        names.push(name.to_string());
+    }
+}
+
+/// Points the calls in a block to nested functions at the functions they
+/// were lifted to, passing the variables they capture.
+fn rewrite_block(block: &mut [ast::Stmt], callees: &[Callee]) {
+    for stmt in block {
+        match stmt.kind {
+            ast::StmtKind::Assign { ref mut value, .. }
+            | ast::StmtKind::AssignValues { ref mut value, .. }
+            | ast::StmtKind::Return(ref mut value)
+            | ast::StmtKind::Print(ref mut value)
+            | ast::StmtKind::Assert(ref mut value)
+            | ast::StmtKind::Halt(ref mut value)
+            | ast::StmtKind::Call(ref mut value) => rewrite_expr(value, callees),
+            ast::StmtKind::If {
+                ref mut branches,
+                ref mut otherwise,
+            } => {
+                for branch in branches {
+                    rewrite_expr(&mut branch.0, callees);
+                    rewrite_block(&mut branch.1, callees);
+                }
+                rewrite_block(otherwise, callees);
+            }
+            ast::StmtKind::Case {
+                ref mut subject,
+                ref mut arms,
+                ref mut otherwise,
+            } => {
+                rewrite_expr(subject, callees);
+                for arm in arms {
+                    rewrite_block(&mut arm.1, callees);
+                }
+                if let Some(ref mut otherwise) = *otherwise {
+                    rewrite_block(otherwise, callees);
+                }
+            }
+            ast::StmtKind::While {
+                ref mut condition,
+                ref mut body,
+            } => {
+                rewrite_expr(condition, callees);
+                rewrite_block(body, callees);
+            }
+            ast::StmtKind::For {
+                ref mut from,
+                ref mut to,
+                ref mut body,
+                ..
+            } => {
+                rewrite_expr(from, callees);
+                rewrite_expr(to, callees);
+                rewrite_block(body, callees);
+            }
+            ast::StmtKind::ReturnValues(ref mut values) => {
+                for value in values {
+                    rewrite_expr(value, callees);
+                }
+            }
+            ast::StmtKind::Store {
+                ref mut place,
+                ref mut value,
+                ..
+            } => {
+                match *place {
+                    Place::Memory(ref mut address) => rewrite_expr(address, callees),
+                    Place::Element(_, ref mut index) => rewrite_expr(index, callees),
+                    Place::Field(ref mut record, _) => rewrite_expr(record, callees),
+                }
+                rewrite_expr(value, callees);
+            }
+            ast::StmtKind::Block(ref mut body) => rewrite_block(body, callees),
+            ast::StmtKind::Read(_) | ast::StmtKind::Function(_) => (),
+        }
+    }
+}
+
+fn rewrite_expr(expr: &mut Expr, callees: &[Callee]) {
+    let span = expr.span;
+    match expr.kind {
+        ExprKind::Call(ref mut name, ref mut arguments) => {
+            for argument in arguments.iter_mut() {
+                rewrite_expr(argument, callees);
+            }
+            if let Some(callee) = callees.iter().find(|callee| callee.name == *name) {
+                name.clone_from(&callee.lifted);
+                arguments.extend(callee.captured.iter().map(|captured| variable(captured, span)));
+            }
+        }
+        ExprKind::Address(ref mut name) => {
+            if let Some(callee) = callees.iter().find(|callee| callee.name == *name) {
+                name.clone_from(&callee.lifted);
+            }
+        }
+        ExprKind::CallIndirect(ref mut callee, ref mut arguments) => {
+            rewrite_expr(callee, callees);
+            for argument in arguments {
+                rewrite_expr(argument, callees);
+            }
+        }
+        ExprKind::Binary(_, ref mut left, ref mut right) => {
+            rewrite_expr(left, callees);
+            rewrite_expr(right, callees);
+        }
+        ExprKind::Load(ref mut inner)
+        | ExprKind::Index(_, ref mut inner)
+        | ExprKind::Field(ref mut inner, _)
+        | ExprKind::View(_, ref mut inner) => rewrite_expr(inner, callees),
+        ExprKind::Conditional(ref mut condition, ref mut then, ref mut otherwise) => {
+            rewrite_expr(condition, callees);
+            rewrite_expr(then, callees);
+            rewrite_expr(otherwise, callees);
+        }
+        ExprKind::Int(..)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::Variable(_)
+        | ExprKind::Read => (),
+    }
+}
@@ -0,0 +1,311 @@
+//! Error reporting. Every error points at a span of the source, which gets
+//! turned into a file, line, and column, followed by the line itself.
+//! Warnings look the same, but don't stop the program compiling.
+//! Nothing gets printed here; whoever ran the compiler decides what to do
+//! with the messages, and whether they're for people, in color or not, or,
+//! with --error-format=json, for programs.
+
+use std::fmt;
+use std::mem;
+
+use json::quote;
+use source_map::{SourceMap, Span};
+
+/// What `abort` returns, for the parser to give up with. The compiler stops
+/// when it gets one, with the errors reported so far.
+#[derive(Debug)]
+pub struct Aborted;
+
+/// The kinds of warning. Each one can be turned off with `-Wno-` and its
+/// name, and back on with `-W` and its name. They're all on to start with.
+/// `--Werror=` and its name makes it an error instead, and `--Werror` does
+/// that for all of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Warning {
+    // A local that's assigned but never read.
+    UnusedVariable,
+
+    // Statements after a `return` in the same block.
+    UnreachableCode,
+
+    // A local in a `begin` block with the same name as one outside it.
+    Shadowing,
+
+    // A `case` on an enum, without an `else`, that leaves out some of its
+    // variants.
+    IncompleteCase,
+}
+
+pub const WARNINGS: &[Warning] = &[
+    Warning::UnusedVariable,
+    Warning::UnreachableCode,
+    Warning::Shadowing,
+    Warning::IncompleteCase,
+];
+
+impl Warning {
+    /// What the warning's called in `-W` flags.
+    pub fn name(self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => "unused-variable",
+            Warning::UnreachableCode => "unreachable-code",
+            Warning::Shadowing => "shadowing",
+            Warning::IncompleteCase => "incomplete-case",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Warning> {
+        WARNINGS.iter().copied().find(|warning| warning.name() == name)
+    }
+}
+
+/// Whether a message is an error or a warning. Messages don't know which
+/// they are; it's whichever list they came from.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// ANSI escape codes, for messages on a terminal.
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_YELLOW: &str = "\x1b[1;33m";
+
+impl Severity {
+    /// What a message starts with when it's printed for people.
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        }
+    }
+
+    /// What it's called in JSON.
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => BOLD_RED,
+            Severity::Warning => BOLD_YELLOW,
+        }
+    }
+}
+
+/// An error or a warning.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub text: String,
+
+    /// Where in the program it points. Errors about the program as a whole,
+    /// like a file that can't be read, don't point anywhere.
+    pub location: Option<MessageLocation>,
+}
+
+/// Where a message points, worked out from its span while the source map's
+/// still around.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageLocation {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+
+    /// How many bytes the span covers.
+    pub length: usize,
+
+    /// The line the span starts on, with the span underlined.
+    pub snippet: String,
+}
+
+impl Message {
+    /// A message that doesn't point at anything in the program.
+    pub fn new(text: String) -> Message {
+        Message { text, location: None }
+    }
+
+    /// The message for a terminal: the same as `Display` has it, after its
+    /// severity, but with the severity and the underline in its color, and
+    /// the message in bold.
+    pub fn to_colored(&self, severity: Severity) -> String {
+        let color = severity.color();
+        let location = match self.location {
+            Some(ref location) => location,
+            None => return format!("{}{}:{} {}{}{}", color, severity.label(), RESET, BOLD, self.text, RESET),
+        };
+
+        // The snippet's last line is the underline, which starts with
+        // whitespace to line it up.
+        let (line, underline) = location.snippet.rsplit_once('\n').expect("snippets have two lines");
+        let (padding, carets) = underline.split_at(underline.find('^').unwrap_or(underline.len()));
+        format!(
+            "{}{}:{} {}{}, line {}, column {}: {}{}\n{}\n{}{}{}{}",
+            color,
+            severity.label(),
+            RESET,
+            BOLD,
+            location.file,
+            location.line,
+            location.column,
+            self.text,
+            RESET,
+            line,
+            padding,
+            color,
+            carets,
+            RESET
+        )
+    }
+
+    /// The message as one line of JSON, for --error-format=json. The
+    /// location's fields are null if there isn't one.
+    pub fn to_json(&self, severity: Severity) -> String {
+        let location = match self.location {
+            Some(ref location) => format!(
+                "\"file\":{},\"line\":{},\"column\":{},\"length\":{}",
+                quote(&location.file),
+                location.line,
+                location.column,
+                location.length
+            ),
+            None => String::from("\"file\":null,\"line\":null,\"column\":null,\"length\":null"),
+        };
+        format!(
+            "{{\"severity\":{},\"message\":{},{}}}",
+            quote(severity.name()),
+            quote(&self.text),
+            location
+        )
+    }
+}
+
+/// The file, line, and column a message points at, then the message, then
+/// the line underneath.
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(ref location) => write!(
+                f,
+                "{}, line {}, column {}: {}\n{}",
+                location.file, location.line, location.column, self.text, location.snippet
+            ),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
+
+/// Collects errors as the compiler finds them.
+pub struct Diagnostics {
+    // The files being compiled, for turning spans into locations.
+    source_map: SourceMap,
+
+    // Every error reported so far, with its location.
+    messages: Vec<Message>,
+
+    // Every warning reported so far and not yet taken, with its location.
+    warnings: Vec<Message>,
+
+    // The warnings that have been turned off.
+    disabled: Vec<Warning>,
+
+    // The warnings that are reported as errors.
+    promoted: Vec<Warning>,
+}
+
+impl Diagnostics {
+    pub fn new(source_map: SourceMap) -> Diagnostics {
+        Diagnostics {
+            source_map,
+            messages: Vec::new(),
+            warnings: Vec::new(),
+            disabled: Vec::new(),
+            promoted: Vec::new(),
+        }
+    }
+
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    pub fn source_map_mut(&mut self) -> &mut SourceMap {
+        &mut self.source_map
+    }
+
+    /// Reports an error pointing at a span, and keeps going. The program
+    /// fails to compile once we're done.
+    pub fn error(&mut self, span: Span, msg: &str) {
+        let message = self.located(span, msg);
+        self.messages.push(message);
+    }
+
+    /// Reports an error pointing at a span that there's no going on from.
+    /// Returns `Aborted`, which the caller gives up with.
+    pub fn abort(&mut self, span: Span, msg: &str) -> Aborted {
+        self.error(span, msg);
+        Aborted
+    }
+
+    /// Reports something that's probably a mistake, but isn't an error,
+    /// unless that kind of warning is turned off, or made an error. The
+    /// message says which flag did that.
+    pub fn warn(&mut self, span: Span, warning: Warning, msg: &str) {
+        if self.disabled.contains(&warning) {
+            return;
+        }
+        if self.promoted.contains(&warning) {
+            let message = self.located(span, &format!("{} [--Werror={}]", msg, warning.name()));
+            self.messages.push(message);
+            return;
+        }
+        let message = self.located(span, &format!("{} [-W{}]", msg, warning.name()));
+        self.warnings.push(message);
+    }
+
+    /// Reports a kind of warning as an error from now on.
+    pub fn promote(&mut self, warning: Warning) {
+        if !self.promoted.contains(&warning) {
+            self.promoted.push(warning);
+        }
+    }
+
+    /// Stops reporting a kind of warning.
+    pub fn disable(&mut self, warning: Warning) {
+        if !self.disabled.contains(&warning) {
+            self.disabled.push(warning);
+        }
+    }
+
+    /// The warnings reported since the last time this was called, in order.
+    pub fn take_warnings(&mut self) -> Vec<Message> {
+        mem::take(&mut self.warnings)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Every error reported so far, in order.
+    pub fn into_messages(self) -> Vec<Message> {
+        self.messages
+    }
+
+    /// Works out where a span is, for a message pointing at it.
+    pub fn located(&self, span: Span, msg: &str) -> Message {
+        let location = self.source_map.location(span);
+        Message {
+            text: msg.to_string(),
+            location: Some(MessageLocation {
+                file: location.file.to_string(),
+                line: location.line,
+                column: location.column,
+                length: span.end - span.start,
+                snippet: self.source_map.snippet(span),
+            }),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Constant folding, with -O1. Arithmetic and comparisons on literals and
+//! constants are worked out at compile time and replaced with the result, so
+//! `2 * 3 + 4` compiles to `(i32.const 10)`. Uses of constants are replaced
+//! with their values too.
+//!
+//! This runs on the core language after type checking, since working out a
+//! value needs the types of the literals in it. Anything that would trap at
+//! runtime, like dividing by zero, is left alone so it still traps.
+
+use std::mem;
+
+use consteval;
+use consteval::Value;
+use hir::{BinaryOp, Expr, ExprKind, Function, Place, Program, Stmt, Type};
+
+/// Folds every function body in a program, and main.
+pub fn fold(program: &mut Program) {
+    let mut folder = Folder {
+        constants: Vec::new(),
+        params: Vec::new(),
+    };
+
+    for constant in &program.constants {
+        if let Some(value) = folder.value(&constant.value) {
+            folder.constants.push((constant.name.clone(), value));
+        }
+    }
+
+    for function in program.functions.iter_mut().chain(&mut program.handlers) {
+        folder.fold_function(function);
+    }
+
+    folder.params.clear();
+    folder.fold_block(&mut program.init);
+    folder.fold_block(&mut program.main);
+}
+
+struct Folder {
+    // The values of constants, except strings, which only have a value once
+    // code generation has put them in memory.
+    constants: Vec<(String, Value)>,
+
+    // The parameters of the function being folded, which hide constants with
+    // the same name.
+    params: Vec<String>,
+}
+
+impl Folder {
+    fn fold_function(&mut self, function: &mut Function) {
+        self.params = function.params.iter().map(|(name, _)| name.clone()).collect();
+        self.fold_block(&mut function.body);
+    }
+
+    fn fold_block(&mut self, block: &mut [Stmt]) {
+        for stmt in block {
+            self.fold_stmt(stmt);
+        }
+    }
+
+    fn fold_stmt(&mut self, stmt: &mut Stmt) {
+        match *stmt {
+            Stmt::Assign { ref mut value, .. }
+            | Stmt::AssignValues { ref mut value, .. }
+            | Stmt::Return(ref mut value)
+            | Stmt::Print(ref mut value)
+            | Stmt::Assert(ref mut value)
+            | Stmt::Halt(ref mut value)
+            | Stmt::Call(ref mut value) => self.fold_expr(value),
+            Stmt::ReturnValues(ref mut values) => {
+                for value in values {
+                    self.fold_expr(value);
+                }
+            }
+            Stmt::If { ref mut condition, ref mut then, ref mut otherwise } => {
+                self.fold_expr(condition);
+                self.fold_block(then);
+                self.fold_block(otherwise);
+            }
+            Stmt::While { ref mut condition, ref mut body } => {
+                self.fold_expr(condition);
+                self.fold_block(body);
+            }
+            Stmt::Case { ref mut subject, ref mut arms, ref mut otherwise } => {
+                self.fold_expr(subject);
+                for (values, body) in arms {
+                    for value in values {
+                        self.fold_expr(value);
+                    }
+                    self.fold_block(body);
+                }
+                if let Some(ref mut otherwise) = *otherwise {
+                    self.fold_block(otherwise);
+                }
+            }
+            Stmt::Block(ref mut body) => self.fold_block(body),
+            Stmt::Store { ref mut place, ref mut value } => {
+                match *place {
+                    Place::Memory(ref mut address) => self.fold_expr(address),
+                    Place::Element(_, ref mut index) => self.fold_expr(index),
+                    Place::Field(ref mut record, _) => self.fold_expr(record),
+                }
+                self.fold_expr(value);
+            }
+        }
+    }
+
+    /// Folds the operands of an expression first, so whole trees of
+    /// arithmetic on literals fold down to one literal.
+    fn fold_expr(&mut self, expr: &mut Expr) {
+        match expr.kind {
+            ExprKind::Int(..)
+            | ExprKind::Float(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Str(_)
+            | ExprKind::Address(_)
+            | ExprKind::Read => (),
+            // Constants compile to a literal anyway, but making that the
+            // literal here lets later passes see what it is.
+            ExprKind::Variable(_) => {
+                if let Some(value) = self.value(expr) {
+                    expr.kind = literal(value);
+                }
+            }
+            ExprKind::Call(_, ref mut arguments) | ExprKind::CallIndirect(_, ref mut arguments) => {
+                for argument in arguments {
+                    self.fold_expr(argument);
+                }
+            }
+            ExprKind::Binary(op, ref mut left, ref mut right) => {
+                self.fold_expr(left);
+                self.fold_expr(right);
+
+                let folded = match (self.value(left), self.value(right)) {
+                    (Some(left), Some(right)) => consteval::binary(op, left, right),
+                    // `false and e` and `true or e` never get as far as `e`.
+                    (Some(left), None) if op.is_logical() => match consteval::truth(left) {
+                        Some(left) if left == (op == BinaryOp::Or) => Some(Value::Bool(left)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(value) = folded {
+                    expr.kind = literal(value);
+                }
+            }
+            ExprKind::Load(ref mut address) => self.fold_expr(address),
+            ExprKind::Index(_, ref mut index) => self.fold_expr(index),
+            ExprKind::Field(ref mut record, _) => self.fold_expr(record),
+            ExprKind::View(_, ref mut address) => self.fold_expr(address),
+            // A condition that's known picks one of the values outright.
+            ExprKind::Conditional(ref mut condition, ref mut then_value, ref mut else_value) => {
+                self.fold_expr(condition);
+                self.fold_expr(then_value);
+                self.fold_expr(else_value);
+
+                if let Some(condition) = self.value(condition).and_then(consteval::truth) {
+                    *expr = pick(mem::replace(&mut expr.kind, ExprKind::Read), condition);
+                }
+            }
+        }
+    }
+
+    /// Returns the value of a literal or a constant, if that's what `expr`
+    /// is.
+    fn value(&self, expr: &Expr) -> Option<Value> {
+        match expr.kind {
+            ExprKind::Int(ref num, Some(ty)) => consteval::int_literal(num, ty),
+            ExprKind::Float(ref num) => num.parse().ok().map(Value::F64),
+            ExprKind::Bool(value) => Some(Value::Bool(value)),
+            ExprKind::Variable(ref name) if !self.params.contains(name) => self
+                .constants
+                .iter()
+                .find(|&(constant, _)| constant == name)
+                .map(|&(_, value)| value),
+            ExprKind::Binary(op, ref left, ref right) => consteval::binary(op, self.value(left)?, self.value(right)?),
+            _ => None,
+        }
+    }
+}
+
+/// Takes the value a conditional picks when its condition is `condition`.
+fn pick(conditional: ExprKind, condition: bool) -> Expr {
+    match conditional {
+        ExprKind::Conditional(_, then_value, else_value) => *if condition { then_value } else { else_value },
+        _ => panic!("expected a conditional"),
+    }
+}
+
+/// The literal for a value. Literals are written the way they go in a
+/// `const` instruction, which code generation copies as is.
+fn literal(value: Value) -> ExprKind {
+    match value {
+        Value::I32(_) => ExprKind::Int(value.to_string(), Some(Type::I32)),
+        Value::I64(_) => ExprKind::Int(value.to_string(), Some(Type::I64)),
+        Value::F64(_) => ExprKind::Float(value.to_string()),
+        Value::Bool(value) => ExprKind::Bool(value),
+        Value::Str { .. } => panic!("strings can't be folded"),
+        Value::V128(_) => panic!("vectors can't be folded"),
+        Value::Func(func, _) => ExprKind::Int(value.to_string(), Some(Type::Func(func))),
+        Value::Record(record, _) => ExprKind::Int(value.to_string(), Some(Type::Record(record))),
+        Value::Enum(ty, _) => ExprKind::Int(value.to_string(), Some(Type::Enum(ty))),
+    }
+}
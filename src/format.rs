@@ -0,0 +1,673 @@
+//! The formatter, for `crenshaw fmt`. It prints a program back in one style:
+//! two spaces of indentation for each block, one statement to a line,
+//! spaces around binary operators, and parentheses only where they're
+//! needed. Comments stay next to the code they were next to, and a blank
+//! line between two things stays one blank line.
+//!
+//! The syntax tree doesn't keep everything that was written, so the parser
+//! hands over a `Layout` with the rest, and we look at the source itself
+//! for the little that's left, like whether `x += 1` was written `x++`.
+
+use ast;
+use ast::{BinaryOp, Expr, ExprKind, Function, Layout, Place, Program, Span, Stmt, StmtKind, Type};
+use parser::Newlines;
+
+const INDENT: &str = "  ";
+
+/// Formats a program parsed with `parse_for_formatting`. `source` is the
+/// file it was parsed from, which starts at offset 0 in the source map.
+pub fn format(program: &Program, layout: &Layout, source: &[u8], newlines: Newlines) -> String {
+    let mut formatter = Formatter {
+        source,
+        comments: &layout.comments,
+        next_comment: 0,
+        last_end: 0,
+        output: String::new(),
+        depth: 0,
+        block_start: true,
+        separator: if newlines == Newlines::Whitespace { ";" } else { "" },
+    };
+
+    for item in items(program, layout) {
+        formatter.item(&item);
+    }
+    formatter.comments_before(source.len() + 1);
+    formatter.output
+}
+
+/// Something at the top level of a program.
+enum Item<'a> {
+    Function(&'a Function, Span),
+    Handler(&'a Function, Span),
+    Global(&'a ast::Global),
+    Constant(&'a ast::Constant),
+    Array(&'a ast::Array),
+    Record(&'a ast::Record, Span),
+    Enum(&'a ast::Enum, Span),
+    Init(&'a [Stmt], Span),
+    Include(&'a str, Span),
+    Stmt(&'a Stmt),
+}
+
+impl<'a> Item<'a> {
+    /// Where it starts, as near as we can tell. Globals, constants, and
+    /// arrays only know where their names are, which is near enough to
+    /// put them in order.
+    fn start(&self) -> usize {
+        match *self {
+            Item::Function(_, span)
+            | Item::Handler(_, span)
+            | Item::Record(_, span)
+            | Item::Enum(_, span)
+            | Item::Init(_, span)
+            | Item::Include(_, span) => span.start,
+            Item::Global(global) => global.name_span.start,
+            Item::Constant(constant) => constant.name_span.start,
+            Item::Array(array) => array.name_span.start,
+            Item::Stmt(stmt) => stmt.span.start,
+        }
+    }
+}
+
+/// Everything at the top level of a program, in the order it was written.
+fn items<'a>(program: &'a Program, layout: &'a Layout) -> Vec<Item<'a>> {
+    let mut items = Vec::new();
+    items.extend(program.functions.iter().zip(&layout.functions).map(|(f, &span)| Item::Function(f, span)));
+    items.extend(program.handlers.iter().zip(&layout.handlers).map(|(h, &span)| Item::Handler(h, span)));
+    items.extend(program.globals.iter().map(Item::Global));
+    items.extend(program.constants.iter().map(Item::Constant));
+    items.extend(program.arrays.iter().map(Item::Array));
+    items.extend(program.records.iter().zip(&layout.records).map(|(r, &span)| Item::Record(r, span)));
+    items.extend(program.enums.iter().zip(&layout.enums).map(|(e, &span)| Item::Enum(e, span)));
+    items.extend(program.main.iter().map(Item::Stmt));
+    items.extend(layout.includes.iter().map(|&(span, ref path)| Item::Include(path, span)));
+
+    let mut init = &program.init[..];
+    for &(span, len) in &layout.init_blocks {
+        let (block, rest) = init.split_at(len);
+        items.push(Item::Init(block, span));
+        init = rest;
+    }
+
+    items.sort_by_key(Item::start);
+    items
+}
+
+struct Formatter<'a> {
+    source: &'a [u8],
+    comments: &'a [Span],
+
+    // The first comment that hasn't been printed yet.
+    next_comment: usize,
+
+    // Where the last thing we printed ends in the source. Whether there's a
+    // blank line after it, or a comment on the same line, is worked out
+    // from here.
+    last_end: usize,
+
+    output: String,
+
+    // How many blocks we're inside.
+    depth: usize,
+
+    // Whether nothing's been printed in the current block yet, so there's
+    // no blank line to keep.
+    block_start: bool,
+
+    // What goes after each statement: nothing when newlines end them, and
+    // a `;` when they don't.
+    separator: &'static str,
+}
+
+impl<'a> Formatter<'a> {
+    fn item(&mut self, item: &Item) {
+        match *item {
+            Item::Function(function, span) => self.function(function, span),
+            // Handler payloads are always i32s, and they always return one.
+            Item::Handler(handler, span) => {
+                let params: Vec<&str> = handler.params.iter().map(|param| param.0.as_str()).collect();
+                let header = format!("on {}({})", handler.name, params.join(", "));
+                let header_end = self.header_end(handler.name_span.end);
+                self.block_item(span, &header, header_end, &handler.body);
+            }
+            Item::Init(body, span) => self.block_item(span, "init", span.start + "init".len(), body),
+            Item::Global(global) => {
+                let mut text = format!("global {}{}", global.name, annotation(global.ty));
+                if let Some(ref value) = global.value {
+                    text.push_str(&format!(" = {}", self.expr(value)));
+                }
+                self.line(global.name_span.start, &text, end_of(global.value.as_ref(), global.name_span));
+            }
+            Item::Constant(constant) => {
+                let text = format!(
+                    "const {}{} = {}",
+                    constant.name,
+                    annotation(constant.ty),
+                    self.expr(&constant.value)
+                );
+                self.line(constant.name_span.start, &text, constant.value.span.end);
+            }
+            Item::Array(array) => {
+                let text = format!("array {}[{}]", array.name, self.expr(&array.len));
+                self.line(array.name_span.start, &text, self.next_token(array.len.span.end) + 1);
+            }
+            Item::Record(record, span) => {
+                self.start_line(span.start);
+                self.output.push_str(&format!("record {}", record.name));
+                self.end_line(record.name_span.end);
+                self.depth += 1;
+                self.block_start = true;
+                for field in &record.fields {
+                    let text = format!("{}: {}", field.name, field.ty.name());
+                    self.line(field.name_span.start, &text, self.line_end(field.name_span.end));
+                }
+                let end = self.next_token(record.fields.last().map_or(record.name_span.end, |field| {
+                    self.line_end(field.name_span.end)
+                }));
+                self.comments_before(end);
+                self.depth -= 1;
+                self.terminator(end, "end", span.end);
+            }
+            Item::Enum(item, span) => {
+                self.start_line(span.start);
+                self.output.push_str(&format!("enum {}", item.name));
+                self.end_line(item.name_span.end);
+                self.depth += 1;
+                self.block_start = true;
+                for &(ref variant, variant_span) in &item.variants {
+                    self.line(variant_span.start, variant, variant_span.end);
+                }
+                let end = self.next_token(item.variants.last().map_or(item.name_span.end, |variant| variant.1.end));
+                self.comments_before(end);
+                self.depth -= 1;
+                self.terminator(end, "end", span.end);
+            }
+            Item::Include(path, span) => self.line(span.start, &format!("include {}", quote(path.as_bytes())), span.end),
+            Item::Stmt(stmt) => self.stmt(stmt),
+        }
+    }
+
+    fn function(&mut self, function: &Function, span: Span) {
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .map(|&(ref name, ty)| {
+                let var = if function.var_params.contains(name) { "var " } else { "" };
+                format!("{}{}: {}", var, name, ty.name())
+            })
+            .collect();
+        let mut results = vec![function.result.name()];
+        results.extend(function.more_results.iter().map(|ty| ty.name()));
+        let header = format!(
+            "{}func {}({}): {}",
+            if function.exported { "export " } else { "" },
+            function.name,
+            params.join(", "),
+            results.join(", ")
+        );
+        let header_end = self.header_end(function.name_span.end);
+        self.block_item(span, &header, header_end, &function.body);
+    }
+
+    /// A function, handler, or `init` block: a header line, a block, and an
+    /// `end`.
+    fn block_item(&mut self, span: Span, header: &str, header_end: usize, body: &[Stmt]) {
+        self.start_line(span.start);
+        self.output.push_str(header);
+        self.end_line(header_end);
+        let end = self.block(body, header_end);
+        self.terminator(end, "end", span.end);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let start = stmt.span.start;
+        match stmt.kind {
+            StmtKind::Assign {
+                ref name,
+                ty,
+                op,
+                ref value,
+            } => {
+                let text = match op {
+                    _ if self.is_step(value) => format!("{}{}", name, self.text(value.span)),
+                    Some(op) => format!("{} {}= {}", name, op_symbol(op), self.expr(value)),
+                    None => format!("{}{} = {}", name, annotation(ty), self.expr(value)),
+                };
+                self.simple(stmt, &text);
+            }
+            StmtKind::Store {
+                ref place,
+                op,
+                ref value,
+            } => {
+                let place = match *place {
+                    Place::Memory(ref address) => format!("mem[{}]", self.expr(address)),
+                    Place::Element(ref name, ref index) => format!("{}[{}]", name, self.expr(index)),
+                    Place::Field(ref record, ref field) => format!("{}.{}", self.expr(record), field),
+                };
+                let text = match op {
+                    _ if self.is_step(value) => format!("{}{}", place, self.text(value.span)),
+                    Some(op) => format!("{} {}= {}", place, op_symbol(op), self.expr(value)),
+                    None => format!("{} = {}", place, self.expr(value)),
+                };
+                self.simple(stmt, &text);
+            }
+            StmtKind::AssignValues { ref names, ref value } => {
+                let text = format!("{} = {}", names.join(", "), self.expr(value));
+                self.simple(stmt, &text);
+            }
+            StmtKind::Return(ref value) => {
+                let text = format!("return {}", self.expr(value));
+                self.simple(stmt, &text);
+            }
+            StmtKind::ReturnValues(ref values) => {
+                let values: Vec<String> = values.iter().map(|value| self.expr(value)).collect();
+                let text = format!("return {}", values.join(", "));
+                self.simple(stmt, &text);
+            }
+            StmtKind::Print(ref value) => {
+                let text = format!("print {}", self.expr(value));
+                self.simple(stmt, &text);
+            }
+            StmtKind::Assert(ref condition) => {
+                let text = format!("assert {}", self.expr(condition));
+                self.simple(stmt, &text);
+            }
+            StmtKind::Halt(ref status) => {
+                let text = format!("halt {}", self.expr(status));
+                self.simple(stmt, &text);
+            }
+            StmtKind::Read(ref name) => self.simple(stmt, &format!("read {}", name)),
+            StmtKind::Call(ref value) => {
+                let text = self.expr(value);
+                self.simple(stmt, &text);
+            }
+            StmtKind::If {
+                ref branches,
+                ref otherwise,
+            } => {
+                let mut keyword = "if";
+                let mut keyword_start = start;
+                for (condition, body) in branches {
+                    let header = format!("{} {}", keyword, self.expr(condition));
+                    if keyword == "if" {
+                        self.start_line(keyword_start);
+                    } else {
+                        self.terminator_line(keyword_start);
+                    }
+                    self.output.push_str(&header);
+                    self.end_line(condition.span.end);
+                    keyword_start = self.block(body, condition.span.end);
+                    keyword = "elseif";
+                }
+                if self.word_at(keyword_start, "else") && !self.word_at(keyword_start, "elseif") {
+                    self.terminator_line(keyword_start);
+                    self.output.push_str("else");
+                    let else_end = keyword_start + "else".len();
+                    self.end_line(else_end);
+                    keyword_start = self.block(otherwise, else_end);
+                }
+                self.terminator(keyword_start, "end", stmt.span.end);
+            }
+            StmtKind::Case {
+                ref subject,
+                ref arms,
+                ref otherwise,
+            } => {
+                self.start_line(start);
+                self.output.push_str(&format!("case {}", self.expr(subject)));
+                self.end_line(subject.span.end);
+                let mut keyword_start = self.next_token(subject.span.end);
+                self.comments_before(keyword_start);
+                for (values, body) in arms {
+                    let header_end = values.last().map_or(keyword_start, |value| value.span.end);
+                    let values: Vec<String> = values.iter().map(|value| self.expr(value)).collect();
+                    self.terminator_line(keyword_start);
+                    self.output.push_str(&format!("when {}", values.join(", ")));
+                    self.end_line(header_end);
+                    keyword_start = self.block(body, header_end);
+                }
+                if let Some(ref otherwise) = *otherwise {
+                    self.terminator_line(keyword_start);
+                    self.output.push_str("else");
+                    let else_end = keyword_start + "else".len();
+                    self.end_line(else_end);
+                    keyword_start = self.block(otherwise, else_end);
+                }
+                self.terminator(keyword_start, "end", stmt.span.end);
+            }
+            StmtKind::While {
+                ref condition,
+                ref body,
+            } => {
+                let header = format!("while {}", self.expr(condition));
+                self.compound(stmt, &header, condition.span.end, body);
+            }
+            StmtKind::For {
+                ref variable,
+                ref from,
+                ref to,
+                ref body,
+            } => {
+                let header = format!("for {} = {} to {}", variable, self.expr(from), self.expr(to));
+                self.compound(stmt, &header, to.span.end, body);
+            }
+            StmtKind::Block(ref body) => self.compound(stmt, "begin", start + "begin".len(), body),
+            StmtKind::Function(ref function) => self.function(function, stmt.span),
+        }
+    }
+
+    /// A statement that fits on one line.
+    fn simple(&mut self, stmt: &Stmt, text: &str) {
+        self.line(stmt.span.start, text, stmt.span.end);
+    }
+
+    /// Prints a line for something that's at `start` to `end` in the
+    /// source, and ends it like a statement.
+    fn line(&mut self, start: usize, text: &str, end: usize) {
+        self.start_line(start);
+        self.output.push_str(text);
+        self.output.push_str(self.separator);
+        self.end_line(end);
+    }
+
+    /// A statement with a header line, a block, and an `end`.
+    fn compound(&mut self, stmt: &Stmt, header: &str, header_end: usize, body: &[Stmt]) {
+        self.start_line(stmt.span.start);
+        self.output.push_str(header);
+        self.end_line(header_end);
+        let end = self.block(body, header_end);
+        self.terminator(end, "end", stmt.span.end);
+    }
+
+    /// Prints the statements in a block, one level in, along with the
+    /// comments before whatever ends it. Returns where that is: the keyword
+    /// after the last statement, or after the header if there aren't any.
+    fn block(&mut self, body: &[Stmt], header_end: usize) -> usize {
+        self.depth += 1;
+        self.block_start = true;
+        for stmt in body {
+            self.stmt(stmt);
+        }
+        let end = self.next_token(body.last().map_or(header_end, |stmt| stmt.span.end));
+        self.comments_before(end);
+        self.depth -= 1;
+        end
+    }
+
+    /// Prints `end` or whatever keyword closes a block, which is at `start`
+    /// in the source, and ends the statement at `end`.
+    fn terminator(&mut self, start: usize, keyword: &str, end: usize) {
+        self.terminator_line(start);
+        self.output.push_str(keyword);
+        self.output.push_str(self.separator);
+        self.end_line(end);
+    }
+
+    /// Starts the line for a keyword in the middle or at the end of a
+    /// statement, which never has a blank line before it.
+    fn terminator_line(&mut self, start: usize) {
+        self.indent();
+        self.block_start = false;
+        self.last_end = start;
+    }
+
+    /// Starts a line for something at `start` in the source, with the
+    /// comments before it, and a blank line if there was one.
+    fn start_line(&mut self, start: usize) {
+        self.comments_before(start);
+        self.blank_line(start);
+        self.indent();
+        self.block_start = false;
+        self.last_end = start;
+    }
+
+    /// Ends the line for something that ends at `end` in the source, with
+    /// any comments that were on the same line after it.
+    fn end_line(&mut self, end: usize) {
+        self.last_end = end;
+        while let Some(&comment) = self.comments.get(self.next_comment) {
+            if comment.start < end || self.source[end..comment.start].contains(&b'\n') {
+                break;
+            }
+            self.output.push(' ');
+            self.output.push_str(&self.text(comment));
+            self.last_end = comment.end;
+            self.next_comment += 1;
+        }
+        self.output.push('\n');
+    }
+
+    /// Prints the comments that start before `offset`, each on its own line.
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(&comment) = self.comments.get(self.next_comment) {
+            if comment.start >= offset {
+                break;
+            }
+            self.next_comment += 1;
+            self.blank_line(comment.start);
+            self.indent();
+            self.block_start = false;
+            self.output.push_str(&self.text(comment));
+            self.output.push('\n');
+            self.last_end = comment.end;
+        }
+    }
+
+    /// Keeps a blank line if there was at least one between the last thing
+    /// printed and `start`.
+    fn blank_line(&mut self, start: usize) {
+        let end = start.min(self.source.len()).max(self.last_end);
+        let newlines = self.source[self.last_end..end].iter().filter(|&&byte| byte == b'\n').count();
+        if newlines > 1 && !self.block_start && !self.output.is_empty() {
+            self.output.push('\n');
+        }
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.output.push_str(INDENT);
+        }
+    }
+
+    /// Where the next thing after `offset` that isn't whitespace, a
+    /// separator, or a comment starts.
+    fn next_token(&self, mut offset: usize) -> usize {
+        while offset < self.source.len() {
+            if let Some(comment) = self.comments.iter().find(|comment| comment.start == offset) {
+                offset = comment.end;
+            } else if self.source[offset].is_ascii_whitespace() || self.source[offset] == b';' {
+                offset += 1;
+            } else {
+                break;
+            }
+        }
+        offset
+    }
+
+    /// Where a function's header ends: its parameters, and its result types
+    /// if it has any. The tree only knows where its name ends.
+    fn header_end(&self, name_end: usize) -> usize {
+        let close = self.source[name_end..]
+            .iter()
+            .position(|&byte| byte == b')')
+            .map_or(name_end, |close| name_end + close + 1);
+        let mut end = close;
+        let mut after = self.next_token(close);
+        while matches!(self.source.get(after), Some(&b':') | Some(&b',')) {
+            let ty = self.next_token(after + 1);
+            end = ty + self.source[ty..]
+                .iter()
+                .take_while(|&&byte| byte.is_ascii_alphanumeric() || byte == b'_')
+                .count();
+            after = self.next_token(end);
+        }
+        end
+    }
+
+    /// Where the code on the line from `offset` ends, before any separator
+    /// or comment.
+    fn line_end(&self, offset: usize) -> usize {
+        let rest = &self.source[offset..];
+        let mut end = rest
+            .iter()
+            .enumerate()
+            .position(|(i, &byte)| byte == b'\n' || byte == b';' || rest[i..].starts_with(b"//"))
+            .unwrap_or(rest.len());
+        while end > 0 && rest[end - 1].is_ascii_whitespace() {
+            end -= 1;
+        }
+        offset + end
+    }
+
+    /// Whether the keyword `word` is at `offset` in the source.
+    fn word_at(&self, offset: usize, word: &str) -> bool {
+        let end = offset + word.len();
+        self.source.get(offset..end) == Some(word.as_bytes())
+            && !self.source.get(end).is_some_and(|&byte| byte.is_ascii_alphanumeric() || byte == b'_')
+    }
+
+    /// Whether a compound assignment's value is the `1` that `++` or `--`
+    /// stands for.
+    fn is_step(&self, value: &Expr) -> bool {
+        let text = self.text(value.span);
+        text == "++" || text == "--"
+    }
+
+    fn text(&self, span: Span) -> String {
+        String::from_utf8_lossy(&self.source[span.start..span.end]).trim_end().to_string()
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        self.expr_in(expr, 0)
+    }
+
+    /// Prints an expression where anything that binds less tightly than
+    /// `min` needs parentheses.
+    fn expr_in(&self, expr: &Expr, min: u32) -> String {
+        let (text, precedence) = match expr.kind {
+            ExprKind::Int(ref num, suffix) => (format!("{}{}", num, suffix.map_or("", Type::name)), ATOM),
+            ExprKind::Float(ref num) => (num.clone(), ATOM),
+            ExprKind::Bool(value) => (value.to_string(), ATOM),
+            ExprKind::Str(ref bytes) => (quote(bytes), ATOM),
+            ExprKind::Variable(ref name) => (name.clone(), ATOM),
+            ExprKind::Call(ref name, ref arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|argument| self.expr(argument)).collect();
+                (format!("{}({})", name, arguments.join(", ")), ATOM)
+            }
+            ExprKind::Address(ref name) => (format!("@{}", name), ATOM),
+            ExprKind::Load(ref address) => (format!("mem[{}]", self.expr(address)), ATOM),
+            ExprKind::Index(ref name, ref index) => (format!("{}[{}]", name, self.expr(index)), ATOM),
+            ExprKind::Field(ref record, ref field) => (format!("{}.{}", self.expr(record), field), ATOM),
+            // A minus sign in front of anything but a number is parsed as
+            // a subtraction from a zero that isn't in the source.
+            ExprKind::Binary(BinaryOp::Subtract, ref zero, ref operand) if zero.span.start == zero.span.end => {
+                (format!("-{}", self.expr_in(operand, ATOM)), NEGATION)
+            }
+            ExprKind::Binary(op, ref left, ref right) => {
+                let precedence = precedence(op);
+                // Comparisons don't chain, so neither side can be one.
+                let left_min = if op.is_comparison() { precedence + 1 } else { precedence };
+                let text = format!(
+                    "{} {} {}",
+                    self.expr_in(left, left_min),
+                    op_symbol(op),
+                    self.expr_in(right, precedence + 1)
+                );
+                (text, precedence)
+            }
+            ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+                let text = format!(
+                    "{} ? {} : {}",
+                    self.expr_in(condition, 1),
+                    self.expr(then_value),
+                    self.expr(else_value)
+                );
+                (text, 0)
+            }
+            ExprKind::Read => unreachable!("the parser doesn't make reads"),
+            ExprKind::CallIndirect(..) => unreachable!("the parser doesn't make calls through pointers"),
+            ExprKind::View(..) => unreachable!("the parser doesn't make record views"),
+        };
+
+        if precedence < min {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+}
+
+// How tightly a negation binds, and something that can't be split up.
+const NEGATION: u32 = 10;
+const ATOM: u32 = 11;
+
+/// How tightly a binary operator binds. Conditionals are 0.
+fn precedence(op: BinaryOp) -> u32 {
+    match op {
+        BinaryOp::Or => 1,
+        BinaryOp::And => 2,
+        BinaryOp::BitOr => 3,
+        BinaryOp::BitXor => 4,
+        BinaryOp::BitAnd => 5,
+        _ if op.is_comparison() => 6,
+        _ if op.is_shift() => 7,
+        BinaryOp::Add | BinaryOp::Subtract => 8,
+        _ => 9,
+    }
+}
+
+fn op_symbol(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Subtract => "-",
+        BinaryOp::Multiply => "*",
+        BinaryOp::Divide => "/",
+        BinaryOp::Remainder => "%",
+        BinaryOp::Equal => "==",
+        BinaryOp::NotEqual => "!=",
+        BinaryOp::Less => "<",
+        BinaryOp::LessEqual => "<=",
+        BinaryOp::Greater => ">",
+        BinaryOp::GreaterEqual => ">=",
+        BinaryOp::BitAnd => "&",
+        BinaryOp::BitOr => "|",
+        BinaryOp::BitXor => "^",
+        BinaryOp::ShiftLeft => "<<",
+        BinaryOp::ShiftRight => ">>",
+        BinaryOp::ShiftRightUnsigned => ">>>",
+        BinaryOp::And => "and",
+        BinaryOp::Or => "or",
+    }
+}
+
+/// `: type`, if there's a type.
+fn annotation(ty: Option<Type>) -> String {
+    ty.map_or(String::new(), |ty| format!(": {}", ty.name()))
+}
+
+/// Where something with an optional value ends: after the value if there
+/// is one, and after its name if there isn't.
+fn end_of(value: Option<&Expr>, name_span: Span) -> usize {
+    value.map_or(name_span.end, |value| value.span.end)
+}
+
+/// A string literal for some bytes, with escapes for the ones that need
+/// them.
+fn quote(bytes: &[u8]) -> String {
+    let mut quoted = Vec::new();
+    quoted.push(b'"');
+    for &byte in bytes {
+        match byte {
+            b'\n' => quoted.extend_from_slice(b"\\n"),
+            b'\t' => quoted.extend_from_slice(b"\\t"),
+            b'\r' => quoted.extend_from_slice(b"\\r"),
+            0 => quoted.extend_from_slice(b"\\0"),
+            b'"' => quoted.extend_from_slice(b"\\\""),
+            b'\\' => quoted.extend_from_slice(b"\\\\"),
+            byte => quoted.push(byte),
+        }
+    }
+    quoted.push(b'"');
+    String::from_utf8_lossy(&quoted).into_owned()
+}
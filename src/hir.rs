@@ -0,0 +1,162 @@
+//! The core language. The desugaring pass lowers the syntax tree to this, so
+//! code generation (and anything else that comes after parsing) only has to
+//! deal with a handful of constructs. Every node keeps the span of the source
+//! it came from, so errors still point at what the user actually wrote.
+
+pub use ast::{
+    Array, BinaryOp, Constant, Enum, EnumType, Expr, ExprKind, Field, FuncType, Global, Place, Record, RecordType, Span,
+    Type,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub handlers: Vec<Function>,
+    pub globals: Vec<Global>,
+    pub constants: Vec<Constant>,
+    pub arrays: Vec<Array>,
+    pub records: Vec<Record>,
+    pub enums: Vec<Enum>,
+
+    /// The functions the program takes the address of, in the order they go
+    /// in the module's table. A pointer to one is its index in this, plus
+    /// one. Type checking fills this in.
+    pub table: Vec<String>,
+
+    /// What runs when the module's instantiated, before anything calls it.
+    pub init: Vec<Stmt>,
+
+    pub main: Vec<Stmt>,
+
+    /// The globals the program passes to `var` parameters, and the
+    /// variables of the init blocks and main that they pass to them. Like a
+    /// function's `homes`, they're in memory instead. The init blocks and
+    /// main only run once at a time, so theirs can have fixed addresses.
+    /// Type checking fills these in.
+    pub global_homes: Vec<String>,
+    pub init_homes: Vec<String>,
+    pub main_homes: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub name_span: Span,
+    pub params: Vec<(String, Type)>,
+
+    /// The parameters declared `var`. They hold the address of the caller's
+    /// variable or array element, so reading or assigning one reads or
+    /// writes that.
+    pub var_params: Vec<String>,
+
+    /// The parameters and locals it passes to `var` parameters of its own
+    /// or another function's, other than its own `var` parameters, which
+    /// already have an address. They need one, so instead of being in a
+    /// local, each is in a cell of a frame on the `var` stack, which the
+    /// function takes when it starts and gives back when it returns. Type
+    /// checking fills this in.
+    pub homes: Vec<String>,
+
+    pub result: Type,
+
+    /// What else it returns, after `result`. Multi-value functions return
+    /// them all at once, on the wasm stack.
+    pub more_results: Vec<Type>,
+
+    pub body: Vec<Stmt>,
+
+    /// Whether the module exports it, under its name.
+    pub exported: bool,
+
+    /// For a function that was nested in another one, the one it was in.
+    /// Desugaring lifts nested functions out and names them after it, as
+    /// `outer.inner`.
+    pub nested_in: Option<String>,
+
+    /// How many of the parameters, at the end, are variables of the
+    /// functions it's nested in, which it uses. They're `var` parameters,
+    /// named after the variables, and type checking gives them their types.
+    pub captured: usize,
+}
+
+/// A statement. Statements don't keep spans of their own; the expressions
+/// inside them do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stmt {
+    /// `x = e`, with the type the program gave `x` here, if any, and where
+    /// `x` is, for warnings about it.
+    Assign {
+        name: String,
+        name_span: Span,
+        ty: Option<Type>,
+        value: Expr,
+    },
+    If {
+        condition: Expr,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    While { condition: Expr, body: Vec<Stmt> },
+
+    /// `case e when a, b ... end`, as it was written. Type checking makes
+    /// sure every value is a constant of the subject's type.
+    Case {
+        subject: Expr,
+        arms: Vec<(Vec<Expr>, Vec<Stmt>)>,
+        otherwise: Option<Vec<Stmt>>,
+    },
+
+    /// `a, b = f(x)`, with the type of each variable once type checking has
+    /// worked them out. The value is always a call.
+    AssignValues {
+        names: Vec<String>,
+        types: Vec<Type>,
+        value: Expr,
+    },
+
+    Return(Expr),
+    ReturnValues(Vec<Expr>),
+    Print(Expr),
+    Assert(Expr),
+
+    /// `halt status`, which stops the program. Nothing after it runs, the
+    /// same as after a `return`.
+    Halt(Expr),
+
+    /// `f(x)` on its own. Whatever the call returns is thrown away.
+    Call(Expr),
+
+    /// `mem[address] = value` or `a[i] = value`
+    Store { place: Place, value: Expr },
+
+    /// `begin ... end`. Type checking gives the locals declared in it names
+    /// that are unique in the function, so after that, it's just the
+    /// statements in it.
+    Block(Vec<Stmt>),
+}
+
+impl Stmt {
+    /// Where the statement is, as near as we can tell. Statements don't have
+    /// spans, so this is the span of the expression they start with, or for
+    /// a block, its first statement's. Empty blocks don't have one.
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            Stmt::Assign { ref value, .. }
+            | Stmt::AssignValues { ref value, .. }
+            | Stmt::Return(ref value)
+            | Stmt::Print(ref value)
+            | Stmt::Assert(ref value)
+            | Stmt::Halt(ref value)
+            | Stmt::Call(ref value) => Some(value.span),
+            Stmt::ReturnValues(ref values) => values.first().map(|value| value.span),
+            Stmt::If { ref condition, .. } | Stmt::While { ref condition, .. } => Some(condition.span),
+            Stmt::Case { ref subject, .. } => Some(subject.span),
+            Stmt::Store { ref place, .. } => match *place {
+                Place::Memory(ref address) => Some(address.span),
+                Place::Element(_, ref index) => Some(index.span),
+                Place::Field(ref record, _) => Some(record.span),
+            },
+            Stmt::Block(ref body) => body.first().and_then(Stmt::span),
+        }
+    }
+}
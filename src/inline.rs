@@ -0,0 +1,379 @@
+//! Inlining, with -O2 and --optimize-for. A call to a function that only
+//! returns an expression of its parameters is replaced with that
+//! expression, with the arguments in place of the parameters, when the cost
+//! model says it's worth it. `square(n)` becomes `n * n`.
+//!
+//! Only calls whose arguments are literals or locals get inlined. Those
+//! can be read any number of times, in any order, and still have the value
+//! they had when the call would have been made, since nothing in an
+//! expression assigns a local. Functions with `var` parameters, or that
+//! take or return strings or records, are left alone, and so are the ones
+//! that call themselves, even through others.
+
+use std::slice;
+
+use cost::CostModel;
+use hir::{Expr, ExprKind, Function, Place, Program, Stmt, Type};
+
+/// Inlines the calls worth inlining in every function body in a program,
+/// and main.
+pub fn inline(program: &mut Program, model: &CostModel) {
+    let mut inliner = Inliner {
+        model,
+        inlinable: program.functions.iter().filter_map(inlinable).collect(),
+        locals: Vec::new(),
+    };
+    inliner.forget_recursive();
+
+    let globals: Vec<&str> = program.globals.iter().map(|global| global.name.as_str()).collect();
+    for function in program.functions.iter_mut().chain(&mut program.handlers) {
+        // `var` parameters and the variables that are passed to them can
+        // change in a call, through the address.
+        inliner.locals = function
+            .params
+            .iter()
+            .map(|(name, _)| name.clone())
+            .filter(|name| !function.var_params.contains(name) && !function.homes.contains(name))
+            .collect();
+        locals_in_block(&function.body, &globals, &function.homes, &mut inliner.locals);
+        inliner.inline_block(&mut function.body);
+    }
+    inliner.locals.clear();
+    locals_in_block(&program.init, &globals, &program.init_homes, &mut inliner.locals);
+    inliner.inline_block(&mut program.init);
+    inliner.locals.clear();
+    locals_in_block(&program.main, &globals, &program.main_homes, &mut inliner.locals);
+    inliner.inline_block(&mut program.main);
+}
+
+/// A function calls to which can be inlined.
+struct Inlinable {
+    name: String,
+    params: Vec<String>,
+    result: Expr,
+}
+
+/// Returns the function as one calls to which can be inlined, if it's
+/// just `return` of an expression that only reads its own parameters.
+fn inlinable(function: &Function) -> Option<Inlinable> {
+    let counted = |ty: Type| matches!(ty, Type::Str | Type::Record(_));
+    if !function.var_params.is_empty()
+        || !function.more_results.is_empty()
+        || counted(function.result)
+        || function.params.iter().any(|&(_, ty)| counted(ty))
+    {
+        return None;
+    }
+
+    let params: Vec<String> = function.params.iter().map(|(name, _)| name.clone()).collect();
+    match function.body[..] {
+        [Stmt::Return(ref result)] if reads_only(result, &params) => Some(Inlinable {
+            name: function.name.clone(),
+            params,
+            result: result.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// Whether the only variables an expression reads are `names`. Calls and
+/// addresses name functions, not variables.
+fn reads_only(expr: &Expr, names: &[String]) -> bool {
+    let mut only = true;
+    visit(expr, &mut |expr| {
+        if let ExprKind::Variable(ref name) = expr.kind {
+            only &= names.contains(name);
+        }
+    });
+    only
+}
+
+/// Calls `f` on an expression and everything in it.
+fn visit(expr: &Expr, f: &mut dyn FnMut(&Expr)) {
+    f(expr);
+    match expr.kind {
+        ExprKind::Int(..)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::Address(_)
+        | ExprKind::Variable(_)
+        | ExprKind::Read => (),
+        ExprKind::Call(_, ref arguments) => {
+            for argument in arguments {
+                visit(argument, f);
+            }
+        }
+        ExprKind::CallIndirect(ref callee, ref arguments) => {
+            visit(callee, f);
+            for argument in arguments {
+                visit(argument, f);
+            }
+        }
+        ExprKind::Binary(_, ref left, ref right) => {
+            visit(left, f);
+            visit(right, f);
+        }
+        ExprKind::Load(ref inner)
+        | ExprKind::Index(_, ref inner)
+        | ExprKind::Field(ref inner, _)
+        | ExprKind::View(_, ref inner) => visit(inner, f),
+        ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+            visit(condition, f);
+            visit(then_value, f);
+            visit(else_value, f);
+        }
+    }
+}
+
+/// Adds the locals a block assigns to `locals`, except the ones passed to
+/// `var` parameters, which are in `homes`.
+fn locals_in_block(block: &[Stmt], globals: &[&str], homes: &[String], locals: &mut Vec<String>) {
+    for stmt in block {
+        let names = match *stmt {
+            Stmt::Assign { ref name, .. } => slice::from_ref(name),
+            Stmt::AssignValues { ref names, .. } => &names[..],
+            _ => &[],
+        };
+        for name in names {
+            if !globals.contains(&name.as_str()) && !homes.contains(name) && !locals.contains(name) {
+                locals.push(name.clone());
+            }
+        }
+
+        match *stmt {
+            Stmt::If { ref then, ref otherwise, .. } => {
+                locals_in_block(then, globals, homes, locals);
+                locals_in_block(otherwise, globals, homes, locals);
+            }
+            Stmt::While { ref body, .. } | Stmt::Block(ref body) => locals_in_block(body, globals, homes, locals),
+            Stmt::Case { ref arms, ref otherwise, .. } => {
+                for arm in arms {
+                    locals_in_block(&arm.1, globals, homes, locals);
+                }
+                if let Some(ref otherwise) = *otherwise {
+                    locals_in_block(otherwise, globals, homes, locals);
+                }
+            }
+            Stmt::Assign { .. }
+            | Stmt::AssignValues { .. }
+            | Stmt::Return(_)
+            | Stmt::ReturnValues(_)
+            | Stmt::Print(_)
+            | Stmt::Assert(_)
+            | Stmt::Halt(_)
+            | Stmt::Call(_)
+            | Stmt::Store { .. } => (),
+        }
+    }
+}
+
+struct Inliner<'a> {
+    model: &'a CostModel,
+    inlinable: Vec<Inlinable>,
+
+    // The parameters and locals of the body being inlined into that can
+    // be arguments.
+    locals: Vec<String>,
+}
+
+impl<'a> Inliner<'a> {
+    /// Forgets the functions that call themselves, directly or through
+    /// others, so inlining always comes to an end.
+    fn forget_recursive(&mut self) {
+        let calls: Vec<Vec<usize>> = self
+            .inlinable
+            .iter()
+            .map(|function| {
+                let mut calls = Vec::new();
+                visit(&function.result, &mut |expr| {
+                    if let ExprKind::Call(ref name, _) = expr.kind {
+                        calls.extend(self.inlinable.iter().position(|callee| callee.name == *name));
+                    }
+                });
+                calls
+            })
+            .collect();
+
+        let recursive: Vec<bool> = (0..calls.len())
+            .map(|start| {
+                let mut seen = vec![false; calls.len()];
+                let mut stack = calls[start].clone();
+                while let Some(i) = stack.pop() {
+                    if i == start {
+                        return true;
+                    }
+                    if !seen[i] {
+                        seen[i] = true;
+                        stack.extend(calls[i].iter().cloned());
+                    }
+                }
+                false
+            })
+            .collect();
+
+        let mut i = 0;
+        self.inlinable.retain(|_| {
+            i += 1;
+            !recursive[i - 1]
+        });
+    }
+
+    fn inline_block(&mut self, block: &mut [Stmt]) {
+        for stmt in block {
+            match *stmt {
+                Stmt::Assign { ref mut value, .. }
+                | Stmt::AssignValues { ref mut value, .. }
+                | Stmt::Return(ref mut value)
+                | Stmt::Print(ref mut value)
+                | Stmt::Assert(ref mut value)
+                | Stmt::Halt(ref mut value)
+                | Stmt::Call(ref mut value) => self.inline_expr(value),
+                Stmt::ReturnValues(ref mut values) => {
+                    for value in values {
+                        self.inline_expr(value);
+                    }
+                }
+                Stmt::If { ref mut condition, ref mut then, ref mut otherwise } => {
+                    self.inline_expr(condition);
+                    self.inline_block(then);
+                    self.inline_block(otherwise);
+                }
+                Stmt::While { ref mut condition, ref mut body } => {
+                    self.inline_expr(condition);
+                    self.inline_block(body);
+                }
+                Stmt::Case { ref mut subject, ref mut arms, ref mut otherwise } => {
+                    self.inline_expr(subject);
+                    for arm in arms {
+                        self.inline_block(&mut arm.1);
+                    }
+                    if let Some(ref mut otherwise) = *otherwise {
+                        self.inline_block(otherwise);
+                    }
+                }
+                Stmt::Block(ref mut body) => self.inline_block(body),
+                Stmt::Store { ref mut place, ref mut value } => {
+                    match *place {
+                        Place::Memory(ref mut address) => self.inline_expr(address),
+                        Place::Element(_, ref mut index) => self.inline_expr(index),
+                        Place::Field(ref mut record, _) => self.inline_expr(record),
+                    }
+                    self.inline_expr(value);
+                }
+            }
+        }
+    }
+
+    /// Inlines the calls in an expression's operands first, so a call
+    /// that's an argument to another one can make that one's arguments
+    /// simple enough.
+    fn inline_expr(&mut self, expr: &mut Expr) {
+        match expr.kind {
+            ExprKind::Int(..)
+            | ExprKind::Float(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Str(_)
+            | ExprKind::Address(_)
+            | ExprKind::Variable(_)
+            | ExprKind::Read => (),
+            ExprKind::Call(_, ref mut arguments) => {
+                for argument in arguments {
+                    self.inline_expr(argument);
+                }
+            }
+            ExprKind::CallIndirect(ref mut callee, ref mut arguments) => {
+                self.inline_expr(callee);
+                for argument in arguments {
+                    self.inline_expr(argument);
+                }
+            }
+            ExprKind::Binary(_, ref mut left, ref mut right) => {
+                self.inline_expr(left);
+                self.inline_expr(right);
+            }
+            ExprKind::Load(ref mut inner)
+            | ExprKind::Index(_, ref mut inner)
+            | ExprKind::Field(ref mut inner, _)
+            | ExprKind::View(_, ref mut inner) => self.inline_expr(inner),
+            ExprKind::Conditional(ref mut condition, ref mut then_value, ref mut else_value) => {
+                self.inline_expr(condition);
+                self.inline_expr(then_value);
+                self.inline_expr(else_value);
+            }
+        }
+
+        if let Some(mut inlined) = self.inlined(expr) {
+            // The function's body can have calls worth inlining too, now
+            // that its parameters are the arguments.
+            self.inline_expr(&mut inlined);
+            if self.model.prefer_inline(expr, &inlined) {
+                *expr = inlined;
+            }
+        }
+    }
+
+    /// What a call would be, inlined, if it can be.
+    fn inlined(&self, expr: &Expr) -> Option<Expr> {
+        let (name, arguments) = match expr.kind {
+            ExprKind::Call(ref name, ref arguments) => (name, arguments),
+            _ => return None,
+        };
+        let function = self.inlinable.iter().find(|function| function.name == *name)?;
+        let simple = |argument: &Expr| match argument.kind {
+            ExprKind::Int(..) | ExprKind::Float(_) | ExprKind::Bool(_) => true,
+            ExprKind::Variable(ref name) => self.locals.contains(name),
+            _ => false,
+        };
+        if !arguments.iter().all(simple) {
+            return None;
+        }
+
+        let mut inlined = function.result.clone();
+        substitute(&mut inlined, &function.params, arguments);
+        Some(inlined)
+    }
+}
+
+/// Replaces the parameters in an expression with the arguments.
+fn substitute(expr: &mut Expr, params: &[String], arguments: &[Expr]) {
+    match expr.kind {
+        ExprKind::Int(..)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::Address(_)
+        | ExprKind::Read => (),
+        ExprKind::Variable(_) => {
+            let param = match expr.kind {
+                ExprKind::Variable(ref name) => params.iter().position(|param| param == name),
+                _ => None,
+            };
+            expr.kind = arguments[param.expect("inlined a function that reads more than its parameters")].kind.clone();
+        }
+        ExprKind::Call(_, ref mut arguments_in) => {
+            for argument in arguments_in {
+                substitute(argument, params, arguments);
+            }
+        }
+        ExprKind::CallIndirect(ref mut callee, ref mut arguments_in) => {
+            substitute(callee, params, arguments);
+            for argument in arguments_in {
+                substitute(argument, params, arguments);
+            }
+        }
+        ExprKind::Binary(_, ref mut left, ref mut right) => {
+            substitute(left, params, arguments);
+            substitute(right, params, arguments);
+        }
+        ExprKind::Load(ref mut inner)
+        | ExprKind::Index(_, ref mut inner)
+        | ExprKind::Field(ref mut inner, _)
+        | ExprKind::View(_, ref mut inner) => substitute(inner, params, arguments),
+        ExprKind::Conditional(ref mut condition, ref mut then_value, ref mut else_value) => {
+            substitute(condition, params, arguments);
+            substitute(then_value, params, arguments);
+            substitute(else_value, params, arguments);
+        }
+    }
+}
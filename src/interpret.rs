@@ -0,0 +1,1341 @@
+//! Runs a program without compiling it, by walking the core language. It
+//! does what the generated code would do when run by a host like the
+//! browser loader: `print` writes a line to the output, and `read` reads
+//! one from the input. Linear memory is laid out exactly as code generation
+//! lays it out, so programs that look at addresses see the same ones.
+//!
+//! The program has to have passed type checking. The `init` blocks run, then
+//! `main`; event handlers are there for the host to call, and there's no
+//! host here.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Write};
+use std::mem;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use builtins;
+use codegen;
+use codegen::BoundsCheck;
+use consteval;
+use consteval::Value;
+use hir::{Array, BinaryOp, Expr, ExprKind, FuncType, Function, Place, Program, Span, Stmt, Type};
+use layout;
+use layout::Layout;
+use profile::Counter;
+use {Options, STACK_SIZE};
+
+/// How much stack the program's calls can take before we give up, the way
+/// a wasm engine runs out of stack. An engine's limit is in bytes too, so
+/// calls with bigger frames run out sooner in both. The interpreter runs on
+/// a thread with `STACK_SIZE` of stack, and the rest of it is for whatever
+/// the deepest call is in the middle of.
+const STACK_BUDGET: usize = STACK_SIZE - 32 * 1024 * 1024;
+
+/// What a call that runs out of stack traps with. Wasmtime says the same.
+pub const STACK_EXHAUSTED: &str = "call stack exhausted";
+
+/// Something that stopped the program: where the generated code would trap,
+/// or where the interpreter couldn't go on.
+pub struct Trap {
+    pub span: Span,
+    pub message: String,
+
+    // The status, if this is a `halt` rather than anything going wrong.
+    // Running the program catches it, so it's never handed back.
+    halted: Option<i32>,
+
+    // Whether it stopped because whatever was reading the output went away,
+    // like `head` does once it has enough. That's no fault of the program's,
+    // so there's nothing to report.
+    pub output_closed: bool,
+}
+
+impl Trap {
+    fn new(span: Span, message: &str) -> Trap {
+        Trap {
+            span,
+            message: message.to_string(),
+            halted: None,
+            output_closed: false,
+        }
+    }
+
+    fn halt(span: Span, status: i32) -> Trap {
+        Trap {
+            span,
+            message: String::from("halted"),
+            halted: Some(status),
+            output_closed: false,
+        }
+    }
+
+    /// Stops the program because printing failed.
+    fn output(span: Span, err: io::Error) -> Trap {
+        Trap {
+            span,
+            message: format!("couldn't write the output: {}", err),
+            halted: None,
+            output_closed: err.kind() == io::ErrorKind::BrokenPipe,
+        }
+    }
+}
+
+/// What running a statement did to the rest of the function.
+enum Flow {
+    Next,
+    Return(Value),
+
+    // `return a, b`, from a function that returns more than one value.
+    ReturnValues(Vec<Value>),
+
+    // A `return` of a call, with --tail-calls. Whoever made the call that
+    // got us here makes this one instead, so it doesn't go any deeper.
+    TailCall(Span, String, Vec<Value>),
+}
+
+/// The variables of a function that's running. Every local exists from the
+/// start of the function, as 0, like wasm locals do.
+#[derive(Default)]
+struct Frame {
+    variables: Vec<(String, Value)>,
+
+    // The `var` parameters, with the address of their value and its type.
+    references: Vec<(String, u64, Type)>,
+}
+
+impl Frame {
+    fn variable(&mut self, name: &str) -> Option<&mut Value> {
+        self.variables
+            .iter_mut()
+            .find(|&&mut (ref variable, _)| variable == name)
+            .map(|&mut (_, ref mut value)| value)
+    }
+
+    fn reference(&self, name: &str) -> Option<(u64, Type)> {
+        self.references
+            .iter()
+            .find(|&(reference, _, _)| reference == name)
+            .map(|&(_, address, ty)| (address, ty))
+    }
+}
+
+/// Everything a run keeps outside of the functions that are running. A REPL
+/// holds on to this between inputs, each of which is the program so far with
+/// more added to the end, and only runs what's new.
+#[derive(Default)]
+pub struct State {
+    // Globals and constants, by name.
+    globals: Vec<(String, Value)>,
+    constants: Vec<(String, Value)>,
+
+    // Where arrays and strings are, and the memory they're in.
+    layout: Layout,
+    memory: Vec<u8>,
+
+    // How many strings have been copied into memory.
+    strings: usize,
+
+    // The globals passed to `var` parameters, which are in memory, with
+    // their addresses and types.
+    homes: Vec<(String, u64, Type)>,
+
+    // Where the variables of the init blocks and main that are passed to
+    // `var` parameters are.
+    init_homes: Vec<(String, u64)>,
+    main_homes: Vec<(String, u64)>,
+
+    // The bottom of the stack functions' frames go on, once a function has
+    // a `var` parameter, and where its top is now.
+    stack: Option<u32>,
+    stack_pointer: u32,
+
+    // Main's variables, which outlive any one run of it.
+    main: Frame,
+
+    // How many statements of the init blocks and main have run.
+    init_run: usize,
+    main_run: usize,
+}
+
+pub struct Interpreter<'a> {
+    options: &'a Options,
+    program: &'a Program,
+    state: State,
+
+    // Where the stack was when the program started running, for working
+    // out how much of it the program's calls have taken.
+    stack_base: usize,
+
+    // With --metering, how many more calls and times round a loop there can
+    // be, like the generated code's fuel.
+    fuel: i32,
+
+    // With --profile, the calls to each function and times round each loop,
+    // like the generated code's `$profile` globals.
+    profile: HashMap<Counter, u64>,
+
+    // Where `random` is up to. It's xorshift, seeded with the time, since
+    // there's no host to ask.
+    random: u64,
+
+    // When `clock` started counting from.
+    started: Instant,
+
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(
+        options: &'a Options,
+        program: &'a Program,
+        input: &'a mut dyn BufRead,
+        output: &'a mut dyn Write,
+    ) -> Interpreter<'a> {
+        Interpreter::resume(options, program, State::default(), input, output)
+    }
+
+    /// Makes an interpreter that carries on from `state`, which an earlier
+    /// one left behind, with `program` being what that one ran with more
+    /// on the end.
+    pub fn resume(
+        options: &'a Options,
+        program: &'a Program,
+        state: State,
+        input: &'a mut dyn BufRead,
+        output: &'a mut dyn Write,
+    ) -> Interpreter<'a> {
+        Interpreter {
+            options,
+            program,
+            state,
+            stack_base: 0,
+            fuel: codegen::FUEL,
+            profile: HashMap::new(),
+            random: seed(),
+            started: Instant::now(),
+            input,
+            output,
+        }
+    }
+
+    /// With --profile, what each counter counted, in no particular order.
+    pub fn profile(&self) -> Vec<(Counter, u64)> {
+        self.profile.iter().map(|(counter, &count)| (counter.clone(), count)).collect()
+    }
+
+    /// Gives back the state, for resuming later.
+    pub fn into_state(self) -> State {
+        self.state
+    }
+
+    /// Runs the program and returns what `main` returns.
+    pub fn run(&mut self) -> Result<i32, Trap> {
+        match self.run_new()? {
+            Some(Value::I32(result)) => Ok(result),
+            None => Ok(0),
+            _ => panic!("main didn't return an i32"),
+        }
+    }
+
+    /// Runs everything that's been added to the program since the state was
+    /// new: the constants, arrays, and globals are set up, then the init
+    /// blocks run, then `main`. Returns what `main` returns, if it got to a
+    /// `return`, or the status it halted with.
+    pub fn run_new(&mut self) -> Result<Option<Value>, Trap> {
+        let program = self.program;
+        self.stack_base = stack_address();
+
+        for constant in &program.constants[self.state.constants.len()..] {
+            let value = self.evaluate_constant(&constant.value)?;
+            self.state.constants.push((constant.name.clone(), value));
+        }
+
+        for array in &program.arrays {
+            if self.state.layout.array(&array.name).is_none() {
+                self.define_array(array)?;
+            }
+        }
+        for record in &program.records {
+            self.state.layout.add_record(record);
+        }
+
+        for global in &program.globals[self.state.globals.len()..] {
+            let ty = global.ty.expect("global wasn't type checked");
+            let value = match global.value {
+                Some(ref value) => self.evaluate_constant(value)?,
+                None => Value::zero(ty),
+            };
+            self.state.globals.push((global.name.clone(), value));
+        }
+
+        // Variables passed to `var` parameters that need a fixed place come
+        // next, then the stack for functions' frames, like code generation
+        // puts them.
+        let homed = self.state.homes.len();
+        for global in &program.globals {
+            let homed = self.state.homes.iter().any(|home| home.0 == global.name);
+            if program.global_homes.contains(&global.name) && !homed {
+                let address = self.reserve_home(global.name_span)?;
+                let ty = global.ty.expect("global wasn't type checked");
+                self.state.homes.push((global.name.clone(), address, ty));
+            }
+        }
+        for home in &program.init_homes {
+            if self.state.init_homes.iter().all(|(name, _)| name != home) {
+                let address = self.reserve_home(Self::first_span(&program.init))?;
+                self.state.init_homes.push((home.clone(), address));
+            }
+        }
+        for home in &program.main_homes {
+            if self.state.main_homes.iter().all(|(name, _)| name != home) {
+                let address = self.reserve_home(Self::first_span(&program.main))?;
+                self.state.main_homes.push((home.clone(), address));
+            }
+        }
+
+        let takes_var = program.functions.iter().find(|function| !function.var_params.is_empty());
+        if let (Some(function), None) = (takes_var, self.state.stack) {
+            match self.state.layout.reserve(layout::STACK_SIZE) {
+                Some(bottom) => self.state.stack = Some(bottom),
+                None => return Err(Trap::new(function.name_span, "there's no room in memory for `var` arguments")),
+            }
+        }
+        // A trap in an earlier run could have left things on the stack.
+        if let Some(bottom) = self.state.stack {
+            self.state.stack_pointer = (u64::from(bottom) + layout::STACK_SIZE) as u32;
+        }
+
+        // Strings go in memory in the order code generation comes across
+        // them, which is the order the code is written in. The ones placed
+        // before keep their addresses.
+        for function in program.functions.iter().chain(&program.handlers) {
+            self.place_strings(&function.body);
+        }
+        self.place_strings(&program.init);
+        self.place_strings(&program.main);
+
+        self.grow_memory();
+        let strings = self.state.strings;
+        self.state.strings = self.state.layout.strings().len();
+        for &(ref bytes, address) in &self.state.layout.strings()[strings..] {
+            let address = address as usize;
+            self.state.memory[address..address + bytes.len()].copy_from_slice(bytes);
+        }
+        for (name, address, _) in self.state.homes[homed..].iter().cloned() {
+            let value = self.global(&name).expect("unknown global after type checking");
+            let bytes = value.to_le_bytes();
+            let address = address as usize;
+            self.state.memory[address..address + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        // The init blocks can't return, so there's no value to look at.
+        let init = &program.init[self.state.init_run..];
+        self.state.init_run = program.init.len();
+        if let Some(span) = init.first().and_then(Stmt::span) {
+            self.use_fuel(span)?;
+            self.count(Counter::Init);
+        }
+        let mut frame = Frame::default();
+        self.declare_locals(&mut frame, init);
+        let homes = self.state.init_homes.clone();
+        self.move_homes(&mut frame, &homes);
+        self.run_block(&mut frame, init)?;
+
+        // Main's variables stay in the state, so anything added to it later
+        // can see them.
+        let main = &program.main[self.state.main_run..];
+        self.state.main_run = program.main.len();
+        let mut frame = mem::take(&mut self.state.main);
+        self.declare_locals(&mut frame, main);
+        let homes = self.state.main_homes.clone();
+        self.move_homes(&mut frame, &homes);
+        if let Some(span) = main.first().and_then(Stmt::span) {
+            self.use_fuel(span)?;
+            self.count(Counter::Main);
+        }
+        let flow = self.run_block(&mut frame, main);
+        self.state.main = frame;
+
+        let result = match flow {
+            Ok(Flow::Return(value)) => Ok(Some(value)),
+            Ok(Flow::ReturnValues(_)) => panic!("main returned more than one value"),
+            Ok(Flow::TailCall(span, name, arguments)) => self.call(span, &name, arguments).map(Some),
+            Ok(Flow::Next) => Ok(None),
+            Err(trap) => Err(trap),
+        };
+        // A `halt` anywhere is like main returning the status, which is
+        // all the host sees either way.
+        match result {
+            Err(Trap { halted: Some(status), .. }) => Ok(Some(Value::I32(status))),
+            result => result,
+        }
+    }
+
+    /// Works out the value of a constant, or a global's initial value, which
+    /// can only use literals and constants.
+    fn evaluate_constant(&mut self, expr: &Expr) -> Result<Value, Trap> {
+        match expr.kind {
+            ExprKind::Int(..) => Ok(int_value(expr)),
+            ExprKind::Float(ref num) => Ok(Value::F64(num.parse().expect("invalid float literal"))),
+            ExprKind::Bool(value) => Ok(Value::Bool(value)),
+            ExprKind::Str(ref bytes) => Ok(self.string(bytes)),
+            ExprKind::Variable(ref name) => Ok(self.constant(name).expect("unknown constant after type checking")),
+            ExprKind::Binary(op, ref left, ref right) if op.is_logical() => {
+                let left = consteval::truth(self.evaluate_constant(left)?).expect("condition wasn't type checked");
+                if left == (op == BinaryOp::Or) {
+                    return Ok(Value::Bool(left));
+                }
+                let right = consteval::truth(self.evaluate_constant(right)?).expect("condition wasn't type checked");
+                Ok(Value::Bool(right))
+            }
+            ExprKind::Binary(op, ref left, ref right) => {
+                let left = self.evaluate_constant(left)?;
+                let right = self.evaluate_constant(right)?;
+                binary(expr.span, op, left, right)
+            }
+            ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+                let condition = self.evaluate_constant(condition)?;
+                let value = if consteval::truth(condition).expect("condition wasn't type checked") {
+                    then_value
+                } else {
+                    else_value
+                };
+                self.evaluate_constant(value)
+            }
+            _ => panic!("constant wasn't type checked"),
+        }
+    }
+
+    fn constant(&self, name: &str) -> Option<Value> {
+        self.state
+            .constants
+            .iter()
+            .find(|&(constant, _)| constant == name)
+            .map(|&(_, value)| value)
+    }
+
+    /// Makes room for an array, with the same checks code generation makes.
+    fn define_array(&mut self, array: &Array) -> Result<(), Trap> {
+        let len = match self.evaluate_constant(&array.len)? {
+            Value::I32(len) if len < 0 => return Err(Trap::new(array.len.span, "array length can't be negative")),
+            Value::I32(len) => len as u32,
+            _ => panic!("array length wasn't type checked"),
+        };
+
+        match self.state.layout.add_array(&array.name, len) {
+            Some(_) => Ok(()),
+            None => Err(Trap::new(array.len.span, "array doesn't fit in memory")),
+        }
+    }
+
+    /// Puts a string's bytes in the layout, unless they're already there,
+    /// and returns the string.
+    fn string(&mut self, bytes: &[u8]) -> Value {
+        Value::Str {
+            address: self.state.layout.add_string(bytes),
+            len: bytes.len() as u32,
+        }
+    }
+
+    /// Places every string literal in a block, in the order code generation
+    /// would compile them.
+    fn place_strings(&mut self, block: &[Stmt]) {
+        for stmt in block {
+            match *stmt {
+                Stmt::Assign { ref value, .. }
+                | Stmt::AssignValues { ref value, .. }
+                | Stmt::Return(ref value)
+                | Stmt::Print(ref value)
+                | Stmt::Assert(ref value)
+                | Stmt::Halt(ref value)
+                | Stmt::Call(ref value) => self.place_strings_in(value),
+                Stmt::ReturnValues(ref values) => {
+                    for value in values {
+                        self.place_strings_in(value);
+                    }
+                }
+                Stmt::If { ref condition, ref then, ref otherwise } => {
+                    self.place_strings_in(condition);
+                    self.place_strings(then);
+                    self.place_strings(otherwise);
+                }
+                Stmt::While { ref condition, ref body } => {
+                    self.place_strings_in(condition);
+                    self.place_strings(body);
+                }
+                Stmt::Case { ref subject, ref arms, ref otherwise } => {
+                    self.place_strings_in(subject);
+                    for arm in arms {
+                        self.place_strings(&arm.1);
+                    }
+                    if let Some(ref otherwise) = *otherwise {
+                        self.place_strings(otherwise);
+                    }
+                }
+                Stmt::Block(ref body) => self.place_strings(body),
+                Stmt::Store { ref place, ref value } => {
+                    match *place {
+                        Place::Memory(ref address) => self.place_strings_in(address),
+                        Place::Element(_, ref index) => self.place_strings_in(index),
+                        Place::Field(ref record, _) => self.place_strings_in(record),
+                    }
+                    self.place_strings_in(value);
+                }
+            }
+        }
+    }
+
+    fn place_strings_in(&mut self, expr: &Expr) {
+        match expr.kind {
+            ExprKind::Int(..)
+            | ExprKind::Float(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Variable(_)
+            | ExprKind::Address(_)
+            | ExprKind::Read => (),
+            ExprKind::Str(ref bytes) => {
+                self.string(bytes);
+            }
+            ExprKind::Call(_, ref arguments) | ExprKind::CallIndirect(_, ref arguments) => {
+                for argument in arguments {
+                    self.place_strings_in(argument);
+                }
+            }
+            ExprKind::Binary(_, ref left, ref right) => {
+                self.place_strings_in(left);
+                self.place_strings_in(right);
+            }
+            ExprKind::Load(ref address) | ExprKind::View(_, ref address) => self.place_strings_in(address),
+            ExprKind::Index(_, ref index) => self.place_strings_in(index),
+            ExprKind::Field(ref record, _) => self.place_strings_in(record),
+            ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+                self.place_strings_in(condition);
+                self.place_strings_in(then_value);
+                self.place_strings_in(else_value);
+            }
+        }
+    }
+
+    /// Runs a function's body with its parameters set to `arguments`, and
+    /// returns how it ended: with a `return`, a tail call, or by running off
+    /// the end. The argument for a `var` parameter is the address of its
+    /// value. Like in the generated code, the variables it passes to `var`
+    /// parameters are in a frame on the `var` stack while it runs.
+    fn call_body(&mut self, span: Span, function: &Function, arguments: Vec<Value>) -> Result<Flow, Trap> {
+        let mut frame = Frame::default();
+        for (&(ref name, ty), value) in function.params.iter().zip(arguments) {
+            match value {
+                Value::I32(address) if function.var_params.contains(name) => {
+                    frame.references.push((name.clone(), u64::from(address as u32), ty))
+                }
+                value => frame.variables.push((name.clone(), value)),
+            }
+        }
+        self.declare_locals(&mut frame, &function.body);
+
+        let size = function.homes.len() as u32 * layout::CELL_SIZE;
+        if size > 0 {
+            let bottom = self.state.stack.expect("no var stack for a function with var parameters");
+            if self.state.stack_pointer < bottom + size {
+                return Err(Trap::new(span, "out of room for `var` arguments"));
+            }
+            self.state.stack_pointer -= size;
+        }
+        let cells = (u64::from(self.state.stack_pointer)..).step_by(layout::CELL_SIZE as usize);
+        let homes: Vec<(String, u64)> = function.homes.iter().cloned().zip(cells).collect();
+        self.move_homes(&mut frame, &homes);
+
+        let flow = self.run_block(&mut frame, &function.body);
+        self.state.stack_pointer += size;
+        flow
+    }
+
+    /// Moves the variables in `homes` out of the frame and into memory, at
+    /// the addresses that go with them, where reads and assignments go from
+    /// then on. Ones that have already moved stay where they are.
+    fn move_homes(&mut self, frame: &mut Frame, homes: &[(String, u64)]) {
+        for &(ref home, address) in homes {
+            let position = frame.variables.iter().position(|(variable, _)| variable == home);
+            if let Some(position) = position {
+                let (name, value) = frame.variables.remove(position);
+                let bytes = value.to_le_bytes();
+                let address = address as usize;
+                self.state.memory[address..address + bytes.len()].copy_from_slice(&bytes);
+                frame.references.push((name, address as u64, value.ty()));
+            }
+        }
+    }
+
+    /// Makes room in the layout for a variable that's passed to `var`
+    /// parameters, and has a fixed place. `span` is for the error if there
+    /// isn't any.
+    fn reserve_home(&mut self, span: Span) -> Result<u64, Trap> {
+        match self.state.layout.reserve(u64::from(layout::CELL_SIZE)) {
+            Some(address) => Ok(u64::from(address)),
+            None => Err(Trap::new(span, "there's no room in memory for the variables passed to `var` parameters")),
+        }
+    }
+
+    /// The span of the first statement in a block that has one.
+    fn first_span(block: &[Stmt]) -> Span {
+        block.iter().find_map(Stmt::span).expect("nothing's passed to `var` parameters")
+    }
+
+    /// Adds every variable a block assigns to the frame, unless it's a
+    /// global. Code generation declares a local at the first assignment to
+    /// anything that isn't a parameter or global, and so do we.
+    fn declare_locals(&self, frame: &mut Frame, block: &[Stmt]) {
+        for stmt in block {
+            match *stmt {
+                Stmt::Assign { ref name, ty, .. } => {
+                    if frame.variable(name).is_none() && frame.reference(name).is_none() && self.global(name).is_none() {
+                        let ty = ty.expect("assignment wasn't type checked");
+                        frame.variables.push((name.clone(), Value::zero(ty)));
+                    }
+                }
+                Stmt::AssignValues { ref names, ref types, .. } => {
+                    for (name, &ty) in names.iter().zip(types) {
+                        if frame.variable(name).is_none() && frame.reference(name).is_none() && self.global(name).is_none() {
+                            frame.variables.push((name.clone(), Value::zero(ty)));
+                        }
+                    }
+                }
+                Stmt::If { ref then, ref otherwise, .. } => {
+                    self.declare_locals(frame, then);
+                    self.declare_locals(frame, otherwise);
+                }
+                Stmt::While { ref body, .. } | Stmt::Block(ref body) => self.declare_locals(frame, body),
+                Stmt::Case { ref arms, ref otherwise, .. } => {
+                    for arm in arms {
+                        self.declare_locals(frame, &arm.1);
+                    }
+                    if let Some(ref otherwise) = *otherwise {
+                        self.declare_locals(frame, otherwise);
+                    }
+                }
+                Stmt::Return(_)
+                | Stmt::ReturnValues(_)
+                | Stmt::Print(_)
+                | Stmt::Assert(_)
+                | Stmt::Halt(_)
+                | Stmt::Call(_)
+                | Stmt::Store { .. } => (),
+            }
+        }
+    }
+
+    fn global(&self, name: &str) -> Option<Value> {
+        self.state
+            .globals
+            .iter()
+            .find(|&(global, _)| global == name)
+            .map(|&(_, value)| value)
+    }
+
+    fn run_block(&mut self, frame: &mut Frame, block: &[Stmt]) -> Result<Flow, Trap> {
+        for stmt in block {
+            match self.run_stmt(frame, stmt)? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn run_stmt(&mut self, frame: &mut Frame, stmt: &Stmt) -> Result<Flow, Trap> {
+        match *stmt {
+            Stmt::Assign { ref name, ref value, .. } => {
+                let span = value.span;
+                let value = self.evaluate(frame, value)?;
+                self.assign(frame, span, name, value)?;
+            }
+            Stmt::AssignValues { ref names, ref value, .. } => {
+                let values = match value.kind {
+                    ExprKind::Call(ref name, ref arguments) if self.takes_var(name) => {
+                        self.call_with_var(frame, value.span, name, arguments)?
+                    }
+                    ExprKind::Call(ref name, ref arguments) => {
+                        let mut values = Vec::new();
+                        for argument in arguments {
+                            values.push(self.evaluate(frame, argument)?);
+                        }
+                        self.call_all(value.span, name, values)?
+                    }
+                    _ => panic!("assigned values weren't type checked"),
+                };
+                for (name, result) in names.iter().zip(values) {
+                    self.assign(frame, value.span, name, result)?;
+                }
+            }
+            Stmt::If { ref condition, ref then, ref otherwise } => {
+                let block = if self.condition(frame, condition)? { then } else { otherwise };
+                return self.run_block(frame, block);
+            }
+            Stmt::While { ref condition, ref body } => {
+                while self.condition(frame, condition)? {
+                    self.count(Counter::Loop(condition.span));
+                    match self.run_block(frame, body)? {
+                        Flow::Next => self.use_fuel(condition.span)?,
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            Stmt::Case { ref subject, ref arms, ref otherwise } => {
+                let subject = self.evaluate(frame, subject)?;
+                for (values, body) in arms {
+                    for value in values {
+                        if self.evaluate(frame, value)? == subject {
+                            return self.run_block(frame, body);
+                        }
+                    }
+                }
+                if let Some(ref otherwise) = *otherwise {
+                    return self.run_block(frame, otherwise);
+                }
+            }
+            Stmt::Block(ref body) => return self.run_block(frame, body),
+            Stmt::Return(Expr { kind: ExprKind::Call(ref name, ref arguments), span }) if self.is_tail_call(name) => {
+                let mut values = Vec::new();
+                for argument in arguments {
+                    values.push(self.evaluate(frame, argument)?);
+                }
+                return Ok(Flow::TailCall(span, name.clone(), values));
+            }
+            Stmt::Return(Expr { kind: ExprKind::CallIndirect(ref callee, ref arguments), span }) if self.options.tail_calls => {
+                let (function, values) = self.resolve_pointer_call(frame, span, callee, arguments)?;
+                return Ok(Flow::TailCall(span, function.to_string(), values));
+            }
+            Stmt::Return(ref value) => return Ok(Flow::Return(self.evaluate(frame, value)?)),
+            Stmt::ReturnValues(ref values) => {
+                let mut results = Vec::new();
+                for value in values {
+                    results.push(self.evaluate(frame, value)?);
+                }
+                return Ok(Flow::ReturnValues(results));
+            }
+            Stmt::Print(ref value) => {
+                let span = value.span;
+                let value = self.evaluate(frame, value)?;
+                self.print(value).map_err(|err| Trap::output(span, err))?;
+            }
+            Stmt::Call(ref value) => {
+                self.evaluate(frame, value)?;
+            }
+            Stmt::Assert(ref condition) => {
+                if !self.condition(frame, condition)? {
+                    return Err(Trap::new(condition.span, "assertion failed"));
+                }
+            }
+            Stmt::Halt(ref status) => match self.evaluate(frame, status)? {
+                Value::I32(code) => return Err(Trap::halt(status.span, code)),
+                _ => panic!("halt status wasn't type checked"),
+            },
+            Stmt::Store { ref place, ref value } => {
+                let (address, span) = match *place {
+                    Place::Memory(ref address) => (self.address(frame, address)?, address.span),
+                    Place::Element(ref array, ref index) => (self.element(frame, array, index)?, index.span),
+                    Place::Field(ref record, ref field) => (self.field(frame, record, field)?.0, record.span),
+                };
+                let value = self.evaluate(frame, value)?;
+                self.store_value(span, address, value)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Sets a variable: a local, the value a `var` parameter is the address
+    /// of, or else a global.
+    fn assign(&mut self, frame: &mut Frame, span: Span, name: &str, value: Value) -> Result<(), Trap> {
+        if let Some((address, _)) = frame.reference(name) {
+            return self.store_value(span, address, value);
+        }
+        match frame.variable(name) {
+            Some(variable) => *variable = value,
+            None => match self.global_home(name) {
+                Some((address, _)) => return self.store_value(span, address, value),
+                None => self.set_global(name, value),
+            },
+        }
+        Ok(())
+    }
+
+    /// The address and type of a global that's in memory, because it's
+    /// passed to `var` parameters.
+    fn global_home(&self, name: &str) -> Option<(u64, Type)> {
+        self.state
+            .homes
+            .iter()
+            .find(|&(home, _, _)| home == name)
+            .map(|&(_, address, ty)| (address, ty))
+    }
+
+    fn set_global(&mut self, name: &str, value: Value) {
+        let global = self
+            .state
+            .globals
+            .iter_mut()
+            .find(|&&mut (ref global, _)| global == name)
+            .expect("unknown variable after type checking");
+        global.1 = value;
+    }
+
+    /// Works out whether a condition is true. Integers are true if they
+    /// aren't zero.
+    fn condition(&mut self, frame: &mut Frame, condition: &Expr) -> Result<bool, Trap> {
+        match self.evaluate(frame, condition)? {
+            Value::Bool(value) => Ok(value),
+            Value::I32(value) => Ok(value != 0),
+            Value::I64(value) => Ok(value != 0),
+            _ => panic!("condition wasn't type checked"),
+        }
+    }
+
+    /// Writes a value to the output on a line of its own. Bools print as 1
+    /// or 0, like the generated code passes them to the host.
+    fn print(&mut self, value: Value) -> io::Result<()> {
+        match value {
+            Value::Bool(value) => writeln!(self.output, "{}", value as i32),
+            Value::Str { address, len } => {
+                let start = address as usize;
+                let bytes = &self.state.memory[start..start + len as usize];
+                self.output.write_all(bytes).and_then(|()| writeln!(self.output))
+            }
+            value => writeln!(self.output, "{}", value),
+        }
+    }
+
+    fn evaluate(&mut self, frame: &mut Frame, expr: &Expr) -> Result<Value, Trap> {
+        match expr.kind {
+            ExprKind::Int(..) => Ok(int_value(expr)),
+            ExprKind::Float(ref num) => Ok(Value::F64(num.parse().expect("invalid float literal"))),
+            ExprKind::Bool(value) => Ok(Value::Bool(value)),
+            ExprKind::Str(ref bytes) => Ok(self.string(bytes)),
+            ExprKind::Variable(ref name) if frame.reference(name).is_some() => {
+                let (address, ty) = frame.reference(name).unwrap();
+                self.load_value(expr.span, address, ty)
+            }
+            ExprKind::Variable(ref name) if frame.variable(name).is_none() && self.global_home(name).is_some() => {
+                let (address, ty) = self.global_home(name).unwrap();
+                self.load_value(expr.span, address, ty)
+            }
+            ExprKind::Variable(ref name) => match frame.variable(name) {
+                Some(&mut value) => Ok(value),
+                None => Ok(self
+                    .global(name)
+                    .or_else(|| self.constant(name))
+                    .expect("unknown variable after type checking")),
+            },
+            ExprKind::Call(ref name, ref arguments) if self.takes_var(name) => {
+                Ok(self.call_with_var(frame, expr.span, name, arguments)?[0])
+            }
+            ExprKind::Call(ref name, ref arguments) => {
+                let mut values = Vec::new();
+                for argument in arguments {
+                    values.push(self.evaluate(frame, argument)?);
+                }
+                self.call(expr.span, name, values)
+            }
+            ExprKind::Address(ref name) => {
+                let program = self.program;
+                let function = program
+                    .functions
+                    .iter()
+                    .find(|function| function.name == *name)
+                    .expect("unknown function after type checking");
+                let index = program.table.iter().position(|function| function == name);
+                let index = index.expect("function isn't in the table");
+                let params = function.params.iter().map(|&(_, ty)| ty).collect();
+                Ok(Value::Func(FuncType::new(params, function.result), index as u32 + 1))
+            }
+            ExprKind::CallIndirect(ref callee, ref arguments) => {
+                let (function, values) = self.resolve_pointer_call(frame, expr.span, callee, arguments)?;
+                self.call(expr.span, function, values)
+            }
+            ExprKind::Binary(op, ref left, ref right) if op.is_logical() => {
+                let left = self.condition(frame, left)?;
+                if left == (op == BinaryOp::Or) {
+                    return Ok(Value::Bool(left));
+                }
+                Ok(Value::Bool(self.condition(frame, right)?))
+            }
+            ExprKind::Binary(op, ref left, ref right) => {
+                let left = self.evaluate(frame, left)?;
+                let right = self.evaluate(frame, right)?;
+                binary(expr.span, op, left, right)
+            }
+            ExprKind::Load(ref address) => {
+                let address = self.address(frame, address)?;
+                self.load(expr.span, address)
+            }
+            ExprKind::Field(ref record, ref field) => {
+                let (address, ty) = self.field(frame, record, field)?;
+                self.load_value(expr.span, address, ty)
+            }
+            ExprKind::View(record, ref address) => {
+                let address = self.address(frame, address)?;
+                Ok(Value::Record(record, address as u32))
+            }
+            ExprKind::Index(ref array, ref index) => {
+                let address = self.element(frame, array, index)?;
+                self.load(expr.span, address)
+            }
+            ExprKind::Read => self.read(expr.span),
+            ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+                let value = if self.condition(frame, condition)? { then_value } else { else_value };
+                self.evaluate(frame, value)
+            }
+        }
+    }
+
+    /// Calls a function that returns one value.
+    fn call(&mut self, span: Span, name: &str, arguments: Vec<Value>) -> Result<Value, Trap> {
+        Ok(self.call_all(span, name, arguments)?[0])
+    }
+
+    /// Calls a function the program defines, or else a builtin, and returns
+    /// every value it returns. Running off the end returns 0s. The tail
+    /// calls it ends with are made from here, at the same depth.
+    fn call_all(&mut self, span: Span, name: &str, arguments: Vec<Value>) -> Result<Vec<Value>, Trap> {
+        let program = self.program;
+        let mut function: &Function = match program.functions.iter().find(|function| function.name == name) {
+            Some(function) => function,
+            None => {
+                let builtin = builtins::lookup(name).expect("unknown function after type checking");
+                // The compiler won't take a lane that's out of range, so
+                // this can't happen to a program that compiles.
+                let in_range = match arguments.last() {
+                    Some(&Value::I32(lane)) => (0..builtins::LANES).contains(&lane),
+                    _ => false,
+                };
+                if builtin.lane && !in_range {
+                    return Err(Trap::new(span, "lane out of range"));
+                }
+                return match (builtin.evaluate, builtin.runtime) {
+                    (Some(evaluate), _) => Ok(vec![evaluate(&arguments)]),
+                    (None, Some(runtime)) => Ok(vec![self.run_runtime(span, runtime, &arguments)?]),
+                    (None, None) if builtin.is_atomic() => Ok(vec![self.run_atomic(span, name, &arguments)?]),
+                    (None, None) => panic!("builtin `{}` can't be run", name),
+                };
+            }
+        };
+
+        if stack_address().abs_diff(self.stack_base) > STACK_BUDGET {
+            return Err(Trap::new(span, STACK_EXHAUSTED));
+        }
+        let mut arguments = arguments;
+        let result = loop {
+            if let Err(trap) = self.use_fuel(span) {
+                break Err(trap);
+            }
+            self.count(Counter::Call(function.name.clone()));
+            match self.call_body(span, function, arguments) {
+                Ok(Flow::TailCall(_, name, next)) => {
+                    function = program
+                        .functions
+                        .iter()
+                        .find(|function| function.name == name)
+                        .expect("unknown function after type checking");
+                    arguments = next;
+                }
+                Ok(Flow::Return(value)) => break Ok(vec![value]),
+                Ok(Flow::ReturnValues(values)) => break Ok(values),
+                Ok(Flow::Next) => {
+                    let mut results = vec![Value::zero(function.result)];
+                    results.extend(function.more_results.iter().map(|&ty| Value::zero(ty)));
+                    break Ok(results);
+                }
+                Err(trap) => break Err(trap),
+            }
+        };
+        result
+    }
+
+    /// Works out the arguments of a call through a pointer, then the
+    /// pointer, and finds the function it points to, trapping where
+    /// `call_indirect` would: if the pointer's null, or the function there
+    /// takes or returns different wasm types than the pointer says.
+    fn resolve_pointer_call(
+        &mut self,
+        frame: &mut Frame,
+        span: Span,
+        callee: &Expr,
+        arguments: &[Expr],
+    ) -> Result<(&'a str, Vec<Value>), Trap> {
+        let mut values = Vec::new();
+        for argument in arguments {
+            values.push(self.evaluate(frame, argument)?);
+        }
+        let (func, index) = match self.evaluate(frame, callee)? {
+            Value::Func(func, index) => (func, index as usize),
+            _ => panic!("call through something that isn't a pointer"),
+        };
+
+        let program = self.program;
+        let name = match index.checked_sub(1).and_then(|index| program.table.get(index)) {
+            Some(name) => name,
+            None if index == 0 => return Err(Trap::new(span, "uninitialized element")),
+            None => return Err(Trap::new(span, "undefined element")),
+        };
+        let function = program
+            .functions
+            .iter()
+            .find(|function| function.name == *name)
+            .expect("unknown function in the table");
+
+        let wasm_types = |types: &[Type]| types.iter().map(|ty| ty.wasm_name()).collect::<Vec<_>>();
+        let params: Vec<Type> = function.params.iter().map(|&(_, ty)| ty).collect();
+        if wasm_types(&params) != wasm_types(&func.params()) || function.result.wasm_name() != func.result().wasm_name() {
+            return Err(Trap::new(span, "indirect call type mismatch"));
+        }
+        Ok((name, values))
+    }
+
+    /// Whether a `return` of a call to `name` is a tail call. It is with
+    /// --tail-calls, when code generation would make it a `return_call`.
+    fn is_tail_call(&self, name: &str) -> bool {
+        self.options.tail_calls
+            && !self.takes_var(name)
+            && self.program.functions.iter().any(|function| function.name == name)
+    }
+
+    /// Whether `name` is a function the program defines with a `var`
+    /// parameter.
+    fn takes_var(&self, name: &str) -> bool {
+        self.program
+            .functions
+            .iter()
+            .any(|function| function.name == name && !function.var_params.is_empty())
+    }
+
+    /// Calls a function with `var` parameters the way the generated code
+    /// does: a variable passed to one is passed by its address, which it
+    /// has because it's passed to one, and so is an array element.
+    fn call_with_var(&mut self, frame: &mut Frame, span: Span, name: &str, arguments: &[Expr]) -> Result<Vec<Value>, Trap> {
+        let program = self.program;
+        let function = program
+            .functions
+            .iter()
+            .find(|function| function.name == name)
+            .expect("unknown function after type checking");
+
+        let mut values = Vec::new();
+        for (argument, (param, _)) in arguments.iter().zip(&function.params) {
+            let by_reference = function.var_params.contains(param);
+            let value = match argument.kind {
+                ExprKind::Variable(ref variable) if by_reference => {
+                    let (address, _) = frame
+                        .reference(variable)
+                        .or_else(|| self.global_home(variable))
+                        .expect("variable passed to a `var` parameter isn't in memory");
+                    Value::I32(address as i32)
+                }
+                ExprKind::Index(ref array, ref index) if by_reference => {
+                    Value::I32(self.element(frame, array, index)? as i32)
+                }
+                _ => self.evaluate(frame, argument)?,
+            };
+            values.push(value);
+        }
+        self.call_all(span, name, values)
+    }
+
+    /// Reads an i32 from a line of input.
+    fn read(&mut self, span: Span) -> Result<Value, Trap> {
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) => Err(Trap::new(span, "there's nothing left to read")),
+            Ok(_) => match line.trim().parse() {
+                Ok(value) => Ok(Value::I32(value)),
+                Err(_) => Err(Trap::new(span, &format!("expected an i32 to read, found `{}`", line.trim()))),
+            },
+            Err(err) => Err(Trap::new(span, &format!("could not read input: {}", err))),
+        }
+    }
+
+    /// Works out a `mem[address]` address. Addresses are unsigned.
+    fn address(&mut self, frame: &mut Frame, address: &Expr) -> Result<u64, Trap> {
+        match self.evaluate(frame, address)? {
+            Value::I32(address) => Ok(u64::from(address as u32)),
+            Value::I64(address) => Ok(address as u64),
+            _ => panic!("address wasn't type checked"),
+        }
+    }
+
+    /// Works out the address and type of a record's field. Like a load's
+    /// offset, adding the field's offset doesn't wrap. With --gc, records
+    /// are the engine's, and `Point(0)` is a null reference, which traps.
+    fn field(&mut self, frame: &mut Frame, record: &Expr, field: &str) -> Result<(u64, Type), Trap> {
+        match self.evaluate(frame, record)? {
+            Value::Record(_, 0) if self.options.gc => Err(Trap::new(record.span, "null reference")),
+            Value::Record(record, address) => {
+                let (offset, ty) = self.state.layout.field(record, field).expect("unknown field after type checking");
+                Ok((u64::from(address) + u64::from(offset), ty))
+            }
+            _ => panic!("record wasn't type checked"),
+        }
+    }
+
+    /// Works out the address of an array element, checking the index the
+    /// way --bounds-check says to. With --gc, arrays are the engine's, which
+    /// always trap on an index that's out of bounds, so not checking traps
+    /// too.
+    fn element(&mut self, frame: &mut Frame, array: &str, index: &Expr) -> Result<u64, Trap> {
+        let array = self.state.layout.array(array).expect("unknown array after type checking");
+        let span = index.span;
+        let index = match self.evaluate(frame, index)? {
+            Value::I32(index) => index as u32,
+            _ => panic!("index wasn't type checked"),
+        };
+
+        // Negative indexes are huge unsigned ones, so they're out of bounds
+        // too. There's nothing to clamp to in an empty array.
+        let index = match self.options.bounds_check {
+            BoundsCheck::Off if !self.options.gc => index,
+            _ if index < array.len => index,
+            BoundsCheck::Clamp if array.len > 0 => array.len - 1,
+            _ => return Err(Trap::new(span, "array index out of bounds")),
+        };
+
+        // The multiplication wraps, like i32.mul, unless memory64 does it
+        // in i64. Adding the array's address doesn't, like a load's offset.
+        let offset = if self.options.memory64 {
+            u64::from(index) * u64::from(layout::ELEMENT_SIZE)
+        } else {
+            u64::from(index.wrapping_mul(layout::ELEMENT_SIZE))
+        };
+        Ok(u64::from(array.address) + offset)
+    }
+
+    fn load(&mut self, span: Span, address: u64) -> Result<Value, Trap> {
+        let bytes = self.memory_at(span, address, 4)?;
+        Ok(Value::I32(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+    }
+
+    /// Loads a value of type `ty` the way the generated code would: bools
+    /// and pointers are an i32, a string is an i64 with its length in the
+    /// top half, and a vector is four i32s.
+    fn load_value(&mut self, span: Span, address: u64, ty: Type) -> Result<Value, Trap> {
+        if ty == Type::V128 {
+            let bytes = self.memory_at(span, address, 16)?;
+            let mut lanes = [0; 4];
+            for (lane, bytes) in lanes.iter_mut().zip(bytes.chunks(4)) {
+                *lane = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+            return Ok(Value::V128(lanes));
+        }
+
+        let size = if ty.wasm_name() == "i32" { 4 } else { 8 };
+        let mut word = [0; 8];
+        word[..size].copy_from_slice(self.memory_at(span, address, size)?);
+        let bits = u64::from_le_bytes(word);
+        Ok(match ty {
+            Type::I32 => Value::I32(bits as i32),
+            Type::I64 => Value::I64(bits as i64),
+            Type::F64 => Value::F64(f64::from_bits(bits)),
+            Type::Bool => Value::Bool(bits != 0),
+            Type::Str => Value::Str {
+                address: bits as u32,
+                len: (bits >> 32) as u32,
+            },
+            Type::Func(func) => Value::Func(func, bits as u32),
+            Type::Record(record) => Value::Record(record, bits as u32),
+            Type::Enum(ty) => Value::Enum(ty, bits as i32),
+            Type::V128 => unreachable!("vectors are loaded above"),
+        })
+    }
+
+    /// Stores a value the way `load_value` loads it.
+    fn store_value(&mut self, span: Span, address: u64, value: Value) -> Result<(), Trap> {
+        let bytes = value.to_le_bytes();
+        self.memory_at(span, address, bytes.len())?.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    /// Does what a builtin's runtime function does in the generated code.
+    fn run_runtime(&mut self, span: Span, name: &str, arguments: &[Value]) -> Result<Value, Trap> {
+        match name {
+            "heap.alloc" => match arguments[0] {
+                // The size is unsigned, like the generated code treats it.
+                Value::I32(size) => Ok(Value::I32(self.alloc(span, u64::from(size as u32))? as i32)),
+                _ => panic!("alloc takes an i32"),
+            },
+            // Out of bounds, these trap before they write anything, like
+            // `memory.copy` and `memory.fill`.
+            "mem.move" => match (arguments[0], arguments[1], arguments[2]) {
+                (Value::I32(to), Value::I32(from), Value::I32(len)) => {
+                    let bytes = self.memory_at(span, u64::from(from as u32), len as u32 as usize)?.to_vec();
+                    self.memory_at(span, u64::from(to as u32), bytes.len())?.copy_from_slice(&bytes);
+                    Ok(Value::I32(to))
+                }
+                _ => panic!("copy takes three i32s"),
+            },
+            "mem.fill" => match (arguments[0], arguments[1], arguments[2]) {
+                (Value::I32(to), Value::I32(value), Value::I32(len)) => {
+                    self.memory_at(span, u64::from(to as u32), len as u32 as usize)?.fill(value as u8);
+                    Ok(Value::I32(to))
+                }
+                _ => panic!("fill takes three i32s"),
+            },
+            "host.random" => Ok(Value::I32(self.random())),
+            "host.clock" => Ok(Value::I64(self.started.elapsed().as_millis() as i64)),
+            "str.concat" => {
+                let mut bytes = self.string_bytes(span, arguments[0])?;
+                bytes.extend(self.string_bytes(span, arguments[1])?);
+                let address = self.alloc(span, bytes.len() as u64)?;
+                self.memory_at(span, u64::from(address), bytes.len())?.copy_from_slice(&bytes);
+                Ok(Value::Str {
+                    address,
+                    len: bytes.len() as u32,
+                })
+            }
+            "str.compare" => {
+                let left = self.string_bytes(span, arguments[0])?;
+                let right = self.string_bytes(span, arguments[1])?;
+                Ok(Value::I32(left.cmp(&right) as i32))
+            }
+            "str.substr" => match (arguments[0], arguments[1], arguments[2]) {
+                (Value::Str { address, len }, Value::I32(start), Value::I32(count)) => {
+                    if start < 0 || count < 0 || start as u32 + count as u32 > len {
+                        return Err(Trap::new(span, "substring out of range"));
+                    }
+                    Ok(Value::Str {
+                        address: address.wrapping_add(start as u32),
+                        len: count as u32,
+                    })
+                }
+                _ => panic!("substr takes a str and two i32s"),
+            },
+            _ => panic!("no runtime function called {}", name),
+        }
+    }
+
+    /// The next random number, from 0 to 2^31 - 1, like the host's.
+    fn random(&mut self) -> i32 {
+        random(&mut self.random)
+    }
+
+    /// With --profile, adds one to a counter.
+    fn count(&mut self, counter: Counter) {
+        if self.options.profile {
+            *self.profile.entry(counter).or_insert(0) += 1;
+        }
+    }
+
+    /// With --metering, uses up a unit of fuel, or traps if there's none
+    /// left, where the generated code would call `out_of_fuel`.
+    fn use_fuel(&mut self, span: Span) -> Result<(), Trap> {
+        if !self.options.metering {
+            return Ok(());
+        }
+        if self.fuel <= 0 {
+            return Err(Trap::new(span, "out of fuel"));
+        }
+        self.fuel -= 1;
+        Ok(())
+    }
+
+    /// Does what an atomic function's instruction does. There's only one
+    /// thread here, so it's a load and a store.
+    fn run_atomic(&mut self, span: Span, name: &str, arguments: &[Value]) -> Result<Value, Trap> {
+        let address = match arguments[0] {
+            Value::I32(address) => address as u32,
+            _ => panic!("{} takes an i32 address", name),
+        };
+        if address % 4 != 0 {
+            return Err(Trap::new(span, "unaligned atomic access"));
+        }
+        let old = self.load(span, u64::from(address))?;
+        let new = match (name, old, arguments.get(1)) {
+            ("atomic_load", _, None) => return Ok(old),
+            ("atomic_store", _, Some(&value)) => value,
+            ("atomic_add", Value::I32(old), Some(&Value::I32(value))) => Value::I32(old.wrapping_add(value)),
+            _ => panic!("no atomic function called {}", name),
+        };
+        self.store_value(span, u64::from(address), new)?;
+        Ok(old)
+    }
+
+    /// Returns a copy of a string's bytes.
+    fn string_bytes(&mut self, span: Span, string: Value) -> Result<Vec<u8>, Trap> {
+        match string {
+            Value::Str { address, len } => Ok(self.memory_at(span, u64::from(address), len as usize)?.to_vec()),
+            _ => panic!("expected a str"),
+        }
+    }
+
+    /// Makes room for `size` bytes on the heap, like `$heap.alloc`. The
+    /// heap starts where the layout ends, so it's the layout that keeps
+    /// track of it, and memory grows to fit.
+    fn alloc(&mut self, span: Span, size: u64) -> Result<u32, Trap> {
+        let address = match self.state.layout.reserve(size) {
+            Some(address) => address,
+            None => return Err(Trap::new(span, "out of memory")),
+        };
+        self.grow_memory();
+        Ok(address)
+    }
+
+    /// Makes memory big enough for everything in the layout.
+    fn grow_memory(&mut self) {
+        let size = (self.state.layout.pages() * layout::PAGE_SIZE) as usize;
+        if self.state.memory.len() < size {
+            self.state.memory.resize(size, 0);
+        }
+    }
+
+    /// Returns `size` bytes of memory at `address`, or traps if they're past
+    /// the end.
+    fn memory_at(&mut self, span: Span, address: u64, size: usize) -> Result<&mut [u8], Trap> {
+        if address + size as u64 > self.state.memory.len() as u64 {
+            return Err(Trap::new(span, "out of bounds memory access"));
+        }
+        let address = address as usize;
+        Ok(&mut self.state.memory[address..address + size])
+    }
+}
+
+/// Applies a binary operator, trapping where the wasm instruction would.
+fn binary(span: Span, op: BinaryOp, left: Value, right: Value) -> Result<Value, Trap> {
+    match consteval::binary(op, left, right) {
+        Some(value) => Ok(value),
+        None if right == Value::zero(right.ty()) => Err(Trap::new(span, "integer divide by zero")),
+        None => Err(Trap::new(span, "integer overflow")),
+    }
+}
+
+/// Returns the value of an integer literal, which type checking has made
+/// sure fits in its type.
+fn int_value(expr: &Expr) -> Value {
+    match expr.kind {
+        ExprKind::Int(ref num, Some(ty)) => consteval::int_literal(num, ty).expect("literal doesn't fit in its type"),
+        _ => panic!("expected a type checked integer literal"),
+    }
+}
+
+/// Roughly where the top of the stack is: the address of something in a
+/// frame of its own.
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    &marker as *const u8 as usize
+}
+
+/// A seed for `random`, from the time. Xorshift gets stuck at 0, so the seed
+/// can't be.
+pub fn seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+        | 1
+}
+
+/// Moves xorshift on from `state` and returns the next random number, from
+/// 0 to 2^31 - 1, like the host's.
+pub fn random(state: &mut u64) -> i32 {
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    (state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 33) as i32
+}
@@ -0,0 +1,269 @@
+//! Just enough JSON for the compiler's own needs: writing diagnostics and
+//! dependency lists, and reading and writing the messages an editor sends
+//! with --lsp.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+
+    // The fields in the order they were written. Objects are small enough
+    // that looking a field up by going through them all is fine.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses a whole JSON document. Returns None if it isn't one.
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut parser = JsonParser {
+            chars: text.chars().collect(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position == parser.chars.len() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// An object with these fields, for building messages.
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// A field of an object, if this is an object and it has one.
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref fields) => fields.iter().find(|field| field.0 == name).map(|field| &field.1),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value of a number that's a whole number, and not negative.
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Json::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as usize),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the value compactly, all on one line.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            // Whole numbers are written without a fraction, so ids come
+            // back the way they were sent.
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(ref s) => write!(f, "{}", quote(s)),
+            Json::Array(ref values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(ref fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", quote(name), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Quotes and escapes a string for use in JSON output.
+pub fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl JsonParser {
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        match self.peek()? {
+            'n' => self.word("null", Json::Null),
+            't' => self.word("true", Json::Bool(true)),
+            'f' => self.word("false", Json::Bool(false)),
+            '"' => self.string().map(Json::String),
+            '[' => self.array(),
+            '{' => self.object(),
+            '-' | '0'..='9' => self.number(),
+            _ => None,
+        }
+    }
+
+    fn word(&mut self, word: &str, value: Json) -> Option<Json> {
+        for expected in word.chars() {
+            if self.next()? != expected {
+                return None;
+            }
+        }
+        Some(value)
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                self.position += 1;
+            } else {
+                break;
+            }
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse().ok().map(Json::Number)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Some(s),
+                '\\' => match self.next()? {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'r' => s.push('\r'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'u' => s.push(self.escaped_char()?),
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// The character after a `\u`, which takes two escapes if it's outside
+    /// the basic multilingual plane.
+    fn escaped_char(&mut self) -> Option<char> {
+        let first = self.hex4()?;
+        if (0xd800..0xdc00).contains(&first) {
+            self.expect('\\')?;
+            self.expect('u')?;
+            let second = self.hex4()?;
+            char::from_u32(0x10000 + ((first - 0xd800) << 10) + (second.checked_sub(0xdc00)?))
+        } else {
+            char::from_u32(first)
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let mut n = 0;
+        for _ in 0..4 {
+            n = n * 16 + self.next()?.to_digit(16)?;
+        }
+        Some(n)
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Some(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => (),
+                ']' => return Some(Json::Array(values)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Some(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((name, self.value()?));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => (),
+                '}' => return Some(Json::Object(fields)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Option<()> {
+        if self.next()? == expected {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        Some(c)
+    }
+}
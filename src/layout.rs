@@ -0,0 +1,188 @@
+//! Plans where the things a program keeps in linear memory go. Everything
+//! gets a fixed address at compile time, one after the other in the order
+//! code generation asks for them, starting at address 0. Arrays are aligned
+//! to their elements; strings are just bytes, so they go wherever they fit.
+//!
+//! It also works out where the fields of each record go, relative to the
+//! record's address, though where records themselves go is up to the
+//! program.
+
+use ast::{Record, RecordType, Type};
+
+/// The size of an array element, in bytes. Arrays only hold i32s.
+pub const ELEMENT_SIZE: u32 = 4;
+
+/// The size of a page of wasm memory, in bytes.
+pub const PAGE_SIZE: u64 = 65536;
+
+/// How much room there is for the copies of variables passed to `var`
+/// parameters, and how much each copy takes, which is enough for any type
+/// but a v128. Those can't be `var` parameters.
+/// Each call takes what it needs off the top, and gives it back when it
+/// returns.
+pub const STACK_SIZE: u64 = 65536;
+pub const CELL_SIZE: u32 = 8;
+
+/// How many bytes a value of a type takes in memory. Values are aligned to
+/// their size.
+pub fn size_of(ty: Type) -> u32 {
+    match ty.wasm_name() {
+        "v128" => 16,
+        "i64" | "f64" => 8,
+        _ => 4,
+    }
+}
+
+/// Where a record's fields are, and how big it is.
+pub struct RecordLayout {
+    /// Each field's name, type, and offset from the record's address.
+    pub fields: Vec<(String, Type, u32)>,
+
+    /// Rounded up to the biggest field's size, so records can go one after
+    /// another and their fields still be aligned.
+    pub size: u32,
+}
+
+impl RecordLayout {
+    /// Lays out the fields in order, each at the first offset after the one
+    /// before that's a multiple of its size.
+    pub fn new(record: &Record) -> RecordLayout {
+        let mut fields = Vec::new();
+        let mut end: u32 = 0;
+        let mut align = 1;
+        for field in &record.fields {
+            let size = size_of(field.ty);
+            let offset = end.next_multiple_of(size);
+            fields.push((field.name.clone(), field.ty, offset));
+            end = offset + size;
+            align = align.max(size);
+        }
+        RecordLayout {
+            fields,
+            size: end.next_multiple_of(align),
+        }
+    }
+
+    /// The offset and type of a field, if the record has it.
+    pub fn field(&self, name: &str) -> Option<(u32, Type)> {
+        self.fields
+            .iter()
+            .find(|&(field, _, _)| field == name)
+            .map(|&(_, ty, offset)| (offset, ty))
+    }
+}
+
+/// Where an array is, and how many elements it has.
+#[derive(Clone, Copy)]
+pub struct Array {
+    pub address: u32,
+    pub len: u32,
+}
+
+#[derive(Default)]
+pub struct Layout {
+    // Arrays by name.
+    arrays: Vec<(String, Array)>,
+
+    // The bytes of each string, and their address. Strings with the same
+    // bytes share them.
+    strings: Vec<(Vec<u8>, u32)>,
+
+    // The first address after everything placed so far.
+    end: u64,
+
+    // Each record's fields, by record.
+    records: Vec<(RecordType, RecordLayout)>,
+}
+
+impl Layout {
+    pub fn new() -> Layout {
+        Layout::default()
+    }
+
+    /// Makes room for an array. Returns None if it doesn't fit in a 32-bit
+    /// address space.
+    pub fn add_array(&mut self, name: &str, len: u32) -> Option<Array> {
+        let address = self.reserve(u64::from(len) * u64::from(ELEMENT_SIZE))?;
+        let array = Array { address, len };
+        self.arrays.push((name.to_string(), array));
+        Some(array)
+    }
+
+    /// Makes room for `size` bytes, aligned like an array, and returns their
+    /// address. Returns None if they don't fit in a 32-bit address space.
+    pub fn reserve(&mut self, size: u64) -> Option<u32> {
+        let address = self.end.next_multiple_of(u64::from(ELEMENT_SIZE));
+        let end = address + size;
+        if end > u64::from(u32::MAX) + 1 {
+            return None;
+        }
+
+        self.end = end;
+        Some(address as u32)
+    }
+
+    /// Returns the array called `name`, if there's one.
+    pub fn array(&self, name: &str) -> Option<Array> {
+        self.arrays
+            .iter()
+            .find(|&(array, _)| array == name)
+            .map(|&(_, array)| array)
+    }
+
+    /// Works out where a record's fields go, unless that's been done
+    /// already.
+    pub fn add_record(&mut self, record: &Record) {
+        let ty = RecordType::new(&record.name);
+        if self.records.iter().all(|&(record, _)| record != ty) {
+            self.records.push((ty, RecordLayout::new(record)));
+        }
+    }
+
+    /// The offset and type of a field of a record that's been added.
+    pub fn field(&self, record: RecordType, name: &str) -> Option<(u32, Type)> {
+        self.records
+            .iter()
+            .find(|&&(ty, _)| ty == record)
+            .and_then(|(_, layout)| layout.field(name))
+    }
+
+    /// The layout of a record that's been added.
+    pub fn record(&self, record: RecordType) -> Option<&RecordLayout> {
+        self.records.iter().find(|&&(ty, _)| ty == record).map(|(_, layout)| layout)
+    }
+
+    /// Returns the address of a string's bytes, placing them if they
+    /// haven't been already.
+    pub fn add_string(&mut self, bytes: &[u8]) -> u32 {
+        if let Some(&(_, address)) = self.strings.iter().find(|&(string, _)| string == bytes) {
+            return address;
+        }
+
+        let address = self.end as u32;
+        self.strings.push((bytes.to_vec(), address));
+        self.end += bytes.len() as u64;
+        address
+    }
+
+    /// The strings to put in data segments, and where each goes.
+    pub fn strings(&self) -> &[(Vec<u8>, u32)] {
+        &self.strings
+    }
+
+    /// The first address after everything placed so far.
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// Returns true if nothing's been placed in memory.
+    pub fn is_empty(&self) -> bool {
+        self.end == 0
+    }
+
+    /// How many pages of memory it takes to hold everything. Always at
+    /// least one, so there's somewhere for the program to read and write.
+    pub fn pages(&self) -> u64 {
+        self.end.div_ceil(PAGE_SIZE).max(1)
+    }
+}
@@ -0,0 +1,978 @@
+//! A compiler for a small language in the style of Jack Crenshaw's "Let's
+//! Build a Compiler", producing WebAssembly. `compile` runs the whole
+//! pipeline; the modules are public so tools can use the pieces on their own.
+
+#[cfg(any(feature = "check", feature = "debug"))]
+extern crate wasmparser;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "run")]
+extern crate wasmtime;
+#[cfg(feature = "debug")]
+extern crate wast;
+#[cfg(feature = "assemble")]
+extern crate wat;
+
+pub mod ast;
+pub mod browser;
+pub mod builtins;
+pub mod codegen;
+pub mod consteval;
+pub mod cost;
+pub mod crash;
+pub mod debug;
+pub mod deadcode;
+pub mod desugar;
+pub mod diagnostics;
+pub mod fold;
+pub mod format;
+pub mod hir;
+pub mod inline;
+pub mod interpret;
+pub mod json;
+pub mod layout;
+pub mod locals;
+pub mod lsp;
+pub mod mangle;
+pub mod parser;
+pub mod passes;
+pub mod prelude;
+pub mod profile;
+pub mod repl;
+#[cfg(feature = "run")]
+pub mod run;
+pub mod runtime;
+pub mod source_map;
+pub mod typeck;
+pub mod unroll;
+#[cfg(feature = "check")]
+pub mod validate;
+pub mod wasi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::IsTerminal;
+use std::io::Read;
+use std::panic;
+use std::thread;
+
+use codegen::{BoundsCheck, CodeGen, Platform};
+use cost::Target;
+use crash::Progress;
+use diagnostics::{Diagnostics, Message, Severity, Warning, WARNINGS};
+use hir::Program;
+use interpret::Interpreter;
+use parser::{Newlines, Parser};
+use source_map::{SourceMap, Span};
+use typeck::Truthiness;
+
+/// How much stack the compiler and the interpreter run with. The parser and
+/// every pass after it recurse over the tree, as deep as
+/// `parser::MAX_DEPTH`, and in a debug build that takes more than a thread's
+/// usual 8 MB. The interpreter recurses for each call the program makes, and
+/// has to be able to go at least as deep as a wasm engine does. It's only
+/// address space until something gets that deep.
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// What the compiler prints when it's done.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Emit {
+    // The compiled module, as WAT.
+    #[default]
+    Wat,
+
+    // The files the output depends on, as a make rule.
+    Deps,
+
+    // The files the output depends on, as JSON.
+    DepsJson,
+}
+
+/// How errors and warnings are printed, picked with --error-format.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum ErrorFormat {
+    // For people: where it is, the message, and the line it's on.
+    #[default]
+    Human,
+
+    // One JSON object per line, for editors and scripts.
+    Json,
+}
+
+/// Whether errors and warnings are printed in color, picked with --color.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Color {
+    // If stderr is a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Settings that change what the compiler emits, collected from the command
+/// line.
+#[derive(Default, Clone)]
+pub struct Options {
+    // Use the memory64 proposal: 64-bit memory declarations and addresses.
+    pub memory64: bool,
+
+    // Use the tail call proposal: `return f(x)` becomes `return_call`, which
+    // doesn't use up any stack.
+    pub tail_calls: bool,
+
+    // Don't use the bulk memory proposal: `copy` and `fill`, and copying
+    // strings, are loops instead of `memory.copy` and `memory.fill`.
+    pub no_bulk_memory: bool,
+
+    // Run every optimization pass twice and check that the second run
+    // doesn't change anything, then compile the program again and check
+    // that the output's the same.
+    pub verify_passes: bool,
+
+    // Validate the output before printing it. This needs the `check`
+    // feature.
+    pub check: bool,
+
+    // Run the output with wasmtime and print what main returns, instead of
+    // printing the output. This needs the `run` feature.
+    pub run: bool,
+
+    // Run the program with the interpreter and the output with wasmtime,
+    // and report any difference in what they print, what they return, or
+    // whether they trap. This needs the `run` feature too.
+    pub verify: bool,
+
+    // What to print once the program compiles.
+    pub emit: Emit,
+
+    // How errors and warnings are printed.
+    pub error_format: ErrorFormat,
+
+    // Whether errors and warnings are in color, when they're for people.
+    pub color: Color,
+
+    // What optimizations should aim for. Without this, we generate the
+    // straightforward code and leave it at that.
+    pub optimize_for: Option<Target>,
+
+    // How hard to optimize, from -O0 to -O2. Each level runs the passes in
+    // `passes` that it turns on.
+    pub opt_level: u32,
+
+    // Whether newlines end statements.
+    pub newlines: Newlines,
+
+    // What counts as a condition in `if` and `while`.
+    pub truthiness: Truthiness,
+
+    // What happens when an array index is out of bounds. With --gc, arrays
+    // trap on one anyway, so off is the same as trap.
+    pub bounds_check: BoundsCheck,
+
+    // Check for integer division by zero, and tell the host where it
+    // happened before trapping.
+    pub checked_div: bool,
+
+    // Use up a unit of fuel at the start of every function and every time
+    // round a loop, and tell the host before trapping once it's gone. The
+    // module exports how much is left, so the host can set it.
+    pub metering: bool,
+
+    // Count how many times each statement runs, in memory after everything
+    // else, and write a file next to the input that says where the counters
+    // are and which statement each one is for.
+    pub coverage: bool,
+
+    // Count the calls to each function and the times round each loop, in
+    // globals the module exports, and with --interpret, print which were
+    // busiest once the program's finished.
+    pub profile: bool,
+
+    // Count the references to strings and records on the heap, and reuse
+    // their memory once nothing refers to them. Records have to be made with
+    // `alloc`, or be the null one. The interpreter doesn't count them; it
+    // never frees anything.
+    pub rc: bool,
+
+    // Make records and arrays WasmGC structs and arrays instead of putting
+    // them in linear memory, so the engine looks after them. The interpreter
+    // keeps them in memory either way.
+    pub gc: bool,
+
+    // Allow `v128` and the vector functions, which compile to SIMD
+    // instructions.
+    pub simd: bool,
+
+    // Make memory shared, so that workers can use it at the same time, and
+    // allow the atomic functions. Shared memory needs a maximum size, so
+    // it's the most wasm32 allows.
+    pub threads: bool,
+
+    // What's going to run the module.
+    pub platform: Platform,
+
+    // Write a page that runs the program to this file.
+    pub html: Option<String>,
+
+    // What main is exported as, if not `main`.
+    pub entry_name: Option<String>,
+
+    // Main doesn't return anything, for hosts that don't want a result.
+    pub no_result: bool,
+
+    // Leave out the prelude, the functions programs can call without
+    // defining them.
+    pub no_prelude: bool,
+
+    // Export every function, not just the ones marked `export`.
+    pub export_all: bool,
+
+    // Put the program's source in a custom section of the module.
+    pub embed_source: bool,
+
+    // Put a comment with the line of source each statement came from before
+    // its code.
+    pub comments: bool,
+
+    // Write a source map with the module, so debuggers can step through the
+    // program's source.
+    pub debug: bool,
+
+    // Run the program instead of compiling it.
+    pub interpret: bool,
+
+    // Run statements as they're typed, instead of compiling a program.
+    pub repl: bool,
+
+    // Compile the input again every time it changes, until we're stopped.
+    pub watch: bool,
+
+    // Be a language server for an editor, over stdin and stdout.
+    pub lsp: bool,
+
+    // Print each rule the parser enters and leaves to stderr.
+    pub trace: bool,
+
+    // Write a crash report next to the input if the compiler crashes.
+    pub save_crash: bool,
+
+    // The warnings turned off with -Wno-<name>.
+    pub disabled_warnings: Vec<Warning>,
+
+    // The warnings that are errors, with --Werror or --Werror=<name>.
+    pub error_warnings: Vec<Warning>,
+
+    // The file to compile. We read stdin if there isn't one.
+    pub input: Option<String>,
+
+    // More files to compile into the same module, after the input. The
+    // input is the one the output is named after.
+    pub linked: Vec<String>,
+
+    // What's in files the input includes or is linked with, by name, to
+    // read instead of what's on disk. Replaying a crash report sets this.
+    pub saved_files: Vec<(String, Vec<u8>)>,
+}
+
+impl Options {
+    /// Parses the command line arguments (without the program name).
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            if arg == "--enable" {
+                match args.next() {
+                    Some(feature) => options.enable(&feature)?,
+                    None => return Err(String::from("--enable needs a feature name")),
+                }
+            } else if let Some(feature) = arg.strip_prefix("--enable=") {
+                options.enable(feature)?;
+            } else if arg == "--tail-calls" {
+                options.tail_calls = true;
+            } else if arg == "--verify-passes" {
+                options.verify_passes = true;
+            } else if arg == "--export-all" {
+                options.export_all = true;
+            } else if arg == "--no-result" {
+                options.no_result = true;
+            } else if arg == "--no-bulk-memory" {
+                options.no_bulk_memory = true;
+            } else if arg == "--no-prelude" {
+                options.no_prelude = true;
+            } else if arg == "--entry-name" {
+                match args.next() {
+                    Some(name) => options.entry_name = Some(name),
+                    None => return Err(String::from("--entry-name needs a name")),
+                }
+            } else if let Some(name) = arg.strip_prefix("--entry-name=") {
+                options.entry_name = Some(name.to_string());
+            } else if arg == "--comments" {
+                options.comments = true;
+            } else if arg == "--debug" {
+                if !cfg!(feature = "debug") {
+                    return Err(String::from("--debug needs the compiler built with the debug feature"));
+                }
+                options.debug = true;
+            } else if arg == "--embed-source" {
+                options.embed_source = true;
+            } else if arg == "--check" {
+                if !cfg!(feature = "check") {
+                    return Err(String::from("--check needs the compiler built with the check feature"));
+                }
+                options.check = true;
+            } else if arg == "--run" {
+                if !cfg!(feature = "run") {
+                    return Err(String::from("--run needs the compiler built with the run feature"));
+                }
+                options.run = true;
+            } else if arg == "--verify" {
+                if !cfg!(feature = "run") {
+                    return Err(String::from("--verify needs the compiler built with the run feature"));
+                }
+                options.verify = true;
+            } else if arg == "--interpret" {
+                options.interpret = true;
+            } else if arg == "--repl" {
+                options.repl = true;
+            } else if arg == "--lsp" {
+                options.lsp = true;
+            } else if arg == "--trace" {
+                options.trace = true;
+            } else if arg == "--watch" {
+                match args.next() {
+                    Some(path) => options.watch(path)?,
+                    None => return Err(String::from("--watch needs a file name")),
+                }
+            } else if let Some(path) = arg.strip_prefix("--watch=") {
+                options.watch(path.to_string())?;
+            } else if arg == "--save-crash" {
+                options.save_crash = true;
+            } else if arg == "--emit" {
+                match args.next() {
+                    Some(kind) => options.emit = Options::parse_emit(&kind)?,
+                    None => return Err(String::from("--emit needs an output kind")),
+                }
+            } else if let Some(kind) = arg.strip_prefix("--emit=") {
+                options.emit = Options::parse_emit(kind)?;
+            } else if arg == "--error-format" {
+                match args.next() {
+                    Some(format) => options.error_format = Options::parse_error_format(&format)?,
+                    None => return Err(String::from("--error-format needs human or json")),
+                }
+            } else if let Some(format) = arg.strip_prefix("--error-format=") {
+                options.error_format = Options::parse_error_format(format)?;
+            } else if arg == "--color" {
+                match args.next() {
+                    Some(when) => options.color = Options::parse_color(&when)?,
+                    None => return Err(String::from("--color needs always, never, or auto")),
+                }
+            } else if let Some(when) = arg.strip_prefix("--color=") {
+                options.color = Options::parse_color(when)?;
+            } else if arg == "--optimize-for" {
+                match args.next() {
+                    Some(target) => options.optimize_for = Some(Options::parse_target(&target)?),
+                    None => return Err(String::from("--optimize-for needs size or speed")),
+                }
+            } else if let Some(target) = arg.strip_prefix("--optimize-for=") {
+                options.optimize_for = Some(Options::parse_target(target)?);
+            } else if let Some(level) = arg.strip_prefix("-O") {
+                options.opt_level = Options::parse_opt_level(level)?;
+            } else if arg == "--Werror" {
+                options.error_warnings = WARNINGS.to_vec();
+            } else if let Some(name) = arg.strip_prefix("--Werror=") {
+                let warning = Options::parse_warning(name)?;
+                if !options.error_warnings.contains(&warning) {
+                    options.error_warnings.push(warning);
+                }
+            } else if let Some(name) = arg.strip_prefix("-Wno-") {
+                let warning = Options::parse_warning(name)?;
+                if !options.disabled_warnings.contains(&warning) {
+                    options.disabled_warnings.push(warning);
+                }
+            } else if let Some(name) = arg.strip_prefix("-W") {
+                let warning = Options::parse_warning(name)?;
+                options.disabled_warnings.retain(|&disabled| disabled != warning);
+            } else if arg == "--newlines" {
+                match args.next() {
+                    Some(mode) => options.newlines = Options::parse_newlines(&mode)?,
+                    None => return Err(String::from("--newlines needs significant or whitespace")),
+                }
+            } else if let Some(mode) = arg.strip_prefix("--newlines=") {
+                options.newlines = Options::parse_newlines(mode)?;
+            } else if arg == "--truthiness" {
+                match args.next() {
+                    Some(rule) => options.truthiness = Options::parse_truthiness(&rule)?,
+                    None => return Err(String::from("--truthiness needs strict or nonzero")),
+                }
+            } else if let Some(rule) = arg.strip_prefix("--truthiness=") {
+                options.truthiness = Options::parse_truthiness(rule)?;
+            } else if arg == "--bounds-check" {
+                match args.next() {
+                    Some(policy) => options.bounds_check = Options::parse_bounds_check(&policy)?,
+                    None => return Err(String::from("--bounds-check needs trap, clamp, or off")),
+                }
+            } else if let Some(policy) = arg.strip_prefix("--bounds-check=") {
+                options.bounds_check = Options::parse_bounds_check(policy)?;
+            } else if arg == "--checked-div" {
+                options.checked_div = true;
+            } else if arg == "--metering" {
+                options.metering = true;
+            } else if arg == "--coverage" {
+                options.coverage = true;
+            } else if arg == "--profile" {
+                options.profile = true;
+            } else if arg == "--rc" {
+                options.rc = true;
+            } else if arg == "--gc" {
+                options.gc = true;
+            } else if arg == "--simd" {
+                options.simd = true;
+            } else if arg == "--threads" {
+                options.threads = true;
+            } else if arg == "--bounds-checks" {
+                options.bounds_check = BoundsCheck::Trap;
+            } else if arg == "--target" {
+                match args.next() {
+                    Some(platform) => options.platform = Options::parse_platform(&platform)?,
+                    None => return Err(String::from("--target needs host, browser, or wasi")),
+                }
+            } else if let Some(platform) = arg.strip_prefix("--target=") {
+                options.platform = Options::parse_platform(platform)?;
+            } else if arg == "--html" {
+                match args.next() {
+                    Some(path) => options.html = Some(path),
+                    None => return Err(String::from("--html needs a file name")),
+                }
+            } else if let Some(path) = arg.strip_prefix("--html=") {
+                options.html = Some(path.to_string());
+            } else if !arg.starts_with('-') && options.input.is_none() {
+                options.input = Some(arg);
+            } else if !arg.starts_with('-') {
+                options.linked.push(arg);
+            } else {
+                return Err(format!("unknown argument {}", arg));
+            }
+        }
+
+        // WASI's functions all take 32-bit addresses.
+        if options.platform == Platform::Wasi && options.memory64 {
+            return Err(String::from("--target wasi doesn't work with memory64"));
+        }
+
+        // The page provides the same imports as any other host.
+        if options.platform == Platform::Wasi && options.html.is_some() {
+            return Err(String::from("--html doesn't work with --target wasi"));
+        }
+
+        // The check reports to a function the host provides.
+        if options.platform == Platform::Wasi && options.checked_div {
+            return Err(String::from("--checked-div doesn't work with --target wasi"));
+        }
+        if options.platform == Platform::Wasi && options.metering {
+            return Err(String::from("--metering doesn't work with --target wasi"));
+        }
+
+        // A function gives up the references it holds after its body runs,
+        // so it can't end by jumping to another function.
+        if options.rc && options.tail_calls {
+            return Err(String::from("--rc doesn't work with --tail-calls"));
+        }
+
+        // Strings in records would need their references counted by the
+        // engine's collector, which knows nothing about them.
+        if options.rc && options.gc {
+            return Err(String::from("--rc doesn't work with --gc"));
+        }
+
+        // The interpreter doesn't have any output to run.
+        if (options.run || options.verify) && options.interpret {
+            return Err(String::from("--run and --verify don't work with --interpret"));
+        }
+
+        // WASI always runs `_start`.
+        if options.platform == Platform::Wasi && options.entry_name.is_some() {
+            return Err(String::from("--entry-name doesn't work with --target wasi"));
+        }
+
+        // The statements come from stdin, as they're typed.
+        if options.repl && options.watch {
+            return Err(String::from("--repl doesn't work with --watch"));
+        }
+        if options.repl && options.input.is_some() {
+            return Err(String::from("--repl reads from stdin, so it doesn't take an input file"));
+        }
+
+        // The editor sends the files, over stdin.
+        if options.lsp && (options.repl || options.watch) {
+            return Err(String::from("--lsp doesn't work with --repl or --watch"));
+        }
+        if options.lsp && options.input.is_some() {
+            return Err(String::from("--lsp gets files from the editor, so it doesn't take an input file"));
+        }
+
+        match options.entry_name.as_deref() {
+            Some("") => return Err(String::from("--entry-name needs a name")),
+            Some(name @ "memory") | Some(name @ "dispatch") => {
+                return Err(format!("--entry-name can't be {}, which the module already exports", name))
+            }
+            _ => (),
+        }
+
+        Ok(options)
+    }
+
+    /// The name main is exported as.
+    pub fn entry_name(&self) -> &str {
+        self.entry_name.as_deref().unwrap_or("main")
+    }
+
+    /// Parses the argument to --emit.
+    fn parse_emit(kind: &str) -> Result<Emit, String> {
+        match kind {
+            "wat" => Ok(Emit::Wat),
+            "deps" => Ok(Emit::Deps),
+            "deps-json" => Ok(Emit::DepsJson),
+            _ => Err(format!("unknown output kind {}", kind)),
+        }
+    }
+
+    /// Parses the argument to --error-format.
+    fn parse_error_format(format: &str) -> Result<ErrorFormat, String> {
+        match format {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!("unknown error format {}", format)),
+        }
+    }
+
+    /// Parses the argument to --color.
+    fn parse_color(when: &str) -> Result<Color, String> {
+        match when {
+            "always" => Ok(Color::Always),
+            "never" => Ok(Color::Never),
+            "auto" => Ok(Color::Auto),
+            _ => Err(format!("unknown color mode {}", when)),
+        }
+    }
+
+    /// Parses the argument to --optimize-for.
+    fn parse_target(target: &str) -> Result<Target, String> {
+        match target {
+            "size" => Ok(Target::Size),
+            "speed" => Ok(Target::Speed),
+            _ => Err(format!("can only optimize for size or speed, not {}", target)),
+        }
+    }
+
+    /// Parses the level after -O.
+    fn parse_opt_level(level: &str) -> Result<u32, String> {
+        match level.parse() {
+            Ok(level) if level <= passes::MAX_LEVEL => Ok(level),
+            _ => Err(format!("unknown optimization level -O{}", level)),
+        }
+    }
+
+    /// Parses the name after -W or -Wno-.
+    fn parse_warning(name: &str) -> Result<Warning, String> {
+        Warning::from_name(name).ok_or_else(|| {
+            let names: Vec<&str> = WARNINGS.iter().map(|warning| warning.name()).collect();
+            format!("unknown warning {}; there's {}", name, names.join(", "))
+        })
+    }
+
+    /// Parses the argument to --newlines.
+    fn parse_newlines(mode: &str) -> Result<Newlines, String> {
+        match mode {
+            "significant" => Ok(Newlines::Significant),
+            "whitespace" => Ok(Newlines::Whitespace),
+            _ => Err(format!("newlines can be significant or whitespace, not {}", mode)),
+        }
+    }
+
+    /// Parses the argument to --truthiness.
+    fn parse_truthiness(rule: &str) -> Result<Truthiness, String> {
+        match rule {
+            "strict" => Ok(Truthiness::Strict),
+            "nonzero" => Ok(Truthiness::Nonzero),
+            _ => Err(format!("unknown truthiness rule {}", rule)),
+        }
+    }
+
+    /// Parses the argument to --bounds-check.
+    fn parse_bounds_check(policy: &str) -> Result<BoundsCheck, String> {
+        match policy {
+            "trap" => Ok(BoundsCheck::Trap),
+            "clamp" => Ok(BoundsCheck::Clamp),
+            "off" => Ok(BoundsCheck::Off),
+            _ => Err(format!("bounds checks can trap, clamp, or be off, not {}", policy)),
+        }
+    }
+
+    /// Parses the argument to --target.
+    fn parse_platform(platform: &str) -> Result<Platform, String> {
+        match platform {
+            "host" => Ok(Platform::Host),
+            "browser" => Ok(Platform::Browser),
+            "wasi" => Ok(Platform::Wasi),
+            _ => Err(format!("unknown target {}", platform)),
+        }
+    }
+
+    /// Watches `path`, which is the input too.
+    fn watch(&mut self, path: String) -> Result<(), String> {
+        if self.input.is_some() {
+            return Err(String::from("--watch takes the place of the input file, so there can't be another"));
+        }
+        self.watch = true;
+        self.input = Some(path);
+        Ok(())
+    }
+
+    /// Turns on a single wasm proposal by name.
+    fn enable(&mut self, feature: &str) -> Result<(), String> {
+        match feature {
+            "memory64" => self.memory64 = true,
+            "tail-calls" => self.tail_calls = true,
+            _ => return Err(format!("unknown feature {}", feature)),
+        }
+        Ok(())
+    }
+}
+
+/// Why `compile_bytes` couldn't compile a program: the errors, each with its
+/// location and the line it's on.
+#[derive(Debug)]
+pub struct CompileError {
+    pub errors: Vec<Message>,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for CompileError {}
+
+/// Compiles a program to WAT, without printing anything or exiting. Returns
+/// the errors if there were any, each with its location and the line it's
+/// on. Warnings are dropped. The same program and options always give
+/// exactly the same output.
+///
+/// It runs on a thread of its own with `STACK_SIZE` of stack, since the
+/// caller's might not be big enough for a deeply nested program.
+pub fn compile_to_string(program: &[u8], options: &Options) -> Result<String, Vec<Message>> {
+    with_stack(|| compile_with_progress(program, options, &mut Progress::default(), &mut Vec::new()))
+}
+
+/// Runs `f` on a thread with `STACK_SIZE` of stack, and waits for it. If it
+/// panics, the panic carries on on this thread.
+fn with_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        let thread = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("couldn't start the compiler's thread");
+        thread.join().unwrap_or_else(|payload| panic::resume_unwind(payload))
+    })
+}
+
+/// Compiles any bytes at all to WAT with the default options, for fuzzing.
+/// Input that isn't a program is a `CompileError`; nothing gets printed,
+/// and nothing exits. A panic here is a bug in the compiler.
+pub fn compile_bytes(program: &[u8]) -> Result<Vec<u8>, CompileError> {
+    compile_to_string(program, &Options::default())
+        .map(String::into_bytes)
+        .map_err(|errors| CompileError { errors })
+}
+
+/// Compiles a program to WAT, reading it from `program` as it goes. Returns
+/// None if there were errors, which have already been printed by the time
+/// this returns, along with any warnings. `progress` keeps track of the
+/// passes as they run.
+pub fn compile<R: Read>(program: R, options: &Options, progress: &mut Progress) -> Option<String> {
+    let mut warnings = Vec::new();
+    let result = compile_with_progress(program, options, progress, &mut warnings);
+    report_warnings(options, &warnings);
+    result.map_err(|errors| report(options, &errors)).ok()
+}
+
+fn compile_with_progress<R: Read>(
+    program: R,
+    options: &Options,
+    progress: &mut Progress,
+    warnings: &mut Vec<Message>,
+) -> Result<String, Vec<Message>> {
+    let (mut hir, mut diagnostics) = check(program, options, progress, warnings)?;
+
+    passes::optimize(
+        &mut hir,
+        options.opt_level,
+        options.optimize_for,
+        options.verify_passes,
+        progress,
+    );
+
+    progress.pass = "codegen";
+    let mut codegen = CodeGen::new(options, &mut diagnostics);
+    let output = codegen.generate(&hir);
+    progress.coverage = codegen.coverage_map();
+    warnings.extend(diagnostics.take_warnings());
+
+    if diagnostics.error_count() > 0 {
+        Err(diagnostics.into_messages())
+    } else {
+        Ok(output)
+    }
+}
+
+/// Formats a program the canonical way, for `crenshaw fmt`. Returns None if
+/// it doesn't parse, in which case the errors have already been printed.
+pub fn format_source<R: Read>(program: R, options: &Options) -> Option<String> {
+    let mut source_map = SourceMap::new();
+    let name = options.input.as_ref().map_or("<stdin>", |input| input.as_str());
+    let file = source_map.add_file(name, Vec::new());
+    let mut diagnostics = Diagnostics::new(source_map);
+
+    let mut parser = Parser::new(program, file, options.newlines, &mut diagnostics);
+    if options.trace {
+        parser.trace();
+    }
+    let (ast, layout) = match parser.parse_for_formatting() {
+        Ok(parsed) if diagnostics.error_count() == 0 => parsed,
+        _ => {
+            report(options, &diagnostics.into_messages());
+            return None;
+        }
+    };
+
+    let source = diagnostics.source_map().files()[0].source();
+    Some(format::format(&ast, &layout, source, options.newlines))
+}
+
+/// What running a program with the interpreter did.
+pub struct Outcome {
+    // Everything it printed.
+    pub output: Vec<u8>,
+
+    // What main returned, or the status it halted with, or why it trapped,
+    // pointing at where.
+    pub result: Result<i32, Message>,
+}
+
+/// Runs a program without compiling it, and without printing anything or
+/// exiting. `read` reads from `input`, and what the program prints is kept
+/// in the outcome. Returns the errors if the program doesn't compile.
+/// Warnings are dropped. Like `compile_to_string`, it runs on a thread of
+/// its own with `STACK_SIZE` of stack.
+pub fn interpret_captured<R: BufRead + Send>(
+    program: &[u8],
+    options: &Options,
+    mut input: R,
+) -> Result<Outcome, Vec<Message>> {
+    with_stack(move || {
+        let (hir, diagnostics) = check(program, options, &mut Progress::default(), &mut Vec::new())?;
+        let mut output = Vec::new();
+        let result = Interpreter::new(options, &hir, &mut input, &mut output).run();
+        Ok(Outcome {
+            output,
+            result: result.map_err(|trap| diagnostics.located(trap.span, &trap.message)),
+        })
+    })
+}
+
+/// Runs a program without compiling it, reading from stdin and printing to
+/// stdout, and returns what `main` returns. The program itself is read from
+/// `program`, all of it, before it starts running. Returns None if there were
+/// errors, or if the program trapped, which have already been printed by the
+/// time this returns. It's None too if stdout was closed before the program
+/// was done printing, which isn't an error.
+pub fn interpret<R: Read>(program: R, options: &Options, progress: &mut Progress) -> Option<i32> {
+    let mut warnings = Vec::new();
+    let checked = check(program, options, progress, &mut warnings);
+    report_warnings(options, &warnings);
+    let (hir, mut diagnostics) = match checked {
+        Ok(checked) => checked,
+        Err(errors) => {
+            report(options, &errors);
+            return None;
+        }
+    };
+
+    progress.pass = "interpret";
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let (mut input, mut output) = (stdin.lock(), stdout.lock());
+    let mut interpreter = Interpreter::new(options, &hir, &mut input, &mut output);
+    let result = interpreter.run();
+    if options.profile {
+        let counts: Vec<(String, u64)> = interpreter
+            .profile()
+            .iter()
+            .map(|&(ref counter, count)| (counter.label(diagnostics.source_map()), count))
+            .collect();
+        eprint!("{}", profile::report(&counts));
+    }
+
+    match result {
+        Ok(value) => Some(value),
+        Err(ref trap) if trap.output_closed => None,
+        Err(trap) => {
+            diagnostics.error(trap.span, &trap.message);
+            report(options, &diagnostics.into_messages());
+            None
+        }
+    }
+}
+
+/// Prints errors to stderr, in the format the options ask for.
+pub fn report(options: &Options, errors: &[Message]) {
+    print_messages(options, Severity::Error, errors);
+}
+
+/// Prints warnings to stderr, in the format the options ask for.
+fn report_warnings(options: &Options, warnings: &[Message]) {
+    print_messages(options, Severity::Warning, warnings);
+}
+
+fn print_messages(options: &Options, severity: Severity, messages: &[Message]) {
+    let color = match options.color {
+        Color::Always => true,
+        Color::Never => false,
+        Color::Auto => io::stderr().is_terminal(),
+    };
+    for message in messages {
+        match options.error_format {
+            ErrorFormat::Human if color => eprintln!("{}", message.to_colored(severity)),
+            ErrorFormat::Human => eprintln!("{}: {}", severity.label(), message),
+            ErrorFormat::Json => eprintln!("{}", message.to_json(severity)),
+        }
+    }
+}
+
+/// Parses a program, lowers it to the core language, and type checks it.
+/// Returns the errors if there were any. Adds any warnings to `warnings`
+/// either way.
+fn check<R: Read>(
+    program: R,
+    options: &Options,
+    progress: &mut Progress,
+    warnings: &mut Vec<Message>,
+) -> Result<(Program, Diagnostics), Vec<Message>> {
+    let mut source_map = SourceMap::new();
+    let name = options.input.as_ref().map_or("<stdin>", |input| input.as_str());
+    let file = source_map.add_file(name, Vec::new());
+    for (name, source) in &options.saved_files {
+        source_map.save_file(name, source.clone());
+    }
+    let mut diagnostics = Diagnostics::new(source_map);
+
+    let mut linked: Vec<(&String, Box<dyn Read>)> = Vec::new();
+    for path in &options.linked {
+        if let Some(source) = diagnostics.source_map().saved(path) {
+            linked.push((path, Box::new(io::Cursor::new(source.to_vec()))));
+            continue;
+        }
+        match File::open(path) {
+            Ok(file) => linked.push((path, Box::new(file))),
+            Err(err) => return Err(vec![Message::new(format!("could not read {}: {}", path, err))]),
+        }
+    }
+    for &warning in &options.disabled_warnings {
+        diagnostics.disable(warning);
+    }
+    for &warning in &options.error_warnings {
+        diagnostics.promote(warning);
+    }
+
+    // Each file is parsed on its own, then they're all put together, and
+    // the rest of the compiler sees one program.
+    progress.pass = "parse";
+    // A file the parser gave up on stops everything, but only once the
+    // rest have been parsed, for their errors too.
+    let mut ast = parse(program, file, options.newlines, options.trace, &mut diagnostics);
+    for (path, reader) in linked {
+        let file = diagnostics.source_map_mut().add_file(path, Vec::new());
+        match (&mut ast, parse(reader, file, options.newlines, options.trace, &mut diagnostics)) {
+            (Some(ast), Some(parsed)) => ast.link(parsed),
+            _ => ast = None,
+        }
+    }
+    progress.files = diagnostics
+        .source_map()
+        .files()
+        .iter()
+        .map(|file| file.name().to_string())
+        .collect();
+    if options.save_crash || options.debug {
+        progress.sources = diagnostics
+            .source_map()
+            .files()
+            .iter()
+            .map(|file| (file.name().to_string(), file.source().to_vec()))
+            .collect();
+    }
+    let mut ast = match ast {
+        Some(ast) => ast,
+        None => return Err(diagnostics.into_messages()),
+    };
+
+    // The prelude isn't one of the files the output depends on, so it goes
+    // in after they've been listed. It's written with newlines, whatever
+    // --newlines says, and it isn't traced, since it isn't the program.
+    let mut prelude = Vec::new();
+    if !options.no_prelude {
+        let file = diagnostics.source_map_mut().add_file(prelude::NAME, Vec::new());
+        let parsed = parse(prelude::SOURCE.as_bytes(), file, Newlines::Significant, false, &mut diagnostics);
+        prelude = prelude::add(&mut ast, parsed.expect("the prelude doesn't parse"));
+    }
+    if options.save_crash {
+        progress.ast = Some(format!("{:#?}", ast));
+    }
+
+    progress.pass = "desugar";
+    let mut hir = desugar::desugar(ast, &mut diagnostics);
+    if options.save_crash {
+        progress.hir = Some(format!("{:#?}", hir));
+    }
+
+    // Code generation and the interpreter count on the program making
+    // sense, so there's no going on past type errors.
+    progress.pass = "typeck";
+    typeck::check(&mut hir, options, &mut diagnostics);
+    warnings.extend(diagnostics.take_warnings());
+    if diagnostics.error_count() > 0 {
+        return Err(diagnostics.into_messages());
+    }
+    prelude::remove_unused(&mut hir, &prelude);
+
+    Ok((hir, diagnostics))
+}
+
+/// Parses one file, which goes in `file` in the source map, printing the
+/// parser's trace if `trace` is set. Returns None if the parser gave up on
+/// an error it couldn't recover from.
+fn parse<R: Read>(
+    program: R,
+    file: Span,
+    newlines: Newlines,
+    trace: bool,
+    diagnostics: &mut Diagnostics,
+) -> Option<ast::Program> {
+    let mut parser = Parser::new(program, file, newlines, diagnostics);
+    if trace {
+        parser.trace();
+    }
+    parser.parse_program().ok()
+}
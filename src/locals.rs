@@ -0,0 +1,315 @@
+//! Local slot reuse, with -O1. Desugaring makes up a new temporary every
+//! time it needs one, and each would get its own local. Temporaries only
+//! live for a statement or two, though, so ones that are never live at the
+//! same time, and have the same type, can share a local. This renames them
+//! to `temp.0`, `temp.1`, and so on, one name per shared local, and code
+//! generation declares each name once.
+//!
+//! Temporaries are the variables with a dot in their names. Each one is
+//! assigned once, before it's read, which is what makes this simple: a
+//! temporary is live from its assignment to its last read. (Once this has
+//! run, the ones that share a local are one temporary, assigned by each of
+//! them.) A read inside a
+//! loop that the assignment isn't in happens on every iteration, so the
+//! temporary stays live until the end of the loop.
+
+use std::collections::BTreeMap;
+
+use hir::{Expr, ExprKind, Place, Program, Stmt, Type};
+
+/// Shares locals between temporaries in every function body in a program,
+/// and main.
+pub fn reuse_temporaries(program: &mut Program) {
+    for function in program.functions.iter_mut().chain(&mut program.handlers) {
+        reuse_in_body(&mut function.body);
+    }
+    reuse_in_body(&mut program.init);
+    reuse_in_body(&mut program.main);
+}
+
+fn reuse_in_body(body: &mut [Stmt]) {
+    let mut walker = Walker::default();
+    walker.walk_block(body);
+
+    // Temporaries are already in the order they're assigned, so each one
+    // takes the first slot of its type that's free by then.
+    let mut slots: Vec<(Type, usize)> = Vec::new();
+    let mut names = BTreeMap::new();
+    for &(ref name, ty, start, _) in &walker.assignments {
+        // One that's assigned more than once is already sharing a local,
+        // from running this before. It's live from its first assignment
+        // until the last read after any of them.
+        if names.contains_key(name) {
+            continue;
+        }
+        let end = walker
+            .assignments
+            .iter()
+            .filter(|assignment| assignment.0 == *name)
+            .map(|&(_, _, start, ref loops)| walker.live_until(name, start, loops))
+            .max()
+            .unwrap_or(start);
+        let free = slots.iter().position(|&(slot, busy_until)| slot == ty && busy_until < start);
+        let slot = match free {
+            Some(slot) => {
+                slots[slot].1 = end;
+                slot
+            }
+            None => {
+                slots.push((ty, end));
+                slots.len() - 1
+            }
+        };
+        names.insert(name.clone(), format!("temp.{}", slot));
+    }
+
+    rename_block(body, &names);
+}
+
+fn is_temporary(name: &str) -> bool {
+    name.contains('.')
+}
+
+/// Numbers the statements of a function body in the order they're written,
+/// and notes where temporaries are assigned and read.
+#[derive(Default)]
+struct Walker {
+    // The number of the next statement.
+    position: usize,
+
+    // The loops we're inside, outermost first, as indexes into `loop_ends`.
+    loops: Vec<usize>,
+
+    // The number of the last statement in each loop.
+    loop_ends: Vec<usize>,
+
+    // Each temporary, its type, the statement that assigns it, and the loops
+    // that statement is in.
+    assignments: Vec<(String, Type, usize, Vec<usize>)>,
+
+    // Each read of a temporary, the statement it's in, and the loops that
+    // statement is in.
+    reads: Vec<(String, usize, Vec<usize>)>,
+}
+
+impl Walker {
+    fn walk_block(&mut self, block: &[Stmt]) {
+        for stmt in block {
+            self.walk_stmt(stmt);
+        }
+    }
+
+    fn walk_stmt(&mut self, stmt: &Stmt) {
+        let position = self.position;
+        self.position += 1;
+
+        match *stmt {
+            Stmt::Assign { ref name, ty, ref value, .. } => {
+                self.walk_expr(value, position);
+                if is_temporary(name) {
+                    let ty = ty.expect("assignment wasn't type checked");
+                    self.assignments.push((name.clone(), ty, position, self.loops.clone()));
+                }
+            }
+            Stmt::If { ref condition, ref then, ref otherwise } => {
+                self.walk_expr(condition, position);
+                self.walk_block(then);
+                self.walk_block(otherwise);
+            }
+            // The condition's part of the loop: it's checked again after
+            // every iteration.
+            Stmt::While { ref condition, ref body } => {
+                let id = self.loop_ends.len();
+                self.loop_ends.push(position);
+                self.loops.push(id);
+                self.walk_expr(condition, position);
+                self.walk_block(body);
+                self.loops.pop();
+                self.loop_ends[id] = self.position - 1;
+            }
+            // The values are constants, so only the subject can read a
+            // temporary.
+            Stmt::Case { ref subject, ref arms, ref otherwise } => {
+                self.walk_expr(subject, position);
+                for arm in arms {
+                    self.walk_block(&arm.1);
+                }
+                if let Some(ref otherwise) = *otherwise {
+                    self.walk_block(otherwise);
+                }
+            }
+            Stmt::Block(ref body) => self.walk_block(body),
+            // Only the program's own variables get more than one value at a
+            // time.
+            Stmt::AssignValues { ref value, .. }
+            | Stmt::Return(ref value)
+            | Stmt::Print(ref value)
+            | Stmt::Assert(ref value)
+            | Stmt::Halt(ref value)
+            | Stmt::Call(ref value) => self.walk_expr(value, position),
+            Stmt::ReturnValues(ref values) => {
+                for value in values {
+                    self.walk_expr(value, position);
+                }
+            }
+            Stmt::Store { ref place, ref value } => {
+                match *place {
+                    Place::Memory(ref address) => self.walk_expr(address, position),
+                    Place::Element(_, ref index) => self.walk_expr(index, position),
+                    Place::Field(ref record, _) => self.walk_expr(record, position),
+                }
+                self.walk_expr(value, position);
+            }
+        }
+    }
+
+    fn walk_expr(&mut self, expr: &Expr, position: usize) {
+        match expr.kind {
+            ExprKind::Int(..)
+            | ExprKind::Float(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Str(_)
+            | ExprKind::Address(_)
+            | ExprKind::Read => (),
+            ExprKind::Variable(ref name) => {
+                if is_temporary(name) {
+                    self.reads.push((name.clone(), position, self.loops.clone()));
+                }
+            }
+            ExprKind::Call(_, ref arguments) => {
+                for argument in arguments {
+                    self.walk_expr(argument, position);
+                }
+            }
+            ExprKind::CallIndirect(ref callee, ref arguments) => {
+                self.walk_expr(callee, position);
+                for argument in arguments {
+                    self.walk_expr(argument, position);
+                }
+            }
+            ExprKind::Binary(_, ref left, ref right) => {
+                self.walk_expr(left, position);
+                self.walk_expr(right, position);
+            }
+            ExprKind::Load(ref address) => self.walk_expr(address, position),
+            ExprKind::Index(_, ref index) => self.walk_expr(index, position),
+            ExprKind::Field(ref inner, _) | ExprKind::View(_, ref inner) => self.walk_expr(inner, position),
+            ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+                self.walk_expr(condition, position);
+                self.walk_expr(then_value, position);
+                self.walk_expr(else_value, position);
+            }
+        }
+    }
+
+    /// Returns the number of the last statement a temporary is live in,
+    /// given the statement that assigns it and the loops that's in.
+    fn live_until(&self, name: &str, start: usize, loops: &[usize]) -> usize {
+        let mut end = start;
+        for &(ref read, position, ref read_loops) in &self.reads {
+            if read != name {
+                continue;
+            }
+
+            // The outermost loop the read is in that the assignment isn't
+            // runs the read again after everything else in it.
+            let outer = read_loops.iter().find(|read_loop| !loops.contains(read_loop));
+            let last = match outer {
+                Some(&outer) => self.loop_ends[outer],
+                None => position,
+            };
+            end = end.max(last);
+        }
+        end
+    }
+}
+
+fn rename_block(block: &mut [Stmt], names: &BTreeMap<String, String>) {
+    for stmt in block {
+        match *stmt {
+            Stmt::Assign { ref mut name, ref mut value, .. } => {
+                if let Some(slot) = names.get(name) {
+                    *name = slot.clone();
+                }
+                rename_expr(value, names);
+            }
+            Stmt::If { ref mut condition, ref mut then, ref mut otherwise } => {
+                rename_expr(condition, names);
+                rename_block(then, names);
+                rename_block(otherwise, names);
+            }
+            Stmt::While { ref mut condition, ref mut body } => {
+                rename_expr(condition, names);
+                rename_block(body, names);
+            }
+            Stmt::Case { ref mut subject, ref mut arms, ref mut otherwise } => {
+                rename_expr(subject, names);
+                for arm in arms {
+                    rename_block(&mut arm.1, names);
+                }
+                if let Some(ref mut otherwise) = *otherwise {
+                    rename_block(otherwise, names);
+                }
+            }
+            Stmt::Block(ref mut body) => rename_block(body, names),
+            Stmt::AssignValues { ref mut value, .. }
+            | Stmt::Return(ref mut value)
+            | Stmt::Print(ref mut value)
+            | Stmt::Assert(ref mut value)
+            | Stmt::Halt(ref mut value)
+            | Stmt::Call(ref mut value) => rename_expr(value, names),
+            Stmt::ReturnValues(ref mut values) => {
+                for value in values {
+                    rename_expr(value, names);
+                }
+            }
+            Stmt::Store { ref mut place, ref mut value } => {
+                match *place {
+                    Place::Memory(ref mut address) => rename_expr(address, names),
+                    Place::Element(_, ref mut index) => rename_expr(index, names),
+                    Place::Field(ref mut record, _) => rename_expr(record, names),
+                }
+                rename_expr(value, names);
+            }
+        }
+    }
+}
+
+fn rename_expr(expr: &mut Expr, names: &BTreeMap<String, String>) {
+    match expr.kind {
+        ExprKind::Int(..)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::Address(_)
+        | ExprKind::Read => (),
+        ExprKind::Variable(ref mut name) => {
+            if let Some(slot) = names.get(name) {
+                *name = slot.clone();
+            }
+        }
+        ExprKind::Call(_, ref mut arguments) => {
+            for argument in arguments {
+                rename_expr(argument, names);
+            }
+        }
+        ExprKind::CallIndirect(ref mut callee, ref mut arguments) => {
+            rename_expr(callee, names);
+            for argument in arguments {
+                rename_expr(argument, names);
+            }
+        }
+        ExprKind::Binary(_, ref mut left, ref mut right) => {
+            rename_expr(left, names);
+            rename_expr(right, names);
+        }
+        ExprKind::Load(ref mut address) => rename_expr(address, names),
+        ExprKind::Index(_, ref mut index) => rename_expr(index, names),
+        ExprKind::Field(ref mut inner, _) | ExprKind::View(_, ref mut inner) => rename_expr(inner, names),
+        ExprKind::Conditional(ref mut condition, ref mut then_value, ref mut else_value) => {
+            rename_expr(condition, names);
+            rename_expr(then_value, names);
+            rename_expr(else_value, names);
+        }
+    }
+}
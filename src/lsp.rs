@@ -0,0 +1,653 @@
+//! The language server, with --lsp. An editor starts the compiler and talks
+//! to it over stdin and stdout, in the Language Server Protocol. Every time
+//! a file is opened or changed, it's checked the way it would be compiled,
+//! and the errors and warnings go back to the editor. The editor can also
+//! ask where a variable or function under the cursor is defined.
+//!
+//! The editor sends the whole file on every change. Positions are counted
+//! in bytes, which is what the protocol's UTF-16 positions are for ASCII.
+
+use std::io::{BufRead, Write};
+use std::panic;
+
+use ast::{Function, Program, Stmt, StmtKind};
+use crash::Progress;
+use diagnostics::{Diagnostics, Message, Severity};
+use json::Json;
+use source_map::{SourceMap, Span};
+use {check, parse, Options};
+
+// What the protocol calls the errors we send back for requests we can't
+// answer.
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// Answers the editor until it tells us to exit, or closes stdin. Returns
+/// whether it asked us to shut down first, which is how the protocol says
+/// things went as planned.
+pub fn run(options: &Options, input: &mut dyn BufRead, output: &mut dyn Write) -> bool {
+    let mut server = Server {
+        options,
+        output,
+        documents: Vec::new(),
+        shut_down: false,
+    };
+
+    while let Some(message) = read_message(input) {
+        let message = match Json::parse(&message) {
+            Some(message) => message,
+            None => {
+                server.reply_error(Json::Null, PARSE_ERROR, "that isn't JSON");
+                continue;
+            }
+        };
+        if message.get("method").and_then(Json::as_str) == Some("exit") {
+            break;
+        }
+        server.handle(&message);
+    }
+    server.shut_down
+}
+
+struct Server<'a> {
+    options: &'a Options,
+    output: &'a mut dyn Write,
+
+    // The open files, by URI, with what's in them as of the last change.
+    documents: Vec<(String, String)>,
+
+    // Whether the editor's asked us to shut down.
+    shut_down: bool,
+}
+
+impl<'a> Server<'a> {
+    fn handle(&mut self, message: &Json) {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let id = message.get("id").cloned();
+
+        match method {
+            "initialize" => {
+                let capabilities = Json::object(vec![
+                    // The editor sends the whole file every time.
+                    ("textDocumentSync", Json::Number(1.0)),
+                    ("definitionProvider", Json::Bool(true)),
+                ]);
+                let info = Json::object(vec![("name", Json::String(String::from("crenshaw")))]);
+                let result = Json::object(vec![("capabilities", capabilities), ("serverInfo", info)]);
+                self.reply(id, result);
+            }
+            "shutdown" => {
+                self.shut_down = true;
+                self.reply(id, Json::Null);
+            }
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").and_then(|document| document.get("text"));
+                if let (Some(uri), Some(text)) = (document_uri(params), text.and_then(Json::as_str)) {
+                    self.update(uri, text.to_string());
+                }
+            }
+            "textDocument/didChange" => {
+                let text = match params.get("contentChanges") {
+                    Some(Json::Array(changes)) => changes.last().and_then(|change| change.get("text")),
+                    _ => None,
+                };
+                if let (Some(uri), Some(text)) = (document_uri(params), text.and_then(Json::as_str)) {
+                    self.update(uri, text.to_string());
+                }
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = document_uri(params) {
+                    self.documents.retain(|document| document.0 != uri);
+                    self.publish(uri, Vec::new());
+                }
+            }
+            "textDocument/definition" => {
+                let result = self.definition(params).unwrap_or(Json::Null);
+                self.reply(id, result);
+            }
+            // Anything else that wants an answer gets told we don't do it.
+            // Notifications we don't know are fine to ignore.
+            _ => {
+                if let Some(id) = id {
+                    self.reply_error(id, METHOD_NOT_FOUND, &format!("unknown method {}", method));
+                }
+            }
+        }
+    }
+
+    /// Keeps the new text of a file, and tells the editor what's wrong with
+    /// it now.
+    fn update(&mut self, uri: &str, text: String) {
+        let diagnostics = self.check(uri, &text);
+        match self.documents.iter_mut().find(|document| document.0 == uri) {
+            Some(document) => document.1 = text,
+            None => self.documents.push((uri.to_string(), text)),
+        }
+        self.publish(uri, diagnostics);
+    }
+
+    /// Checks a file the way it would be compiled, and returns the errors
+    /// and warnings in it, as the protocol has them.
+    fn check(&self, uri: &str, text: &str) -> Vec<Json> {
+        let path = path_from_uri(uri);
+        let mut options = self.options.clone();
+        options.input = Some(path.clone());
+
+        // A crash is a bug in the compiler, which shouldn't take the
+        // editor's server down with it.
+        let mut warnings = Vec::new();
+        let checked = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            check(text.as_bytes(), &options, &mut Progress::default(), &mut warnings)
+        }));
+        let errors = match checked {
+            Ok(Ok(_)) => Vec::new(),
+            Ok(Err(errors)) => errors,
+            Err(_) => vec![Message::new(String::from("internal compiler error"))],
+        };
+
+        let messages = errors
+            .iter()
+            .map(|error| (Severity::Error, error))
+            .chain(warnings.iter().map(|warning| (Severity::Warning, warning)));
+
+        // Errors in files it includes are reported when they're opened.
+        messages
+            .filter(|&(_, message)| message.location.as_ref().is_none_or(|location| location.file == path))
+            .map(|(severity, message)| {
+                let range = match message.location {
+                    Some(ref location) => range(location.line - 1, location.column - 1, location.length),
+                    None => range(0, 0, 0),
+                };
+                let severity = match severity {
+                    Severity::Error => 1.0,
+                    Severity::Warning => 2.0,
+                };
+                Json::object(vec![
+                    ("range", range),
+                    ("severity", Json::Number(severity)),
+                    ("source", Json::String(String::from("crenshaw"))),
+                    ("message", Json::String(message.text.clone())),
+                ])
+            })
+            .collect()
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Json>) {
+        let params = Json::object(vec![
+            ("uri", Json::String(uri.to_string())),
+            ("diagnostics", Json::Array(diagnostics)),
+        ]);
+        self.send(Json::object(vec![
+            ("jsonrpc", Json::String(String::from("2.0"))),
+            ("method", Json::String(String::from("textDocument/publishDiagnostics"))),
+            ("params", params),
+        ]));
+    }
+
+    /// Finds where the name under the cursor is defined. Returns None if
+    /// there's no name there, or it isn't defined anywhere we can see.
+    fn definition(&self, params: &Json) -> Option<Json> {
+        let uri = document_uri(params)?;
+        let position = params.get("position")?;
+        let line = position.get("line")?.as_usize()?;
+        let character = position.get("character")?.as_usize()?;
+        let text = &self.documents.iter().find(|document| document.0 == uri)?.1;
+
+        let offset = offset(text, line, character);
+        let (start, end) = word_at(text.as_bytes(), offset)?;
+        let name = &text[start..end];
+        let is_call = text[end..].trim_start().starts_with('(');
+        let before_dot = text[..start].strip_suffix('.');
+
+        // Parse the file again on its own, includes and all, for the spans
+        // of everything in it. It doesn't have to type check, or be traced
+        // again.
+        let path = path_from_uri(uri);
+        let mut source_map = SourceMap::new();
+        let file = source_map.add_file(&path, Vec::new());
+        let mut diagnostics = Diagnostics::new(source_map);
+        let program = parse(text.as_bytes(), file, self.options.newlines, false, &mut diagnostics)?;
+        let source_map = diagnostics.source_map();
+
+        let span = find_definition(&program, source_map, name, file.start + start, is_call, before_dot)?;
+        let location = source_map.location(span);
+        let uri = if location.file == path {
+            uri.to_string()
+        } else {
+            uri_from_path(location.file)
+        };
+        Some(Json::object(vec![
+            ("uri", Json::String(uri)),
+            (
+                "range",
+                range(location.line - 1, location.column - 1, span.end - span.start),
+            ),
+        ]))
+    }
+
+    fn reply(&mut self, id: Option<Json>, result: Json) {
+        self.send(Json::object(vec![
+            ("jsonrpc", Json::String(String::from("2.0"))),
+            ("id", id.unwrap_or(Json::Null)),
+            ("result", result),
+        ]));
+    }
+
+    fn reply_error(&mut self, id: Json, code: i32, message: &str) {
+        let error = Json::object(vec![
+            ("code", Json::Number(f64::from(code))),
+            ("message", Json::String(message.to_string())),
+        ]);
+        self.send(Json::object(vec![
+            ("jsonrpc", Json::String(String::from("2.0"))),
+            ("id", id),
+            ("error", error),
+        ]));
+    }
+
+    /// Sends a message, with the header the protocol puts in front of it.
+    /// If the editor's gone, there's no one to tell.
+    fn send(&mut self, message: Json) {
+        let body = message.to_string();
+        let _ =
+            write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body).and_then(|()| self.output.flush());
+    }
+}
+
+/// The URI of the file a message is about.
+fn document_uri(params: &Json) -> Option<&str> {
+    params.get("textDocument")?.get("uri")?.as_str()
+}
+
+/// Reads the next message: headers, a blank line, and then as many bytes of
+/// JSON as the Content-Length header says. Returns None once the input's
+/// run out.
+fn read_message(input: &mut dyn BufRead) -> Option<String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            // A message without a length can't be read, so skip to the
+            // next one.
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    Some(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// A range on one line, as the protocol has it. Both numbers are counted
+/// from 0. An end past the end of the line means the end of the line.
+fn range(line: usize, character: usize, length: usize) -> Json {
+    let position = |character: usize| {
+        Json::object(vec![
+            ("line", Json::Number(line as f64)),
+            ("character", Json::Number(character as f64)),
+        ])
+    };
+    Json::object(vec![
+        ("start", position(character)),
+        ("end", position(character + length)),
+    ])
+}
+
+/// Turns a line and character, both counted from 0, into an offset in the
+/// text. Positions past the end of a line are at the end of it.
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(newline) => start += newline + 1,
+            None => return text.len(),
+        }
+    }
+    let end = text[start..].find('\n').map_or(text.len(), |newline| start + newline);
+    (start + character).min(end)
+}
+
+/// The start and end of the name at an offset, including when the offset
+/// is just past the end of it, where a cursor sits after typing it.
+fn word_at(text: &[u8], offset: usize) -> Option<(usize, usize)> {
+    let is_word = |i: usize| text.get(i).is_some_and(|&byte| byte.is_ascii_alphanumeric() || byte == b'_');
+    let offset = if !is_word(offset) && offset > 0 && is_word(offset - 1) {
+        offset - 1
+    } else {
+        offset
+    };
+    if !is_word(offset) {
+        return None;
+    }
+
+    let mut start = offset;
+    while start > 0 && is_word(start - 1) {
+        start -= 1;
+    }
+    let mut end = offset;
+    while is_word(end) {
+        end += 1;
+    }
+    // Numbers aren't names.
+    if text[start].is_ascii_digit() {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// Finds where `name`, used at `offset`, is defined, the way type checking
+/// would: a parameter or global first, then a local, then a function, then
+/// a record or an enum. A name that's being called is only looked for among the
+/// functions, and the records, since calling one is how a record's made.
+///
+/// A name right after a dot, with `before_dot` the text before it, is one
+/// of an enum's variants if an enum's name is before the dot, and a field
+/// otherwise. Which record's depends on the type of what's before the dot,
+/// which would take type checking to know, so it's the first record with a
+/// field by that name.
+///
+/// In a nested function, the parameters and locals of the functions it's
+/// nested in are in scope too, since it can capture them, and so are the
+/// functions nested alongside it. A parameter hides anything with the same
+/// name outside its function. A local is the outermost function's that
+/// assigns it, since the ones inside it capture it rather than having their
+/// own.
+fn find_definition(
+    program: &Program,
+    source_map: &SourceMap,
+    name: &str,
+    offset: usize,
+    is_call: bool,
+    before_dot: Option<&str>,
+) -> Option<Span> {
+    if let Some(before_dot) = before_dot {
+        let variant = program
+            .enums
+            .iter()
+            .find(|enum_| is_name_at_end(before_dot, &enum_.name))
+            .map(|enum_| enum_.variants.iter().find(|variant| variant.0 == name).map(|variant| variant.1));
+        if let Some(variant) = variant {
+            return variant;
+        }
+        return program
+            .records
+            .iter()
+            .flat_map(|record| &record.fields)
+            .find(|field| field.name == name)
+            .map(|field| field.name_span);
+    }
+
+    let enclosing = enclosing_functions(program, offset);
+
+    let function = enclosing
+        .iter()
+        .flat_map(|function| nested_functions(&function.body))
+        .chain(&program.functions)
+        .find(|function| function.name == name)
+        .map(|function| function.name_span);
+    let record = program
+        .records
+        .iter()
+        .find(|record| record.name == name)
+        .map(|record| record.name_span);
+    if is_call {
+        return function.or(record);
+    }
+
+    for function in &enclosing {
+        if let Some(index) = function.params.iter().position(|param| param.0 == name) {
+            return Some(function.param_spans[index]);
+        }
+    }
+
+    let global = program
+        .globals
+        .iter()
+        .find(|global| global.name == name)
+        .map(|global| global.name_span);
+    let constant = program
+        .constants
+        .iter()
+        .find(|constant| constant.name == name)
+        .map(|constant| constant.name_span);
+    let array = program
+        .arrays
+        .iter()
+        .find(|array| array.name == name)
+        .map(|array| array.name_span);
+    if let Some(span) = global.or(constant).or(array) {
+        return Some(span);
+    }
+
+    let local = if enclosing.is_empty() {
+        first_assignment(&program.init, source_map, name).or_else(|| first_assignment(&program.main, source_map, name))
+    } else {
+        enclosing
+            .iter()
+            .rev()
+            .find_map(|function| first_assignment(&function.body, source_map, name))
+    };
+    let enum_ = program
+        .enums
+        .iter()
+        .find(|enum_| enum_.name == name)
+        .map(|enum_| enum_.name_span);
+    local.or(function).or(record).or(enum_)
+}
+
+/// Whether `text` ends with the whole name `name`, and not just the end of
+/// a longer one.
+fn is_name_at_end(text: &str, name: &str) -> bool {
+    match text.strip_suffix(name) {
+        Some(rest) => !rest.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+        None => false,
+    }
+}
+
+/// The functions `offset` is in, innermost first: a nested one, then the
+/// ones it's nested in, out to one at the top level. None of them, in main
+/// or the init blocks.
+fn enclosing_functions(program: &Program, offset: usize) -> Vec<&Function> {
+    let contains = |function: &&Function| function.name_span.start <= offset && offset <= body_end(&function.body);
+
+    let mut enclosing = Vec::new();
+    let mut functions: Vec<&Function> = program.functions.iter().chain(&program.handlers).collect();
+    while let Some(function) = functions.into_iter().find(contains) {
+        enclosing.push(function);
+        functions = nested_functions(&function.body);
+    }
+    enclosing.reverse();
+    enclosing
+}
+
+/// The functions nested in a block, and in the blocks inside it, but not
+/// the ones nested in those functions.
+fn nested_functions(body: &[Stmt]) -> Vec<&Function> {
+    let mut functions = Vec::new();
+    for stmt in body {
+        match stmt.kind {
+            StmtKind::Function(ref function) => functions.push(&**function),
+            StmtKind::If {
+                ref branches,
+                ref otherwise,
+            } => {
+                for branch in branches {
+                    functions.extend(nested_functions(&branch.1));
+                }
+                functions.extend(nested_functions(otherwise));
+            }
+            StmtKind::Case {
+                ref arms,
+                ref otherwise,
+                ..
+            } => {
+                for arm in arms {
+                    functions.extend(nested_functions(&arm.1));
+                }
+                functions.extend(nested_functions(otherwise.as_deref().unwrap_or(&[])));
+            }
+            StmtKind::While { ref body, .. } | StmtKind::For { ref body, .. } | StmtKind::Block(ref body) => {
+                functions.extend(nested_functions(body))
+            }
+            _ => (),
+        }
+    }
+    functions
+}
+
+/// Where the first statement that assigns to a variable names it: an
+/// assignment, a `read`, or a `for` loop.
+fn first_assignment(body: &[Stmt], source_map: &SourceMap, name: &str) -> Option<Span> {
+    for stmt in body {
+        let assigns = match stmt.kind {
+            StmtKind::Assign { name: ref assigned, .. } | StmtKind::Read(ref assigned) => assigned == name,
+            StmtKind::AssignValues { ref names, .. } => names.iter().any(|assigned| assigned == name),
+            StmtKind::For { ref variable, .. } => variable == name,
+            _ => false,
+        };
+        if assigns {
+            return name_in(stmt.span, source_map, name);
+        }
+
+        let found = match stmt.kind {
+            StmtKind::If {
+                ref branches,
+                ref otherwise,
+            } => branches
+                .iter()
+                .find_map(|branch| first_assignment(&branch.1, source_map, name))
+                .or_else(|| first_assignment(otherwise, source_map, name)),
+            StmtKind::Case {
+                ref arms,
+                ref otherwise,
+                ..
+            } => arms
+                .iter()
+                .find_map(|arm| first_assignment(&arm.1, source_map, name))
+                .or_else(|| first_assignment(otherwise.as_deref().unwrap_or(&[]), source_map, name)),
+            StmtKind::While { ref body, .. } | StmtKind::For { ref body, .. } | StmtKind::Block(ref body) => {
+                first_assignment(body, source_map, name)
+            }
+            _ => None,
+        };
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+/// The span of the first time a name's written in a statement, which is
+/// where the name being assigned is, for the statements that assign.
+fn name_in(stmt: Span, source_map: &SourceMap, name: &str) -> Option<Span> {
+    let file = source_map.file(stmt);
+    let source = &file.source()[stmt.start - file.span().start..];
+    let mut from = 0;
+    while let Some(found) = source[from..]
+        .windows(name.len())
+        .position(|window| window == name.as_bytes())
+    {
+        let start = from + found;
+        let end = start + name.len();
+        let before = start > 0 && (source[start - 1].is_ascii_alphanumeric() || source[start - 1] == b'_');
+        let after = source.get(end).is_some_and(|&byte| byte.is_ascii_alphanumeric() || byte == b'_');
+        if !before && !after {
+            return Some(Span {
+                start: stmt.start + start,
+                end: stmt.start + end,
+            });
+        }
+        from = end;
+    }
+    None
+}
+
+/// Where a function's body ends, as far as its statements go.
+fn body_end(body: &[Stmt]) -> usize {
+    body.iter()
+        .map(|stmt| {
+            let nested = match stmt.kind {
+                StmtKind::If {
+                    ref branches,
+                    ref otherwise,
+                } => branches
+                    .iter()
+                    .map(|branch| body_end(&branch.1))
+                    .max()
+                    .unwrap_or(0)
+                    .max(body_end(otherwise)),
+                StmtKind::Case {
+                    ref arms,
+                    ref otherwise,
+                    ..
+                } => arms
+                    .iter()
+                    .map(|arm| body_end(&arm.1))
+                    .max()
+                    .unwrap_or(0)
+                    .max(body_end(otherwise.as_deref().unwrap_or(&[]))),
+                StmtKind::While { ref body, .. } | StmtKind::For { ref body, .. } | StmtKind::Block(ref body) => {
+                    body_end(body)
+                }
+                _ => 0,
+            };
+            stmt.span.end.max(nested)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// The path a `file://` URI points at, with its escapes worked out. Other
+/// URIs are used as they are, as names.
+fn path_from_uri(uri: &str) -> String {
+    let path = match uri.strip_prefix("file://") {
+        Some(path) => path,
+        None => return uri.to_string(),
+    };
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(&String::from_utf8_lossy(hex), 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The `file://` URI for a path, escaping what can't go in one as it is.
+fn uri_from_path(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
@@ -1,322 +1,678 @@
+extern crate crenshaw;
+
+use std::any::Any;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io;
-use std::io::{Read, Cursor};
+use std::io::Read;
+use std::iter;
+#[cfg(any(feature = "check", feature = "run"))]
+use std::mem;
+use std::panic;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::thread;
+#[cfg(feature = "check")]
+use std::time::Instant;
+use std::time::{Duration, SystemTime};
 
-struct Compiler {
-    // The next character we're going to consider.
-    lookahead: Option<char>,
+use crenshaw::browser;
+use crenshaw::codegen::Platform;
+use crenshaw::crash::{Progress, Snapshot};
+#[cfg(feature = "debug")]
+use crenshaw::debug;
+use crenshaw::diagnostics::Message;
+use crenshaw::json::quote;
+use crenshaw::lsp;
+#[cfg(feature = "run")]
+use crenshaw::profile;
+#[cfg(feature = "check")]
+use crenshaw::mangle;
+use crenshaw::repl;
+#[cfg(feature = "run")]
+use crenshaw::run;
+#[cfg(feature = "run")]
+use crenshaw::run::Unverified;
+#[cfg(feature = "check")]
+use crenshaw::validate::validate;
+use crenshaw::{compile, format_source, interpret, report, Emit, Options, STACK_SIZE};
 
-    // Our input stream of bytes. Cursor is so dope.
-    input_stream: Cursor<Vec<u8>>,
-}
+fn main() {
+    let compiler = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(cli)
+        .expect("couldn't start the compiler's thread");
 
-impl Compiler {
-    fn new(program: Vec<u8>) -> Compiler {
-        Compiler {
-            lookahead: None,
-            input_stream: Cursor::new(program),
-        }
+    // A crash has been reported by the time the thread's done, so all
+    // that's left is to exit the way a panic on this thread would.
+    if compiler.join().is_err() {
+        process::exit(101);
     }
+}
 
-    fn init(&mut self) {
-        self.get_char();
-        self.skip_whitespace();
+fn cli() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.first().map(String::as_str) == Some("replay") {
+        replay(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("validate-dir") {
+        validate_dir(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("fmt") {
+        fmt(&args[1..]);
+        return;
     }
 
-    fn emit(&mut self) {
-        self.init();
-        self.emit_module_start();
-        self.emit_main_start();
-        self.parse_assignment();
-        
-        if let Some(c) = self.lookahead {
-            if c != '\n' {
-                self.expected("newline", &c.to_string());
-            }
-        } else {
-            self.expected("newline", "nothing");
+    let options = match Options::parse(args.iter().cloned()) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("Error: {}", msg);
+            process::exit(1);
         }
-        
-        self.emit_main_end();
-        self.emit_module_end();
-    }
-
-    /// Consumes the next byte in the stream, converts it to a character,
-    /// stores it in the lookahead, and returns the character.
-    fn get_char(&mut self) -> Option<char> {
-        // Read a single byte from the stream.
-        let mut buf = [0];
-        let result = self.input_stream.read_exact(&mut buf).ok();
-        let byte = match result {
-            Some(_) => buf[0],
-            None => {
-                self.lookahead = None;
-                return None;
-            }
-        };
+    };
 
-        // Convert the byte into an ASCII character.
-        self.lookahead = Some(char::from(byte));
-        self.lookahead
+    if options.repl {
+        run_repl(&options);
+        return;
+    }
+    if options.lsp {
+        run_lsp(&options);
+    }
+    if options.watch {
+        watch(&options);
     }
 
-    fn skip_whitespace(&mut self) {
-        loop {
-            if let Some(lookahead) = self.lookahead {
-                if lookahead == ' ' {
-                    self.get_char();
-                } else {
-                    break;
-                }
-        } else {
-                break;
-            }
-        }
+    // The compiler reads the input file (or stdin) as it goes. Crash
+    // reports, --verify-passes, and --verify need the source again once
+    // it's compiled, though, so for those I slurp it all up front.
+    let mut program = Vec::new();
+    let input: Box<dyn Read> = if options.save_crash || options.verify_passes || options.verify {
+        open_input(&options).read_to_end(&mut program).unwrap_or_else(|err| {
+            fail(&options, format!("could not read {}: {}", input_name(&options), err));
+        });
+        Box::new(&program[..])
+    } else {
+        open_input(&options)
+    };
+
+    if options.interpret {
+        run_interpreter(input, &program, &options, &args);
+    } else {
+        run(input, &program, &options, &args);
     }
+}
 
-    /// Prints an error message.
-    fn error(&self, msg: &str) {
-        eprintln!("Error: {}", msg);
+/// Opens the input file, or stdin if there isn't one, exiting if we can't.
+fn open_input(options: &Options) -> Box<dyn Read> {
+    match options.input {
+        Some(ref path) => Box::new(File::open(path).unwrap_or_else(|err| {
+            fail(options, format!("could not read {}: {}", path, err));
+        })),
+        None => Box::new(io::stdin()),
     }
-    
-    /// Prints an error message and exits.
-    fn abort(&self, msg: &str) {
-        self.error(msg);
+}
+
+/// Reports an error that isn't in the program itself, in the format the
+/// options ask for, and exits.
+fn fail(options: &Options, msg: String) -> ! {
+    report(options, &[Message::new(msg)]);
+    process::exit(1);
+}
+
+/// What to call the input in errors.
+fn input_name(options: &Options) -> &str {
+    options.input.as_ref().map_or("stdin", |input| input.as_str())
+}
+
+/// `crenshaw replay <file.crash>`: runs the compiler again on the source and
+/// options saved in a crash report.
+fn replay(args: &[String]) {
+    let path = match args {
+        [path] => Path::new(path),
+        _ => {
+            eprintln!("Error: replay needs exactly one crash report");
+            process::exit(1);
+        }
+    };
+
+    let snapshot = Snapshot::load(path).unwrap_or_else(|msg| {
+        eprintln!("Error: {}", msg);
+        process::exit(1);
+    });
+
+    let mut options = Options::parse(snapshot.args.iter().cloned()).unwrap_or_else(|msg| {
+        eprintln!("Error: {} (in {})", msg, path.display());
         process::exit(1);
+    });
+    // Don't overwrite the report we're replaying.
+    options.save_crash = false;
+    options.saved_files = snapshot.files;
+
+    eprintln!("Replaying a crash in {}: {}", snapshot.pass, snapshot.message);
+    if options.interpret {
+        run_interpreter(&snapshot.source[..], &snapshot.source, &options, &snapshot.args);
+    } else {
+        run(&snapshot.source[..], &snapshot.source, &options, &snapshot.args);
     }
+}
 
-    /// Prints an error message prefixed with "expected" and exits.
-    fn expected(&self, what: &str, found: &str) {
-        self.abort(&format!("expected {}, found {}", what, found));
+/// `crenshaw fmt [options] [file]`: prints the program in a file, or stdin,
+/// formatted the canonical way.
+fn fmt(args: &[String]) {
+    let options = Options::parse(args.iter().cloned()).unwrap_or_else(|msg| {
+        eprintln!("Error: {}", msg);
+        process::exit(1);
+    });
+
+    match format_source(open_input(&options), &options) {
+        Some(formatted) => print!("{}", formatted),
+        None => process::exit(1),
     }
+}
 
-    /// If the current lookahead is not equal to the matching character,
-    /// prints an error and exits. Otherwise, consumes another character from
-    /// the input stream, puts it in the lookahead, and returns it.
-    fn consume_exact_char(&mut self, c: char) -> Option<char> {
-        if let Some(lookahead) = self.lookahead {
-            if lookahead == c {
-                self.get_char();
-                self.skip_whitespace();
-                Some(lookahead)
-            } else {
-                self.expected(&c.to_string(), &lookahead.to_string());
-                None
-            }
-        } else {
-            self.expected(&c.to_string(), "nothing");
-            None
+/// `crenshaw validate-dir <dir> [options]`: compiles every source file under a
+/// directory with the given options, checks that each output is a valid
+/// module, and prints how each one did.
+#[cfg(feature = "check")]
+fn validate_dir(args: &[String]) {
+    let options = Options::parse(args.iter().cloned()).unwrap_or_else(|msg| {
+        eprintln!("Error: {}", msg);
+        process::exit(1);
+    });
+    let dir = match options.input {
+        Some(ref dir) => Path::new(dir),
+        None => {
+            eprintln!("Error: validate-dir needs a directory");
+            process::exit(1);
         }
+    };
+
+    let mut paths = Vec::new();
+    if let Err(err) = source_files(dir, &mut paths) {
+        eprintln!("Error: could not read {}: {}", dir.display(), err);
+        process::exit(1);
     }
+    paths.sort();
 
-    fn consume_name(&mut self) -> Option<String> {
-        let mut name = String::from("");
-        
-        loop {
-            if let Some(lookahead) = self.lookahead {
-                if lookahead.is_ascii_alphanumeric() {
-                    name.push(lookahead);
-                    self.get_char();
-                } else {
-                    break;
-                }
-            } else {
-                self.expected("name", "nothing");
-            }
-        }
+    let mut failed = 0;
+    for path in &paths {
+        let start = Instant::now();
+        let result = validate_file(path, &options);
+        let elapsed = start.elapsed();
 
-        self.skip_whitespace();
-        Some(name)
-    }
-
-    /// If the current lookahead is not a digit, prints an error and exits.
-    /// Otherwise, consumes another byte from the input stream, puts it in the
-    /// lookahead, and returns it.
-    fn consume_num(&mut self) -> Option<String> {
-        let mut num = String::from("");
-        
-        loop {
-            if let Some(lookahead) = self.lookahead {
-                if lookahead.is_digit(10) {
-                    num.push(lookahead);
-                    self.get_char();
-                } else {
-                    break;
-                }
-            } else {
-                self.expected("integer", "nothing");
+        match result {
+            Ok(()) => println!("PASS {} ({:.1?})", path.display(), elapsed),
+            Err(msg) => {
+                println!("FAIL {} ({:.1?}): {}", path.display(), elapsed, msg);
+                failed += 1;
             }
         }
+    }
 
-        self.skip_whitespace();
-        Some(num)
+    println!("{} passed, {} failed", paths.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
     }
-    
-    /// Prints the start of a new WebAssembly module.
-    fn emit_module_start(&self) {
-        println!("(module");
+}
+
+/// Without the `check` feature, there's nothing to validate with.
+#[cfg(not(feature = "check"))]
+fn validate_dir(_: &[String]) {
+    eprintln!("Error: validate-dir needs the compiler built with the check feature");
+    process::exit(1);
+}
+
+/// Finds every `.cr` file under a directory.
+#[cfg(feature = "check")]
+fn source_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            source_files(&path, paths)?;
+        } else if path.extension().is_some_and(|extension| extension == "cr") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Compiles a single file and validates the output.
+#[cfg(feature = "check")]
+fn validate_file(path: &Path, options: &Options) -> Result<(), String> {
+    let program = File::open(path).map_err(|err| format!("could not read it: {}", err))?;
+
+    let mut options = options.clone();
+    options.input = Some(path.to_string_lossy().into_owned());
+
+    let mut progress = Progress::default();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| compile(program, &options, &mut progress)));
+    match result {
+        Ok(Some(output)) => validate(&output).map_err(|msg| format!("invalid output: {}", msg)),
+        Ok(None) => Err(String::from("compile errors")),
+        Err(_) => Err(format!("internal compiler error in {}", progress.pass)),
     }
+}
+
+/// Compiles the program in `input` and prints whatever the options asked
+/// for. `program` is the source, if it was read up front, and `args` is the
+/// command line the options came from; both go in crash reports.
+fn run<R: Read>(input: R, program: &[u8], options: &Options, args: &[String]) {
+    let mut progress = Progress::default();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| compile(input, options, &mut progress)));
 
-    /// Prints the closing paren of a WebAssembly module.
-    fn emit_module_end(&self) {
-        println!(")");
+    let output = match result {
+        Ok(Some(output)) => output,
+        Ok(None) => process::exit(1),
+        Err(payload) => crashed(program, options, args, progress, panic_message(payload)),
+    };
+
+    if options.verify_passes {
+        verify_deterministic(program, options, args, &output);
+    }
+    #[cfg(feature = "check")]
+    if options.check {
+        check_output(program, options, args, &mut progress, &output);
+    }
+    #[cfg(feature = "run")]
+    if options.verify {
+        verify_output(program, options, args, &mut progress, &output);
+        return;
+    }
+    #[cfg(feature = "run")]
+    if options.run {
+        run_output(options, &output);
+        return;
     }
 
-    /// Prints the start of a function called main.
-    fn emit_main_start(&self) {
-        println!("(func $main (result i32)");
+    emit(options, &output, &progress);
+}
+
+/// Prints the compiled output, or whatever else the options asked for, and
+/// writes the files that go with it. `progress` is what compiling it left
+/// behind: the files the program was read from, and the map of its coverage
+/// counters, if it has any.
+fn emit(options: &Options, output: &str, progress: &Progress) {
+    match options.emit {
+        Emit::Wat => print!("{}", output),
+        Emit::Deps | Emit::DepsJson => print_deps(options, &progress.files),
     }
 
-    /// Prints the closing paren and export statement of the main function.
-    fn emit_main_end(&self) {
-        println!("(return)");
-        println!(")");
-        println!("(export \"main\" (func $main))");
+    if options.emit == Emit::Wat && options.platform == Platform::Browser {
+        write_loader(options, output, &progress.sources);
+    } else if options.emit == Emit::Wat && options.debug {
+        write_module(options, &module_path(options), output, &progress.sources);
+    }
+    if let (Emit::Wat, Some(ref html)) = (options.emit, &options.html) {
+        write_page(options, Path::new(html), output);
     }
+    if let (Emit::Wat, Some(ref coverage)) = (options.emit, &progress.coverage) {
+        write_coverage(options, coverage);
+    }
+}
 
-    fn parse_assignment(&mut self) {
-        let name = self.consume_name();
+/// Runs the program in `input` with the interpreter and prints what `main`
+/// returns, like the browser loader does. `program` and `args` are as for
+/// `run`.
+fn run_interpreter<R: Read>(input: R, program: &[u8], options: &Options, args: &[String]) {
+    let mut progress = Progress::default();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| interpret(input, options, &mut progress)));
 
-        if let Some(name) = name {
-            println!("(local ${} i32)", name);
-            self.consume_exact_char('=');
-            println!("(set_local ${}", name);
-            self.parse_expression();
-            println!(")");
-            println!("(get_local ${})", name);
-        } else {
-            self.expected("identifier", "nothing");
+    match result {
+        Ok(Some(value)) => {
+            if !options.no_result {
+                println!("{}", value);
+            }
         }
+        Ok(None) => process::exit(1),
+        Err(payload) => crashed(program, options, args, progress, panic_message(payload)),
     }
-    
-    /// <expression> ::= <leading> <term> <addop>
-    /// <leading> ::= "+" | "-" | ""
-    /// <addop> ::= <add-expression> | <subtract-expression>
-    fn parse_expression(&mut self) {
-        
-        if let Some(c) = self.lookahead {
-            if c == '+' {
-                self.consume_exact_char('+');
-                self.parse_term();
-            } else if c == '-' {
-                println!("(i32.const 0)");
-            } else {
-                self.parse_term();
-            }
-        } else {
-            self.expected("term or addop", "nothing");
+}
+
+/// Runs the compiled output with wasmtime, reading from stdin and printing
+/// to stdout, and prints what the entry returns, like the browser loader
+/// does.
+#[cfg(feature = "run")]
+fn run_output(options: &Options, output: &str) {
+    let ran = run::run(output, options, io::stdin().lock(), io::stdout())
+        .unwrap_or_else(|msg| fail(options, format!("internal compiler error: could not run the output: {}", msg)));
+    finish_run(options, ran);
+}
+
+/// Prints which functions and loops were busiest, with --profile, like the
+/// interpreter does, then what the module returned, or why it trapped.
+#[cfg(feature = "run")]
+fn finish_run<W>(options: &Options, ran: run::Run<W>) {
+    if options.profile {
+        eprint!("{}", profile::report(&ran.profile));
+    }
+    match ran.result {
+        Ok(Some(value)) => println!("{}", value),
+        Ok(None) => (),
+        Err(msg) => fail(options, msg),
+    }
+}
+
+/// Runs the REPL on stdin. If an input returns from main, prints what it
+/// returned, like the interpreter does.
+fn run_repl(options: &Options) {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let result = repl::run(options, &mut stdin.lock(), &mut stdout.lock());
+
+    if let Some(value) = result {
+        if !options.no_result {
+            println!("{}", value);
         }
+    }
+}
 
-        loop {
-            if let Some(c) = self.lookahead {
-                if c == '+' {
-                    self.parse_add();
-                } else if c == '-' {
-                    self.parse_subtract();
-                } else {
-                    break;
-                }
-            } else {
-                self.expected("addop", "nothing");
+/// Runs the language server on stdin and stdout, until the editor's done
+/// with it.
+fn run_lsp(options: &Options) -> ! {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let shut_down = lsp::run(options, &mut stdin.lock(), &mut stdout.lock());
+    process::exit(if shut_down { 0 } else { 1 });
+}
+
+/// How often `--watch` looks at the file.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `--watch`: compiles the input, or runs it with --interpret, then does it
+/// again every time it or a file it includes or is linked with changes,
+/// until we're stopped. Errors in the program, and crashes in the compiler,
+/// are reported and then we go back to waiting, so one bad save doesn't stop
+/// the watching.
+fn watch(options: &Options) -> ! {
+    let path = Path::new(options.input.as_ref().expect("--watch sets the input"));
+    let mut files: Vec<String> = iter::once(path.to_string_lossy().into_owned())
+        .chain(options.linked.iter().cloned())
+        .collect();
+    let mut last_modified = Vec::new();
+
+    loop {
+        // Editors often save by replacing the file, so it can be missing
+        // for a moment. We'll see it when it's back.
+        let modified: Vec<_> = files.iter().map(|file| last_changed(file)).collect();
+        if modified[0].is_some() && modified != last_modified {
+            eprintln!("Compiling {}", path.display());
+
+            // The files the program includes can change from one build to
+            // the next.
+            let read = rebuild(path, options);
+            if !read.is_empty() {
+                files = read;
             }
+            last_modified = files.iter().map(|file| last_changed(file)).collect();
         }
+        thread::sleep(WATCH_INTERVAL);
     }
-    
-    /// <term> ::= <factor> <multop>
-    /// <multop> ::= <multiply-expression> | <divide-expression>
-    fn parse_term(&mut self) {
-        self.parse_factor();
-        loop {
-            if let Some(c) = self.lookahead {
-                if c == '*' {
-                    self.parse_multiply();
-                } else if c == '/' {
-                    self.parse_divide();
-                } else {
-                    break;
+}
+
+/// When a file was last changed, if it's there.
+fn last_changed(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Compiles or runs the watched file once, without exiting. Returns the
+/// files it read, if it got as far as reading them.
+fn rebuild(path: &Path, options: &Options) -> Vec<String> {
+    let program = match File::open(path) {
+        Ok(program) => program,
+        Err(err) => {
+            report(options, &[Message::new(format!("could not read {}: {}", path.display(), err))]);
+            return Vec::new();
+        }
+    };
+
+    let mut progress = Progress::default();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        if options.interpret {
+            if let Some(value) = interpret(program, options, &mut progress) {
+                if !options.no_result {
+                    println!("{}", value);
                 }
-            } else {
-                self.expected("multop", "nothing");
             }
+        } else if let Some(output) = compile(program, options, &mut progress) {
+            emit(options, &output, &progress);
         }
+    }));
+
+    if result.is_err() {
+        let msg = format!("internal compiler error in {}", progress.pass);
+        report(options, &[Message::new(msg)]);
     }
+    progress.files
+}
 
-    /// <factor> ::= "(" <expression> ")" | <number>
-    fn parse_factor(&mut self) {
-        if let Some(c) = self.lookahead {
-            if c == '(' {
-                self.consume_exact_char('(');
-                self.parse_expression();
-                self.consume_exact_char(')');
-            } else if c.is_ascii_alphabetic() {
-                self.parse_identifier();
-            } else {
-                let num = self.consume_num().unwrap();
-                println!("(i32.const {})", num);
-            }
-        } else {
-            self.expected("expression", "nothing");
-        }
+/// The message a panic was started with. The panic hook has already printed
+/// it, so we only need it for crash reports.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|msg| msg.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| String::from("unknown panic"))
+}
+
+/// Writes the script that runs the program in a browser next to the input,
+/// or to out.js if we read stdin, and the .wasm file with the same name that
+/// it loads. `sources` are as for `write_module`.
+fn write_loader(options: &Options, output: &str, sources: &[(String, Vec<u8>)]) {
+    let path = match options.input {
+        Some(ref input) => Path::new(input).with_extension("js"),
+        None => PathBuf::from("out.js"),
+    };
+    let wasm = module_path(options);
+    write_module(options, &wasm, output, sources);
+    let wasm = wasm.file_name().map_or(String::from("out.wasm"), |name| name.to_string_lossy().into_owned());
+
+    write_file(options, &path, browser::loader(&wasm, options.entry_name()).as_bytes());
+}
+
+/// Where the module goes: next to the input, or out.wasm if we read stdin.
+fn module_path(options: &Options) -> PathBuf {
+    match options.input {
+        Some(ref input) => Path::new(input).with_extension("wasm"),
+        None => PathBuf::from("out.wasm"),
     }
+}
 
-    fn parse_identifier(&mut self) {
-        let name = self.consume_name().unwrap();
-        if let Some(c) = self.lookahead {
-            if c == '(' {
-                self.consume_exact_char('(');
-                self.consume_exact_char(')');
-                println!("(call ${})", name);
-            } else {
-                println!("(get_local ${})", name);
-            }
-        }
+/// Assembles the output and writes it to `path`. `sources` are the files
+/// the program was read from, which only --debug needs.
+#[cfg(all(feature = "assemble", not(feature = "debug")))]
+fn write_module(options: &Options, path: &Path, output: &str, _: &[(String, Vec<u8>)]) {
+    write_file(options, path, &assemble(options, output));
+}
+
+/// Assembles the output and writes it to `path`. With --debug, the source
+/// map goes next to it, with .map on the end of its name, and has a copy of
+/// `sources`, the files the program was read from and what was in them.
+#[cfg(feature = "debug")]
+fn write_module(options: &Options, path: &Path, output: &str, sources: &[(String, Vec<u8>)]) {
+    if !options.debug {
+        write_file(options, path, &assemble(options, output));
+        return;
+    }
+
+    let map = path.with_extension("wasm.map");
+    let url = map.file_name().map_or(String::from("out.wasm.map"), |name| name.to_string_lossy().into_owned());
+    let (module, source_map) = debug::assemble(output, sources, &url)
+        .unwrap_or_else(|err| fail(options, format!("could not assemble the output: {}", err)));
+    write_file(options, path, &module);
+    write_file(options, &map, source_map.as_bytes());
+}
+
+/// Assembles the output, exiting if we can't.
+#[cfg(feature = "assemble")]
+fn assemble(options: &Options, output: &str) -> Vec<u8> {
+    browser::assemble(output).unwrap_or_else(|err| fail(options, format!("could not assemble the output: {}", err)))
+}
+
+/// Without the `assemble` feature, there's nothing to assemble with, so the
+/// output has to be assembled by hand, and the loader says so if it isn't.
+#[cfg(not(feature = "assemble"))]
+fn write_module(_: &Options, path: &Path, _: &str, _: &[(String, Vec<u8>)]) {
+    eprintln!("Assemble the output to {} for the loader to load it.", path.display());
+}
+
+/// Writes the map of the coverage counters next to the input, or to
+/// out.coverage.json if we read stdin.
+fn write_coverage(options: &Options, coverage: &str) {
+    let path = match options.input {
+        Some(ref input) => Path::new(input).with_extension("coverage.json"),
+        None => PathBuf::from("out.coverage.json"),
+    };
+    write_file(options, &path, coverage.as_bytes());
+}
+
+/// Writes a page that runs the program in a browser. The module's in the
+/// page, so there's nothing else to open.
+fn write_page(options: &Options, path: &Path, output: &str) {
+    let title = options.input.as_ref().map_or("crenshaw", |input| input.as_str());
+    let url = module_url(options, &path.with_extension("wasm"), output);
+    write_file(options, path, browser::page(title, &url, options.entry_name()).as_bytes());
+}
+
+/// The URL of the module, for the page: the module itself, in a data URL.
+#[cfg(feature = "assemble")]
+fn module_url(options: &Options, _: &Path, output: &str) -> String {
+    browser::data_url(&assemble(options, output))
+}
+
+/// Without the `assemble` feature, the page loads the .wasm file with the
+/// same name, which has to be assembled by hand.
+#[cfg(not(feature = "assemble"))]
+fn module_url(_: &Options, wasm: &Path, _: &str) -> String {
+    eprintln!("Assemble the output to {} for the page to load it.", wasm.display());
+    wasm.file_name().map_or(String::from("out.wasm"), |name| name.to_string_lossy().into_owned())
+}
+
+/// Writes a file next to the output, exiting if we can't.
+fn write_file(options: &Options, path: &Path, contents: &[u8]) {
+    if let Err(err) = fs::write(path, contents) {
+        fail(options, format!("could not write {}: {}", path.display(), err));
     }
+}
+
+/// Handles an internal compiler error: saves a crash report if we were asked
+/// to, then exits.
+fn crashed(program: &[u8], options: &Options, args: &[String], progress: Progress, message: String) -> ! {
+    if options.save_crash {
+        let path = match options.input {
+            Some(ref input) => Path::new(input).with_extension("crash"),
+            None => Path::new("stdin.crash").to_path_buf(),
+        };
 
-    /// <add-expression> ::= <empty> | <plus-term>
-    /// <plus-term> ::= "+" <term>
-    fn parse_add(&mut self) {
-        // Consume a '+' character from the stream.
-        self.consume_exact_char('+');
-        
-        // Call term() again to consume one more term.
-        self.parse_term();
-        
-        // Add the two terms on the stack.
-        println!("(i32.add)");
+        let snapshot = Snapshot {
+            args: args.to_vec(),
+            source: program.to_vec(),
+            // The input's first, and it's already in the report.
+            files: progress.sources.into_iter().skip(1).collect(),
+            pass: progress.pass.to_string(),
+            message,
+            ast: progress.ast,
+            hir: progress.hir,
+        };
+
+        match snapshot.save(&path) {
+            Ok(()) => eprintln!(
+                "Saved a crash report to {0}. Run `crenshaw replay {0}` to reproduce it.",
+                path.display()
+            ),
+            Err(msg) => report(options, &[Message::new(msg)]),
+        }
     }
 
-    /// <subtract-expression> ::= <empty> | <minus-term>
-    /// <minus-term> ::= "-" <term>
-    fn parse_subtract(&mut self) {
-        self.consume_exact_char('-');
-        self.parse_term();
-        println!("(i32.sub)");
+    process::exit(101);
+}
+
+/// Prints the files the compiled output depends on, so build systems know
+/// when to recompile: the input, and any files it includes or is linked
+/// with.
+fn print_deps(options: &Options, dependencies: &[String]) {
+    let input = match options.input {
+        Some(ref input) => input,
+        None => fail(options, String::from("listing dependencies needs an input file")),
+    };
+
+    let target = Path::new(input).with_extension("wat");
+    let target = target.to_string_lossy();
+
+    if options.emit == Emit::Deps {
+        let escaped: Vec<String> = dependencies.iter().map(|dep| dep.replace(' ', "\\ ")).collect();
+        println!("{}: {}", target.replace(' ', "\\ "), escaped.join(" "));
+    } else {
+        let quoted: Vec<String> = dependencies.iter().map(|dep| quote(dep)).collect();
+        println!("{{\"target\":{},\"dependencies\":[{}]}}", quote(&target), quoted.join(","));
     }
+}
+
+/// Hashes a chunk of generated output so runs can be compared cheaply.
+fn output_hash(output: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    output.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Compiles the program a second time and makes sure we get exactly the same
+/// output. Each optimization pass has already been checked, as it ran, to
+/// change nothing when it runs again.
+fn verify_deterministic(program: &[u8], options: &Options, args: &[String], output: &str) {
+    let mut progress = Progress::default();
+    let second = compile(program, options, &mut progress);
 
-    /// <multiply-expression> ::= <empty> | <multiply-factor>
-    /// <multiply-factor> ::= "*" <factor>
-    fn parse_multiply(&mut self) {
-        self.consume_exact_char('*');
-        self.parse_factor();
-        println!("(i32.mul)");
+    if second.map(|second| output_hash(&second)) != Some(output_hash(output)) {
+        let message = "output changed between two identical runs";
+        report(options, &[Message::new(format!("internal compiler error: {}", message))]);
+        progress.pass = "verify-passes";
+        crashed(program, options, args, progress, message.to_string());
     }
+}
 
-    /// <divide-expression> ::= <empty> | <divide-factor>
-    /// <divide-factor> ::= "/" <factor>
-    fn parse_divide(&mut self) {
-        self.consume_exact_char('/');
-        self.parse_factor();
-        println!("(i32.div_s)");
+/// Makes sure the output is a valid module before we print it, so a code
+/// generation bug shows up as a compiler error rather than a module that
+/// doesn't load.
+#[cfg(feature = "check")]
+fn check_output(program: &[u8], options: &Options, args: &[String], progress: &mut Progress, output: &str) {
+    if let Err(msg) = validate(output) {
+        let message = format!("invalid output: {}", mangle::explain(&msg));
+        report(options, &[Message::new(format!("internal compiler error: {}", message))]);
+        progress.pass = "check";
+        crashed(program, options, args, mem::take(progress), message);
     }
 }
 
-fn main() {
-    // I slurp up everything from stdin into a Cursor<Vec<u8>>.
-    // I'm not planning on writing big programs, so this works for now.
-    // Later on, I can replace the cursor with a file and it should keep working as is.
-    let mut program = Vec::new();
-    io::stdin().read_to_end(&mut program).expect("could not read from stdin");
-    
-    let mut compiler = Compiler::new(program);
-    compiler.emit();
+/// Runs the program with the interpreter, reading from stdin, and the output
+/// with wasmtime, and makes sure they do the same thing. If they do, prints
+/// what the module printed and returned, like --run.
+#[cfg(feature = "run")]
+fn verify_output(program: &[u8], options: &Options, args: &[String], progress: &mut Progress, output: &str) {
+    // The interpreter runs on a thread of its own, and a lock on stdin can't
+    // go with it.
+    let mut input = io::BufReader::new(io::stdin());
+    let ran = match run::verify(program, output, options, &mut input) {
+        Ok(ran) => ran,
+        Err(Unverified::OutOfStack(which)) => {
+            let msg = format!("--verify couldn't compare the interpreter and the module: {} ran out of stack", which);
+            fail(options, msg)
+        }
+        Err(Unverified::Differs(msg)) => {
+            let message = format!("--verify failed: {}", msg);
+            report(options, &[Message::new(format!("internal compiler error: {}", message))]);
+            progress.pass = "verify";
+            crashed(program, options, args, mem::take(progress), message);
+        }
+    };
+
+    if let Err(err) = io::Write::write_all(&mut io::stdout(), &ran.output) {
+        fail(options, format!("could not write the output: {}", err));
+    }
+    finish_run(options, ran);
 }
@@ -0,0 +1,95 @@
+//! Turning the program's names into WAT `$names`. Most names go in as they
+//! are, so the output reads like the program. Two kinds don't:
+//!
+//! - Names the compiler uses for its own functions, like `dispatch` or
+//!   `print_i64`, would clash with them, so they get a `%` on the end.
+//! - Bytes that can't go in a `$name`, and `%` itself, are written as `%`
+//!   and two hex digits.
+//!
+//! A `%` is either followed by two hex digits or is the last character, so
+//! no two names mangle to the same thing, and `demangle` can undo it.
+//!
+//! The compiler's other names, like `$heap.top` or `$var.frame`, all have a
+//! `.` in them, which the program's names can't, so they never clash.
+
+/// The names of the functions the compiler adds to a module or imports.
+const RESERVED: &[&str] = &[
+    "main",
+    "init",
+    "dispatch",
+    "print",
+    "print_i64",
+    "print_f64",
+    "print_str",
+    "read",
+    "divide_by_zero",
+    "out_of_fuel",
+    "assertion_failed",
+    "exit",
+    "random",
+    "random_get",
+    "now",
+    "clock_time_get",
+    "_start",
+    "proc_exit",
+    "fd_write",
+];
+
+/// Returns the `$name` for a name from the program, without the `$`.
+pub fn mangle(name: &str) -> String {
+    let mut mangled = String::new();
+    for byte in name.bytes() {
+        if is_id_char(byte) && byte != b'%' {
+            mangled.push(char::from(byte));
+        } else {
+            mangled.push_str(&format!("%{:02x}", byte));
+        }
+    }
+    if RESERVED.contains(&name) {
+        mangled.push('%');
+    }
+    mangled
+}
+
+/// Returns the name from the program that `mangle` turned into `mangled`.
+/// Names that didn't come from `mangle` come back as they are.
+pub fn demangle(mangled: &str) -> String {
+    let mangled = mangled.strip_suffix('%').unwrap_or(mangled).as_bytes();
+    let mut name = Vec::new();
+    let mut i = 0;
+    while i < mangled.len() {
+        let escape = mangled.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match escape.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) if mangled[i] == b'%' => {
+                name.push(byte);
+                i += 3;
+            }
+            _ => {
+                name.push(mangled[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&name).into_owned()
+}
+
+/// Adds what each mangled `$name` in a message was called in the program, so
+/// a message about the output can be traced back to it.
+pub fn explain(message: &str) -> String {
+    let mut explained = String::from(message);
+    let mut seen = Vec::new();
+    for word in message.split(|c: char| c.is_whitespace() || c == '`') {
+        let name = match word.strip_prefix('$') {
+            Some(name) if name.contains('%') && !seen.contains(&name) => name,
+            _ => continue,
+        };
+        explained.push_str(&format!(" (`${}` is `{}` in the program)", name, demangle(name)));
+        seen.push(name);
+    }
+    explained
+}
+
+/// The characters the text format allows in a `$name`.
+fn is_id_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-./:<=>?@\\^_`|~".contains(&byte)
+}
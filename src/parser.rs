@@ -0,0 +1,2219 @@
+//! The scanner and recursive descent parser. We read the program a character
+//! at a time, Crenshaw style, and build the syntax tree in `ast`. The program
+//! can come from anything that implements `Read`, and only gets read as far
+//! as the parser has got.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use ast::{
+    Array, BinaryOp, Constant, Enum, Expr, ExprKind, Field, FuncType, Function, Global, Layout, Place, Program, Record,
+    RecordType, Span, Stmt, StmtKind, Type,
+};
+use builtins;
+use diagnostics::{Aborted, Diagnostics};
+
+/// What a newline means, picked with --newlines.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Newlines {
+    // A newline ends a statement, like a `;` does.
+    #[default]
+    Significant,
+
+    // Newlines are whitespace, and statements have to end with a `;`.
+    Whitespace,
+}
+
+/// How deeply statements and expressions can nest. Everything after the
+/// parser walks the tree recursively, so without a limit, a deep enough
+/// program would overflow the stack.
+pub const MAX_DEPTH: usize = 1000;
+
+/// How many bytes of the program get read at a time.
+const CHUNK_SIZE: usize = 4096;
+
+/// Words that can't be used as identifiers.
+const KEYWORDS: &[&str] = &[
+    "func", "end", "if", "elseif", "else", "while", "for", "to", "return", "global", "on", "const", "true",
+    "false", "mem", "array", "print", "read", "export", "init", "and", "or", "begin", "include", "var",
+    "record", "enum", "case", "when", "assert", "halt",
+];
+
+/// Something that would have been legal where the parser is. The parser
+/// checks for far more of these than ever go missing, so they're only turned
+/// into text when there's an error to report.
+#[derive(Clone, Copy)]
+enum Expected {
+    // A character, operator, or keyword, shown in backticks.
+    Char(char),
+    Token(&'static str),
+
+    // A kind of token, like an identifier, shown as it is.
+    Kind(&'static str),
+}
+
+impl Expected {
+    fn describe(self) -> String {
+        match self {
+            Expected::Char(c) => format!("`{}`", c),
+            Expected::Token(token) => format!("`{}`", token),
+            Expected::Kind(kind) => kind.to_string(),
+        }
+    }
+}
+
+/// Where the program comes from. Each chunk read goes on the end of the file
+/// in the source map, which is the only copy of the program: errors need the
+/// text to show where they are, and the parser looks at it from there.
+struct Source<R> {
+    reader: R,
+
+    // Whether the reader has run out.
+    done: bool,
+
+    // Whether it ran out because reading failed. That's been reported, so
+    // running into the end of the program after it isn't.
+    failed: bool,
+}
+
+/// A function's parameters, with their types, where each one's name is, and
+/// the names of the ones declared `var`.
+type Params = (Vec<(String, Type)>, Vec<Span>, Vec<String>);
+
+pub struct Parser<'a, R> {
+    // The next character we're going to consider.
+    lookahead: Option<char>,
+
+    // The program, read as we go.
+    source: Source<R>,
+
+    // The file the program goes in, in the source map.
+    file: Span,
+
+    // The offset of the next byte to read, from the start of the file.
+    position: usize,
+
+    // Whether a newline ends a statement. If it doesn't, newlines are just
+    // whitespace and statements have to be separated with semicolons.
+    newlines_significant: bool,
+
+    // Where the last token we consumed ends. Spans end here.
+    token_end: usize,
+
+    // Everything the parser checked the lookahead against since it last
+    // consumed a character. When it gets stuck, this is the list of things
+    // that would have been legal.
+    expected_tokens: Vec<Expected>,
+
+    // How deeply nested what we're parsing is, in the tree we're building.
+    depth: usize,
+
+    // Whether we're in a function's body, where other functions can be
+    // defined.
+    in_function: bool,
+
+    // The files being parsed, as absolute paths, from the outermost one to
+    // the one that included this one, and then this one, so a file can't
+    // include itself.
+    includes: Vec<PathBuf>,
+
+    // What the formatter needs that the tree doesn't keep, when we're
+    // parsing for it.
+    layout: Option<Layout>,
+
+    // The rules we're in the middle of, innermost last, when we're printing
+    // each one as it's entered and left.
+    trace: Option<Vec<&'static str>>,
+
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl<'a, R: Read> Parser<'a, R> {
+    /// Makes a parser for the program in `reader`. It goes in `file`, the
+    /// last file in the source map that `diagnostics` reports against, which
+    /// should start out empty.
+    pub fn new(reader: R, file: Span, newlines: Newlines, diagnostics: &'a mut Diagnostics) -> Parser<'a, R> {
+        // Stdin isn't a file, so nothing can include it.
+        let name = diagnostics.source_map().file(file).name();
+        let includes = fs::canonicalize(name).into_iter().collect();
+
+        Parser {
+            lookahead: None,
+            source: Source {
+                reader,
+                done: false,
+                failed: false,
+            },
+            file,
+            position: 0,
+            newlines_significant: newlines == Newlines::Significant,
+            token_end: 0,
+            expected_tokens: Vec::new(),
+            depth: 0,
+            in_function: false,
+            includes,
+            layout: None,
+            trace: None,
+            diagnostics,
+        }
+    }
+
+    /// Prints each rule of the grammar to stderr as it's entered and left,
+    /// with where we are and what the lookahead is, for --trace. It's the
+    /// recursive descent laid out as it happens.
+    pub fn trace(&mut self) {
+        self.trace = Some(Vec::new());
+    }
+
+    fn init(&mut self) {
+        self.get_char();
+        self.skip_trivia();
+    }
+
+    /// <program> ::= <separators> { <item> <separators> }
+    /// <item> ::= <function> | <handler> | <global> | <constant> | <record> | <enum> | <include> | <statement>
+    /// <separators> ::= { newline | ";" }, where newlines only count with
+    ///                  significant newlines
+    pub fn parse_program(&mut self) -> Result<Program, Aborted> {
+        self.init();
+        self.enter("parse_program");
+
+        let mut program = Program {
+            functions: Vec::new(),
+            handlers: Vec::new(),
+            globals: Vec::new(),
+            constants: Vec::new(),
+            arrays: Vec::new(),
+            records: Vec::new(),
+            enums: Vec::new(),
+            init: Vec::new(),
+            main: Vec::new(),
+        };
+
+        self.skip_separators();
+
+        while !self.at_end() {
+            if self.at_keyword("func") || self.at_keyword("export") {
+                let function = self.parse_function()?;
+                program.functions.push(function);
+            } else if self.at_keyword("on") {
+                let handler = self.parse_handler()?;
+                program.handlers.push(handler);
+            } else if self.at_keyword("global") {
+                let global = self.parse_global()?;
+                program.globals.push(global);
+            } else if self.at_keyword("const") {
+                let constant = self.parse_constant()?;
+                program.constants.push(constant);
+            } else if self.at_keyword("array") {
+                let array = self.parse_array()?;
+                program.arrays.push(array);
+            } else if self.at_keyword("record") {
+                let record = self.parse_record()?;
+                program.records.push(record);
+            } else if self.at_keyword("enum") {
+                let item = self.parse_enum()?;
+                program.enums.push(item);
+            } else if self.at_keyword("init") {
+                let init = self.parse_init()?;
+                program.init.extend(init);
+            } else if self.at_keyword("include") {
+                self.parse_include(&mut program)?;
+            } else {
+                let stmt = self.parse_statement()?;
+                program.main.push(stmt);
+            }
+
+            self.end_statement()?;
+        }
+
+        Ok(self.leave(program))
+    }
+
+    /// Parses the program for the formatter, which needs to print it back
+    /// the way it was written: comments and all, with its `init` blocks
+    /// apart and its `include`s as they are, rather than followed.
+    pub fn parse_for_formatting(&mut self) -> Result<(Program, Layout), Aborted> {
+        self.layout = Some(Layout::default());
+        let program = self.parse_program()?;
+        Ok((program, self.layout.take().unwrap_or_default()))
+    }
+
+    /// <function> ::= [ "export" ] "func" <name> <params> [ ":" <type> { "," <type> } ] <block> "end"
+    fn parse_function(&mut self) -> Result<Function, Aborted> {
+        self.enter("parse_function");
+        let function_start = self.offset();
+        let exported = self.at_keyword("export");
+        if exported {
+            self.consume_keyword("export")?;
+        }
+        self.consume_keyword("func")?;
+
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+
+        let start = self.offset();
+        let name = self.consume_name();
+        let name_span = self.span_from(start);
+        let (params, param_spans, var_params) = self.parse_params()?;
+        let result = self.parse_annotation()?.unwrap_or_default();
+        let mut more_results = Vec::new();
+        while self.at(',') {
+            self.consume_exact_char(',')?;
+            more_results.push(self.parse_type()?);
+        }
+        let nested = self.in_function;
+        self.in_function = true;
+        let (body, _) = self.parse_block(&["end"])?;
+        self.in_function = nested;
+        let span = self.span_from(function_start);
+        if let Some(ref mut layout) = self.layout {
+            // Nested functions are statements, which the formatter finds in
+            // the tree.
+            if !nested {
+                layout.functions.push(span);
+            }
+        }
+
+        Ok(self.leave(Function {
+            name,
+            name_span,
+            params,
+            param_spans,
+            var_params,
+            result,
+            more_results,
+            body,
+            exported,
+        }))
+    }
+
+    /// <handler> ::= "on" <name> <params> <block> "end"
+    ///
+    /// Handlers take at most one parameter, the event's payload, which is an
+    /// i32. They return an i32.
+    fn parse_handler(&mut self) -> Result<Function, Aborted> {
+        self.enter("parse_handler");
+        let handler_start = self.offset();
+        self.consume_keyword("on")?;
+
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+
+        let start = self.offset();
+        let name = self.consume_name();
+        let name_span = self.span_from(start);
+        let (params, param_spans, var_params) = self.parse_params()?;
+        if params.len() > 1 {
+            self.error(name_span, "event handlers take at most one parameter");
+        }
+        if params.iter().any(|&(_, ty)| ty != Type::I32) {
+            self.error(name_span, "event payloads are i32");
+        }
+        if !var_params.is_empty() {
+            self.error(name_span, "event payloads can't be `var`; the host has no variable to pass");
+        }
+        let (body, _) = self.parse_block(&["end"])?;
+        let span = self.span_from(handler_start);
+        if let Some(ref mut layout) = self.layout {
+            layout.handlers.push(span);
+        }
+
+        Ok(self.leave(Function {
+            name,
+            name_span,
+            params,
+            param_spans,
+            var_params: Vec::new(),
+            more_results: Vec::new(),
+            result: Type::I32,
+            body,
+            exported: false,
+        }))
+    }
+
+    /// <init> ::= "init" <block> "end"
+    ///
+    /// A program can have any number of these. They run in order, when the
+    /// module's instantiated.
+    fn parse_init(&mut self) -> Result<Vec<Stmt>, Aborted> {
+        self.enter("parse_init");
+        let start = self.offset();
+        self.consume_keyword("init")?;
+        let (body, _) = self.parse_block(&["end"])?;
+        let span = self.span_from(start);
+        if let Some(ref mut layout) = self.layout {
+            layout.init_blocks.push((span, body.len()));
+        }
+        Ok(self.leave(body))
+    }
+
+    /// <include> ::= "include" <string>
+    ///
+    /// Parses another file, found relative to this one, and adds everything
+    /// in it to `program`, as if it were written here. Errors in it point
+    /// into it, since it goes in the source map as a file of its own.
+    fn parse_include(&mut self, program: &mut Program) -> Result<(), Aborted> {
+        let include_start = self.offset();
+        self.consume_keyword("include")?;
+        if !self.at('"') {
+            return Err(self.unexpected());
+        }
+        let start = self.offset();
+        let name = String::from_utf8_lossy(&self.consume_string()).into_owned();
+        let span = self.span_from(start);
+
+        let item = self.span_from(include_start);
+        if let Some(ref mut layout) = self.layout {
+            layout.includes.push((item, name));
+            return Ok(());
+        }
+
+        let here = Path::new(self.diagnostics.source_map().file(self.file).name());
+        let path = here.parent().unwrap_or(Path::new("")).join(&name);
+        // A saved file might not be on disk, so it might not have a
+        // canonical path either.
+        let opened = match self.diagnostics.source_map().saved(&path.to_string_lossy()) {
+            Some(source) => {
+                let canonical = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                Ok((canonical, Box::new(io::Cursor::new(source.to_vec())) as Box<dyn Read>))
+            }
+            None => fs::canonicalize(&path)
+                .and_then(|canonical| Ok((canonical, Box::new(File::open(&path)?) as Box<dyn Read>))),
+        };
+        let (canonical, reader) = match opened {
+            Ok(opened) => opened,
+            Err(err) => {
+                self.error(span, &format!("could not read {}: {}", path.display(), err));
+                return Ok(());
+            }
+        };
+        if self.includes.contains(&canonical) {
+            self.error(span, &format!("can't include {}, which is already being included", path.display()));
+            return Ok(());
+        }
+
+        // Only the last file in the source map can grow, so the rest of
+        // this one has to be read before the included one goes in.
+        while !self.source.done {
+            self.read_chunk();
+        }
+
+        let newlines = if self.newlines_significant {
+            Newlines::Significant
+        } else {
+            Newlines::Whitespace
+        };
+        let file = self
+            .diagnostics
+            .source_map_mut()
+            .add_file(&path.to_string_lossy(), Vec::new());
+        let mut parser = Parser::new(reader, file, newlines, self.diagnostics);
+        parser.trace = self.trace.clone();
+        parser.includes = self.includes.clone();
+        parser.includes.push(canonical);
+        program.link(parser.parse_program()?);
+        Ok(())
+    }
+
+    /// <record> ::= "record" <name> <separators> { <name> ":" <type> <separator> <separators> } "end"
+    fn parse_record(&mut self) -> Result<Record, Aborted> {
+        self.enter("parse_record");
+        let record_start = self.offset();
+        self.consume_keyword("record")?;
+
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+
+        let start = self.offset();
+        let name = self.consume_name();
+        let name_span = self.span_from(start);
+        self.skip_separators();
+
+        let mut fields = Vec::new();
+        while !self.at_keyword("end") {
+            if !self.at_identifier() {
+                return Err(self.unexpected());
+            }
+
+            let start = self.offset();
+            let field = self.consume_name();
+            let field_span = self.span_from(start);
+            if fields.iter().any(|existing: &Field| existing.name == field) {
+                self.error(field_span, &format!("`{}` already has a field called `{}`", name, field));
+            }
+            self.consume_exact_char(':')?;
+            let ty = self.parse_type()?;
+            fields.push(Field {
+                name: field,
+                name_span: field_span,
+                ty,
+            });
+            self.end_statement()?;
+        }
+        self.consume_keyword("end")?;
+
+        let span = self.span_from(record_start);
+        if let Some(ref mut layout) = self.layout {
+            layout.records.push(span);
+        }
+
+        Ok(self.leave(Record {
+            name,
+            name_span,
+            fields,
+        }))
+    }
+
+    /// <enum> ::= "enum" <name> <separators> { <name> <separator> <separators> } "end"
+    fn parse_enum(&mut self) -> Result<Enum, Aborted> {
+        self.enter("parse_enum");
+        let enum_start = self.offset();
+        self.consume_keyword("enum")?;
+
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+
+        let start = self.offset();
+        let name = self.consume_name();
+        let name_span = self.span_from(start);
+        self.skip_separators();
+
+        let mut variants: Vec<(String, Span)> = Vec::new();
+        while !self.at_keyword("end") {
+            if !self.at_identifier() {
+                return Err(self.unexpected());
+            }
+
+            let start = self.offset();
+            let variant = self.consume_name();
+            let variant_span = self.span_from(start);
+            if variants.iter().any(|(existing, _)| *existing == variant) {
+                self.error(variant_span, &format!("`{}` already has a variant called `{}`", name, variant));
+            }
+            variants.push((variant, variant_span));
+            self.end_statement()?;
+        }
+        self.consume_keyword("end")?;
+
+        let span = self.span_from(enum_start);
+        if let Some(ref mut layout) = self.layout {
+            layout.enums.push(span);
+        }
+
+        Ok(self.leave(Enum {
+            name,
+            name_span,
+            variants,
+        }))
+    }
+
+    /// <global> ::= "global" <name> [ ":" <type> ] [ "=" <literal> ]
+    fn parse_global(&mut self) -> Result<Global, Aborted> {
+        self.enter("parse_global");
+        self.consume_keyword("global")?;
+
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+
+        let start = self.offset();
+        let name = self.consume_name();
+        let name_span = self.span_from(start);
+        let annotation = self.parse_annotation()?;
+
+        let value = if self.at('=') {
+            self.consume_exact_char('=')?;
+            Some(self.parse_literal()?)
+        } else {
+            None
+        };
+
+        Ok(self.leave(Global {
+            name,
+            name_span,
+            ty: annotation,
+            value,
+        }))
+    }
+
+    /// <constant> ::= "const" <name> [ ":" <type> ] "=" <conditional>
+    fn parse_constant(&mut self) -> Result<Constant, Aborted> {
+        self.enter("parse_constant");
+        self.consume_keyword("const")?;
+
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+
+        let start = self.offset();
+        let name = self.consume_name();
+        let name_span = self.span_from(start);
+        let ty = self.parse_annotation()?;
+        self.consume_exact_char('=')?;
+        let value = self.parse_conditional()?;
+
+        Ok(self.leave(Constant {
+            name,
+            name_span,
+            ty,
+            value,
+        }))
+    }
+
+    /// <array> ::= "array" <name> "[" <conditional> "]"
+    fn parse_array(&mut self) -> Result<Array, Aborted> {
+        self.enter("parse_array");
+        self.consume_keyword("array")?;
+
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+
+        let start = self.offset();
+        let name = self.consume_name();
+        let name_span = self.span_from(start);
+        let len = self.parse_index()?;
+
+        Ok(self.leave(Array { name, name_span, len }))
+    }
+
+    /// <params> ::= "(" [ <param> { "," <param> } ] ")"
+    /// <param> ::= [ "var" ] <name> [ ":" <type> ]
+    ///
+    /// Parameters without a type are i32s. Returns the parameters, and the
+    /// names of the ones declared `var`.
+    fn parse_params(&mut self) -> Result<Params, Aborted> {
+        let mut params: Vec<(String, Type)> = Vec::new();
+        let mut param_spans = Vec::new();
+        let mut var_params = Vec::new();
+        self.consume_exact_char('(')?;
+
+        if !self.at(')') {
+            loop {
+                let by_reference = self.at_keyword("var");
+                if by_reference {
+                    self.consume_keyword("var")?;
+                }
+                if !self.at_identifier() {
+                    return Err(self.unexpected());
+                }
+
+                let start = self.offset();
+                let param = self.consume_name();
+                let span = self.span_from(start);
+                if by_reference {
+                    var_params.push(param.clone());
+                }
+                if params.iter().any(|(existing, _)| *existing == param) {
+                    self.error(span, &format!("duplicate parameter `{}`", param));
+                }
+                let ty = self.parse_annotation()?.unwrap_or_default();
+                params.push((param, ty));
+                param_spans.push(span);
+
+                if self.at(',') {
+                    self.consume_exact_char(',')?;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        self.consume_exact_char(')')?;
+        Ok((params, param_spans, var_params))
+    }
+
+    /// [ ":" <type> ]
+    fn parse_annotation(&mut self) -> Result<Option<Type>, Aborted> {
+        if self.at(':') {
+            self.consume_exact_char(':')?;
+            Ok(Some(self.parse_type()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// <type> ::= "i32" | "i64" | "f64" | "bool" | "str" | <func-type>
+    fn parse_type(&mut self) -> Result<Type, Aborted> {
+        if self.at_keyword("func") {
+            return self.parse_func_type();
+        }
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+
+        // Any other name is a record's or an enum's, which might not have
+        // been defined yet. Type checking works out which.
+        let name = self.consume_name();
+        Ok(Type::from_name(&name).unwrap_or_else(|| Type::Record(RecordType::new(&name))))
+    }
+
+    /// <func-type> ::= "func" "(" [ <type> { "," <type> } ] ")" [ ":" <type> ]
+    ///
+    /// Like a function, a pointer's function returns an i32 unless it says
+    /// otherwise.
+    fn parse_func_type(&mut self) -> Result<Type, Aborted> {
+        self.consume_keyword("func")?;
+        self.consume_exact_char('(')?;
+        let mut params = Vec::new();
+        if !self.at(')') {
+            loop {
+                params.push(self.parse_type()?);
+                if self.at(',') {
+                    self.consume_exact_char(',')?;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.consume_exact_char(')')?;
+        let result = self.parse_annotation()?.unwrap_or_default();
+        Ok(Type::Func(FuncType::new(params, result)))
+    }
+
+    /// <block> ::= <separators> { <statement> <separators> } <terminator>
+    ///
+    /// Returns the statements along with the keyword that ended the block.
+    fn parse_block(&mut self, terminators: &[&'static str]) -> Result<(Vec<Stmt>, &'static str), Aborted> {
+        self.enter("parse_block");
+        let depth = self.nest()?;
+        let mut block = Vec::new();
+        self.skip_separators();
+
+        loop {
+            for &terminator in terminators {
+                if self.at_keyword(terminator) {
+                    self.consume_keyword(terminator)?;
+                    self.depth = depth;
+                    return Ok(self.leave((block, terminator)));
+                }
+            }
+
+            block.push(self.parse_statement()?);
+            self.end_statement()?;
+        }
+    }
+
+    /// Checks that a statement is followed by a separator or the end of the
+    /// program, and skips the separators.
+    fn end_statement(&mut self) -> Result<(), Aborted> {
+        if !self.at_separator() && !self.at_end() {
+            return Err(self.unexpected());
+        }
+
+        self.skip_separators();
+        Ok(())
+    }
+
+    /// Skips any number of statement separators along with the trivia
+    /// surrounding them.
+    fn skip_separators(&mut self) {
+        while self.at_separator() {
+            self.get_char();
+            self.skip_trivia();
+        }
+    }
+
+    /// <statement> ::= <if> | <case> | <while> | <for> | <return> | <print> | <assert> | <halt> | <read>
+    ///               | <store> | <assignment> | <call> | <nested function>
+    fn parse_statement(&mut self) -> Result<Stmt, Aborted> {
+        self.enter("parse_statement");
+        let start = self.offset();
+
+        let stmt = if self.at_keyword("if") {
+            self.parse_if(start)?
+        } else if self.at_keyword("case") {
+            self.parse_case(start)?
+        } else if self.at_keyword("while") {
+            self.parse_while(start)?
+        } else if self.at_keyword("begin") {
+            self.parse_begin(start)?
+        } else if self.at_keyword("for") {
+            self.parse_for(start)?
+        } else if self.at_keyword("return") {
+            self.parse_return(start)?
+        } else if self.at_keyword("print") {
+            self.parse_print(start)?
+        } else if self.at_keyword("assert") {
+            self.parse_assert(start)?
+        } else if self.at_keyword("halt") {
+            self.parse_halt(start)?
+        } else if self.at_keyword("read") {
+            self.parse_read(start)?
+        } else if self.at_keyword("func") {
+            self.parse_nested_function(start)?
+        } else if self.at_keyword("mem") {
+            let address = self.parse_address()?;
+            self.parse_store(start, Place::Memory(address))?
+        } else if self.at_identifier() {
+            self.parse_assignment(start)?
+        } else {
+            return Err(self.unexpected());
+        };
+        Ok(self.leave(stmt))
+    }
+
+    /// <nested function> ::= <function>, in another function's body
+    fn parse_nested_function(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_nested_function");
+        if !self.in_function {
+            let keyword = self.span(start, start + "func".len());
+            self.error(keyword, "functions can only be nested in other functions");
+        }
+        let function = self.parse_function()?;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::Function(Box::new(function)),
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <if> ::= "if" <conditional> <block> { "elseif" <conditional> <block> } [ "else" <block> ] "end"
+    fn parse_if(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_if");
+        self.consume_keyword("if")?;
+
+        let depth = self.depth;
+        let mut branches = Vec::new();
+        let mut otherwise = Vec::new();
+
+        // Each `elseif` becomes an `if` inside the `else` of the one before,
+        // so it's a level deeper than the branch before it. The first
+        // branch's block is the `if`'s only level.
+        loop {
+            if !branches.is_empty() {
+                self.nest()?;
+            }
+            let condition = self.parse_conditional()?;
+            let (body, terminator) = self.parse_block(&["elseif", "else", "end"])?;
+            branches.push((condition, body));
+
+            if terminator == "else" {
+                otherwise = self.parse_block(&["end"])?.0;
+                break;
+            } else if terminator == "end" {
+                break;
+            }
+        }
+        self.depth = depth;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::If { branches, otherwise },
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <case> ::= "case" <conditional> <separators> { "when" <expression> { "," <expression> } <block> }
+    ///            [ "else" <block> ] "end"
+    fn parse_case(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_case");
+        self.consume_keyword("case")?;
+        let subject = self.parse_conditional()?;
+        self.skip_separators();
+
+        // The first `when` comes after the subject, and each one after that
+        // ends the block before it.
+        let depth = self.depth;
+        let mut arms = Vec::new();
+        let mut otherwise = None;
+        let mut terminator = "when";
+        if self.at_keyword("else") {
+            self.consume_keyword("else")?;
+            terminator = "else";
+        } else if self.at_keyword("end") {
+            self.consume_keyword("end")?;
+            terminator = "end";
+        } else {
+            self.consume_keyword("when")?;
+        }
+
+        // Like `elseif`, each `when` after the first is a level deeper.
+        while terminator == "when" {
+            if !arms.is_empty() {
+                self.nest()?;
+            }
+            let mut values = vec![self.parse_expression()?];
+            while self.at(',') {
+                self.consume_exact_char(',')?;
+                values.push(self.parse_expression()?);
+            }
+            let (body, end) = self.parse_block(&["when", "else", "end"])?;
+            arms.push((values, body));
+            terminator = end;
+        }
+        if terminator == "else" {
+            otherwise = Some(self.parse_block(&["end"])?.0);
+        }
+        self.depth = depth;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::Case {
+                subject,
+                arms,
+                otherwise,
+            },
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <while> ::= "while" <conditional> <block> "end"
+    fn parse_while(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_while");
+        self.consume_keyword("while")?;
+        let condition = self.parse_conditional()?;
+        let (body, _) = self.parse_block(&["end"])?;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::While { condition, body },
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <begin> ::= "begin" <block> "end"
+    fn parse_begin(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_begin");
+        self.consume_keyword("begin")?;
+        let (body, _) = self.parse_block(&["end"])?;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::Block(body),
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <for> ::= "for" <name> "=" <expression> "to" <expression> <block> "end"
+    fn parse_for(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_for");
+        self.consume_keyword("for")?;
+
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+
+        let variable = self.consume_name();
+        self.consume_exact_char('=')?;
+        let from = self.parse_expression()?;
+        self.consume_keyword("to")?;
+        let to = self.parse_expression()?;
+        let (body, _) = self.parse_block(&["end"])?;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::For {
+                variable,
+                from,
+                to,
+                body,
+            },
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <return> ::= "return" <conditional> { "," <conditional> }
+    fn parse_return(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_return");
+        self.consume_keyword("return")?;
+        let value = self.parse_conditional()?;
+        if !self.at(',') {
+            return Ok(self.leave(Stmt {
+                kind: StmtKind::Return(value),
+                span: self.span_from(start),
+            }));
+        }
+
+        let mut values = vec![value];
+        while self.at(',') {
+            self.consume_exact_char(',')?;
+            values.push(self.parse_conditional()?);
+        }
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::ReturnValues(values),
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <print> ::= "print" <conditional>
+    fn parse_print(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_print");
+        self.consume_keyword("print")?;
+        let value = self.parse_conditional()?;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::Print(value),
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <assert> ::= "assert" <conditional>
+    fn parse_assert(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_assert");
+        self.consume_keyword("assert")?;
+        let condition = self.parse_conditional()?;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::Assert(condition),
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <halt> ::= "halt" <conditional>
+    fn parse_halt(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_halt");
+        self.consume_keyword("halt")?;
+        let status = self.parse_conditional()?;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::Halt(status),
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <read> ::= "read" <name>
+    fn parse_read(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_read");
+        self.consume_keyword("read")?;
+
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+        let name = self.consume_name();
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::Read(name),
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <assignment> ::= <identifier> [ ":" <type> ] <assignop> <conditional>
+    ///                | <identifier> <step>
+    ///                | <identifier> "," <identifier> { "," <identifier> } "=" <conditional>
+    /// <call> ::= <callee> "(" [ <conditional> { "," <conditional> } ] ")"
+    ///
+    /// A call starts with a name too, so it's parsed here.
+    fn parse_assignment(&mut self, start: usize) -> Result<Stmt, Aborted> {
+        self.enter("parse_assignment");
+        let name = self.consume_name();
+        let name = self.parse_builtin_name(name)?;
+        if self.at('(') {
+            let arguments = self.parse_arguments()?;
+            let value = Expr {
+                kind: ExprKind::Call(name, arguments),
+                span: self.span_from(start),
+            };
+            return Ok(self.leave(Stmt {
+                kind: StmtKind::Call(value),
+                span: self.span_from(start),
+            }));
+        }
+        if self.at(',') {
+            let values = self.parse_values(start, name)?;
+            return Ok(self.leave(values));
+        }
+        if self.at('[') {
+            let index = self.parse_index()?;
+            let store = self.parse_store(start, Place::Element(name, index))?;
+            return Ok(self.leave(store));
+        }
+        if self.at('.') {
+            let mut record = Expr {
+                kind: ExprKind::Variable(name),
+                span: self.span_from(start),
+            };
+            let mut field = self.parse_field()?;
+            while self.at('.') {
+                record = Expr {
+                    kind: ExprKind::Field(Box::new(record), field),
+                    span: self.span_from(start),
+                };
+                field = self.parse_field()?;
+            }
+            let store = self.parse_store(start, Place::Field(record, field))?;
+            return Ok(self.leave(store));
+        }
+
+        if let Some((op, value)) = self.parse_step()? {
+            return Ok(self.leave(Stmt {
+                kind: StmtKind::Assign {
+                    name,
+                    ty: None,
+                    op: Some(op),
+                    value,
+                },
+                span: self.span_from(start),
+            }));
+        }
+
+        let ty = self.parse_annotation()?;
+        let op = self.parse_assignop()?;
+
+        if ty.is_some() && op.is_some() {
+            let span = self.span_from(start);
+            self.error(span, "only plain assignments can give a variable a type");
+        }
+
+        let value = self.parse_conditional()?;
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::Assign { name, ty, op, value },
+            span: self.span_from(start),
+        }))
+    }
+
+    /// The rest of `a, b = f(x)`, after the first name.
+    fn parse_values(&mut self, start: usize, first: String) -> Result<Stmt, Aborted> {
+        let mut names = vec![first];
+        while self.at(',') {
+            self.consume_exact_char(',')?;
+            if !self.at_identifier() {
+                return Err(self.unexpected());
+            }
+
+            let name_start = self.offset();
+            let name = self.consume_name();
+            if names.contains(&name) {
+                let span = self.span_from(name_start);
+                self.error(span, &format!("`{}` is assigned more than once", name));
+            }
+            names.push(name);
+        }
+        self.consume_exact_char('=')?;
+        let value = self.parse_conditional()?;
+
+        Ok(Stmt {
+            kind: StmtKind::AssignValues { names, value },
+            span: self.span_from(start),
+        })
+    }
+
+    /// <store> ::= <place> <assignop> <conditional> | <place> <step>
+    /// <place> ::= <memory> | <name> "[" <conditional> "]" | <name> "." <name> { "." <name> }
+    ///
+    /// The caller has already parsed the place.
+    fn parse_store(&mut self, start: usize, place: Place) -> Result<Stmt, Aborted> {
+        self.enter("parse_store");
+        let (op, value) = match self.parse_step()? {
+            Some((op, value)) => (Some(op), value),
+            None => (self.parse_assignop()?, self.parse_conditional()?),
+        };
+
+        Ok(self.leave(Stmt {
+            kind: StmtKind::Store { place, op, value },
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <step> ::= "++" | "--"
+    ///
+    /// `x++` is short for `x += 1`, and `x--` for `x -= 1`. Returns the
+    /// operator and the `1`, or None if there's no step here.
+    fn parse_step(&mut self) -> Result<Option<(BinaryOp, Expr)>, Aborted> {
+        let op = if self.at_op("++") {
+            BinaryOp::Add
+        } else if self.at_op("--") {
+            BinaryOp::Subtract
+        } else {
+            return Ok(None);
+        };
+
+        let start = self.offset();
+        self.consume_op(2);
+        let one = Expr {
+            kind: ExprKind::Int(String::from("1"), None),
+            span: self.span_from(start),
+        };
+        Ok(Some((op, one)))
+    }
+
+    /// <assignop> ::= "=" | "+=" | "-=" | "*=" | "/="
+    ///
+    /// Returns the operator for the compound assignments, and None for a
+    /// plain `=`.
+    fn parse_assignop(&mut self) -> Result<Option<BinaryOp>, Aborted> {
+        let op = if self.at_op("=") {
+            None
+        } else if self.at_op("+=") {
+            Some(BinaryOp::Add)
+        } else if self.at_op("-=") {
+            Some(BinaryOp::Subtract)
+        } else if self.at_op("*=") {
+            Some(BinaryOp::Multiply)
+        } else if self.at_op("/=") {
+            Some(BinaryOp::Divide)
+        } else {
+            return Err(self.unexpected());
+        };
+
+        self.consume_op(if op.is_some() { 2 } else { 1 });
+        Ok(op)
+    }
+
+    /// <memory> ::= "mem" "[" <conditional> "]"
+    ///
+    /// Returns the address.
+    fn parse_address(&mut self) -> Result<Expr, Aborted> {
+        self.consume_keyword("mem")?;
+        self.parse_index()
+    }
+
+    /// Parses an expression in square brackets.
+    fn parse_index(&mut self) -> Result<Expr, Aborted> {
+        self.consume_exact_char('[')?;
+        let index = self.parse_conditional()?;
+        self.consume_exact_char(']')?;
+        Ok(index)
+    }
+
+    /// <conditional> ::= <or> [ "?" <conditional> ":" <conditional> ]
+    fn parse_conditional(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_conditional");
+        let depth = self.nest()?;
+        let condition = self.parse_or()?;
+        if !self.at('?') {
+            self.depth = depth;
+            return Ok(self.leave(condition));
+        }
+
+        self.consume_exact_char('?')?;
+        let then_value = self.parse_conditional()?;
+        self.consume_exact_char(':')?;
+        let else_value = self.parse_conditional()?;
+        self.depth = depth;
+        Ok(self.leave(Expr {
+            span: condition.span.to(else_value.span),
+            kind: ExprKind::Conditional(Box::new(condition), Box::new(then_value), Box::new(else_value)),
+        }))
+    }
+
+    /// <or> ::= <and> { "or" <and> }
+    fn parse_or(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_or");
+        let depth = self.depth;
+        let mut expr = self.parse_and()?;
+        while self.at_keyword("or") {
+            self.consume_keyword("or")?;
+            self.nest()?;
+            let right = self.parse_and()?;
+            expr = binary(BinaryOp::Or, expr, right);
+        }
+        self.depth = depth;
+        Ok(self.leave(expr))
+    }
+
+    /// <and> ::= <bitwise-or> { "and" <bitwise-or> }
+    fn parse_and(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_and");
+        let depth = self.depth;
+        let mut expr = self.parse_bitwise_or()?;
+        while self.at_keyword("and") {
+            self.consume_keyword("and")?;
+            self.nest()?;
+            let right = self.parse_bitwise_or()?;
+            expr = binary(BinaryOp::And, expr, right);
+        }
+        self.depth = depth;
+        Ok(self.leave(expr))
+    }
+
+    /// <bitwise-or> ::= <bitwise-xor> { "|" <bitwise-xor> }
+    ///
+    /// The bitwise operators bind more loosely than comparisons, like they do
+    /// in C, so `x & 1 == 0` is `x & (1 == 0)`.
+    fn parse_bitwise_or(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_bitwise_or");
+        let depth = self.depth;
+        let mut expr = self.parse_bitwise_xor()?;
+        while self.at('|') {
+            self.consume_exact_char('|')?;
+            self.nest()?;
+            let right = self.parse_bitwise_xor()?;
+            expr = binary(BinaryOp::BitOr, expr, right);
+        }
+        self.depth = depth;
+        Ok(self.leave(expr))
+    }
+
+    /// <bitwise-xor> ::= <bitwise-and> { "^" <bitwise-and> }
+    fn parse_bitwise_xor(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_bitwise_xor");
+        let depth = self.depth;
+        let mut expr = self.parse_bitwise_and()?;
+        while self.at('^') {
+            self.consume_exact_char('^')?;
+            self.nest()?;
+            let right = self.parse_bitwise_and()?;
+            expr = binary(BinaryOp::BitXor, expr, right);
+        }
+        self.depth = depth;
+        Ok(self.leave(expr))
+    }
+
+    /// <bitwise-and> ::= <relation> { "&" <relation> }
+    fn parse_bitwise_and(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_bitwise_and");
+        let depth = self.depth;
+        let mut expr = self.parse_relation()?;
+        while self.at('&') {
+            self.consume_exact_char('&')?;
+            self.nest()?;
+            let right = self.parse_relation()?;
+            expr = binary(BinaryOp::BitAnd, expr, right);
+        }
+        self.depth = depth;
+        Ok(self.leave(expr))
+    }
+
+    /// <relation> ::= <shift> [ <relop> <shift> ]
+    /// <relop> ::= "==" | "!=" | "<" | "<=" | ">" | ">="
+    fn parse_relation(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_relation");
+        let left = self.parse_shift()?;
+
+        let relops = [
+            ("==", BinaryOp::Equal),
+            ("!=", BinaryOp::NotEqual),
+            ("<=", BinaryOp::LessEqual),
+            ("<", BinaryOp::Less),
+            (">=", BinaryOp::GreaterEqual),
+            (">", BinaryOp::Greater),
+        ];
+
+        for &(relop, op) in &relops {
+            if self.at_op(relop) {
+                self.consume_op(relop.len());
+                let right = self.parse_shift()?;
+                return Ok(self.leave(binary(op, left, right)));
+            }
+        }
+
+        Ok(self.leave(left))
+    }
+
+    /// <shift> ::= <expression> { <shiftop> <expression> }
+    /// <shiftop> ::= "<<" | ">>" | ">>>"
+    fn parse_shift(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_shift");
+        let shiftops = [
+            ("<<", BinaryOp::ShiftLeft),
+            (">>>", BinaryOp::ShiftRightUnsigned),
+            (">>", BinaryOp::ShiftRight),
+        ];
+
+        let depth = self.depth;
+        let mut expr = self.parse_expression()?;
+        'shifts: loop {
+            for &(shiftop, op) in &shiftops {
+                if self.at_op(shiftop) {
+                    self.consume_op(shiftop.len());
+                    self.nest()?;
+                    let right = self.parse_expression()?;
+                    expr = binary(op, expr, right);
+                    continue 'shifts;
+                }
+            }
+            self.depth = depth;
+            return Ok(self.leave(expr));
+        }
+    }
+
+    /// <expression> ::= <leading> <term> <addop>
+    /// <leading> ::= "+" | ""
+    /// <addop> ::= <add-expression> | <subtract-expression>
+    ///
+    /// A leading minus is part of the first factor.
+    fn parse_expression(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_expression");
+        let depth = self.depth;
+        let mut expr = if self.at('+') {
+            self.consume_exact_char('+')?;
+            self.parse_term()?
+        } else {
+            self.parse_term()?
+        };
+
+        loop {
+            if self.at('+') {
+                expr = self.parse_add(expr)?;
+            } else if self.at('-') {
+                expr = self.parse_subtract(expr)?;
+            } else {
+                break;
+            }
+        }
+
+        self.depth = depth;
+        Ok(self.leave(expr))
+    }
+
+    /// <term> ::= <factor> <multop>
+    /// <multop> ::= <multiply-expression> | <divide-expression> | <remainder-expression>
+    fn parse_term(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_term");
+        let depth = self.depth;
+        let mut expr = self.parse_factor()?;
+        loop {
+            if self.at('*') {
+                expr = self.parse_multiply(expr)?;
+            } else if self.at('/') {
+                expr = self.parse_divide(expr)?;
+            } else if self.at('%') {
+                expr = self.parse_remainder(expr)?;
+            } else {
+                break;
+            }
+        }
+        self.depth = depth;
+        Ok(self.leave(expr))
+    }
+
+    /// <factor> ::= "-" <factor> | "(" <conditional> ")" | <memory> | <address> | <identifier> | <literal>
+    /// <address> ::= "@" <name>
+    fn parse_factor(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_factor");
+        let start = self.offset();
+
+        let factor = if self.at('-') {
+            self.consume_exact_char('-')?;
+            let depth = self.nest()?;
+            let operand = self.parse_factor()?;
+            self.depth = depth;
+            self.negate(start, operand)
+        } else if self.at('(') {
+            self.consume_exact_char('(')?;
+            let mut expr = self.parse_conditional()?;
+            self.consume_exact_char(')')?;
+            expr.span = self.span_from(start);
+            expr
+        } else if self.at_keyword("mem") {
+            let address = self.parse_address()?;
+            Expr {
+                kind: ExprKind::Load(Box::new(address)),
+                span: self.span_from(start),
+            }
+        } else if self.at('@') {
+            self.consume_exact_char('@')?;
+            if !self.at_identifier() {
+                return Err(self.unexpected());
+            }
+            Expr {
+                kind: ExprKind::Address(self.consume_name()),
+                span: self.span_from(start),
+            }
+        } else if self.at_identifier() {
+            self.parse_identifier()?
+        } else {
+            self.parse_literal()?
+        };
+        Ok(self.leave(factor))
+    }
+
+    /// Negates `operand`, which starts after a minus sign at `start`. A minus
+    /// sign in front of a number is part of the number, so `-2147483648` is
+    /// an i32 like `2147483647` is. Anything else is subtracted from zero.
+    fn negate(&mut self, start: usize, operand: Expr) -> Expr {
+        let span = self.span_from(start);
+        let kind = match operand.kind {
+            ExprKind::Int(num, suffix) => ExprKind::Int(negated(num), suffix),
+            ExprKind::Float(num) => ExprKind::Float(negated(num)),
+            kind => {
+                let zero = Expr {
+                    kind: ExprKind::Int(String::from("0"), None),
+                    span: self.span(start, start),
+                };
+                let operand = Expr { kind, span: operand.span };
+                return binary(BinaryOp::Subtract, zero, operand);
+            }
+        };
+
+        Expr { kind, span }
+    }
+
+    /// <literal> ::= [ "-" ] <number> | <bool> | <string>
+    /// <number> ::= <digits> [ "." <digits> ] [ <type> ]
+    ///            | "0x" <hex-digits> [ <type> ]
+    ///            | "0o" <octal-digits> [ <type> ]
+    ///            | "0b" <binary-digits> [ <type> ]
+    fn parse_literal(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_literal");
+        let start = self.offset();
+
+        let kind = if let Some(value) = self.parse_bool()? {
+            ExprKind::Bool(value)
+        } else if self.at('"') {
+            ExprKind::Str(self.consume_string())
+        } else if self.at('-') {
+            self.consume_exact_char('-')?;
+            if !self.at_integer() {
+                return Err(self.unexpected());
+            }
+            let operand = self.parse_literal()?;
+            let literal = self.negate(start, operand);
+            return Ok(self.leave(literal));
+        } else if self.at_integer() {
+            let (num, suffix) = self.consume_num()?;
+            if num.contains('.') {
+                ExprKind::Float(num)
+            } else {
+                ExprKind::Int(num, suffix)
+            }
+        } else {
+            return Err(self.unexpected());
+        };
+
+        Ok(self.leave(Expr {
+            kind,
+            span: self.span_from(start),
+        }))
+    }
+
+    /// <bool> ::= "true" | "false"
+    ///
+    /// Returns None, without consuming anything, if there isn't a bool here.
+    fn parse_bool(&mut self) -> Result<Option<bool>, Aborted> {
+        if self.at_keyword("true") {
+            self.consume_keyword("true")?;
+            Ok(Some(true))
+        } else if self.at_keyword("false") {
+            self.consume_keyword("false")?;
+            Ok(Some(false))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// <identifier> ::= <callee> "(" [ <conditional> { "," <conditional> } ] ")" { "." <name> }
+    ///                | <name> [ "[" <conditional> "]" ] { "." <name> }
+    fn parse_identifier(&mut self) -> Result<Expr, Aborted> {
+        self.enter("parse_identifier");
+        let start = self.offset();
+        let name = self.consume_name();
+        let name = self.parse_builtin_name(name)?;
+
+        let kind = if self.at('(') {
+            ExprKind::Call(name, self.parse_arguments()?)
+        } else if self.at('[') {
+            ExprKind::Index(name, Box::new(self.parse_index()?))
+        } else {
+            ExprKind::Variable(name)
+        };
+
+        let mut expr = Expr {
+            kind,
+            span: self.span_from(start),
+        };
+        while self.at('.') {
+            let field = self.parse_field()?;
+            expr = Expr {
+                kind: ExprKind::Field(Box::new(expr), field),
+                span: self.span_from(start),
+            };
+        }
+        Ok(self.leave(expr))
+    }
+
+    /// <callee> ::= <name> | "i32x4" "." <name>
+    ///
+    /// Takes the name that's already been consumed. Some builtins' names
+    /// have a `.` in them, like the instructions they compile to, so after
+    /// the part before it, a `.` is more of the name, not a field.
+    fn parse_builtin_name(&mut self, name: String) -> Result<String, Aborted> {
+        if builtins::is_prefix(&name) && self.at('.') {
+            let rest = self.parse_field()?;
+            return Ok(format!("{}.{}", name, rest));
+        }
+        Ok(name)
+    }
+
+    /// The `.x` of `r.x`. Returns the field's name.
+    fn parse_field(&mut self) -> Result<String, Aborted> {
+        self.consume_exact_char('.')?;
+        if !self.at_identifier() {
+            return Err(self.unexpected());
+        }
+        Ok(self.consume_name())
+    }
+
+    /// Parses the argument list of a call.
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, Aborted> {
+        let mut arguments = Vec::new();
+        self.consume_exact_char('(')?;
+
+        if !self.at(')') {
+            loop {
+                arguments.push(self.parse_conditional()?);
+
+                if self.at(',') {
+                    self.consume_exact_char(',')?;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        self.consume_exact_char(')')?;
+        Ok(arguments)
+    }
+
+    /// <add-expression> ::= <empty> | <plus-term>
+    /// <plus-term> ::= "+" <term>
+    fn parse_add(&mut self, left: Expr) -> Result<Expr, Aborted> {
+        self.enter("parse_add");
+        // Consume a '+' character from the stream.
+        self.consume_exact_char('+')?;
+        self.nest()?;
+
+        // Call term() again to consume one more term, and add the two.
+        let right = self.parse_term()?;
+        Ok(self.leave(binary(BinaryOp::Add, left, right)))
+    }
+
+    /// <subtract-expression> ::= <empty> | <minus-term>
+    /// <minus-term> ::= "-" <term>
+    fn parse_subtract(&mut self, left: Expr) -> Result<Expr, Aborted> {
+        self.enter("parse_subtract");
+        self.consume_exact_char('-')?;
+        self.nest()?;
+        let right = self.parse_term()?;
+        Ok(self.leave(binary(BinaryOp::Subtract, left, right)))
+    }
+
+    /// <multiply-expression> ::= <empty> | <multiply-factor>
+    /// <multiply-factor> ::= "*" <factor>
+    fn parse_multiply(&mut self, left: Expr) -> Result<Expr, Aborted> {
+        self.enter("parse_multiply");
+        self.consume_exact_char('*')?;
+        self.nest()?;
+        let right = self.parse_factor()?;
+        Ok(self.leave(binary(BinaryOp::Multiply, left, right)))
+    }
+
+    /// <divide-expression> ::= <empty> | <divide-factor>
+    /// <divide-factor> ::= "/" <factor>
+    fn parse_divide(&mut self, left: Expr) -> Result<Expr, Aborted> {
+        self.enter("parse_divide");
+        self.consume_exact_char('/')?;
+        self.nest()?;
+        let right = self.parse_factor()?;
+        Ok(self.leave(binary(BinaryOp::Divide, left, right)))
+    }
+
+    /// <remainder-expression> ::= <empty> | <remainder-factor>
+    /// <remainder-factor> ::= "%" <factor>
+    fn parse_remainder(&mut self, left: Expr) -> Result<Expr, Aborted> {
+        self.enter("parse_remainder");
+        self.consume_exact_char('%')?;
+        self.nest()?;
+        let right = self.parse_factor()?;
+        Ok(self.leave(binary(BinaryOp::Remainder, left, right)))
+    }
+
+    /// Consumes the next byte in the stream, converts it to a character,
+    /// stores it in the lookahead, and returns the character.
+    fn get_char(&mut self) -> Option<char> {
+        // We're moving on, so whatever we were expecting before no longer
+        // applies.
+        self.expected_tokens.clear();
+
+        // Read a single byte from the stream.
+        let byte = match self.byte_at(self.position) {
+            Some(byte) => byte,
+            None => {
+                self.lookahead = None;
+                return None;
+            }
+        };
+        self.position += 1;
+
+        // Convert the byte into an ASCII character.
+        self.lookahead = Some(char::from(byte));
+        self.lookahead
+    }
+
+    /// Returns the byte offset of the lookahead in the input.
+    fn offset(&self) -> usize {
+        if self.lookahead.is_some() {
+            self.position - 1
+        } else {
+            self.position
+        }
+    }
+
+    /// Returns the byte at an offset in the file, reading more of the
+    /// program if we haven't got that far yet, or None past the end.
+    fn byte_at(&mut self, offset: usize) -> Option<u8> {
+        while self.text().len() <= offset && !self.source.done {
+            self.read_chunk();
+        }
+        self.text().get(offset).copied()
+    }
+
+    /// The part of the program read so far.
+    fn text(&self) -> &[u8] {
+        self.diagnostics.source_map().file(self.file).source()
+    }
+
+    /// Reads the next chunk of the program into the source map. A read error
+    /// is reported like any other, where the program stopped, and the rest
+    /// of the program is treated as if it weren't there.
+    fn read_chunk(&mut self) {
+        let mut buf = [0; CHUNK_SIZE];
+        loop {
+            match self.source.reader.read(&mut buf) {
+                Ok(0) => self.source.done = true,
+                Ok(len) => self.diagnostics.source_map_mut().extend_file(self.file, &buf[..len]),
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    self.source.done = true;
+                    self.source.failed = true;
+                    let end = self.text().len();
+                    let span = self.span(end, end);
+                    self.diagnostics.error(span, &format!("could not read the program: {}", err));
+                }
+            }
+            return;
+        }
+    }
+
+    /// Returns a span from `start` to the end of the last token consumed.
+    fn span_from(&self, start: usize) -> Span {
+        self.span(start, self.token_end)
+    }
+
+    /// Turns a pair of offsets in the file into a span in the source map.
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span {
+            start: self.file.start + start,
+            end: self.file.start + end,
+        }
+    }
+
+    /// Returns the character after the lookahead without consuming anything.
+    fn peek_char(&mut self) -> Option<char> {
+        self.byte_at(self.position).map(char::from)
+    }
+
+    /// Returns the run of letters, digits, and underscores starting at the
+    /// lookahead, without consuming anything.
+    fn peek_word(&mut self) -> String {
+        let mut word = String::new();
+        let mut offset = self.offset();
+        while let Some(byte) = self.byte_at(offset).filter(|&byte| is_name_byte(byte)) {
+            word.push(char::from(byte));
+            offset += 1;
+        }
+        word
+    }
+
+    /// Skips everything the parser doesn't care about: whitespace and
+    /// comments. Comments start with `#` or `//` and run until the end of the
+    /// line. Newlines are left alone if they separate statements.
+    fn skip_trivia(&mut self) {
+        while let Some(lookahead) = self.lookahead {
+            if self.is_whitespace(lookahead) {
+                self.get_char();
+            } else if lookahead == '#' || (lookahead == '/' && self.peek_char() == Some('/')) {
+                self.skip_comment();
+            } else if lookahead == '/' && self.peek_char() == Some('*') {
+                self.skip_block_comment();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Returns true for any ASCII whitespace character the parser should
+    /// skip over. This covers tabs and the carriage return in CRLF line
+    /// endings, so files from any platform look the same to the parser.
+    fn is_whitespace(&self, c: char) -> bool {
+        if c == '\n' {
+            !self.newlines_significant
+        } else {
+            c.is_ascii_whitespace()
+        }
+    }
+
+    /// Consumes characters up to (but not including) the next newline.
+    fn skip_comment(&mut self) {
+        let start = self.offset();
+        while let Some(lookahead) = self.lookahead {
+            if lookahead == '\n' {
+                break;
+            }
+            self.get_char();
+        }
+        self.keep_comment(start);
+    }
+
+    /// Keeps where the comment that started at `start` and ends here is,
+    /// if we're parsing for the formatter.
+    fn keep_comment(&mut self, start: usize) {
+        let span = self.span(start, self.offset());
+        if let Some(ref mut layout) = self.layout {
+            layout.comments.push(span);
+        }
+    }
+
+    /// Consumes a `/* ... */` comment. If the comment is never closed, reports
+    /// where it started and picks up again at the end of that line, so the
+    /// rest of the program still gets checked.
+    fn skip_block_comment(&mut self) {
+        let start = self.offset();
+        self.get_char();
+        self.get_char();
+
+        while let Some(lookahead) = self.lookahead {
+            if lookahead == '*' && self.peek_char() == Some('/') {
+                self.get_char();
+                self.get_char();
+                self.keep_comment(start);
+                return;
+            }
+            self.get_char();
+        }
+
+        let span = self.span(start, start + 2);
+        self.error(span, "block comment is never closed");
+        self.recover_at_next_line(start);
+    }
+
+    /// Rewinds the input to the first newline after `offset`, or leaves it at
+    /// the end if there isn't one.
+    fn recover_at_next_line(&mut self, offset: usize) {
+        if let Some(newline) = self.next_newline(offset) {
+            self.position = newline;
+            self.get_char();
+        }
+    }
+
+    /// Returns the offset of the first newline at or after `offset`, reading
+    /// up to it if we haven't yet.
+    fn next_newline(&mut self, mut offset: usize) -> Option<usize> {
+        while let Some(byte) = self.byte_at(offset) {
+            if byte == b'\n' {
+                return Some(offset);
+            }
+            offset += 1;
+        }
+        None
+    }
+
+    /// Reports an error pointing at a span, and keeps going. The rest of the
+    /// line gets read first, so the error can show all of it.
+    fn error(&mut self, span: Span, msg: &str) {
+        self.next_newline(span.start - self.file.start);
+        self.diagnostics.error(span, msg);
+    }
+
+    /// Reports an error pointing at a span, like `error`, and returns the
+    /// `Aborted` for giving up with.
+    fn abort(&mut self, span: Span, msg: &str) -> Aborted {
+        self.next_newline(span.start - self.file.start);
+        self.diagnostics.abort(span, msg)
+    }
+
+    /// Reports an error listing everything that would have been legal at the
+    /// current position, along with what we found instead, and returns the
+    /// `Aborted` for giving up with. Running out of a program that couldn't
+    /// be read has already been reported, so it isn't again.
+    fn unexpected(&mut self) -> Aborted {
+        if self.source.failed && self.lookahead.is_none() {
+            return Aborted;
+        }
+
+        let mut expected: Vec<String> = Vec::new();
+        for token in &self.expected_tokens {
+            let token = token.describe();
+            if !expected.contains(&token) {
+                expected.push(token);
+            }
+        }
+
+        let what = match expected.split_last() {
+            None => String::from("nothing"),
+            Some((only, [])) => only.clone(),
+            Some((last, [first])) => format!("{} or {}", first, last),
+            Some((last, rest)) => format!("{}, or {}", rest.join(", "), last),
+        };
+
+        let found = self.describe_lookahead();
+        let span = self.span(self.offset(), self.offset());
+        self.abort(span, &format!("expected {}, found {}", what, found))
+    }
+
+    /// Describes the lookahead for a person: the whole word if it starts
+    /// one, and the character if it doesn't.
+    fn describe_lookahead(&mut self) -> String {
+        match self.lookahead {
+            Some('\n') => String::from("newline"),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => format!("`{}`", self.peek_word()),
+            Some(c) => format!("`{}`", c),
+            None => String::from("end of input"),
+        }
+    }
+
+    /// Starts the rule `rule`, and prints that we have if we're tracing.
+    fn enter(&mut self, rule: &'static str) {
+        if self.trace.is_some() {
+            self.print_trace("enter", rule);
+        }
+        if let Some(ref mut rules) = self.trace {
+            rules.push(rule);
+        }
+    }
+
+    /// Finishes the innermost rule we're in, which parsed `parsed`, and
+    /// prints that we have if we're tracing. Returns `parsed`.
+    fn leave<T>(&mut self, parsed: T) -> T {
+        if let Some(rule) = self.trace.as_mut().and_then(Vec::pop) {
+            self.print_trace("leave", rule);
+        }
+        parsed
+    }
+
+    /// Prints a line of the trace, indented by how many rules we're in.
+    fn print_trace(&mut self, event: &str, rule: &str) {
+        let depth = self.trace.as_ref().map_or(0, Vec::len);
+        let lookahead = self.describe_lookahead();
+        let span = self.span(self.offset(), self.offset());
+        let location = self.diagnostics.source_map().location(span);
+        eprintln!(
+            "{}{} {} at {}:{}:{}, looking at {}",
+            "  ".repeat(depth),
+            event,
+            rule,
+            location.file,
+            location.line,
+            location.column,
+            lookahead
+        );
+    }
+
+    /// Goes a level deeper into the tree, and gives up if that's deeper than
+    /// `MAX_DEPTH`. Each block, parenthesis, and operator an expression is
+    /// an operand of is a level. Returns the depth from before, for going back to once
+    /// we're done with whatever's nested.
+    fn nest(&mut self) -> Result<usize, Aborted> {
+        if self.depth == MAX_DEPTH {
+            let span = self.span(self.offset(), self.offset());
+            return Err(self.abort(
+                span,
+                &format!(
+                    "this is nested too deeply; the limit is {} levels of blocks, parentheses, and operators",
+                    MAX_DEPTH
+                ),
+            ));
+        }
+        self.depth += 1;
+        Ok(self.depth - 1)
+    }
+
+    /// Remembers that `what` would have been legal at the current position.
+    fn expect(&mut self, what: Expected) {
+        self.expected_tokens.push(what);
+    }
+
+    /// Returns true if the lookahead is `c`.
+    fn at(&mut self, c: char) -> bool {
+        self.expect(Expected::Char(c));
+        self.lookahead == Some(c)
+    }
+
+    /// Returns true if the input continues with the operator `op`.
+    fn at_op(&mut self, op: &'static str) -> bool {
+        self.expect(Expected::Token(op));
+        self.continues_with(op)
+    }
+
+    /// Returns true if the lookahead starts the keyword `keyword`.
+    fn at_keyword(&mut self, keyword: &'static str) -> bool {
+        self.expect(Expected::Token(keyword));
+        self.at_word(keyword)
+    }
+
+    /// Returns true if the lookahead starts an identifier.
+    fn at_identifier(&mut self) -> bool {
+        self.expect(Expected::Kind("identifier"));
+        self.lookahead.is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && !KEYWORDS.iter().any(|keyword| self.at_word(keyword))
+    }
+
+    /// Returns true if the input continues with `text`, starting at the
+    /// lookahead.
+    fn continues_with(&mut self, text: &str) -> bool {
+        let start = self.offset();
+        self.lookahead.is_some() && text.bytes().enumerate().all(|(i, byte)| self.byte_at(start + i) == Some(byte))
+    }
+
+    /// Returns true if the run of letters, digits, and underscores starting
+    /// at the lookahead is `word`, like `peek_word` would, without building it.
+    fn at_word(&mut self, word: &str) -> bool {
+        let end = self.offset() + word.len();
+        self.continues_with(word) && !self.byte_at(end).is_some_and(is_name_byte)
+    }
+
+    /// Returns true if the lookahead starts an integer.
+    fn at_integer(&mut self) -> bool {
+        self.expect(Expected::Kind("integer"));
+        self.lookahead.is_some_and(|c| c.is_ascii_digit())
+    }
+
+    /// Returns true if the lookahead separates two statements.
+    fn at_separator(&mut self) -> bool {
+        if self.newlines_significant {
+            self.expect(Expected::Kind("newline"));
+        }
+        self.expect(Expected::Char(';'));
+        self.lookahead == Some('\n') || self.lookahead == Some(';')
+    }
+
+    /// Returns true if there's no input left.
+    fn at_end(&mut self) -> bool {
+        self.expect(Expected::Kind("end of input"));
+        self.lookahead.is_none()
+    }
+
+    /// If the current lookahead is not equal to the matching character,
+    /// reports an error and gives up. Otherwise, consumes it and skips any trivia
+    /// that follows.
+    fn consume_exact_char(&mut self, c: char) -> Result<(), Aborted> {
+        if !self.at(c) {
+            return Err(self.unexpected());
+        }
+
+        self.get_char();
+        self.token_end = self.offset();
+        self.skip_trivia();
+        Ok(())
+    }
+
+    /// Consumes an operator the caller has already checked for with `at_op`.
+    fn consume_op(&mut self, length: usize) {
+        for _ in 0..length {
+            self.get_char();
+        }
+        self.token_end = self.offset();
+        self.skip_trivia();
+    }
+
+    /// Consumes `keyword`, or reports an error and gives up if it isn't next.
+    fn consume_keyword(&mut self, keyword: &'static str) -> Result<(), Aborted> {
+        if !self.at_keyword(keyword) {
+            return Err(self.unexpected());
+        }
+        self.consume_name();
+        Ok(())
+    }
+
+    fn consume_name(&mut self) -> String {
+        let mut name = String::from("");
+
+        while let Some(lookahead) = self.lookahead {
+            if lookahead.is_ascii_alphanumeric() || lookahead == '_' {
+                name.push(lookahead);
+                self.get_char();
+            } else {
+                break;
+            }
+        }
+
+        self.token_end = self.offset();
+        self.skip_trivia();
+        name
+    }
+
+    /// If the current lookahead is not a digit, reports an error and gives up.
+    /// Otherwise, consumes a decimal number, or a hex, octal, or binary
+    /// integer, then the type suffix if there is one. Numbers with a
+    /// fractional part are always f64s, so they come back with that type.
+    ///
+    /// `f` is a hex digit, so hex literals can't have an `f64` suffix: it
+    /// would read as more of the number.
+    fn consume_num(&mut self) -> Result<(String, Option<Type>), Aborted> {
+        let radix = match (self.lookahead, self.peek_char()) {
+            (Some('0'), Some('x')) => Some((16, "hex")),
+            (Some('0'), Some('o')) => Some((8, "octal")),
+            (Some('0'), Some('b')) => Some((2, "binary")),
+            _ => None,
+        };
+
+        let mut suffix = None;
+        let num = if let Some((radix, name)) = radix {
+            self.consume_prefixed_digits(radix, name)
+        } else {
+            let mut num = self.consume_digits();
+            if num.is_empty() {
+                return Err(self.unexpected());
+            }
+
+            if self.lookahead == Some('.') && self.peek_char().is_some_and(|c| c.is_ascii_digit()) {
+                self.get_char();
+                num.push('.');
+                num.push_str(&self.consume_digits());
+                suffix = Some(Type::F64);
+            }
+            num
+        };
+
+        if self.lookahead.is_some_and(|c| c.is_ascii_alphabetic()) {
+            let start = self.offset();
+            let name = self.peek_word();
+            for _ in 0..name.len() {
+                self.get_char();
+            }
+            self.token_end = self.offset();
+            let span = self.span_from(start);
+
+            match Type::from_name(&name) {
+                Some(ty) if suffix.is_none() || !ty.is_integer() => suffix = Some(ty),
+                Some(_) => self.error(span, "only integers can have an integer suffix"),
+                None => self.error(span, &format!("unknown number suffix `{}`", name)),
+            }
+        }
+
+        self.token_end = self.offset();
+        self.skip_trivia();
+        Ok((num, suffix))
+    }
+
+    /// <string> ::= '"' { <character> | <escape> } '"'
+    /// <escape> ::= "\\" ( "n" | "t" | "r" | "0" | "\"" | "\\" )
+    ///
+    /// Strings can't run over more than one line. If one isn't closed by the
+    /// end of its line, reports where it started and picks up again on the
+    /// next line, like an unclosed block comment. Returns the bytes in the
+    /// string, with escapes replaced by what they stand for.
+    fn consume_string(&mut self) -> Vec<u8> {
+        let start = self.offset();
+        self.get_char();
+
+        let mut bytes = Vec::new();
+        loop {
+            match self.lookahead {
+                Some('"') => break,
+                None | Some('\n') => {
+                    let span = self.span(start, start + 1);
+                    self.error(span, "string is never closed");
+                    self.recover_at_next_line(start);
+                    self.token_end = self.offset();
+                    return bytes;
+                }
+                Some('\\') => {
+                    let escape_start = self.offset();
+                    self.get_char();
+                    let byte = match self.lookahead {
+                        Some('n') => b'\n',
+                        Some('t') => b'\t',
+                        Some('r') => b'\r',
+                        Some('0') => 0,
+                        Some('"') => b'"',
+                        Some('\\') => b'\\',
+                        _ => {
+                            let end = if self.lookahead.is_some() { self.offset() + 1 } else { self.offset() };
+                            let span = self.span(escape_start, end);
+                            self.error(span, "unknown escape in string");
+                            b'?'
+                        }
+                    };
+                    bytes.push(byte);
+                    if self.lookahead != Some('\n') {
+                        self.get_char();
+                    }
+                }
+                // The scanner reads bytes, so this gets back the byte we read,
+                // even if it's part of a UTF-8 character.
+                Some(c) => {
+                    bytes.push(c as u8);
+                    self.get_char();
+                }
+            }
+        }
+
+        self.get_char();
+        self.token_end = self.offset();
+        self.skip_trivia();
+        bytes
+    }
+
+    /// Consumes an integer in another base, with its prefix: `0x1f`, `0o17`,
+    /// or `0b1010`. `name` is what the base is called, for error messages.
+    fn consume_prefixed_digits(&mut self, radix: u32, name: &str) -> String {
+        let start = self.offset();
+        let mut num = String::new();
+        for _ in 0..2 {
+            num.push(self.lookahead.unwrap());
+            self.get_char();
+        }
+
+        let mut any_digits = false;
+        while let Some(lookahead) = self.lookahead {
+            if lookahead.is_digit(radix) {
+                num.push(lookahead);
+                self.get_char();
+            } else if lookahead.is_ascii_digit() {
+                // A digit that's too big for the base, like the 2 in 0b102.
+                let digit_start = self.offset();
+                self.get_char();
+                let span = self.span(digit_start, self.offset());
+                self.error(span, &format!("`{}` isn't a digit in {}", lookahead, name));
+            } else {
+                break;
+            }
+            any_digits = true;
+        }
+
+        if !any_digits {
+            let span = self.span(start, self.offset());
+            self.error(span, &format!("expected {} digits after `{}`", name, num));
+        }
+        if num.len() == 2 {
+            num.push('0');
+        }
+
+        num
+    }
+
+    /// Consumes digits for as long as there are any.
+    fn consume_digits(&mut self) -> String {
+        let mut digits = String::new();
+
+        while let Some(lookahead) = self.lookahead {
+            if lookahead.is_ascii_digit() {
+                digits.push(lookahead);
+                self.get_char();
+            } else {
+                break;
+            }
+        }
+
+        digits
+    }
+}
+
+/// Adds a minus sign to the text of a literal, or takes it away if there's
+/// one already.
+fn negated(num: String) -> String {
+    match num.strip_prefix('-') {
+        Some(positive) => positive.to_string(),
+        None => format!("-{}", num),
+    }
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    let span = left.span.to(right.span);
+    Expr {
+        kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+        span,
+    }
+}
+
+/// Whether `byte` can be part of a name, after its first character.
+fn is_name_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
@@ -0,0 +1,104 @@
+//! The optimization passes, and the -O level that turns each one on. They
+//! all run on the core language, after type checking, in the order they're
+//! listed here. At -O0 none of them run, and code generation turns the
+//! program into wasm one step at a time, the way the book does.
+//!
+//! The ones that trade bigger code for faster code ask the cost model
+//! whether it's worth it, so they only run with --optimize-for.
+
+use cost::{CostModel, Target};
+use crash::Progress;
+use deadcode;
+use fold;
+use hir::Program;
+use inline;
+use locals;
+use unroll;
+
+pub struct Pass {
+    // What the pass is called in crash reports.
+    pub name: &'static str,
+
+    // The lowest -O level that runs it.
+    pub level: u32,
+
+    pub run: Run,
+}
+
+pub enum Run {
+    Always(fn(&mut Program)),
+
+    // Needs the cost model for what we're optimizing for.
+    Costed(fn(&mut Program, &CostModel)),
+}
+
+pub const PASSES: &[Pass] = &[
+    Pass {
+        name: "fold",
+        level: 1,
+        run: Run::Always(fold::fold),
+    },
+    // These go after folding, which turns constants into the literals
+    // they look for.
+    Pass {
+        name: "inline",
+        level: 2,
+        run: Run::Costed(inline::inline),
+    },
+    Pass {
+        name: "unroll",
+        level: 2,
+        run: Run::Costed(unroll::unroll),
+    },
+    Pass {
+        name: "deadcode",
+        level: 1,
+        run: Run::Always(deadcode::eliminate),
+    },
+    Pass {
+        name: "unreachable",
+        level: 2,
+        run: Run::Always(deadcode::remove_unreachable),
+    },
+    // Goes last, so it sees the temporaries that are left.
+    Pass {
+        name: "locals",
+        level: 1,
+        run: Run::Always(locals::reuse_temporaries),
+    },
+];
+
+/// The highest -O level that turns on anything.
+pub const MAX_LEVEL: u32 = 2;
+
+/// Runs every pass the -O level turns on, in order. With `verify`, each one
+/// runs a second time on what it made, which shouldn't change anything: a
+/// pass that finds more to do the second time missed it the first time.
+/// One that does is an internal compiler error, in that pass.
+pub fn optimize(
+    program: &mut Program,
+    level: u32,
+    optimize_for: Option<Target>,
+    verify: bool,
+    progress: &mut Progress,
+) {
+    let model = optimize_for.map(CostModel::new);
+    let run = |pass: &Pass, program: &mut Program| match (&pass.run, model.as_ref()) {
+        (&Run::Always(run), _) => run(program),
+        (&Run::Costed(run), Some(model)) => run(program, model),
+        (&Run::Costed(_), None) => (),
+    };
+
+    for pass in PASSES.iter().filter(|pass| pass.level <= level) {
+        progress.pass = pass.name;
+        run(pass, program);
+
+        if verify {
+            let once = program.clone();
+            run(pass, program);
+            if *program != once {
+                panic!("running the {} pass a second time changed the program", pass.name);
+            }
+        }
+    }
+}
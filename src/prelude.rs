@@ -0,0 +1,161 @@
+//! The prelude: a few functions, written in the language itself, that every
+//! program can call without defining them. It's parsed after the program's
+//! own files and linked in with them, unless --no-prelude leaves it out.
+//!
+//! A function the program defines itself takes the place of the prelude's
+//! one with the same name, so adding to the prelude can't break a program
+//! that already works. The prelude's functions that nothing calls are
+//! dropped once type checking is done, so they don't end up in the module.
+
+use ast;
+use hir::{Expr, ExprKind, Place, Program, Stmt};
+
+/// What the prelude's called in the source map, and so in errors.
+pub const NAME: &str = "<prelude>";
+
+pub const SOURCE: &str = include_str!("prelude.cr");
+
+/// Adds the prelude's functions to a program, except the ones it defines
+/// itself. Returns the names of the ones that were added.
+pub fn add(program: &mut ast::Program, prelude: ast::Program) -> Vec<String> {
+    let mut added = Vec::new();
+    for function in prelude.functions {
+        if program.functions.iter().any(|defined| defined.name == function.name) {
+            continue;
+        }
+        added.push(function.name.clone());
+        program.functions.push(function);
+    }
+    added
+}
+
+/// Removes the functions named in `prelude` that the rest of the program
+/// doesn't call, directly or through other prelude functions. Taking a
+/// function's address counts as calling it.
+pub fn remove_unused(program: &mut Program, prelude: &[String]) {
+    let mut called = Vec::new();
+    for function in program.functions.iter().chain(&program.handlers) {
+        if !prelude.contains(&function.name) {
+            calls_in_block(&function.body, &mut called);
+        }
+    }
+    calls_in_block(&program.init, &mut called);
+    calls_in_block(&program.main, &mut called);
+
+    // Each prelude function that's called can call more of them.
+    let mut i = 0;
+    while i < called.len() {
+        if let Some(function) = program.functions.iter().find(|function| function.name == called[i]) {
+            if prelude.contains(&function.name) {
+                calls_in_block(&function.body, &mut called);
+            }
+        }
+        i += 1;
+    }
+
+    program
+        .functions
+        .retain(|function| !prelude.contains(&function.name) || called.contains(&function.name));
+}
+
+/// Adds the names of the functions a block calls to `called`, if they
+/// aren't there already.
+fn calls_in_block(block: &[Stmt], called: &mut Vec<String>) {
+    for stmt in block {
+        match *stmt {
+            Stmt::Assign { ref value, .. }
+            | Stmt::AssignValues { ref value, .. }
+            | Stmt::Return(ref value)
+            | Stmt::Print(ref value)
+            | Stmt::Assert(ref value)
+            | Stmt::Halt(ref value)
+            | Stmt::Call(ref value) => calls_in_expr(value, called),
+            Stmt::ReturnValues(ref values) => {
+                for value in values {
+                    calls_in_expr(value, called);
+                }
+            }
+            Stmt::If {
+                ref condition,
+                ref then,
+                ref otherwise,
+            } => {
+                calls_in_expr(condition, called);
+                calls_in_block(then, called);
+                calls_in_block(otherwise, called);
+            }
+            Stmt::While {
+                ref condition,
+                ref body,
+            } => {
+                calls_in_expr(condition, called);
+                calls_in_block(body, called);
+            }
+            // The values are constants, which can't call anything.
+            Stmt::Case {
+                ref subject,
+                ref arms,
+                ref otherwise,
+            } => {
+                calls_in_expr(subject, called);
+                for arm in arms {
+                    calls_in_block(&arm.1, called);
+                }
+                if let Some(ref otherwise) = *otherwise {
+                    calls_in_block(otherwise, called);
+                }
+            }
+            Stmt::Store { ref place, ref value } => {
+                match *place {
+                    Place::Memory(ref address) => calls_in_expr(address, called),
+                    Place::Element(_, ref index) => calls_in_expr(index, called),
+                    Place::Field(ref record, _) => calls_in_expr(record, called),
+                }
+                calls_in_expr(value, called);
+            }
+            Stmt::Block(ref body) => calls_in_block(body, called),
+        }
+    }
+}
+
+fn calls_in_expr(expr: &Expr, called: &mut Vec<String>) {
+    match expr.kind {
+        ExprKind::Int(..)
+        | ExprKind::Float(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Str(_)
+        | ExprKind::Variable(_)
+        | ExprKind::Read => (),
+        ExprKind::Call(ref name, ref arguments) => {
+            if !called.contains(name) {
+                called.push(name.clone());
+            }
+            for argument in arguments {
+                calls_in_expr(argument, called);
+            }
+        }
+        ExprKind::Address(ref name) => {
+            if !called.contains(name) {
+                called.push(name.clone());
+            }
+        }
+        ExprKind::CallIndirect(_, ref arguments) => {
+            for argument in arguments {
+                calls_in_expr(argument, called);
+            }
+        }
+        ExprKind::Binary(_, ref left, ref right) => {
+            calls_in_expr(left, called);
+            calls_in_expr(right, called);
+        }
+        ExprKind::Load(ref inner)
+        | ExprKind::Index(_, ref inner)
+        | ExprKind::Field(ref inner, _)
+        | ExprKind::View(_, ref inner) => calls_in_expr(inner, called),
+        ExprKind::Conditional(ref condition, ref then_value, ref else_value) => {
+            calls_in_expr(condition, called);
+            calls_in_expr(then_value, called);
+            calls_in_expr(else_value, called);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! With --profile, every function counts how many times it's been called,
+//! and every loop how many times it's gone round, in a global the module
+//! exports as `profile:` followed by what it counts. The host reads them back
+//! once the program's finished, and so does --run. The interpreter keeps the
+//! same counts itself, and prints the report once it's run the program.
+
+use source_map::{SourceMap, Span};
+
+/// What a counter counts: calls to the function with a name, runs of main
+/// or the init blocks, or times round the loop whose condition is at a span.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Counter {
+    Call(String),
+    Main,
+    Init,
+    Loop(Span),
+}
+
+impl Counter {
+    /// What the report calls the counter, which is also what its export is
+    /// named after. Main and the init blocks are in brackets, so they can't
+    /// be mistaken for functions with those names.
+    pub fn label(&self, source_map: &SourceMap) -> String {
+        match *self {
+            Counter::Call(ref name) => name.clone(),
+            Counter::Main => String::from("(main)"),
+            Counter::Init => String::from("(init)"),
+            Counter::Loop(span) => {
+                let location = source_map.location(span);
+                format!("loop at {}:{}:{}", location.file, location.line, location.column)
+            }
+        }
+    }
+}
+
+/// Returns the hot-spot summary for a run, given each counter's label and
+/// count: the busiest first, with its share of everything counted, and
+/// nothing for what never ran.
+pub fn report(counts: &[(String, u64)]) -> String {
+    let mut counts: Vec<&(String, u64)> = counts.iter().filter(|&&(_, count)| count > 0).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let total: u64 = counts.iter().map(|&&(_, count)| count).sum();
+
+    let mut report = String::from("profile:\n");
+    for &&(ref label, count) in &counts {
+        let share = 100.0 * count as f64 / total as f64;
+        report.push_str(&format!("{:>12} {:>5.1}%  {}\n", count, share, label));
+    }
+    report
+}
@@ -0,0 +1,105 @@
+//! The interactive mode, with --repl. Each input is a statement or two, or
+//! an item like a function, and the interpreter runs it as soon as it's
+//! entered. An input that's just an expression prints its value.
+//!
+//! Every input goes on the end of the program so far, and the whole thing is
+//! checked again, so inputs can use anything the ones before them defined.
+//! Only what's new runs; the interpreter's state, main's variables included,
+//! carries over from one input to the next.
+
+use std::io::{BufRead, Write};
+
+use consteval::Value;
+use crash::Progress;
+use diagnostics::Warning;
+use interpret::{Interpreter, State};
+use {check, report, report_warnings, Options};
+
+/// What's printed when the REPL is ready for an input, and when the input
+/// so far isn't finished, like an `if` without its `end`.
+const PROMPT: &str = "> ";
+const CONTINUE: &str = ". ";
+
+/// Runs inputs, one or more lines at a time, until `input` runs out. Returns
+/// what main returns if an input returns from it, which ends the session.
+pub fn run(options: &Options, input: &mut dyn BufRead, output: &mut dyn Write) -> Option<Value> {
+    // A variable that hasn't been read yet might be in the next input.
+    let mut options = options.clone();
+    options.disabled_warnings.push(Warning::UnusedVariable);
+
+    let mut program = String::new();
+    let mut pending = String::new();
+    let mut state = State::default();
+
+    // Checking the whole program again finds the same warnings again, so
+    // only the ones we haven't seen get printed.
+    let mut seen_warnings = Vec::new();
+
+    loop {
+        prompt(output, if pending.is_empty() { PROMPT } else { CONTINUE });
+        let mut line = String::new();
+        if let Ok(0) | Err(_) = input.read_line(&mut line) {
+            let _ = writeln!(output);
+            return None;
+        }
+        pending.push_str(&line);
+
+        let mut warnings = Vec::new();
+        let source = format!("{}{}", program, pending);
+        let checked = match check(source.as_bytes(), &options, &mut Progress::default(), &mut warnings) {
+            Ok(checked) => Some((checked, source)),
+
+            // Wait for the rest of an unfinished input, unless the line
+            // was blank, which means there isn't any more.
+            Err(ref errors) if !line.trim().is_empty() && errors.iter().any(|error| ends_early(&error.text)) => continue,
+
+            Err(errors) => {
+                warnings.clear();
+                let print = format!("{}print {}", program, pending);
+                match check(print.as_bytes(), &options, &mut Progress::default(), &mut warnings) {
+                    Ok(checked) => Some((checked, print)),
+                    Err(_) => {
+                        report(&options, &errors);
+                        None
+                    }
+                }
+            }
+        };
+        pending.clear();
+
+        let ((hir, mut diagnostics), source) = match checked {
+            Some(checked) => checked,
+            None => continue,
+        };
+        warnings.retain(|warning| !seen_warnings.contains(warning));
+        report_warnings(&options, &warnings);
+        seen_warnings.extend(warnings);
+
+        // Whatever happens, the input stays in the program: some of it may
+        // have run before a trap.
+        program = source;
+        let mut interpreter = Interpreter::resume(&options, &hir, state, input, output);
+        let result = interpreter.run_new();
+        state = interpreter.into_state();
+
+        match result {
+            Ok(Some(value)) => return Some(value),
+            Ok(None) => (),
+            Err(trap) => {
+                diagnostics.error(trap.span, &trap.message);
+                report(&options, &diagnostics.into_messages());
+            }
+        }
+    }
+}
+
+/// Prints a prompt, without a newline.
+fn prompt(output: &mut dyn Write, prompt: &str) {
+    let _ = write!(output, "{}", prompt).and_then(|()| output.flush());
+}
+
+/// Returns true if an error is the parser running out of input, so the
+/// input might just not be finished yet.
+fn ends_early(error: &str) -> bool {
+    error.contains("found end of input")
+}
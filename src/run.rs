@@ -0,0 +1,456 @@
+//! With the `run` feature, --run runs the compiled module in-process with
+//! wasmtime instead of printing it. The host here gives the module the same
+//! `env` functions the browser loader does, except that what it prints goes
+//! to the output it's given, and what the module says just before it traps
+//! becomes the trap's message. With --target wasi, it gives it the few WASI
+//! functions the compiler uses instead, and runs `_start`.
+//!
+//! With --profile, the counters are read back out of the module once it's
+//! done, for the same report the interpreter prints.
+//!
+//! --verify runs the program with the interpreter too, and checks that it
+//! does exactly what the module does, which catches code generation bugs
+//! that still make a valid module.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::time::Instant;
+
+use wasmtime::{Caller, Config, Engine, Error, Extern, Global, Instance, Linker, Module, Store, Trap, Val};
+
+use codegen::Platform;
+use consteval::Value;
+use interpret;
+use wasi;
+use {interpret_captured, Options};
+
+/// What running a module did.
+pub struct Run<W> {
+    // The output it printed to, given back.
+    pub output: W,
+
+    // What the entry returned, if it returns anything, or the status it
+    // halted with. If it trapped, why.
+    pub result: Result<Option<i32>, String>,
+
+    // With --profile, each counter's label and count, from the globals the
+    // module exports, for `profile::report`.
+    pub profile: Vec<(String, u64)>,
+}
+
+/// What the `env` functions have to work with.
+struct Host<R, W> {
+    input: R,
+    output: W,
+
+    // Why the module's about to trap, once it's called the function that
+    // says so.
+    reason: Option<String>,
+
+    // Where `random` is up to, like the interpreter's.
+    random: u64,
+
+    // When `now` started counting from.
+    started: Instant,
+}
+
+/// What `exit` stops the module with, since there's no other way to stop it
+/// from a host function. The call gives it back as its error.
+#[derive(Debug)]
+struct Exit(i32);
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "exited with status {}", self.0)
+    }
+}
+
+impl error::Error for Exit {}
+
+/// Assembles a module in the text format and calls its entry, reading from
+/// `input` and printing to `output`. Returns an error if the module doesn't
+/// load, which is a bug in the code generator.
+pub fn run<R, W>(text: &str, options: &Options, input: R, output: W) -> Result<Run<W>, String>
+where
+    R: BufRead + 'static,
+    W: Write + 'static,
+{
+    let mut config = Config::new();
+    config
+        .wasm_gc(true)
+        .wasm_function_references(true)
+        .wasm_simd(true)
+        .wasm_threads(true)
+        .shared_memory(true)
+        .wasm_memory64(true)
+        .wasm_multi_memory(true)
+        .wasm_tail_call(true);
+    let engine = Engine::new(&config).map_err(|err| err.to_string())?;
+    let module = Module::new(&engine, text).map_err(|err| err.to_string())?;
+
+    let mut linker = Linker::new(&engine);
+    define_env(&mut linker).map_err(|err| err.to_string())?;
+    define_wasi(&mut linker).map_err(|err| err.to_string())?;
+
+    let host = Host {
+        input,
+        output,
+        reason: None,
+        random: interpret::seed(),
+        started: Instant::now(),
+    };
+    let mut store = Store::new(&engine, host);
+
+    // Instantiating it runs the init blocks, which can trap too.
+    let entry = match options.platform {
+        Platform::Wasi => "_start",
+        _ => options.entry_name(),
+    };
+    let (result, instance) = match linker.instantiate(&mut store, &module) {
+        Ok(instance) => (call(instance, &mut store, entry), Some(instance)),
+        Err(err) => (Err(err), None),
+    };
+
+    // A module that halts has its status as its result, like in the loader,
+    // and so does one that exits through WASI.
+    let result = match result {
+        Ok(result) => Ok(result),
+        Err(err) => match err.downcast_ref::<Exit>() {
+            Some(&Exit(status)) => Ok(Some(status)),
+            None if err.downcast_ref::<Trap>() == Some(&Trap::StackOverflow) => {
+                Err(String::from(interpret::STACK_EXHAUSTED))
+            }
+            None => Err(store.data_mut().reason.take().unwrap_or_else(|| err.root_cause().to_string())),
+        },
+    };
+
+    let profile = match instance {
+        Some(instance) if options.profile => profile(instance, &mut store),
+        _ => Vec::new(),
+    };
+
+    Ok(Run {
+        output: store.into_data().output,
+        result,
+        profile,
+    })
+}
+
+/// Calls the entry.
+fn call<T>(instance: Instance, store: &mut Store<T>, entry: &str) -> wasmtime::Result<Option<i32>> {
+    let main = match instance.get_func(&mut *store, entry) {
+        Some(main) => main,
+        None => return Err(Error::msg(format!("the module doesn't export `{}`", entry))),
+    };
+
+    let mut results = vec![Val::I32(0); main.ty(&*store).results().len()];
+    main.call(&mut *store, &[], &mut results)?;
+    Ok(results.first().and_then(Val::i32))
+}
+
+/// Reads the counters back out of the `profile:` globals, labelled with
+/// what's after the colon. They're unsigned, like in the loader.
+fn profile<T>(instance: Instance, store: &mut Store<T>) -> Vec<(String, u64)> {
+    let counters: Vec<(String, Global)> = instance
+        .exports(&mut *store)
+        .filter_map(|export| {
+            let label = export.name().strip_prefix("profile:")?.to_string();
+            Some((label, export.into_global()?))
+        })
+        .collect();
+
+    counters
+        .into_iter()
+        .map(|(label, global)| {
+            let count = global.get(&mut *store).i32().map_or(0, |count| u64::from(count as u32));
+            (label, count)
+        })
+        .collect()
+}
+
+/// Defines every function the module can import from `env`. Most modules
+/// only import a few of them, and the rest go unused.
+fn define_env<R, W>(linker: &mut Linker<Host<R, W>>) -> wasmtime::Result<()>
+where
+    R: BufRead + 'static,
+    W: Write + 'static,
+{
+    linker.func_wrap("env", "print", |mut caller: Caller<'_, Host<R, W>>, value: i32| {
+        writeln!(caller.data_mut().output, "{}", value).map_err(Error::from)
+    })?;
+    linker.func_wrap("env", "print_i64", |mut caller: Caller<'_, Host<R, W>>, value: i64| {
+        writeln!(caller.data_mut().output, "{}", value).map_err(Error::from)
+    })?;
+    // The same way the interpreter prints them.
+    linker.func_wrap("env", "print_f64", |mut caller: Caller<'_, Host<R, W>>, value: f64| {
+        writeln!(caller.data_mut().output, "{}", Value::F64(value)).map_err(Error::from)
+    })?;
+    linker.func_wrap(
+        "env",
+        "print_str",
+        |mut caller: Caller<'_, Host<R, W>>, address: i32, len: i32| {
+            let bytes = string(&mut caller, address as u32, len as u32)?;
+            let output = &mut caller.data_mut().output;
+            output.write_all(&bytes)?;
+            writeln!(output).map_err(Error::from)
+        },
+    )?;
+
+    linker.func_wrap("env", "read", |mut caller: Caller<'_, Host<R, W>>| {
+        let mut line = String::new();
+        if caller.data_mut().input.read_line(&mut line)? == 0 {
+            return Err(Error::msg("there's nothing left to read"));
+        }
+        let line = line.trim();
+        line.parse::<i32>()
+            .map_err(|_| Error::msg(format!("expected an i32 to read, found `{}`", line)))
+    })?;
+    linker.func_wrap("env", "random", |mut caller: Caller<'_, Host<R, W>>| {
+        interpret::random(&mut caller.data_mut().random)
+    })?;
+    linker.func_wrap("env", "now", |caller: Caller<'_, Host<R, W>>| {
+        caller.data().started.elapsed().as_millis() as i64
+    })?;
+
+    linker.func_wrap("env", "exit", |status: i32| -> wasmtime::Result<()> { Err(Error::new(Exit(status))) })?;
+    linker.func_wrap(
+        "env",
+        "divide_by_zero",
+        |mut caller: Caller<'_, Host<R, W>>, line: i32, column: i32| {
+            let reason = format!("integer divide by zero at line {}, column {}", line, column);
+            caller.data_mut().reason = Some(reason);
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "assertion_failed",
+        |mut caller: Caller<'_, Host<R, W>>, line: i32, column: i32| {
+            let reason = format!("assertion failed at line {}, column {}", line, column);
+            caller.data_mut().reason = Some(reason);
+        },
+    )?;
+    linker.func_wrap("env", "out_of_fuel", |mut caller: Caller<'_, Host<R, W>>| {
+        caller.data_mut().reason = Some(String::from("out of fuel"));
+    })?;
+    Ok(())
+}
+
+/// Defines the WASI functions the compiler uses, all of which work like
+/// their `env` counterparts. Errors trap, since the module ignores the
+/// error numbers these return.
+fn define_wasi<R, W>(linker: &mut Linker<Host<R, W>>) -> wasmtime::Result<()>
+where
+    R: BufRead + 'static,
+    W: Write + 'static,
+{
+    linker.func_wrap(
+        wasi::MODULE,
+        "fd_write",
+        |mut caller: Caller<'_, Host<R, W>>, fd: i32, iovecs: i32, count: i32, written: i32| {
+            if fd != 1 {
+                return Err(Error::msg(format!("the module wrote to file descriptor {}", fd)));
+            }
+            let mut total = 0;
+            for i in 0..count as u32 {
+                let iovec = string(&mut caller, iovecs as u32 + i * 8, 8)?;
+                let address = u32::from_le_bytes([iovec[0], iovec[1], iovec[2], iovec[3]]);
+                let len = u32::from_le_bytes([iovec[4], iovec[5], iovec[6], iovec[7]]);
+                let bytes = string(&mut caller, address, len)?;
+                caller.data_mut().output.write_all(&bytes)?;
+                total += len;
+            }
+            store(&mut caller, written as u32, &total.to_le_bytes())?;
+            Ok(0)
+        },
+    )?;
+    linker.func_wrap(wasi::MODULE, "proc_exit", |status: i32| -> wasmtime::Result<()> {
+        Err(Error::new(Exit(status)))
+    })?;
+    // `$host.random` only uses the high 31 bits, so the number goes there.
+    linker.func_wrap(
+        wasi::MODULE,
+        "random_get",
+        |mut caller: Caller<'_, Host<R, W>>, address: i32, len: i32| {
+            let mut bytes = Vec::new();
+            while bytes.len() < len as usize {
+                let random = interpret::random(&mut caller.data_mut().random) as u32;
+                bytes.extend_from_slice(&(random << 1).to_le_bytes());
+            }
+            bytes.truncate(len as usize);
+            store(&mut caller, address as u32, &bytes)?;
+            Ok(0)
+        },
+    )?;
+    linker.func_wrap(
+        wasi::MODULE,
+        "clock_time_get",
+        |mut caller: Caller<'_, Host<R, W>>, _: i32, _: i64, address: i32| {
+            let time = caller.data().started.elapsed().as_nanos() as u64;
+            store(&mut caller, address as u32, &time.to_le_bytes())?;
+            Ok(0)
+        },
+    )?;
+    Ok(())
+}
+
+/// Copies bytes into the module's memory, for the WASI functions that
+/// hand things back that way.
+fn store<T>(caller: &mut Caller<'_, T>, address: u32, bytes: &[u8]) -> wasmtime::Result<()> {
+    let range = address as usize..address as usize + bytes.len();
+    let stored = match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => memory
+            .data_mut(&mut *caller)
+            .get_mut(range)
+            .map(|cells| cells.copy_from_slice(bytes)),
+        // Like `string`, we're the only one at shared memory while the
+        // module waits for us.
+        Some(Extern::SharedMemory(memory)) => memory.data().get(range).map(|cells| {
+            for (cell, &byte) in cells.iter().zip(bytes) {
+                unsafe { *cell.get() = byte }
+            }
+        }),
+        _ => return Err(Error::msg("the module doesn't export its memory")),
+    };
+    stored.ok_or_else(|| Error::msg("out of bounds"))
+}
+
+/// Copies a string out of the module's memory.
+fn string<T>(caller: &mut Caller<'_, T>, address: u32, len: u32) -> wasmtime::Result<Vec<u8>> {
+    let range = address as usize..address as usize + len as usize;
+    let bytes = match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => memory.data(&*caller).get(range).map(<[u8]>::to_vec),
+        // With --threads, memory is shared, and wasmtime only lets us at it
+        // a cell at a time, since another thread could be writing it. The
+        // module only ever runs on this one, though, and it's waiting for
+        // us to return.
+        Some(Extern::SharedMemory(memory)) => memory
+            .data()
+            .get(range)
+            .map(|cells| cells.iter().map(|cell| unsafe { *cell.get() }).collect()),
+        _ => return Err(Error::msg("the module doesn't export its memory")),
+    };
+    bytes.ok_or_else(|| Error::msg("string out of bounds"))
+}
+
+/// Reads from another reader, and keeps a copy of everything it reads, so
+/// the module can be given exactly what the interpreter read.
+struct Recorder<'a> {
+    input: &'a mut (dyn BufRead + Send),
+    read: Vec<u8>,
+}
+
+impl<'a> Read for Recorder<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.input.read(buf)?;
+        self.read.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
+
+impl<'a> BufRead for Recorder<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.input.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if let Ok(buf) = self.input.fill_buf() {
+            self.read.extend_from_slice(&buf[..amount]);
+        }
+        self.input.consume(amount);
+    }
+}
+
+/// Why `verify` couldn't vouch for a module.
+#[derive(Debug, PartialEq)]
+pub enum Unverified {
+    // The module doesn't do what the interpreter does, which is a bug in
+    // the compiler. Says how they differ.
+    Differs(String),
+
+    // One of them ran out of stack, so there's nothing to compare: how deep
+    // calls can go is up to whatever runs them. Says which.
+    OutOfStack(&'static str),
+}
+
+/// Runs a program with the interpreter, and `text`, what it compiled to,
+/// with wasmtime. The module reads whatever the interpreter read from
+/// `input`. Returns what the module did if they both did the same thing. If
+/// they didn't, the error says how they differ: in what they printed, what
+/// they returned, or whether they trapped. If either ran out of stack, the
+/// error says which, since then there's no telling.
+pub fn verify(
+    program: &[u8],
+    text: &str,
+    options: &Options,
+    input: &mut (dyn BufRead + Send),
+) -> Result<Run<Vec<u8>>, Unverified> {
+    let mut input = Recorder { input, read: Vec::new() };
+    let interpreted = match interpret_captured(program, options, &mut input) {
+        Ok(outcome) => outcome,
+        Err(_) => return Err(Unverified::Differs(String::from("the program doesn't compile for the interpreter"))),
+    };
+    let ran = run(text, options, io::Cursor::new(input.read), Vec::new()).map_err(Unverified::Differs)?;
+
+    if interpreted.result.as_ref().is_err_and(|trap| trap.text == interpret::STACK_EXHAUSTED) {
+        return Err(Unverified::OutOfStack("the interpreter"));
+    }
+    if ran.result.as_ref().is_err_and(|reason| reason == interpret::STACK_EXHAUSTED) {
+        return Err(Unverified::OutOfStack("the module"));
+    }
+
+    let mut differences = Vec::new();
+    if interpreted.output != ran.output {
+        differences.push(output_difference(&interpreted.output, &ran.output));
+    }
+
+    // Main returns nothing with --no-result, where the interpreter says 0.
+    let same = match (&interpreted.result, &ran.result) {
+        (&Ok(expected), &Ok(Some(actual))) => expected == actual,
+        (&Ok(_), &Ok(None)) => options.no_result,
+        (&Err(_), &Err(_)) => true,
+        _ => false,
+    };
+    if !same {
+        let expected = match interpreted.result {
+            Ok(value) => format!("returned {}", value),
+            Err(ref trap) => format!("trapped ({})", trap.text),
+        };
+        let actual = match ran.result {
+            Ok(Some(value)) => format!("returned {}", value),
+            Ok(None) => String::from("returned nothing"),
+            Err(ref reason) => format!("trapped ({})", reason),
+        };
+        differences.push(format!("the interpreter {} and the module {}", expected, actual));
+    }
+
+    if differences.is_empty() {
+        Ok(ran)
+    } else {
+        Err(Unverified::Differs(differences.join("; ")))
+    }
+}
+
+/// Says where two different outputs first differ, by line.
+fn output_difference(expected: &[u8], actual: &[u8]) -> String {
+    let (expected, actual) = (String::from_utf8_lossy(expected), String::from_utf8_lossy(actual));
+    let (mut expected_lines, mut actual_lines) = (expected.lines(), actual.lines());
+    let shown = |line: Option<&str>| line.map_or(String::from("nothing"), |line| format!("`{}`", line));
+
+    for number in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => (),
+            (None, None) => break,
+            (expected, actual) => {
+                return format!(
+                    "on line {} of the output, the interpreter printed {} and the module printed {}",
+                    number,
+                    shown(expected),
+                    shown(actual)
+                )
+            }
+        }
+    }
+    String::from("the interpreter and the module end their output differently")
+}
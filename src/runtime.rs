@@ -0,0 +1,816 @@
+//! Runtime functions that work the same on every platform, written in WAT.
+//! Code generation only puts the ones a program uses in the module.
+//!
+//! Strings can't change, so a substring can point into the string it came
+//! from, but joining two strings needs somewhere new to put the bytes. That's
+//! the heap, which starts after everything the layout placed and grows
+//! towards the end of memory, making memory bigger when it gets there.
+//! Programs can allocate on it too, with `alloc`. Nothing on it is ever
+//! freed, except with --rc.
+//!
+//! With --rc, strings on the heap have a header in the eight bytes before
+//! them: how many bytes there's room for, and how many references there are
+//! to the string. Once there aren't any, the string goes on a free list,
+//! linked through where the count was, for the next string that fits. Any
+//! string after the start of the heap is one of these; the rest are literals,
+//! which are never freed, so counting their references would be a waste.
+//! Records made with `alloc` have the same header and go on the same list.
+//! Giving up a record's last reference gives up the ones its fields hold.
+//!
+//! Addresses are 32 bits, like the ones in strings, even with memory64; the
+//! functions widen them for loads and stores.
+
+use layout;
+
+/// `$heap.alloc`, which makes room for `size` bytes on the heap and returns
+/// their address. Like the layout's `reserve`, the address is a multiple of
+/// four. Traps if memory can't grow enough.
+pub fn alloc(memory64: bool) -> String {
+    let (size, grow) = if memory64 {
+        ("(memory.size)\n(i32.wrap_i64)", "(i64.extend_i32_u)\n(memory.grow)\n(i64.const -1)\n(i64.eq)")
+    } else {
+        ("(memory.size)", "(memory.grow)\n(i32.const -1)\n(i32.eq)")
+    };
+    format!(
+        "(func $heap.alloc (param $size i32) (result i32)\n\
+         (local $address i32)\n\
+         (local $end i32)\n\
+         (local $pages i32)\n\
+         (local.set $address\n\
+         (global.get $heap.top)\n\
+         (i32.const 3)\n\
+         (i32.add)\n\
+         (i32.const -4)\n\
+         (i32.and)\n\
+         )\n\
+         (local.set $end\n\
+         (local.get $address)\n\
+         (local.get $size)\n\
+         (i32.add)\n\
+         )\n\
+         (local.get $end)\n\
+         (local.get $address)\n\
+         (i32.lt_u)\n\
+         (if\n\
+         (then\n\
+         (unreachable)\n\
+         )\n\
+         )\n\
+         (local.set $pages\n\
+         (local.get $end)\n\
+         (i64.extend_i32_u)\n\
+         (i64.const {last_byte})\n\
+         (i64.add)\n\
+         (i64.const 16)\n\
+         (i64.shr_u)\n\
+         (i32.wrap_i64)\n\
+         )\n\
+         (local.get $pages)\n\
+         {size}\n\
+         (i32.gt_u)\n\
+         (if\n\
+         (then\n\
+         (local.get $pages)\n\
+         {size}\n\
+         (i32.sub)\n\
+         {grow}\n\
+         (if\n\
+         (then\n\
+         (unreachable)\n\
+         )\n\
+         )\n\
+         )\n\
+         )\n\
+         (global.set $heap.top\n\
+         (local.get $end)\n\
+         )\n\
+         (local.get $address)\n\
+         )\n",
+        last_byte = layout::PAGE_SIZE - 1,
+        size = size,
+        grow = grow,
+    )
+}
+
+/// `$mem.copy`, which copies `len` bytes from `from` to `to`. That's
+/// `memory.copy`, or with --no-bulk-memory, a loop that copies a byte at a
+/// time from the first, so `to` mustn't be in the middle of the bytes.
+pub fn copy(memory64: bool, bulk: bool) -> String {
+    let extend = extend(memory64);
+    if bulk {
+        return format!(
+            "(func $mem.copy (param $to i32) (param $from i32) (param $len i32)\n\
+             (local.get $to)\n\
+             {extend}\
+             (local.get $from)\n\
+             {extend}\
+             (local.get $len)\n\
+             {extend}\
+             (memory.copy)\n\
+             )\n",
+            extend = extend,
+        );
+    }
+    format!(
+        "(func $mem.copy (param $to i32) (param $from i32) (param $len i32)\n\
+         (block\n\
+         (loop\n\
+         (local.get $len)\n\
+         (i32.eqz)\n\
+         (br_if 1)\n\
+         (local.get $to)\n\
+         {extend}\
+         (local.get $from)\n\
+         {extend}\
+         (i32.load8_u)\n\
+         (i32.store8)\n\
+         (local.set $to\n\
+         (local.get $to)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (local.set $from\n\
+         (local.get $from)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (local.set $len\n\
+         (local.get $len)\n\
+         (i32.const 1)\n\
+         (i32.sub)\n\
+         )\n\
+         (br 0)\n\
+         )\n\
+         )\n\
+         )\n",
+        extend = extend,
+    )
+}
+
+/// `$mem.move`, which is what `copy` calls: it copies `len` bytes from
+/// `from` to `to`, even if they overlap, and returns `to`. With
+/// --no-bulk-memory, it copies from the last byte when `to` comes after
+/// `from`, so every byte is read before it's written over.
+pub fn move_bytes(memory64: bool, bulk: bool) -> String {
+    let extend = extend(memory64);
+    let copy = if bulk {
+        format!(
+            "(local.get $to)\n\
+             {extend}\
+             (local.get $from)\n\
+             {extend}\
+             (local.get $len)\n\
+             {extend}\
+             (memory.copy)\n",
+            extend = extend,
+        )
+    } else {
+        format!(
+            "(local.get $to)\n\
+             (local.get $from)\n\
+             (i32.gt_u)\n\
+             (if\n\
+             (then\n\
+             (block\n\
+             (loop\n\
+             (local.get $len)\n\
+             (i32.eqz)\n\
+             (br_if 1)\n\
+             (local.set $len\n\
+             (local.get $len)\n\
+             (i32.const 1)\n\
+             (i32.sub)\n\
+             )\n\
+             (local.get $to)\n\
+             (local.get $len)\n\
+             (i32.add)\n\
+             {extend}\
+             (local.get $from)\n\
+             (local.get $len)\n\
+             (i32.add)\n\
+             {extend}\
+             (i32.load8_u)\n\
+             (i32.store8)\n\
+             (br 0)\n\
+             )\n\
+             )\n\
+             )\n\
+             (else\n\
+             (local.get $to)\n\
+             (local.get $from)\n\
+             (local.get $len)\n\
+             (call $mem.copy)\n\
+             )\n\
+             )\n",
+            extend = extend,
+        )
+    };
+    format!(
+        "(func $mem.move (param $to i32) (param $from i32) (param $len i32) (result i32)\n\
+         {copy}\
+         (local.get $to)\n\
+         )\n",
+        copy = copy,
+    )
+}
+
+/// `$mem.fill`, which is what `fill` calls: it sets `len` bytes from `to` on
+/// to the low byte of `value`, and returns `to`. That's `memory.fill`, or
+/// with --no-bulk-memory, a loop that sets a byte at a time.
+pub fn fill(memory64: bool, bulk: bool) -> String {
+    let extend = extend(memory64);
+    let (local, fill) = if bulk {
+        (
+            "",
+            format!(
+                "(local.get $to)\n\
+                 {extend}\
+                 (local.get $value)\n\
+                 (local.get $len)\n\
+                 {extend}\
+                 (memory.fill)\n",
+                extend = extend,
+            ),
+        )
+    } else {
+        (
+            "(local $address i32)\n",
+            format!(
+                "(local.set $address\n\
+                 (local.get $to)\n\
+                 )\n\
+                 (block\n\
+                 (loop\n\
+                 (local.get $len)\n\
+                 (i32.eqz)\n\
+                 (br_if 1)\n\
+                 (local.get $address)\n\
+                 {extend}\
+                 (local.get $value)\n\
+                 (i32.store8)\n\
+                 (local.set $address\n\
+                 (local.get $address)\n\
+                 (i32.const 1)\n\
+                 (i32.add)\n\
+                 )\n\
+                 (local.set $len\n\
+                 (local.get $len)\n\
+                 (i32.const 1)\n\
+                 (i32.sub)\n\
+                 )\n\
+                 (br 0)\n\
+                 )\n\
+                 )\n",
+                extend = extend,
+            ),
+        )
+    };
+    format!(
+        "(func $mem.fill (param $to i32) (param $value i32) (param $len i32) (result i32)\n\
+         {local}\
+         {fill}\
+         (local.get $to)\n\
+         )\n",
+        local = local,
+        fill = fill,
+    )
+}
+
+/// `$str.concat`, which copies two strings' bytes to the heap, one after
+/// the other, and returns the string they make. With --rc, it's a new
+/// string with one reference.
+pub fn concat(rc: bool) -> String {
+    format!(
+        "(func $str.concat (param $left i64) (param $right i64) (result i64)\n\
+         (local $left.len i32)\n\
+         (local $len i32)\n\
+         (local $address i32)\n\
+         (local.set $left.len\n\
+         (local.get $left)\n\
+         (i64.const 32)\n\
+         (i64.shr_u)\n\
+         (i32.wrap_i64)\n\
+         )\n\
+         (local.set $len\n\
+         (local.get $left.len)\n\
+         (local.get $right)\n\
+         (i64.const 32)\n\
+         (i64.shr_u)\n\
+         (i32.wrap_i64)\n\
+         (i32.add)\n\
+         )\n\
+         (local.set $address\n\
+         (local.get $len)\n\
+         (call ${alloc})\n\
+         )\n\
+         (local.get $address)\n\
+         (local.get $left)\n\
+         (i32.wrap_i64)\n\
+         (local.get $left.len)\n\
+         (call $mem.copy)\n\
+         (local.get $address)\n\
+         (local.get $left.len)\n\
+         (i32.add)\n\
+         (local.get $right)\n\
+         (i32.wrap_i64)\n\
+         (local.get $len)\n\
+         (local.get $left.len)\n\
+         (i32.sub)\n\
+         (call $mem.copy)\n\
+         (local.get $len)\n\
+         (i64.extend_i32_u)\n\
+         (i64.const 32)\n\
+         (i64.shl)\n\
+         (local.get $address)\n\
+         (i64.extend_i32_u)\n\
+         (i64.or)\n\
+         )\n",
+        alloc = allocator(rc),
+    )
+}
+
+/// `$str.compare`, which returns -1, 0, or 1 if the first string comes
+/// before, is the same as, or comes after the second. The first byte that
+/// differs decides, as an unsigned number; if there isn't one, the shorter
+/// string comes first.
+pub fn compare(memory64: bool) -> String {
+    let extend = extend(memory64);
+    format!(
+        "(func $str.compare (param $left i64) (param $right i64) (result i32)\n\
+         (local $left.len i32)\n\
+         (local $right.len i32)\n\
+         (local $index i32)\n\
+         (local $left.byte i32)\n\
+         (local $right.byte i32)\n\
+         (local.set $left.len\n\
+         (local.get $left)\n\
+         (i64.const 32)\n\
+         (i64.shr_u)\n\
+         (i32.wrap_i64)\n\
+         )\n\
+         (local.set $right.len\n\
+         (local.get $right)\n\
+         (i64.const 32)\n\
+         (i64.shr_u)\n\
+         (i32.wrap_i64)\n\
+         )\n\
+         (block\n\
+         (loop\n\
+         (local.get $index)\n\
+         (local.get $left.len)\n\
+         (i32.eq)\n\
+         (br_if 1)\n\
+         (local.get $index)\n\
+         (local.get $right.len)\n\
+         (i32.eq)\n\
+         (br_if 1)\n\
+         (local.set $left.byte\n\
+         (local.get $left)\n\
+         (i32.wrap_i64)\n\
+         (local.get $index)\n\
+         (i32.add)\n\
+         {extend}\
+         (i32.load8_u)\n\
+         )\n\
+         (local.set $right.byte\n\
+         (local.get $right)\n\
+         (i32.wrap_i64)\n\
+         (local.get $index)\n\
+         (i32.add)\n\
+         {extend}\
+         (i32.load8_u)\n\
+         )\n\
+         (local.get $left.byte)\n\
+         (local.get $right.byte)\n\
+         (i32.ne)\n\
+         (if\n\
+         (then\n\
+         (local.get $left.byte)\n\
+         (local.get $right.byte)\n\
+         (i32.gt_u)\n\
+         (local.get $left.byte)\n\
+         (local.get $right.byte)\n\
+         (i32.lt_u)\n\
+         (i32.sub)\n\
+         (return)\n\
+         )\n\
+         )\n\
+         (local.set $index\n\
+         (local.get $index)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (br 0)\n\
+         )\n\
+         )\n\
+         (local.get $left.len)\n\
+         (local.get $right.len)\n\
+         (i32.gt_u)\n\
+         (local.get $left.len)\n\
+         (local.get $right.len)\n\
+         (i32.lt_u)\n\
+         (i32.sub)\n\
+         )\n",
+        extend = extend,
+    )
+}
+
+/// `$str.substr`, which returns the `len` bytes of a string starting at
+/// `start`. Traps if either is negative or they go past the end of the
+/// string. The bytes aren't copied, except with --rc, where a string that
+/// pointed into another wouldn't have a header of its own.
+pub fn substr(rc: bool) -> String {
+    let (local, address) = if rc {
+        (
+            "(local $address i32)\n",
+            "(local.set $address\n\
+             (local.get $len)\n\
+             (call $rc.alloc)\n\
+             )\n\
+             (local.get $address)\n\
+             (local.get $string)\n\
+             (i32.wrap_i64)\n\
+             (local.get $start)\n\
+             (i32.add)\n\
+             (local.get $len)\n\
+             (call $mem.copy)\n\
+             (local.get $address)\n",
+        )
+    } else {
+        (
+            "",
+            "(local.get $string)\n\
+             (i32.wrap_i64)\n\
+             (local.get $start)\n\
+             (i32.add)\n",
+        )
+    };
+    format!(
+        "(func $str.substr (param $string i64) (param $start i32) (param $len i32) (result i64)\n\
+         {local}\
+         (local.get $start)\n\
+         (local.get $len)\n\
+         (i32.or)\n\
+         (i32.const 0)\n\
+         (i32.lt_s)\n\
+         (local.get $start)\n\
+         (local.get $len)\n\
+         (i32.add)\n\
+         (local.get $string)\n\
+         (i64.const 32)\n\
+         (i64.shr_u)\n\
+         (i32.wrap_i64)\n\
+         (i32.gt_u)\n\
+         (i32.or)\n\
+         (if\n\
+         (then\n\
+         (unreachable)\n\
+         )\n\
+         )\n\
+         (local.get $len)\n\
+         (i64.extend_i32_u)\n\
+         (i64.const 32)\n\
+         (i64.shl)\n\
+         {address}\
+         (i64.extend_i32_u)\n\
+         (i64.or)\n\
+         )\n",
+        local = local,
+        address = address,
+    )
+}
+
+/// `$rc.alloc`, which makes room for a string of `size` bytes with one
+/// reference, and returns its address. It takes the first string on the
+/// free list that's big enough, or else a new one from the heap.
+pub fn rc_alloc(memory64: bool) -> String {
+    let extend = extend(memory64);
+    format!(
+        "(func $rc.alloc (param $size i32) (result i32)\n\
+         (local $string i32)\n\
+         (local $previous i32)\n\
+         (local.set $string\n\
+         (global.get $rc.free)\n\
+         )\n\
+         (block\n\
+         (loop\n\
+         (local.get $string)\n\
+         (i32.eqz)\n\
+         (br_if 1)\n\
+         (local.get $string)\n\
+         (i32.const 8)\n\
+         (i32.sub)\n\
+         {extend}\
+         (i32.load)\n\
+         (local.get $size)\n\
+         (i32.ge_u)\n\
+         (if\n\
+         (then\n\
+         (local.get $previous)\n\
+         (i32.eqz)\n\
+         (if\n\
+         (then\n\
+         (global.set $rc.free\n\
+         (local.get $string)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         {extend}\
+         (i32.load)\n\
+         )\n\
+         )\n\
+         (else\n\
+         (local.get $previous)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         {extend}\
+         (local.get $string)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         {extend}\
+         (i32.load)\n\
+         (i32.store)\n\
+         )\n\
+         )\n\
+         (local.get $string)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         {extend}\
+         (i32.const 1)\n\
+         (i32.store)\n\
+         (local.get $string)\n\
+         (return)\n\
+         )\n\
+         )\n\
+         (local.set $previous\n\
+         (local.get $string)\n\
+         )\n\
+         (local.set $string\n\
+         (local.get $string)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         {extend}\
+         (i32.load)\n\
+         )\n\
+         (br 0)\n\
+         )\n\
+         )\n\
+         (local.get $size)\n\
+         (i32.const {largest})\n\
+         (i32.gt_u)\n\
+         (if\n\
+         (then\n\
+         (unreachable)\n\
+         )\n\
+         )\n\
+         (local.set $string\n\
+         (local.get $size)\n\
+         (i32.const 8)\n\
+         (i32.add)\n\
+         (call $heap.alloc)\n\
+         (i32.const 8)\n\
+         (i32.add)\n\
+         )\n\
+         (local.get $string)\n\
+         (i32.const 8)\n\
+         (i32.sub)\n\
+         {extend}\
+         (local.get $size)\n\
+         (i32.store)\n\
+         (local.get $string)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         {extend}\
+         (i32.const 1)\n\
+         (i32.store)\n\
+         (local.get $string)\n\
+         )\n",
+        extend = extend,
+        largest = u32::MAX - 8,
+    )
+}
+
+/// `$rc.retain`, which adds a reference to a string and returns it.
+pub fn retain(memory64: bool) -> String {
+    let extend = extend(memory64);
+    format!(
+        "(func $rc.retain (param $string i64) (result i64)\n\
+         (local $count i32)\n\
+         (local.get $string)\n\
+         (i32.wrap_i64)\n\
+         (global.get $heap.base)\n\
+         (i32.gt_u)\n\
+         (if\n\
+         (then\n\
+         (local.set $count\n\
+         (local.get $string)\n\
+         (i32.wrap_i64)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         )\n\
+         (local.get $count)\n\
+         {extend}\
+         (local.get $count)\n\
+         {extend}\
+         (i32.load)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         (i32.store)\n\
+         )\n\
+         )\n\
+         (local.get $string)\n\
+         )\n",
+        extend = extend,
+    )
+}
+
+/// `$rc.release`, which takes away a reference to a string, and puts it on
+/// the free list if that was the last one.
+pub fn release(memory64: bool) -> String {
+    let extend = extend(memory64);
+    format!(
+        "(func $rc.release (param $string i64)\n\
+         (local $count i32)\n\
+         (local.get $string)\n\
+         (i32.wrap_i64)\n\
+         (global.get $heap.base)\n\
+         (i32.le_u)\n\
+         (if\n\
+         (then\n\
+         (return)\n\
+         )\n\
+         )\n\
+         (local.set $count\n\
+         (local.get $string)\n\
+         (i32.wrap_i64)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         )\n\
+         (local.get $count)\n\
+         {extend}\
+         (local.get $count)\n\
+         {extend}\
+         (i32.load)\n\
+         (i32.const 1)\n\
+         (i32.sub)\n\
+         (i32.store)\n\
+         (local.get $count)\n\
+         {extend}\
+         (i32.load)\n\
+         (i32.eqz)\n\
+         (if\n\
+         (then\n\
+         (local.get $count)\n\
+         {extend}\
+         (global.get $rc.free)\n\
+         (i32.store)\n\
+         (global.set $rc.free\n\
+         (local.get $string)\n\
+         (i32.wrap_i64)\n\
+         )\n\
+         )\n\
+         )\n\
+         )\n",
+        extend = extend,
+    )
+}
+
+/// `$rc.new`, which makes room for a record of `size` bytes with one
+/// reference. Memory from the free list has whatever was there before, so
+/// every byte is set to 0 first, the way a new record's fields start.
+pub fn rc_new() -> String {
+    String::from(
+        "(func $rc.new (param $size i32) (result i32)\n\
+         (local.get $size)\n\
+         (call $rc.alloc)\n\
+         (i32.const 0)\n\
+         (local.get $size)\n\
+         (call $mem.fill)\n\
+         )\n",
+    )
+}
+
+/// `$rc.retain.record`, which adds a reference to a record and returns it.
+/// Like strings, records before the start of the heap aren't counted, and
+/// that includes the null one.
+pub fn retain_record(memory64: bool) -> String {
+    let extend = extend(memory64);
+    format!(
+        "(func $rc.retain.record (param $record i32) (result i32)\n\
+         (local $count i32)\n\
+         (local.get $record)\n\
+         (global.get $heap.base)\n\
+         (i32.gt_u)\n\
+         (if\n\
+         (then\n\
+         (local.set $count\n\
+         (local.get $record)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         )\n\
+         (local.get $count)\n\
+         {extend}\
+         (local.get $count)\n\
+         {extend}\
+         (i32.load)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         (i32.store)\n\
+         )\n\
+         )\n\
+         (local.get $record)\n\
+         )\n",
+        extend = extend,
+    )
+}
+
+/// `$rc.release.<name>`, which takes away a reference to a record of type
+/// `name`. If that was the last one, `fields` gives up the references its
+/// fields hold, with the record's address in `$record`, and then the record
+/// goes on the free list.
+pub fn release_record(name: &str, fields: &str, memory64: bool) -> String {
+    let extend = extend(memory64);
+    format!(
+        "(func $rc.release.{name} (param $record i32)\n\
+         (local $count i32)\n\
+         (local.get $record)\n\
+         (global.get $heap.base)\n\
+         (i32.le_u)\n\
+         (if\n\
+         (then\n\
+         (return)\n\
+         )\n\
+         )\n\
+         (local.set $count\n\
+         (local.get $record)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         )\n\
+         (local.get $count)\n\
+         {extend}\
+         (local.get $count)\n\
+         {extend}\
+         (i32.load)\n\
+         (i32.const 1)\n\
+         (i32.sub)\n\
+         (i32.store)\n\
+         (local.get $count)\n\
+         {extend}\
+         (i32.load)\n\
+         (i32.eqz)\n\
+         (if\n\
+         (then\n\
+         {fields}\
+         (local.get $count)\n\
+         {extend}\
+         (global.get $rc.free)\n\
+         (i32.store)\n\
+         (global.set $rc.free\n\
+         (local.get $record)\n\
+         )\n\
+         )\n\
+         )\n\
+         )\n",
+        name = name,
+        fields = fields,
+        extend = extend,
+    )
+}
+
+/// `$host.random`, outside WASI, which returns what the host's `random`
+/// does, without the sign bit in case the host left it set.
+pub fn random() -> String {
+    String::from(
+        "(func $host.random (result i32)\n\
+         (call $random)\n\
+         (i32.const 0x7fffffff)\n\
+         (i32.and)\n\
+         )\n",
+    )
+}
+
+/// `$host.clock`, outside WASI, which returns what the host's `now` does.
+pub fn clock() -> String {
+    String::from(
+        "(func $host.clock (result i64)\n\
+         (call $now)\n\
+         )\n",
+    )
+}
+
+/// The runtime function strings are allocated with.
+fn allocator(rc: bool) -> &'static str {
+    if rc {
+        "rc.alloc"
+    } else {
+        "heap.alloc"
+    }
+}
+
+/// What turns a 32-bit address on the stack into one memory takes.
+fn extend(memory64: bool) -> &'static str {
+    if memory64 {
+        "(i64.extend_i32_u)\n"
+    } else {
+        ""
+    }
+}
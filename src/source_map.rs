@@ -0,0 +1,206 @@
+//! Source files, and positions in them. Every file the compiler reads goes in
+//! one `SourceMap`, which gives each file a range of offsets of its own, so a
+//! span on its own is enough to find the file, line, and column it points at.
+
+/// A range of bytes in the source map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Returns a span that starts where `self` starts and ends where `other`
+    /// ends.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+/// A file in the source map.
+pub struct SourceFile {
+    name: String,
+    source: Vec<u8>,
+
+    // Where the file's offsets start in the source map.
+    start: usize,
+
+    // Where each line starts, relative to the start of the file.
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    /// The name the file was added with, usually its path.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &[u8] {
+        &self.source
+    }
+
+    /// The span covering the whole file. Spans of anything in the file are
+    /// inside it.
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            end: self.start + self.source.len(),
+        }
+    }
+
+    /// Turns an offset from the start of the file into a 1-based line and
+    /// column.
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        (line + 1, offset - self.line_starts[line] + 1)
+    }
+
+    /// Returns the text of a 1-based line, without the line ending.
+    fn line(&self, line: usize) -> &[u8] {
+        let start = self.line_starts[line - 1];
+        let end = self.line_starts.get(line).map_or(self.source.len(), |&next| next - 1);
+        let text = &self.source[start..end];
+        text.strip_suffix(b"\r").unwrap_or(text)
+    }
+}
+
+/// Where a span starts, in terms a person can find.
+pub struct Location<'a> {
+    pub file: &'a str,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// All the files in a compilation.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+
+    // What's in files that are read from here instead of from disk, by
+    // name.
+    saved: Vec<(String, Vec<u8>)>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Makes `source` what's read for the file called `name`, instead of
+    /// what's on disk. Replaying a crash report reads the files the input
+    /// included and was linked with from the report.
+    pub fn save_file(&mut self, name: &str, source: Vec<u8>) {
+        self.saved.push((name.to_string(), source));
+    }
+
+    /// What's been saved for the file called `name`, if anything.
+    pub fn saved(&self, name: &str) -> Option<&[u8]> {
+        self.saved.iter().find(|saved| saved.0 == name).map(|saved| &saved.1[..])
+    }
+
+    /// Adds a file, and returns the span covering it.
+    pub fn add_file(&mut self, name: &str, source: Vec<u8>) -> Span {
+        // Leave a gap of one between files, so the end of one file isn't the
+        // same offset as the start of the next.
+        let start = self.files.last().map_or(0, |file| file.span().end + 1);
+
+        let mut line_starts = vec![0];
+        line_starts.extend(
+            source
+                .iter()
+                .enumerate()
+                .filter(|&(_, &byte)| byte == b'\n')
+                .map(|(i, _)| i + 1),
+        );
+
+        self.files.push(SourceFile {
+            name: name.to_string(),
+            source,
+            start,
+            line_starts,
+        });
+        self.files[self.files.len() - 1].span()
+    }
+
+    /// Adds more bytes to the end of a file, for files that are read as
+    /// they're compiled. Only the file added last can grow, since the next
+    /// file's offsets start right after it.
+    pub fn extend_file(&mut self, file: Span, bytes: &[u8]) {
+        let file = self
+            .files
+            .last_mut()
+            .filter(|last| last.start == file.start)
+            .expect("only the last file can grow");
+
+        let len = file.source.len();
+        file.line_starts.extend(
+            bytes
+                .iter()
+                .enumerate()
+                .filter(|&(_, &byte)| byte == b'\n')
+                .map(|(i, _)| len + i + 1),
+        );
+        file.source.extend_from_slice(bytes);
+    }
+
+    /// Every file, in the order they were added.
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    /// Returns the file a span points into. The span has to have come from
+    /// this source map.
+    pub fn file(&self, span: Span) -> &SourceFile {
+        self.files
+            .iter()
+            .rev()
+            .find(|file| file.start <= span.start)
+            .expect("span isn't from this source map")
+    }
+
+    /// Returns the file, line, and column where a span starts.
+    pub fn location(&self, span: Span) -> Location<'_> {
+        let file = self.file(span);
+        let (line, column) = file.line_column(span.start - file.start);
+        Location {
+            file: &file.name,
+            line,
+            column,
+        }
+    }
+
+    /// Returns the text of the line a span starts on, without the line
+    /// ending.
+    pub fn line(&self, span: Span) -> &[u8] {
+        let file = self.file(span);
+        let (line, _) = file.line_column(span.start - file.start);
+        file.line(line)
+    }
+
+    /// Renders the line a span starts on, with the span underlined, for
+    /// showing under an error message. Spans that run past the end of the
+    /// line are only underlined up to the end of it.
+    pub fn snippet(&self, span: Span) -> String {
+        let file = self.file(span);
+        let (line, column) = file.line_column(span.start - file.start);
+        let text = String::from_utf8_lossy(file.line(line));
+
+        // Copy any tabs from the line, so the underline lines up no matter how
+        // wide tabs are.
+        let padding: String = text
+            .chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let rest_of_line = text.len().saturating_sub(column - 1);
+        let width = (span.end - span.start).min(rest_of_line).max(1);
+
+        format!("    {}\n    {}{}", text, padding, "^".repeat(width))
+    }
+}
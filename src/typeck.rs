@@ -0,0 +1,1768 @@
+//! Checks that a program makes sense before any code is generated: every
+//! name refers to something, nothing is defined twice, calls pass the right
+//! number of arguments, and every value has the type its context needs.
+//!
+//! Along the way, it fills in the types it works out, so code generation
+//! doesn't have to work them out again:
+//!
+//! - Integer literals without a suffix get the type they're used as.
+//! - Every assignment gets the type of the variable it assigns to.
+//! - Every constant and global gets its type.
+//! - Calls to a record's name become views of memory as that record, and
+//!   `sizeof(Point)` becomes the record's size.
+//! - Types that name enums, which the parser took for records, become enum
+//!   types, and `Color.red` becomes the variant's number.
+//!
+//! Code generation only runs on programs that pass, so it can count on all of
+//! these being there.
+//!
+//! It also warns about locals that are never read, locals that shadow
+//! others, and `case`s that leave out some of an enum's variants. (Code
+//! after a `return` gets its warning in `desugar`, which still knows where
+//! whole statements are.)
+
+use std::mem;
+
+use builtins;
+use codegen::Platform;
+use consteval;
+use diagnostics::{Diagnostics, Warning};
+use consteval::Value;
+use hir::{
+    Array, BinaryOp, Constant, Enum, EnumType, Expr, ExprKind, FuncType, Function, Global, Place, Program, Record,
+    RecordType, Span, Stmt, Type,
+};
+use layout::RecordLayout;
+use Options;
+
+/// What `if` and `while` accept as a condition, picked with --truthiness.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Truthiness {
+    // Only bools.
+    #[default]
+    Strict,
+
+    // Bools, or integers, which count as true if they aren't zero.
+    Nonzero,
+}
+
+/// Checks a whole program, reporting whatever's wrong with it.
+pub fn check(program: &mut Program, options: &Options, diagnostics: &mut Diagnostics) {
+    let mut checker = TypeChecker {
+        truthiness: options.truthiness,
+        export_all: options.export_all,
+        simd: options.simd,
+        threads: options.threads,
+        memory64: options.memory64,
+        halt_anywhere: options.platform != Platform::Host,
+        exports: vec![String::from("memory"), options.entry_name().to_string()],
+        functions: Vec::new(),
+        variables: Vec::new(),
+        globals: Vec::new(),
+        constants: Vec::new(),
+        global_homes: Vec::new(),
+        arrays: Vec::new(),
+        records: Vec::new(),
+        enums: Vec::new(),
+        table: Vec::new(),
+        scope: Scope::default(),
+        in_constant: false,
+        diagnostics,
+    };
+    if options.metering {
+        checker.exports.push(String::from("fuel"));
+    }
+
+    // Enums come first, so the types that name them can be told from the
+    // ones that name records. Records can have fields of any record type,
+    // so they're all declared before any fields are checked.
+    for item in &program.enums {
+        checker.declare_enum(item);
+    }
+    for record in &mut program.records {
+        for field in &mut record.fields {
+            field.ty = checker.resolve_type(field.ty);
+        }
+        checker.declare_record(record);
+    }
+    for record in &program.records {
+        for field in &record.fields {
+            checker.check_type_name(field.name_span, field.ty);
+        }
+    }
+
+    for constant in &mut program.constants {
+        checker.check_constant(constant);
+    }
+
+    for global in &mut program.globals {
+        checker.check_global(global);
+    }
+
+    for array in &mut program.arrays {
+        checker.check_array(array);
+    }
+
+    if !program.handlers.is_empty() {
+        checker.exports.push(String::from("dispatch"));
+    }
+
+    // Every function is declared before any of them are checked, so they
+    // can call each other in any order, even from another file.
+    for function in &mut program.functions {
+        checker.declare_function(function);
+    }
+    for (i, function) in program.functions.iter_mut().enumerate() {
+        checker.check_function(i, function);
+    }
+    checker.spread_host_use();
+
+    let mut events: Vec<&str> = Vec::new();
+    for handler in &mut program.handlers {
+        if events.contains(&handler.name.as_str()) {
+            checker.diagnostics.error(
+                handler.name_span,
+                &format!("event `{}` already has a handler", handler.name),
+            );
+        }
+        events.push(&handler.name);
+
+        // Handlers always return an i32, for the host.
+        checker.check_body(handler.params.clone(), Type::I32, Vec::new(), &mut handler.body);
+        handler.homes = mem::take(&mut checker.scope.homes);
+    }
+
+    checker.check_init(&mut program.init);
+    program.init_homes = mem::take(&mut checker.scope.homes);
+    checker.check_main(&mut program.main);
+    program.main_homes = mem::take(&mut checker.scope.homes);
+
+    program.table = checker.table;
+    program.global_homes = checker.global_homes;
+}
+
+/// A function's parameter and result types.
+struct Signature {
+    name: String,
+    params: Vec<Type>,
+
+    // Whether each parameter is `var`.
+    by_reference: Vec<bool>,
+
+    // How many of the parameters, at the end, are variables captured from
+    // the functions it's nested in, which calls pass without being told to.
+    captured: usize,
+
+    result: Type,
+    more_results: Vec<Type>,
+
+    // Whether it prints or reads, or calls something that does.
+    uses_host: bool,
+
+    // The functions it calls, for working out `uses_host`.
+    calls: Vec<String>,
+}
+
+impl Signature {
+    /// The type of a pointer to the function: what the program sees, so
+    /// without the variables it captures.
+    fn pointer(&self) -> Type {
+        let params = &self.params[..self.params.len() - self.captured];
+        Type::Func(FuncType::new(params.to_vec(), self.result))
+    }
+}
+
+/// The variables of the function being checked.
+#[derive(Default)]
+struct Scope {
+    params: Vec<(String, Type)>,
+
+    // The locals that can be seen from here, in order of first assignment.
+    locals: Vec<Local>,
+
+    // How many of `locals` there were at the start of each `begin` block
+    // we're inside, innermost last. The block's own come after that.
+    blocks: Vec<usize>,
+
+    // The unique names and types of every local declared in the function
+    // so far, including ones whose blocks have ended.
+    declared: Vec<(String, Type)>,
+
+    result: Type,
+
+    // What else the function returns, if it returns more than one value.
+    more_results: Vec<Type>,
+
+    // Whether this is the init blocks, which run before the host has the
+    // module, so can't print, read, or return.
+    init: bool,
+
+    // Whether this is main.
+    main: bool,
+
+    // Whether anything so far prints or reads, or calls something that
+    // does. Calls to functions that haven't been checked yet don't count
+    // here, but they're in `calls`.
+    uses_host: bool,
+
+    // The functions called so far.
+    calls: Vec<String>,
+
+    // The parameters and locals passed to `var` parameters so far, by their
+    // unique names.
+    homes: Vec<String>,
+}
+
+/// A local variable.
+struct Local {
+    name: String,
+
+    // The name the rest of the compiler knows it by. That's `name`, unless a
+    // block that's already ended, or an outer one, had a local called that
+    // too.
+    unique: String,
+
+    ty: Type,
+
+    // Where it was first assigned, for pointing at where it came from.
+    span: Span,
+
+    // Whether anything reads it.
+    used: bool,
+}
+
+impl Scope {
+    /// Returns the type of a parameter or local. Locals can be looked up by
+    /// their unique names too.
+    fn variable(&self, name: &str) -> Option<Type> {
+        let param = self.params.iter().find(|&(param, _)| param == name).map(|&(_, ty)| ty);
+        param.or_else(|| self.local(name).map(|local| local.ty))
+    }
+
+    /// Finds a local, innermost first, so ones that shadow others win.
+    fn local(&self, name: &str) -> Option<&Local> {
+        self.position(name).map(|position| &self.locals[position])
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.locals.iter().rposition(|local| local.name == name || local.unique == name)
+    }
+
+    /// Returns the local called `name` that a declaration here would shadow:
+    /// one from outside the innermost block, if the block doesn't have its
+    /// own.
+    fn shadowed(&self, name: &str) -> Option<&Local> {
+        let start = *self.blocks.last()?;
+        if self.locals[start..].iter().any(|local| local.name == name) {
+            return None;
+        }
+        self.locals[..start].iter().rev().find(|local| local.name == name)
+    }
+
+    /// Declares a local in the innermost block, and returns its unique name.
+    fn declare(&mut self, name: &str, ty: Type, span: Span) -> String {
+        let mut unique = name.to_string();
+        let mut suffix = 1;
+        while self.declared.iter().any(|declared| declared.0 == unique) {
+            unique = format!("{}'{}", name, suffix);
+            suffix += 1;
+        }
+
+        self.declared.push((unique.clone(), ty));
+        self.locals.push(Local {
+            name: name.to_string(),
+            unique: unique.clone(),
+            ty,
+            span,
+            used: false,
+        });
+        unique
+    }
+
+    /// Renames a variable to the unique name of the local it refers to, if
+    /// it's a local.
+    fn resolve(&self, name: &mut String) {
+        if let Some(local) = self.local(name) {
+            name.clone_from(&local.unique);
+        }
+    }
+
+    /// Like `resolve`, for a variable that's being read.
+    fn read(&mut self, name: &mut String) {
+        if let Some(position) = self.position(name) {
+            let local = &mut self.locals[position];
+            local.used = true;
+            name.clone_from(&local.unique);
+        }
+    }
+}
+
+struct TypeChecker<'a> {
+    truthiness: Truthiness,
+
+    // Whether every function is exported, with --export-all.
+    export_all: bool,
+
+    // What the module exports besides functions, which exported functions
+    // can't have the same name as.
+    exports: Vec<String>,
+
+    // Every function in the program.
+    functions: Vec<Signature>,
+
+    // The parameters and locals of each function checked so far, by
+    // function, for giving the ones nested in it the types of the variables
+    // they capture.
+    variables: Vec<(String, Vec<(String, Type)>)>,
+
+    // Globals and constants, which are visible everywhere unless a parameter
+    // or local has the same name.
+    globals: Vec<(String, Type)>,
+    constants: Vec<(String, Type)>,
+
+    // The globals passed to `var` parameters so far.
+    global_homes: Vec<String>,
+
+    // The names of every array. Arrays only hold i32s.
+    arrays: Vec<String>,
+
+    // Every record, with where its fields go.
+    records: Vec<(RecordType, RecordLayout)>,
+
+    // Every enum, with the names of its variants, in order.
+    enums: Vec<(EnumType, Vec<String>)>,
+
+    // The functions the program takes the address of, in the order it first
+    // does.
+    table: Vec<String>,
+
+    // The function being checked.
+    scope: Scope,
+
+    // Set while checking a constant's value, which can only use other
+    // constants.
+    in_constant: bool,
+
+    // Whether v128 and the vector functions can be used.
+    simd: bool,
+
+    // Whether the atomic functions can be used.
+    threads: bool,
+
+    // Whether memory is 64-bit, so addresses can be i64s too.
+    memory64: bool,
+
+    // Whether `halt` can be anywhere, because the host has a way to stop the
+    // program. Otherwise it can only be in main, where it returns.
+    halt_anywhere: bool,
+
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl<'a> TypeChecker<'a> {
+    fn declare_enum(&mut self, item: &Enum) {
+        let ty = EnumType::new(&item.name);
+        if Type::from_name(&item.name).is_some() {
+            self.diagnostics.error(item.name_span, &format!("`{}` is already a type", item.name));
+        } else if self.variants(ty).is_some() {
+            self.diagnostics.error(item.name_span, &format!("enum `{}` is already defined", item.name));
+        } else if item.variants.is_empty() {
+            self.diagnostics.error(item.name_span, &format!("enum `{}` doesn't have any variants", item.name));
+        }
+        let variants = item.variants.iter().map(|variant| variant.0.clone()).collect();
+        self.enums.push((ty, variants));
+    }
+
+    fn variants(&self, ty: EnumType) -> Option<&[String]> {
+        self.enums
+            .iter()
+            .find(|&&(item, _)| item == ty)
+            .map(|(_, variants)| &variants[..])
+    }
+
+    /// Turns a type the parser took for a record's into an enum's, if that's
+    /// what it names.
+    fn resolve_type(&self, ty: Type) -> Type {
+        match ty {
+            Type::Record(record) if self.variants(EnumType::new(record.name())).is_some() => {
+                Type::Enum(EnumType::new(record.name()))
+            }
+            Type::Func(func) => {
+                let params = func.params().into_iter().map(|param| self.resolve_type(param)).collect();
+                Type::Func(FuncType::new(params, self.resolve_type(func.result())))
+            }
+            ty => ty,
+        }
+    }
+
+    fn declare_record(&mut self, record: &Record) {
+        let ty = RecordType::new(&record.name);
+        if Type::from_name(&record.name).is_some() {
+            self.diagnostics.error(record.name_span, &format!("`{}` is already a type", record.name));
+        } else if self.record(ty).is_some() {
+            self.diagnostics.error(record.name_span, &format!("record `{}` is already defined", record.name));
+        } else if self.variants(EnumType::new(&record.name)).is_some() {
+            self.diagnostics.error(record.name_span, &format!("`{}` is already an enum", record.name));
+        }
+        self.records.push((ty, RecordLayout::new(record)));
+    }
+
+    fn record(&self, ty: RecordType) -> Option<&RecordLayout> {
+        self.records
+            .iter()
+            .find(|&&(record, _)| record == ty)
+            .map(|(_, layout)| layout)
+    }
+
+    /// Reports a type written in the program that names a record that isn't
+    /// defined. Types don't keep spans, so `span` is of whatever it's the
+    /// type of.
+    fn check_type_name(&mut self, span: Span, ty: Type) {
+        match ty {
+            Type::Record(record) if self.record(record).is_none() => {
+                self.diagnostics.error(span, &format!("unknown type `{}`", record.name()));
+            }
+            Type::Func(func) => {
+                for param in func.params() {
+                    self.check_type_name(span, param);
+                }
+                self.check_type_name(span, func.result());
+            }
+            Type::V128 if !self.simd => self.diagnostics.error(span, "`v128` needs --simd"),
+            _ => (),
+        }
+    }
+
+    /// Constants can only use constants defined before them.
+    fn check_constant(&mut self, constant: &mut Constant) {
+        if self.constant(&constant.name).is_some() {
+            self.diagnostics.error(
+                constant.name_span,
+                &format!("constant `{}` is already defined", constant.name),
+            );
+        }
+
+        constant.ty = constant.ty.map(|ty| self.resolve_type(ty));
+        if let Some(ty) = constant.ty {
+            self.check_type_name(constant.name_span, ty);
+        }
+        let ty = constant.ty.or_else(|| self.infer(&constant.value)).unwrap_or_default();
+        self.in_constant = true;
+        self.check_expr(&mut constant.value, ty);
+        self.in_constant = false;
+
+        constant.ty = Some(ty);
+        self.constants.push((constant.name.clone(), ty));
+    }
+
+    fn constant(&self, name: &str) -> Option<Type> {
+        self.constants
+            .iter()
+            .find(|&(constant, _)| constant == name)
+            .map(|&(_, ty)| ty)
+    }
+
+    /// A global's initial value is a literal, so it's checked like a
+    /// constant.
+    fn check_global(&mut self, global: &mut Global) {
+        if self.constant(&global.name).is_some() {
+            self.diagnostics.error(
+                global.name_span,
+                &format!("`{}` is already defined as a constant", global.name),
+            );
+        }
+
+        if self.global(&global.name).is_some() {
+            self.diagnostics.error(
+                global.name_span,
+                &format!("global `{}` is already defined", global.name),
+            );
+        }
+
+        global.ty = global.ty.map(|ty| self.resolve_type(ty));
+        if let Some(ty) = global.ty {
+            self.check_type_name(global.name_span, ty);
+        }
+        let inferred = global.value.as_ref().and_then(|value| self.infer(value));
+        let ty = global.ty.or(inferred).unwrap_or_default();
+        if let Some(ref mut value) = global.value {
+            self.in_constant = true;
+            self.check_expr(value, ty);
+            self.in_constant = false;
+        }
+
+        global.ty = Some(ty);
+        self.globals.push((global.name.clone(), ty));
+    }
+
+    /// An array's length is worked out at compile time, so it's checked like
+    /// a constant.
+    fn check_array(&mut self, array: &mut Array) {
+        if self.arrays.contains(&array.name) {
+            self.diagnostics.error(
+                array.name_span,
+                &format!("array `{}` is already defined", array.name),
+            );
+        }
+
+        self.in_constant = true;
+        self.check_expr(&mut array.len, Type::I32);
+        self.in_constant = false;
+
+        self.arrays.push(array.name.clone());
+    }
+
+    fn global(&self, name: &str) -> Option<Type> {
+        self.globals
+            .iter()
+            .find(|&(global, _)| global == name)
+            .map(|&(_, ty)| ty)
+    }
+
+    /// Adds a function's signature, so calls to it can be checked.
+    fn declare_function(&mut self, function: &mut Function) {
+        if builtins::lookup(&function.name).is_some() {
+            self.diagnostics.error(
+                function.name_span,
+                &format!("`{}` is a builtin function", function.name),
+            );
+        } else if function.name == "main" || self.signature(&function.name).is_some() {
+            self.diagnostics.error(
+                function.name_span,
+                &format!("function `{}` is already defined", function.name),
+            );
+        } else if self.record(RecordType::new(&function.name)).is_some() {
+            self.diagnostics.error(
+                function.name_span,
+                &format!("`{}` is already a record", function.name),
+            );
+        }
+
+        let types = function.params.iter_mut().map(|param| &mut param.1);
+        for ty in types.chain(Some(&mut function.result)).chain(function.more_results.iter_mut()) {
+            *ty = self.resolve_type(*ty);
+            self.check_type_name(function.name_span, *ty);
+        }
+        for &(ref param, ty) in &function.params {
+            if ty == Type::V128 && function.var_params.contains(param) {
+                self.diagnostics.error(function.name_span, &format!("`{}` can't be `var`, since it's a v128", param));
+            }
+        }
+
+        if (function.exported || self.export_all) && self.exports.contains(&function.name) {
+            self.diagnostics.error(
+                function.name_span,
+                &format!("can't export `{}`, which the module already exports", function.name),
+            );
+        }
+
+        self.functions.push(Signature {
+            name: function.name.clone(),
+            params: function.params.iter().map(|&(_, ty)| ty).collect(),
+            by_reference: function
+                .params
+                .iter()
+                .map(|(param, _)| function.var_params.contains(param))
+                .collect(),
+            captured: function.captured,
+            result: function.result,
+            more_results: function.more_results.clone(),
+            uses_host: false,
+            calls: Vec::new(),
+        });
+    }
+
+    /// Checks the body of the function declared `index`th. A nested
+    /// function comes after the one it was in, so that one's variables, and
+    /// so the types of the ones it captures, are known by now.
+    fn check_function(&mut self, index: usize, function: &mut Function) {
+        if let Some(ref outer) = function.nested_in {
+            let variables = &self
+                .variables
+                .iter()
+                .find(|&(name, _)| name == outer)
+                .expect("nested functions come after the one they're in")
+                .1;
+            let start = function.params.len() - function.captured;
+            for (i, param) in function.params.iter_mut().enumerate().skip(start) {
+                if let Some(&(_, ty)) = variables.iter().find(|&(name, _)| *name == param.0) {
+                    param.1 = ty;
+                    self.functions[index].params[i] = ty;
+                }
+            }
+        }
+
+        self.check_body(
+            function.params.clone(),
+            function.result,
+            function.more_results.clone(),
+            &mut function.body,
+        );
+
+        let var_params = &function.var_params;
+        function.homes = mem::take(&mut self.scope.homes);
+        function.homes.retain(|home| !var_params.contains(home));
+
+        let signature = &mut self.functions[index];
+        signature.uses_host = self.scope.uses_host;
+        signature.calls = mem::take(&mut self.scope.calls);
+
+        let mut variables = function.params.clone();
+        variables.append(&mut self.scope.declared);
+        self.variables.push((function.name.clone(), variables));
+    }
+
+    /// Marks every function that calls one that uses the host, directly or
+    /// not, as using the host too. Functions can call each other in
+    /// circles, so this goes round until nothing changes.
+    fn spread_host_use(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 0..self.functions.len() {
+                let calls_host = self.functions[i]
+                    .calls
+                    .iter()
+                    .any(|name| self.signature(name).is_some_and(|signature| signature.uses_host));
+                if calls_host && !self.functions[i].uses_host {
+                    self.functions[i].uses_host = true;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    fn signature(&self, name: &str) -> Option<&Signature> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// Checks a function body in a fresh scope.
+    fn check_body(&mut self, params: Vec<(String, Type)>, result: Type, more_results: Vec<Type>, body: &mut [Stmt]) {
+        self.scope = Scope {
+            params,
+            result,
+            more_results,
+            ..Scope::default()
+        };
+        self.check_block(body);
+        self.end_scope(0);
+    }
+
+    /// Checks the init blocks, which are all one body.
+    fn check_init(&mut self, init: &mut [Stmt]) {
+        self.scope = Scope {
+            init: true,
+            ..Scope::default()
+        };
+        self.check_block(init);
+        self.end_scope(0);
+    }
+
+    /// Checks main, which returns an i32 for the host.
+    fn check_main(&mut self, main: &mut [Stmt]) {
+        self.scope = Scope {
+            result: Type::I32,
+            main: true,
+            ..Scope::default()
+        };
+        self.check_block(main);
+        self.end_scope(0);
+    }
+
+    /// Forgets the locals declared since there were `start` of them, and
+    /// warns about any of those that were never read. Temporaries don't
+    /// count.
+    fn end_scope(&mut self, start: usize) {
+        for local in self.scope.locals.split_off(start) {
+            if !local.used && !local.name.contains('.') {
+                self.diagnostics.warn(
+                    local.span,
+                    Warning::UnusedVariable,
+                    &format!("`{}` is never read", local.name),
+                );
+            }
+        }
+    }
+
+    /// Notes that the code being checked prints or reads, which the init
+    /// blocks can't do. `what` is how to say what does it.
+    fn use_host(&mut self, span: Span, what: &str) {
+        self.scope.uses_host = true;
+        if self.scope.init {
+            self.diagnostics.error(
+                span,
+                &format!("`init` blocks can't {}; they run before the host can see the module", what),
+            );
+        }
+    }
+
+    fn check_block(&mut self, block: &mut [Stmt]) {
+        for stmt in block {
+            // Checking stops partway through an expression with an error in
+            // it, so the variables after that haven't been seen to be read.
+            // They were, though, and saying they weren't would be wrong.
+            let errors = self.diagnostics.error_count();
+            self.check_stmt(stmt);
+            if self.diagnostics.error_count() > errors {
+                self.read_stmt(stmt);
+            }
+        }
+    }
+
+    /// Marks the locals a statement's own expressions read as used. The
+    /// blocks in it have been checked, so they're already right.
+    fn read_stmt(&mut self, stmt: &Stmt) {
+        match *stmt {
+            Stmt::Assign { ref value, .. }
+            | Stmt::AssignValues { ref value, .. }
+            | Stmt::Return(ref value)
+            | Stmt::Print(ref value)
+            | Stmt::Assert(ref value)
+            | Stmt::Halt(ref value)
+            | Stmt::Call(ref value)
+            | Stmt::If { condition: ref value, .. }
+            | Stmt::While { condition: ref value, .. } => self.read_expr(value),
+            Stmt::ReturnValues(ref values) => {
+                for value in values {
+                    self.read_expr(value);
+                }
+            }
+            Stmt::Case { ref subject, ref arms, .. } => {
+                self.read_expr(subject);
+                for value in arms.iter().flat_map(|arm| &arm.0) {
+                    self.read_expr(value);
+                }
+            }
+            Stmt::Store { ref place, ref value } => {
+                match *place {
+                    Place::Memory(ref inner) | Place::Element(_, ref inner) | Place::Field(ref inner, _) => {
+                        self.read_expr(inner)
+                    }
+                }
+                self.read_expr(value);
+            }
+            Stmt::Block(_) => (),
+        }
+    }
+
+    fn read_expr(&mut self, expr: &Expr) {
+        match expr.kind {
+            ExprKind::Variable(ref name) | ExprKind::Call(ref name, _) => {
+                if let Some(position) = self.scope.position(name) {
+                    self.scope.locals[position].used = true;
+                }
+            }
+            _ => (),
+        }
+        match expr.kind {
+            ExprKind::Call(_, ref arguments) => {
+                for argument in arguments {
+                    self.read_expr(argument);
+                }
+            }
+            ExprKind::CallIndirect(ref callee, ref arguments) => {
+                self.read_expr(callee);
+                for argument in arguments {
+                    self.read_expr(argument);
+                }
+            }
+            ExprKind::Binary(_, ref left, ref right) => {
+                self.read_expr(left);
+                self.read_expr(right);
+            }
+            ExprKind::Load(ref inner)
+            | ExprKind::Index(_, ref inner)
+            | ExprKind::Field(ref inner, _)
+            | ExprKind::View(_, ref inner) => self.read_expr(inner),
+            ExprKind::Conditional(ref condition, ref then, ref otherwise) => {
+                self.read_expr(condition);
+                self.read_expr(then);
+                self.read_expr(otherwise);
+            }
+            ExprKind::Int(..)
+            | ExprKind::Float(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Str(_)
+            | ExprKind::Address(_)
+            | ExprKind::Variable(_)
+            | ExprKind::Read => (),
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &mut Stmt) {
+        match *stmt {
+            Stmt::Assign { ref mut name, name_span, ref mut ty, ref mut value } => {
+                *ty = Some(self.check_assignment(name, name_span, *ty, value));
+            }
+            Stmt::If { ref mut condition, ref mut then, ref mut otherwise } => {
+                self.check_condition(condition);
+                self.check_block(then);
+                self.check_block(otherwise);
+            }
+            Stmt::While { ref mut condition, ref mut body } => {
+                self.check_condition(condition);
+                self.check_block(body);
+            }
+            Stmt::Assert(ref mut condition) => self.check_condition(condition),
+            Stmt::Halt(ref mut status) => {
+                if self.scope.init {
+                    self.diagnostics.error(status.span, "`init` blocks can't halt");
+                } else if !self.scope.main && !self.halt_anywhere {
+                    self.diagnostics.error(status.span, "`halt` outside main needs --target wasi or browser");
+                }
+                self.check_expr(status, Type::I32);
+            }
+            Stmt::Case { ref mut subject, ref mut arms, ref mut otherwise } => {
+                self.check_case(subject, arms, otherwise)
+            }
+            Stmt::Block(ref mut body) => {
+                self.scope.blocks.push(self.scope.locals.len());
+                self.check_block(body);
+                let start = self.scope.blocks.pop().unwrap();
+                self.end_scope(start);
+            }
+            Stmt::AssignValues { ref mut names, ref mut types, ref mut value } => {
+                *types = self.check_assign_values(names, value);
+            }
+            Stmt::Return(ref mut value) => {
+                if self.scope.init {
+                    self.diagnostics.error(value.span, "`init` blocks can't return");
+                }
+                let count = self.scope.more_results.len() + 1;
+                if count > 1 {
+                    self.diagnostics.error(
+                        value.span,
+                        &format!("this function returns {} values, so `return` needs {}", count, count),
+                    );
+                }
+                let result = self.scope.result;
+                self.check_expr(value, result);
+            }
+            Stmt::ReturnValues(ref mut values) => {
+                if self.scope.init {
+                    self.diagnostics.error(values[0].span, "`init` blocks can't return");
+                }
+                let mut results = vec![self.scope.result];
+                results.extend(self.scope.more_results.iter().cloned());
+                if values.len() != results.len() {
+                    self.diagnostics.error(
+                        values[0].span,
+                        &format!("this function returns {} values, found {}", results.len(), values.len()),
+                    );
+                }
+                for (i, value) in values.iter_mut().enumerate() {
+                    let ty = results.get(i).cloned().or_else(|| self.infer(value)).unwrap_or_default();
+                    self.check_expr(value, ty);
+                }
+            }
+            Stmt::Print(ref mut value) => {
+                self.use_host(value.span, "print");
+                // Anything but a pointer, a record, or a vector can be printed. Enums
+                // print their variant's number. Literals without a suffix
+                // are i32s.
+                let ty = self.infer(value).unwrap_or_default();
+                match ty {
+                    Type::Func(_) => self.diagnostics.error(value.span, "can't print a function pointer"),
+                    Type::Record(_) => self.diagnostics.error(value.span, "can't print a record"),
+                    Type::V128 => self.diagnostics.error(value.span, "can't print a v128"),
+                    _ => (),
+                }
+                self.check_expr(value, ty);
+            }
+            Stmt::Call(ref mut value) => self.check_call_stmt(value),
+            Stmt::Store { ref mut place, ref mut value } => {
+                match *place {
+                    Place::Memory(ref mut address) => self.check_memory_address(address),
+                    // Statements don't have spans, so errors about the
+                    // array point at the index.
+                    Place::Element(ref array, ref mut index) => self.check_element(index.span, array, index),
+                    Place::Field(ref mut record, ref field) => {
+                        let span = record.span;
+                        let ty = self.check_field(span, record, field);
+                        let ty = ty.or_else(|| self.infer(value)).unwrap_or_default();
+                        self.check_expr(value, ty);
+                        return;
+                    }
+                }
+                self.check_expr(value, Type::I32);
+            }
+        }
+    }
+
+    /// Checks `case e when a, b ... end`. The subject has to be an integer
+    /// or an enum, and the values constants of the same type, which no
+    /// earlier `when` has. A `case` on an enum without an `else` gets a
+    /// warning if it leaves out any of the enum's variants.
+    fn check_case(
+        &mut self,
+        subject: &mut Expr,
+        arms: &mut [(Vec<Expr>, Vec<Stmt>)],
+        otherwise: &mut Option<Vec<Stmt>>,
+    ) {
+        let ty = self.infer(subject).unwrap_or_default();
+        if !ty.is_integer() && !matches!(ty, Type::Enum(_)) {
+            self.diagnostics.error(
+                subject.span,
+                &format!("`case` only works on integers and enums, not {}s", ty.name()),
+            );
+        }
+        self.check_expr(subject, ty);
+
+        let mut handled: Vec<Value> = Vec::new();
+        for &mut (ref mut values, ref mut body) in arms.iter_mut() {
+            for value in values {
+                self.in_constant = true;
+                self.check_expr(value, ty);
+                self.in_constant = false;
+
+                // Constants' values aren't worked out until code generation,
+                // so only literals and variants can be told apart here.
+                let known = match value.kind {
+                    ExprKind::Int(ref num, Some(found)) if found == ty => consteval::int_literal(num, ty),
+                    _ => None,
+                };
+                if let Some(known) = known {
+                    if handled.contains(&known) {
+                        self.diagnostics.error(value.span, "this value is already handled by an earlier `when`");
+                    }
+                    handled.push(known);
+                }
+            }
+            self.check_block(body);
+        }
+
+        match (otherwise.as_mut(), ty) {
+            (Some(otherwise), _) => self.check_block(otherwise),
+            (None, Type::Enum(item)) => {
+                let missing: Vec<String> = self
+                    .variants(item)
+                    .unwrap_or_default()
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| !handled.contains(&Value::Enum(item, i as i32)))
+                    .map(|(_, variant)| format!("`{}.{}`", item.name(), variant))
+                    .collect();
+                if !missing.is_empty() {
+                    self.diagnostics.warn(
+                        subject.span,
+                        Warning::IncompleteCase,
+                        &format!(
+                            "this `case` doesn't handle {}; add {}, or an `else`",
+                            either(&missing),
+                            if missing.len() == 1 { "it" } else { "them" }
+                        ),
+                    );
+                }
+            }
+            (None, _) => (),
+        }
+    }
+
+    /// Assigning to a global changes the global. Otherwise, the first
+    /// assignment to a name declares it as a local, with the type it was
+    /// given, or else the type of its first value. Inside a `begin` block,
+    /// giving a type declares a new local even if there's one from outside
+    /// the block, which it shadows until the block ends. Constants can't be
+    /// assigned to. Returns the variable's type, and renames a local to its
+    /// unique name.
+    fn check_assignment(
+        &mut self,
+        name: &mut String,
+        name_span: Span,
+        annotation: Option<Type>,
+        value: &mut Expr,
+    ) -> Type {
+        let annotation = annotation.map(|ty| self.resolve_type(ty));
+        let shadowed = match annotation {
+            Some(_) => self.scope.shadowed(name).map(|local| local.span),
+            None => None,
+        };
+        if let Some(span) = shadowed {
+            let location = self.diagnostics.source_map().location(span);
+            self.diagnostics.warn(
+                name_span,
+                Warning::Shadowing,
+                &format!(
+                    "`{}` shadows the `{}` first assigned on line {}, column {}",
+                    name, name, location.line, location.column
+                ),
+            );
+        }
+
+        let existing = match shadowed {
+            Some(_) => None,
+            None => self.scope.variable(name).or_else(|| self.global(name)),
+        };
+        if let Some(annotation) = annotation {
+            self.check_type_name(value.span, annotation);
+        }
+        let ty = match (existing, annotation) {
+            (Some(existing), Some(annotation)) if existing != annotation => {
+                let article = if existing.is_integer() || existing == Type::F64 { "an" } else { "a" };
+                self.diagnostics.error(
+                    value.span,
+                    &format!("`{}` is already {} {}", name, article, existing.name()),
+                );
+                existing
+            }
+            (Some(existing), _) => existing,
+            (None, annotation) => annotation.or_else(|| self.infer(value)).unwrap_or_default(),
+        };
+
+        if self.scope.variable(name).is_none() && self.constant(name).is_some() {
+            self.diagnostics.error(value.span, &format!("can't assign to constant `{}`", name));
+        }
+
+        self.check_expr(value, ty);
+
+        if existing.is_none() {
+            *name = self.scope.declare(name, ty, name_span);
+        } else {
+            self.scope.resolve(name);
+        }
+        ty
+    }
+
+    /// Checks `a, b = f(x)`: `f` has to return as many values as there are
+    /// variables, and each one is assigned like it would be on its own.
+    /// Returns the variables' types, and renames locals to their unique
+    /// names.
+    fn check_assign_values(&mut self, names: &mut [String], value: &mut Expr) -> Vec<Type> {
+        self.resolve_pointer_call(value);
+        let results = match value.kind {
+            ExprKind::CallIndirect(ref callee, _) => {
+                if let ExprKind::Variable(ref name) = callee.kind {
+                    self.diagnostics.error(value.span, &format!("`{}` only returns one value", name));
+                }
+                let ty = self.infer(value).unwrap_or_default();
+                self.check_expr(value, ty);
+                Vec::new()
+            }
+            ExprKind::Call(ref name, ref mut arguments) => {
+                let results = self.signature(name).map(|signature| {
+                    let mut results = vec![signature.result];
+                    results.extend(signature.more_results.iter().cloned());
+                    results
+                });
+                let span = value.span;
+                self.check_call_expr(span, name, arguments);
+                match results {
+                    Some(ref results) if results.len() != names.len() => self.diagnostics.error(
+                        span,
+                        &format!("`{}` returns {} values, found {} variables", name, results.len(), names.len()),
+                    ),
+                    Some(_) => (),
+                    None if builtins::lookup(name).is_some() => {
+                        self.diagnostics.error(span, &format!("`{}` only returns one value", name))
+                    }
+                    None => (),
+                }
+                results.unwrap_or_default()
+            }
+            _ => {
+                self.diagnostics.error(value.span, "only a call can be assigned to more than one variable");
+                Vec::new()
+            }
+        };
+
+        let function = match value.kind {
+            ExprKind::Call(ref function, _) => function.clone(),
+            _ => String::new(),
+        };
+        let mut types = Vec::new();
+        for (i, name) in names.iter_mut().enumerate() {
+            let ty = results.get(i).cloned().unwrap_or_default();
+            let existing = self.scope.variable(name).or_else(|| self.global(name));
+            match existing {
+                Some(existing) => {
+                    if existing != ty && i < results.len() {
+                        let article = |ty: Type| if ty.is_integer() || ty == Type::F64 { "an" } else { "a" };
+                        self.diagnostics.error(
+                            value.span,
+                            &format!(
+                                "`{}` is {} {}, but `{}` returns {} {} for it",
+                                name,
+                                article(existing),
+                                existing.name(),
+                                function,
+                                article(ty),
+                                ty.name()
+                            ),
+                        );
+                    }
+                    self.scope.resolve(name);
+                }
+                None => {
+                    if self.constant(name).is_some() {
+                        self.diagnostics.error(value.span, &format!("can't assign to constant `{}`", name));
+                    }
+                    *name = self.scope.declare(name, ty, value.span);
+                }
+            }
+            types.push(existing.unwrap_or(ty));
+        }
+        types
+    }
+
+    /// Checks a call whose results are thrown away. Unlike a call in an
+    /// expression, it can be to a function that returns more than one value.
+    fn check_call_stmt(&mut self, value: &mut Expr) {
+        self.resolve_pointer_call(value);
+        let span = value.span;
+        if let ExprKind::Call(ref name, ref mut arguments) = value.kind {
+            if self.signature(name).is_some_and(|signature| !signature.more_results.is_empty()) {
+                self.check_call_expr(span, name, arguments);
+                return;
+            }
+        }
+        let ty = self.infer(value).unwrap_or_default();
+        self.check_expr(value, ty);
+    }
+
+    /// Checks the condition of an `if` or `while`, which has to be a bool
+    /// unless integers have been given a truthiness rule.
+    fn check_condition(&mut self, condition: &mut Expr) {
+        let inferred = self.infer(condition);
+
+        match (self.truthiness, inferred) {
+            (Truthiness::Nonzero, Some(ty)) if ty.is_integer() => self.check_expr(condition, ty),
+            (Truthiness::Nonzero, None) => self.check_expr(condition, Type::I32),
+            (Truthiness::Strict, Some(ty)) if ty.is_integer() => self.diagnostics.error(
+                condition.span,
+                &format!(
+                    "expected bool, found {}; compare it with 0, or use --truthiness nonzero",
+                    ty.name()
+                ),
+            ),
+            _ => self.check_expr(condition, Type::Bool),
+        }
+    }
+
+    /// Works out the type of an expression without checking it. Returns None
+    /// if the expression is made up of literals without suffixes, which can
+    /// be whatever type their context needs, or if it's broken in a way that
+    /// checking it will report.
+    /// Checks the address in `mem[address]`, which is an i32, or with
+    /// memory64, an i64 if it needs to be past 4 GiB.
+    fn check_memory_address(&mut self, address: &mut Expr) {
+        let ty = match self.infer(address) {
+            Some(Type::I64) if self.memory64 => Type::I64,
+            _ => Type::I32,
+        };
+        self.check_expr(address, ty);
+    }
+
+    fn infer(&self, expr: &Expr) -> Option<Type> {
+        match expr.kind {
+            ExprKind::Int(_, suffix) => suffix,
+            ExprKind::Float(_) => Some(Type::F64),
+            ExprKind::Bool(_) => Some(Type::Bool),
+            ExprKind::Str(_) => Some(Type::Str),
+            ExprKind::Variable(ref name) => self.variable(name),
+            ExprKind::Call(ref name, ref arguments) if self.is_sizeof(name, arguments) => Some(Type::I32),
+            ExprKind::Call(ref name, _) if self.is_view(name) => Some(Type::Record(RecordType::new(name))),
+            ExprKind::Call(ref name, _) => match self.pointer(name) {
+                Some(func) => Some(func.result()),
+                None => self
+                    .signature(name)
+                    .map(|signature| signature.result)
+                    .or_else(|| builtins::lookup(name).map(|builtin| builtin.result)),
+            },
+            ExprKind::Address(ref name) => self.signature(name).map(Signature::pointer),
+            ExprKind::CallIndirect(ref callee, _) => match self.infer(callee) {
+                Some(Type::Func(func)) => Some(func.result()),
+                _ => None,
+            },
+            ExprKind::Binary(op, _, _) if op.is_comparison() || op.is_logical() => Some(Type::Bool),
+            ExprKind::Binary(_, ref left, ref right) => self.infer(left).or_else(|| self.infer(right)),
+            ExprKind::Load(_) | ExprKind::Index(..) | ExprKind::Read => Some(Type::I32),
+            ExprKind::Field(ref item, _) if self.variant_of(item).is_some() => self.variant_of(item).map(Type::Enum),
+            ExprKind::Field(ref record, ref field) => match self.infer(record) {
+                Some(Type::Record(record)) => self.record(record)?.field(field).map(|(_, ty)| ty),
+                _ => None,
+            },
+            ExprKind::View(record, _) => Some(Type::Record(record)),
+            ExprKind::Conditional(_, ref then_value, ref else_value) => {
+                self.infer(then_value).or_else(|| self.infer(else_value))
+            }
+        }
+    }
+
+    /// Whether a call to `name` is a view of memory as the record called
+    /// that. Functions, and variables that hold pointers, come first.
+    fn is_view(&self, name: &str) -> bool {
+        self.signature(name).is_none() && self.pointer(name).is_none() && self.record(RecordType::new(name)).is_some()
+    }
+
+    /// Whether a call is `sizeof` of a record, which a function called
+    /// `sizeof` would hide.
+    fn is_sizeof(&self, name: &str, arguments: &[Expr]) -> bool {
+        match (name, arguments) {
+            ("sizeof", [Expr { kind: ExprKind::Variable(ref record), .. }]) => {
+                self.signature(name).is_none() && self.record(RecordType::new(record)).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    /// Turns `Point(a)` into a view of the memory at `a` as a Point, and
+    /// `sizeof(Point)` into the number of bytes a Point takes.
+    fn resolve_record_call(&mut self, expr: &mut Expr) {
+        let size = match expr.kind {
+            ExprKind::Call(ref name, ref arguments) if self.is_sizeof(name, arguments) => match arguments[0].kind {
+                ExprKind::Variable(ref record) => self.record(RecordType::new(record)).map(|layout| layout.size),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(size) = size {
+            expr.kind = ExprKind::Int(size.to_string(), None);
+            return;
+        }
+
+        let record = match expr.kind {
+            ExprKind::Call(ref name, _) if self.is_view(name) => RecordType::new(name),
+            _ => return,
+        };
+        if let ExprKind::Call(_, ref mut arguments) = expr.kind {
+            if arguments.len() != 1 {
+                self.diagnostics.error(
+                    expr.span,
+                    &format!("`{}(a)` takes one argument, the record's address", record.name()),
+                );
+            }
+            let address = match arguments.drain(..).next() {
+                Some(address) => address,
+                None => Expr {
+                    kind: ExprKind::Int(String::from("0"), None),
+                    span: expr.span,
+                },
+            };
+            expr.kind = ExprKind::View(record, Box::new(address));
+        }
+    }
+
+    /// The enum `item` is a variant of in `item.variant`, if `item` is an
+    /// enum's name and not a variable's.
+    fn variant_of(&self, item: &Expr) -> Option<EnumType> {
+        match item.kind {
+            ExprKind::Variable(ref name) if self.variable(name).is_none() => {
+                let ty = EnumType::new(name);
+                self.variants(ty).map(|_| ty)
+            }
+            _ => None,
+        }
+    }
+
+    /// Turns `Color.red` into the variant's number, as a literal of the
+    /// enum's type.
+    fn resolve_variant(&mut self, expr: &mut Expr) {
+        let (item, variant) = match expr.kind {
+            ExprKind::Field(ref item, ref variant) => match self.variant_of(item) {
+                Some(ty) => (ty, variant.clone()),
+                None => return,
+            },
+            _ => return,
+        };
+
+        let variants = self.variants(item).unwrap_or_default();
+        let number = match variants.iter().position(|existing| *existing == variant) {
+            Some(number) => number,
+            None => {
+                self.diagnostics.error(
+                    expr.span,
+                    &format!("`{}` doesn't have a variant called `{}`", item.name(), variant),
+                );
+                0
+            }
+        };
+        expr.kind = ExprKind::Int(number.to_string(), Some(Type::Enum(item)));
+    }
+
+    /// Checks `record.field`, and returns the field's type, if the record
+    /// has it.
+    fn check_field(&mut self, span: Span, record: &mut Expr, field: &str) -> Option<Type> {
+        let ty = self.infer(record).unwrap_or_default();
+        self.check_expr(record, ty);
+        let record = match ty {
+            Type::Record(record) => record,
+            _ => {
+                self.diagnostics.error(span, &format!("only records have fields, not {}s", ty.name()));
+                return None;
+            }
+        };
+
+        // Records that aren't defined have already been reported.
+        let found = self.record(record)?.field(field).map(|(_, ty)| ty);
+        if found.is_none() {
+            self.diagnostics.error(
+                span,
+                &format!("`{}` doesn't have a field called `{}`", record.name(), field),
+            );
+        }
+        found
+    }
+
+    /// The type of the pointer in a parameter, local, or global called
+    /// `name`, if there is one. Calls to it go through the pointer.
+    fn pointer(&self, name: &str) -> Option<FuncType> {
+        match self.scope.variable(name).or_else(|| self.global(name)) {
+            Some(Type::Func(func)) => Some(func),
+            _ => None,
+        }
+    }
+
+    /// Turns a call to a variable that holds a function pointer into a call
+    /// through the pointer.
+    fn resolve_pointer_call(&mut self, expr: &mut Expr) {
+        let callee = match expr.kind {
+            ExprKind::Call(ref name, _) if self.pointer(name).is_some() => name.clone(),
+            _ => return,
+        };
+        if let ExprKind::Call(_, ref mut arguments) = expr.kind {
+            let arguments = mem::take(arguments);
+            let callee = Expr {
+                kind: ExprKind::Variable(callee),
+                span: expr.span,
+            };
+            expr.kind = ExprKind::CallIndirect(Box::new(callee), arguments);
+        }
+    }
+
+    /// The type of a parameter, local, global, or constant, in that order.
+    fn variable(&self, name: &str) -> Option<Type> {
+        self.scope
+            .variable(name)
+            .or_else(|| self.global(name))
+            .or_else(|| self.constant(name))
+    }
+
+    /// The type both operands of a binary operator need to have. For
+    /// arithmetic, that's the type the result needs to be. Comparisons always
+    /// give a bool, so they go by what's being compared instead.
+    fn operand_type(&self, op: BinaryOp, left: &Expr, right: &Expr, ty: Type) -> Type {
+        if op.is_comparison() {
+            self.infer(left).or_else(|| self.infer(right)).unwrap_or_default()
+        } else {
+            ty
+        }
+    }
+
+    /// Reports an error if a value has the wrong type. Returns true if the
+    /// types match.
+    fn check_type(&mut self, span: Span, expected: Type, found: Type) -> bool {
+        if expected != found {
+            self.diagnostics.error(
+                span,
+                &format!("expected {}, found {}", expected.name(), found.name()),
+            );
+        }
+        expected == found
+    }
+
+    /// Checks that an expression gives a value of type `ty`.
+    fn check_expr(&mut self, expr: &mut Expr, ty: Type) {
+        if !self.in_constant {
+            self.resolve_pointer_call(expr);
+        }
+        self.resolve_record_call(expr);
+        self.resolve_variant(expr);
+        match expr.kind {
+            ExprKind::Int(ref num, ref mut suffix) => {
+                // Integers without a suffix can be any kind of number, but
+                // they're never bools or strs.
+                let found = suffix.unwrap_or(if ty.is_number() { ty } else { Type::I32 });
+                if self.check_type(expr.span, ty, found) && consteval::int_literal(num, ty).is_none() {
+                    self.diagnostics.error(expr.span, &format!("integer literal out of range for {}", ty.name()));
+                }
+                *suffix = Some(found);
+            }
+            ExprKind::Float(_) => {
+                self.check_type(expr.span, ty, Type::F64);
+            }
+            ExprKind::Bool(_) => {
+                self.check_type(expr.span, ty, Type::Bool);
+            }
+            ExprKind::Str(_) => {
+                self.check_type(expr.span, ty, Type::Str);
+            }
+            ExprKind::Variable(ref name) if self.in_constant => match self.constant(name) {
+                Some(found) => {
+                    self.check_type(expr.span, ty, found);
+                }
+                None => self.diagnostics.error(expr.span, &format!("`{}` is not a constant", name)),
+            },
+            ExprKind::Variable(ref mut name) => match self.variable(name) {
+                Some(found) => {
+                    self.check_type(expr.span, ty, found);
+                    self.scope.read(name);
+                }
+                None => self.diagnostics.error(expr.span, &format!("unknown variable `{}`", name)),
+            },
+            ExprKind::Call(..) if self.in_constant => {
+                self.diagnostics.error(expr.span, "constants can't call functions");
+            }
+            ExprKind::Call(ref name, ref mut arguments) => {
+                let count = self.signature(name).map_or(1, |signature| signature.more_results.len() + 1);
+                if count > 1 {
+                    self.diagnostics.error(
+                        expr.span,
+                        &format!("`{}` returns {} values, which have to be assigned to {} variables", name, count, count),
+                    );
+                }
+                if let Some(result) = self.check_call_expr(expr.span, name, arguments) {
+                    self.check_type(expr.span, ty, result);
+                }
+            }
+            ExprKind::Address(_) if self.in_constant => {
+                self.diagnostics.error(expr.span, "constants can't take the address of a function");
+            }
+            ExprKind::Address(ref name) => {
+                if let Some(found) = self.check_address(expr.span, name) {
+                    self.check_type(expr.span, ty, found);
+                }
+            }
+            ExprKind::CallIndirect(ref mut callee, ref mut arguments) => {
+                let (name, func) = match (&callee.kind, self.infer(callee)) {
+                    (ExprKind::Variable(name), Some(Type::Func(func))) => (name.clone(), func),
+                    _ => unreachable!("only calls to variables that hold pointers go through them"),
+                };
+                self.check_expr(callee, Type::Func(func));
+                let params = func.params();
+                if params.len() != arguments.len() {
+                    self.diagnostics.error(
+                        expr.span,
+                        &format!(
+                            "function pointer `{}` expects {} arguments, found {}",
+                            name,
+                            params.len(),
+                            arguments.len()
+                        ),
+                    );
+                }
+                for (i, argument) in arguments.iter_mut().enumerate() {
+                    let param = params.get(i).cloned().or_else(|| self.infer(argument)).unwrap_or_default();
+                    self.check_expr(argument, param);
+                }
+                self.check_type(expr.span, ty, func.result());
+            }
+            ExprKind::Binary(op, ref mut left, ref mut right) if op.is_logical() => {
+                self.check_type(expr.span, ty, Type::Bool);
+                self.check_condition(left);
+                self.check_condition(right);
+            }
+            ExprKind::Binary(op, ref mut left, ref mut right) if op.is_arithmetic() && ty == Type::Bool => {
+                // Arithmetic never gives a bool, so this is wrong whatever the
+                // operands are. Check them as whatever they look like, to
+                // catch anything else that's wrong with them.
+                let operands = self.infer(left).or_else(|| self.infer(right)).unwrap_or_default();
+                if !operands.is_number() {
+                    self.diagnostics.error(expr.span, &format!("can't do arithmetic on {}s", operands.name()));
+                    return;
+                }
+                self.check_type(expr.span, ty, operands);
+                if !self.in_constant {
+                    self.check_expr(left, operands);
+                    self.check_expr(right, operands);
+                }
+            }
+            ExprKind::Binary(op, ref mut left, ref mut right) => {
+                let operands = self.operand_type(op, left, right, ty);
+                let result = if op.is_comparison() { Type::Bool } else { operands };
+
+                if op.is_arithmetic() && !operands.is_number() {
+                    self.diagnostics.error(expr.span, &format!("can't do arithmetic on {}s", operands.name()));
+                    return;
+                }
+                if op == BinaryOp::Remainder && !operands.is_integer() {
+                    self.diagnostics.error(expr.span, &format!("`%` only works on integers, not {}s", operands.name()));
+                    return;
+                }
+                if op.is_shift() && !operands.is_integer() {
+                    self.diagnostics.error(expr.span, &format!("can only shift integers, not {}s", operands.name()));
+                    return;
+                }
+                if op.is_bitwise() && !operands.is_integer() && operands != Type::Bool {
+                    self.diagnostics.error(
+                        expr.span,
+                        &format!("`&`, `|`, and `^` only work on integers and bools, not {}s", operands.name()),
+                    );
+                    return;
+                }
+                if operands == Type::Bool && op.is_comparison() && !matches!(op, BinaryOp::Equal | BinaryOp::NotEqual) {
+                    self.diagnostics.error(expr.span, "bools can only be compared with `==` and `!=`");
+                    return;
+                }
+                if let (Type::Func(_), false) = (operands, matches!(op, BinaryOp::Equal | BinaryOp::NotEqual)) {
+                    self.diagnostics.error(expr.span, "function pointers can only be compared with `==` and `!=`");
+                    return;
+                }
+                if let (Type::Record(_), false) = (operands, matches!(op, BinaryOp::Equal | BinaryOp::NotEqual)) {
+                    self.diagnostics.error(expr.span, "records can only be compared with `==` and `!=`");
+                    return;
+                }
+                if let (Type::Enum(_), false) = (operands, matches!(op, BinaryOp::Equal | BinaryOp::NotEqual)) {
+                    self.diagnostics.error(expr.span, "enums can only be compared with `==` and `!=`");
+                    return;
+                }
+                if operands == Type::Str && op.is_comparison() {
+                    self.diagnostics.error(expr.span, "strs can't be compared");
+                    return;
+                }
+                if operands == Type::V128 && op.is_comparison() {
+                    self.diagnostics.error(expr.span, "v128s can't be compared");
+                    return;
+                }
+
+                // Constants stop at the first mistake, since there's no
+                // value to work out once there's one.
+                if !self.check_type(expr.span, ty, result) && self.in_constant {
+                    return;
+                }
+                self.check_expr(left, operands);
+                self.check_expr(right, operands);
+            }
+            ExprKind::Load(_) | ExprKind::Index(..) | ExprKind::Field(..) if self.in_constant => {
+                self.diagnostics.error(expr.span, "constants can't read memory");
+            }
+            ExprKind::View(..) if self.in_constant => {
+                self.diagnostics.error(expr.span, "constants can't be records");
+            }
+            ExprKind::Field(ref mut record, ref field) => {
+                if let Some(found) = self.check_field(expr.span, record, field) {
+                    self.check_type(expr.span, ty, found);
+                }
+            }
+            ExprKind::View(record, ref mut address) => {
+                self.check_expr(address, Type::I32);
+                self.check_type(expr.span, ty, Type::Record(record));
+            }
+            ExprKind::Load(ref mut address) => {
+                self.check_memory_address(address);
+                self.check_type(expr.span, ty, Type::I32);
+            }
+            ExprKind::Index(ref array, ref mut index) => {
+                self.check_element(expr.span, array, index);
+                self.check_type(expr.span, ty, Type::I32);
+            }
+            ExprKind::Read => {
+                self.use_host(expr.span, "read");
+                self.check_type(expr.span, ty, Type::I32);
+            }
+            ExprKind::Conditional(ref mut condition, ref mut then_value, ref mut else_value) => {
+                self.check_condition(condition);
+                self.check_expr(then_value, ty);
+                self.check_expr(else_value, ty);
+            }
+        }
+    }
+
+    /// Checks `array[index]`: the array has to exist, and the index has to be
+    /// an i32.
+    fn check_element(&mut self, span: Span, array: &str, index: &mut Expr) {
+        if !self.arrays.iter().any(|name| name == array) {
+            self.diagnostics.error(span, &format!("unknown array `{}`", array));
+        }
+        self.check_expr(index, Type::I32);
+    }
+
+    /// Checks a call and its arguments, and returns what the function
+    /// returns first, if it exists.
+    fn check_call_expr(&mut self, span: Span, name: &str, arguments: &mut [Expr]) -> Option<Type> {
+        self.scope.calls.push(name.to_string());
+        if self.signature(name).is_some_and(|signature| signature.uses_host) {
+            self.use_host(span, &format!("call `{}`, which prints or reads", name));
+        }
+        let signature = self.check_call(span, name, arguments.len());
+        let builtin = builtins::lookup(name).filter(|_| self.signature(name).is_none());
+        if builtin.is_some_and(|builtin| builtin.is_simd()) && !self.simd {
+            self.diagnostics.error(span, &format!("`{}` needs --simd", name));
+        }
+        if builtin.is_some_and(|builtin| builtin.is_atomic()) && !self.threads {
+            self.diagnostics.error(span, &format!("`{}` needs --threads", name));
+        }
+        let by_reference = self
+            .signature(name)
+            .map_or(Vec::new(), |signature| signature.by_reference.clone());
+        let captured = self.signature(name).map_or(0, |signature| signature.captured);
+        let count = arguments.len();
+        for (i, argument) in arguments.iter_mut().enumerate() {
+            // The caller's variables that a nested function captures are
+            // passed as they are. The types they have here are the ones the
+            // function gets, once it's checked.
+            if i + captured >= count {
+                if let ExprKind::Variable(ref variable) = argument.kind {
+                    if self.scope.variable(variable).is_none() {
+                        self.diagnostics.error(
+                            span,
+                            &format!("`{}` uses `{}`, which hasn't been assigned yet", name, variable),
+                        );
+                        continue;
+                    }
+                }
+                let ty = self.infer(argument).unwrap_or_default();
+                self.check_expr(argument, ty);
+                self.note_home(argument);
+                continue;
+            }
+
+            let param = match signature {
+                Some((ref params, _)) if i + captured < params.len() => params.get(i).cloned(),
+                _ => None,
+            };
+            let param = param.or_else(|| self.infer(argument)).unwrap_or_default();
+            if i + captured < by_reference.len() && by_reference[i] {
+                self.check_var_argument(name, argument);
+                self.check_expr(argument, param);
+                self.note_home(argument);
+                continue;
+            }
+            // A lane number goes in the instruction, so it has to be known
+            // at compile time.
+            if builtin.is_some_and(|builtin| builtin.lane) && i + 1 == count {
+                self.in_constant = true;
+                self.check_expr(argument, param);
+                self.in_constant = false;
+                continue;
+            }
+            self.check_expr(argument, param);
+        }
+        signature.map(|(_, result)| result)
+    }
+
+    /// Checks `@name`: the function has to exist, and be one a pointer can
+    /// call. Returns the pointer's type, and puts the function in the table.
+    /// Whatever gets the pointer can call the function, so taking its
+    /// address counts as calling it.
+    fn check_address(&mut self, span: Span, name: &str) -> Option<Type> {
+        let (pointer, var_params, captured, results) = match self.signature(name) {
+            Some(signature) => (
+                signature.pointer(),
+                signature.by_reference.contains(&true),
+                signature.captured,
+                signature.more_results.len() + 1,
+            ),
+            None if builtins::lookup(name).is_some() => {
+                self.diagnostics.error(span, &format!("`{}` is a builtin, so it doesn't have an address", name));
+                return None;
+            }
+            None => {
+                self.diagnostics.error(span, &format!("unknown function `{}`", name));
+                return None;
+            }
+        };
+
+        if captured > 0 {
+            // It's named after the functions it's in, which the program
+            // didn't write, so this only names it.
+            let (outer, inner) = name.rsplit_once('.').expect("nested functions are named after their parent");
+            self.diagnostics.error(
+                span,
+                &format!(
+                    "`{}` uses variables of `{}`, which it's nested in, so it can't be called through a pointer",
+                    inner, outer
+                ),
+            );
+        } else if var_params {
+            self.diagnostics.error(
+                span,
+                &format!("`{}` has `var` parameters, so it can't be called through a pointer", name),
+            );
+        }
+        if results > 1 {
+            self.diagnostics.error(
+                span,
+                &format!("`{}` returns {} values, so it can't be called through a pointer", name, results),
+            );
+        }
+
+        self.scope.calls.push(name.to_string());
+        if self.signature(name).is_some_and(|signature| signature.uses_host) {
+            self.use_host(span, &format!("take the address of `{}`, which prints or reads", name));
+        }
+        if !self.table.iter().any(|function| function == name) {
+            self.table.push(name.to_string());
+        }
+        Some(pointer)
+    }
+
+    /// Checks an argument to a `var` parameter of `function`, which has to be
+    /// a variable the call can assign to: a parameter, local, or global.
+    fn check_var_argument(&mut self, function: &str, argument: &Expr) {
+        match argument.kind {
+            ExprKind::Variable(ref name) if self.scope.variable(name).is_none() && self.global(name).is_none() => {
+                if self.constant(name).is_some() {
+                    self.diagnostics.error(
+                        argument.span,
+                        &format!("can't pass constant `{}` to a `var` parameter of `{}`", name, function),
+                    );
+                }
+            }
+            ExprKind::Variable(_) | ExprKind::Index(..) => (),
+            _ => self.diagnostics.error(
+                argument.span,
+                &format!("`{}` takes this argument as `var`, so it has to be a variable or an array element", function),
+            ),
+        }
+    }
+
+    /// Notes that a variable, once it's been checked, is passed to a `var`
+    /// parameter, so it needs an address: a parameter or local gets one in
+    /// the function's frame, and a global gets one of its own.
+    fn note_home(&mut self, argument: &Expr) {
+        let name = match argument.kind {
+            ExprKind::Variable(ref name) => name,
+            _ => return,
+        };
+        let homes = if self.scope.variable(name).is_some() {
+            &mut self.scope.homes
+        } else if self.global(name).is_some() {
+            &mut self.global_homes
+        } else {
+            return;
+        };
+        if !homes.contains(name) {
+            homes.push(name.clone());
+        }
+    }
+
+    /// Checks a call against the functions defined so far and the builtins:
+    /// the function has to exist and take exactly as many arguments as we're
+    /// passing. Returns the function's parameter and result types if it
+    /// exists.
+    fn check_call(&mut self, span: Span, name: &str, arguments: usize) -> Option<(Vec<Type>, Type)> {
+        let signature = self
+            .signature(name)
+            .map(|signature| (signature.params.clone(), signature.result))
+            .or_else(|| builtins::lookup(name).map(|builtin| (builtin.params.to_vec(), builtin.result)));
+
+        // The variables a nested function captures aren't written in the
+        // call, so they don't count.
+        let captured = self.signature(name).map_or(0, |signature| signature.captured);
+        match signature {
+            None => self.diagnostics.error(span, &format!("unknown function `{}`", name)),
+            Some((ref params, _)) if params.len() != arguments => self.diagnostics.error(
+                span,
+                &format!(
+                    "function `{}` expects {} arguments, found {}",
+                    name,
+                    params.len() - captured,
+                    arguments - captured
+                ),
+            ),
+            Some(_) => (),
+        }
+
+        signature
+    }
+}
+
+/// Lists things as in "`a`, `b`, or `c`".
+fn either(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [only] => only.clone(),
+        [first, second] => format!("{} or {}", first, second),
+        [rest @ .., last] => format!("{}, or {}", rest.join(", "), last),
+    }
+}
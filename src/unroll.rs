@@ -0,0 +1,214 @@
+//! Loop unrolling, with -O2 and --optimize-for. A `for` loop from one
+//! literal to another runs a known number of times, so when the cost model
+//! says it's worth it, the loop is replaced with a copy of its body for
+//! each time round, each setting the counter to its value first. Once
+//! they've all run, the counter's left one past the end, the same as after
+//! the loop.
+//!
+//! Desugaring has already made the loop a `while`, so this looks for what
+//! it makes: the counter and the limit being assigned literals, then a
+//! `while counter <= limit` that adds one to the counter at the end of its
+//! body. Loops whose body assigns the counter or the limit itself are left
+//! alone, and so are ones whose counter is a global, or can be changed
+//! through a `var` parameter, since calls in the body could change those.
+
+use consteval;
+use consteval::Value;
+use cost::CostModel;
+use hir::{BinaryOp, Expr, ExprKind, Program, Span, Stmt, Type};
+
+/// Unrolls the loops worth unrolling in every function body in a program,
+/// and main.
+pub fn unroll(program: &mut Program, model: &CostModel) {
+    let globals: Vec<String> = program.globals.iter().map(|global| global.name.clone()).collect();
+
+    for function in program.functions.iter_mut().chain(&mut program.handlers) {
+        let mut fixed = globals.clone();
+        fixed.extend(function.var_params.iter().cloned());
+        fixed.extend(function.homes.iter().cloned());
+        unroll_block(&mut function.body, &fixed, model);
+    }
+
+    let mut fixed = globals.clone();
+    fixed.extend(program.init_homes.iter().cloned());
+    unroll_block(&mut program.init, &fixed, model);
+
+    let mut fixed = globals;
+    fixed.extend(program.main_homes.iter().cloned());
+    unroll_block(&mut program.main, &fixed, model);
+}
+
+/// A loop that can be unrolled.
+struct Counted {
+    counter: String,
+    name_span: Span,
+    ty: Type,
+    from: i64,
+    trips: u64,
+}
+
+/// Unrolls the loops in a block, and in the blocks in it. Inner loops go
+/// first, so whether to unroll the ones around them is worked out with
+/// them unrolled, if they are. `fixed` are the variables that can't be
+/// counters.
+fn unroll_block(block: &mut Vec<Stmt>, fixed: &[String], model: &CostModel) {
+    for stmt in block.iter_mut() {
+        match *stmt {
+            Stmt::If { ref mut then, ref mut otherwise, .. } => {
+                unroll_block(then, fixed, model);
+                unroll_block(otherwise, fixed, model);
+            }
+            Stmt::While { ref mut body, .. } | Stmt::Block(ref mut body) => unroll_block(body, fixed, model),
+            Stmt::Case { ref mut arms, ref mut otherwise, .. } => {
+                for arm in arms {
+                    unroll_block(&mut arm.1, fixed, model);
+                }
+                if let Some(ref mut otherwise) = *otherwise {
+                    unroll_block(otherwise, fixed, model);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut i = 2;
+    while i < block.len() {
+        let counted = match counted(&block[i - 2..=i], fixed) {
+            Some(counted) => counted,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let body = match block[i] {
+            Stmt::While { ref body, .. } => &body[..body.len() - 1],
+            _ => unreachable!("counted loops are `while`s"),
+        };
+        if !model.prefer_unroll(counted.trips, body) {
+            i += 1;
+            continue;
+        }
+
+        let mut unrolled = Vec::new();
+        for trip in 0..counted.trips {
+            // The first time round, the counter's already been set.
+            if trip > 0 {
+                unrolled.push(set(&counted, counted.from + trip as i64));
+            }
+            unrolled.extend(body.iter().cloned());
+        }
+        unrolled.push(set(&counted, counted.from + counted.trips as i64));
+
+        let count = unrolled.len();
+        block.splice(i..=i, unrolled);
+        i += count;
+    }
+}
+
+/// Works out whether three statements are a counted loop, and if so, how
+/// many times it goes round.
+fn counted(stmts: &[Stmt], fixed: &[String]) -> Option<Counted> {
+    let (counter, name_span, ty, from) = match stmts[0] {
+        Stmt::Assign { ref name, name_span, ty: Some(ty), ref value } => (name, name_span, ty, int(value, ty)?),
+        _ => return None,
+    };
+    let (limit, to) = match stmts[1] {
+        Stmt::Assign { ref name, ref value, .. } => (name, int(value, ty)?),
+        _ => return None,
+    };
+    let (condition, body) = match stmts[2] {
+        Stmt::While { ref condition, ref body } => (condition, body),
+        _ => return None,
+    };
+
+    match condition.kind {
+        ExprKind::Binary(BinaryOp::LessEqual, ref left, ref right)
+            if is_variable(left, counter) && is_variable(right, limit) && counter != limit => {}
+        _ => return None,
+    }
+    match body.last() {
+        Some(Stmt::Assign { name, value, .. }) if name == counter => match value.kind {
+            ExprKind::Binary(BinaryOp::Add, ref left, ref right)
+                if is_variable(left, counter) && int(right, ty) == Some(1) => {}
+            _ => return None,
+        },
+        _ => return None,
+    }
+
+    if fixed.contains(counter) || fixed.contains(limit) || assigns(&body[..body.len() - 1], &[counter, limit]) {
+        return None;
+    }
+
+    // A loop to the biggest value there is never ends, since adding one to
+    // the counter wraps it around.
+    let max = match ty {
+        Type::I32 => i64::from(i32::MAX),
+        _ => i64::MAX,
+    };
+    if to < from || to == max {
+        return None;
+    }
+
+    Some(Counted {
+        counter: counter.clone(),
+        name_span,
+        ty,
+        from,
+        trips: (i128::from(to) - i128::from(from) + 1) as u64,
+    })
+}
+
+/// The value of an integer literal of type `ty`.
+fn int(expr: &Expr, ty: Type) -> Option<i64> {
+    match expr.kind {
+        ExprKind::Int(ref text, Some(literal)) if literal == ty => match consteval::int_literal(text, ty) {
+            Some(Value::I32(value)) => Some(i64::from(value)),
+            Some(Value::I64(value)) => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_variable(expr: &Expr, name: &str) -> bool {
+    matches!(expr.kind, ExprKind::Variable(ref variable) if variable == name)
+}
+
+/// Whether a block assigns any of `names`, anywhere in it.
+fn assigns(block: &[Stmt], names: &[&String]) -> bool {
+    block.iter().any(|stmt| match *stmt {
+        Stmt::Assign { ref name, .. } => names.contains(&name),
+        Stmt::AssignValues { names: ref assigned, .. } => assigned.iter().any(|name| names.contains(&name)),
+        Stmt::If { ref then, ref otherwise, .. } => assigns(then, names) || assigns(otherwise, names),
+        Stmt::While { ref body, .. } | Stmt::Block(ref body) => assigns(body, names),
+        Stmt::Case { ref arms, ref otherwise, .. } => {
+            arms.iter().any(|arm| assigns(&arm.1, names))
+                || otherwise.as_ref().is_some_and(|otherwise| assigns(otherwise, names))
+        }
+        Stmt::Return(_)
+        | Stmt::ReturnValues(_)
+        | Stmt::Print(_)
+        | Stmt::Assert(_)
+        | Stmt::Halt(_)
+        | Stmt::Call(_)
+        | Stmt::Store { .. } => false,
+    })
+}
+
+/// `counter = value`
+fn set(counted: &Counted, value: i64) -> Stmt {
+    let literal = match counted.ty {
+        Type::I32 => Value::I32(value as i32),
+        _ => Value::I64(value),
+    };
+    Stmt::Assign {
+        name: counted.counter.clone(),
+        name_span: counted.name_span,
+        ty: Some(counted.ty),
+        value: Expr {
+            kind: ExprKind::Int(literal.to_string(), Some(counted.ty)),
+            span: counted.name_span,
+        },
+    }
+}
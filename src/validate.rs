@@ -0,0 +1,70 @@
+//! Checks that a module in the text format is valid WebAssembly, so bad output
+//! gets caught here rather than by whatever engine runs it. The `wat` crate
+//! assembles it and `wasmparser` validates it, with every proposal the code
+//! generator can use turned on. This is the `check` feature.
+//!
+//! A validation error is at an offset in the binary, which doesn't mean much
+//! to anyone reading the WAT, so the message says which function it's in
+//! instead, by its `$name`.
+
+use wasmparser::{KnownCustom, Name, Parser, Payload, TypeRef, Validator, WasmFeatures};
+use wat;
+
+/// Checks a whole module. The error says what's wrong and where.
+pub fn validate(text: &str) -> Result<(), String> {
+    let binary = wat::parse_str(text).map_err(|err| err.to_string())?;
+
+    // WebAssembly 3.0 has everything we generate: GC, SIMD, threads,
+    // memory64, tail calls, and bulk memory.
+    let mut validator = Validator::new_with_features(WasmFeatures::WASM3);
+    match validator.validate_all(&binary) {
+        Ok(_) => Ok(()),
+        Err(err) => match function_at(&binary, err.offset()) {
+            Some(name) => Err(format!("in `${}`: {}", name, err.message())),
+            None => Err(err.message().to_string()),
+        },
+    }
+}
+
+/// Returns the name of the function whose body has the byte at `offset` in
+/// it, from the name section the assembler writes, or None if it isn't in a
+/// function that has a name.
+fn function_at(binary: &[u8], offset: usize) -> Option<String> {
+    let mut imported = 0;
+    let mut defined = 0;
+    let mut index = None;
+
+    for payload in Parser::new(0).parse_all(binary) {
+        match payload.ok()? {
+            Payload::ImportSection(imports) => {
+                for import in imports.into_imports() {
+                    if let TypeRef::Func(_) = import.ok()?.ty {
+                        imported += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                if body.range().contains(&offset) {
+                    index = Some(imported + defined);
+                }
+                defined += 1;
+            }
+            Payload::CustomSection(section) => {
+                if let KnownCustom::Name(names) = section.as_known() {
+                    for name in names {
+                        if let Name::Function(functions) = name.ok()? {
+                            for function in functions {
+                                let function = function.ok()?;
+                                if Some(function.index) == index {
+                                    return Some(function.name.to_string());
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
@@ -0,0 +1,966 @@
+//! Support for running under WASI, with --target wasi. WASI doesn't have
+//! anything like `print`, only `fd_write`, which writes bytes from memory to
+//! a file. So programs get print functions, written in WAT, that turn values
+//! into text in a scratch area of memory and write it to stdout.
+//!
+//! The scratch area is laid out like this, from its address:
+//!
+//! - 0: the `iovec` handed to `fd_write`: the address of the bytes to write...
+//! - 4: ...and how many there are.
+//! - 8: where `fd_write` puts how many bytes it wrote, which we ignore.
+//! - 12: room for the text of an i64, and a newline, or the bytes of a
+//!   random number.
+//! - 16: where `clock_time_get` puts the time.
+//!
+//! f64s take a lot more working out, so `$print_f64` has its own, bigger
+//! area, only if the program prints one.
+
+/// The module WASI's functions are imported from.
+pub const MODULE: &str = "wasi_snapshot_preview1";
+
+/// How many bytes of memory the print functions need.
+pub const SCRATCH_SIZE: u32 = 36;
+
+/// `fd_write(fd, iovs, iovs_len, nwritten) -> errno`
+pub const FD_WRITE: &str = "(param i32 i32 i32 i32) (result i32)";
+
+/// `proc_exit(code)`, which doesn't return.
+pub const PROC_EXIT: &str = "(param i32)";
+
+/// `random_get(buf, buf_len) -> errno`
+pub const RANDOM_GET: &str = "(param i32 i32) (result i32)";
+
+/// `clock_time_get(id, precision, time) -> errno`
+pub const CLOCK_TIME_GET: &str = "(param i32 i64 i32) (result i32)";
+
+/// The id of the clock that only goes forwards.
+const MONOTONIC: i32 = 1;
+
+/// `$wasi.write`, which writes `len` bytes from `ptr` to stdout. Errors are
+/// ignored, since there's nowhere to report them.
+pub fn write(scratch: u32) -> String {
+    format!(
+        "(func $wasi.write (param $ptr i32) (param $len i32)\n\
+         (i32.const {iovec})\n\
+         (local.get $ptr)\n\
+         (i32.store)\n\
+         (i32.const {iovec_len})\n\
+         (local.get $len)\n\
+         (i32.store)\n\
+         (i32.const 1)\n\
+         (i32.const {iovec})\n\
+         (i32.const 1)\n\
+         (i32.const {nwritten})\n\
+         (call $fd_write)\n\
+         (drop)\n\
+         )\n",
+        iovec = scratch,
+        iovec_len = scratch + 4,
+        nwritten = scratch + 8,
+    )
+}
+
+/// `$print_i64`, which writes an i64 in decimal and a newline. The digits go
+/// at the end of the buffer, working backwards from the lowest. The
+/// magnitude is unsigned, so it's right even for the most negative i64.
+pub fn print_i64(scratch: u32) -> String {
+    format!(
+        "(func $print_i64 (param $value i64)\n\
+         (local $ptr i32)\n\
+         (local $magnitude i64)\n\
+         (local.set $ptr\n\
+         (i32.const {newline})\n\
+         )\n\
+         (local.get $ptr)\n\
+         (i32.const 10)\n\
+         (i32.store8)\n\
+         (local.set $magnitude\n\
+         (local.get $value)\n\
+         )\n\
+         (local.get $value)\n\
+         (i64.const 0)\n\
+         (i64.lt_s)\n\
+         (if\n\
+         (then\n\
+         (local.set $magnitude\n\
+         (i64.const 0)\n\
+         (local.get $value)\n\
+         (i64.sub)\n\
+         )\n\
+         )\n\
+         )\n\
+         (loop\n\
+         (local.set $ptr\n\
+         (local.get $ptr)\n\
+         (i32.const 1)\n\
+         (i32.sub)\n\
+         )\n\
+         (local.get $ptr)\n\
+         (local.get $magnitude)\n\
+         (i64.const 10)\n\
+         (i64.rem_u)\n\
+         (i32.wrap_i64)\n\
+         (i32.const 48)\n\
+         (i32.add)\n\
+         (i32.store8)\n\
+         (local.set $magnitude\n\
+         (local.get $magnitude)\n\
+         (i64.const 10)\n\
+         (i64.div_u)\n\
+         )\n\
+         (local.get $magnitude)\n\
+         (i64.const 0)\n\
+         (i64.ne)\n\
+         (br_if 0)\n\
+         )\n\
+         (local.get $value)\n\
+         (i64.const 0)\n\
+         (i64.lt_s)\n\
+         (if\n\
+         (then\n\
+         (local.set $ptr\n\
+         (local.get $ptr)\n\
+         (i32.const 1)\n\
+         (i32.sub)\n\
+         )\n\
+         (local.get $ptr)\n\
+         (i32.const 45)\n\
+         (i32.store8)\n\
+         )\n\
+         )\n\
+         (local.get $ptr)\n\
+         (i32.const {end})\n\
+         (local.get $ptr)\n\
+         (i32.sub)\n\
+         (call $wasi.write)\n\
+         )\n",
+        newline = scratch + SCRATCH_SIZE - 1,
+        end = scratch + SCRATCH_SIZE,
+    )
+}
+
+/// How many 32-bit limbs the big numbers `$print_f64` works with have. The
+/// biggest it needs is a bit over 2^1080, for the smallest subnormals.
+const LIMBS: u32 = 40;
+
+/// How many bytes a big number takes.
+const BIG_SIZE: u32 = LIMBS * 4;
+
+/// How many bytes of memory `$print_f64` needs: five big numbers, up to
+/// 17 digits, and the text, which is longest for the smallest subnormals:
+/// `-0.`, 323 zeros, the digits, and a newline.
+pub const PRINT_F64_SIZE: u32 = 5 * BIG_SIZE + 24 + 352;
+
+/// `$print_f64`, which writes an f64 and a newline, the same way the host
+/// does: the fewest digits that read back as the same f64, and never an
+/// exponent, so 1e300 is a 1 and 300 zeros. `buffer` is where its
+/// `PRINT_F64_SIZE` bytes are.
+///
+/// The digits are worked out with big numbers, the way Steele and White's
+/// Dragon4 does, so they're exact. The f64 is `r / s` times a power of ten,
+/// and anything within `minus / s` below it or `plus / s` above it would
+/// read back as the same f64. Taking a digit off the front of `r / s` at a
+/// time, we stop as soon as what's left is within one of them, rounding up
+/// if that's closer.
+pub fn print_f64(buffer: u32) -> String {
+    let big = |i: u32| buffer + i * BIG_SIZE;
+    let (r, s, plus, minus, t) = (big(0), big(1), big(2), big(3), big(4));
+    let digits = big(5);
+    let text = digits + 24;
+    let mut code = big_functions();
+    code.push_str(&format!(
+        "(func $print_f64 (param $value f64)\n\
+         (local $ptr i32)\n\
+         (local $n i32)\n\
+         (local $k i32)\n\
+         (local $i i32)\n\
+         (local.set $ptr\n\
+         (i32.const {text})\n\
+         )\n\
+         (block $text\n\
+         (local.get $value)\n\
+         (local.get $value)\n\
+         (f64.ne)\n\
+         (if\n\
+         (then\n\
+         (local.get $ptr)\n\
+         (i32.const 0x6e616e)\n\
+         (i32.store)\n\
+         (local.set $ptr\n\
+         (i32.const {after_word})\n\
+         )\n\
+         (br $text)\n\
+         )\n\
+         )\n\
+         (local.get $value)\n\
+         (i64.reinterpret_f64)\n\
+         (i64.const 0)\n\
+         (i64.lt_s)\n\
+         (if\n\
+         (then\n\
+         (local.get $ptr)\n\
+         (i32.const 45)\n\
+         (i32.store8)\n\
+         (local.set $ptr\n\
+         (i32.const {after_sign})\n\
+         )\n\
+         )\n\
+         )\n\
+         (local.get $value)\n\
+         (f64.abs)\n\
+         (f64.const inf)\n\
+         (f64.eq)\n\
+         (if\n\
+         (then\n\
+         (local.get $ptr)\n\
+         (i32.const 0x666e69)\n\
+         (i32.store)\n\
+         (local.set $ptr\n\
+         (local.get $ptr)\n\
+         (i32.const 3)\n\
+         (i32.add)\n\
+         )\n\
+         (br $text)\n\
+         )\n\
+         )\n\
+         (local.get $value)\n\
+         (f64.const 0)\n\
+         (f64.eq)\n\
+         (if\n\
+         (then\n\
+         (local.get $ptr)\n\
+         (i32.const 48)\n\
+         (i32.store8)\n\
+         (local.set $ptr\n\
+         (local.get $ptr)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (br $text)\n\
+         )\n\
+         )\n\
+         (local.get $value)\n\
+         (i64.reinterpret_f64)\n\
+         (call $print_f64.digits)\n\
+         (local.set $k)\n\
+         (local.set $n)\n\
+         (local.get $k)\n\
+         (i32.const 0)\n\
+         (i32.le_s)\n\
+         (if\n\
+         (then\n\
+         (local.get $ptr)\n\
+         (i32.const 0x2e30)\n\
+         (i32.store16)\n\
+         (local.set $ptr\n\
+         (local.get $ptr)\n\
+         (i32.const 2)\n\
+         (i32.add)\n\
+         )\n\
+         (block\n\
+         (loop\n\
+         (local.get $k)\n\
+         (i32.eqz)\n\
+         (br_if 1)\n\
+         (local.get $ptr)\n\
+         (i32.const 48)\n\
+         (i32.store8)\n\
+         (local.set $ptr\n\
+         (local.get $ptr)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (local.set $k\n\
+         (local.get $k)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (br 0)\n\
+         )\n\
+         )\n\
+         )\n\
+         )\n\
+         (loop\n\
+         (local.get $i)\n\
+         (local.get $k)\n\
+         (i32.eq)\n\
+         (local.get $i)\n\
+         (i32.const 0)\n\
+         (i32.ne)\n\
+         (i32.and)\n\
+         (if\n\
+         (then\n\
+         (local.get $ptr)\n\
+         (i32.const 46)\n\
+         (i32.store8)\n\
+         (local.set $ptr\n\
+         (local.get $ptr)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         )\n\
+         )\n\
+         (local.get $ptr)\n\
+         (local.get $i)\n\
+         (i32.load8_u offset={digits})\n\
+         (i32.store8)\n\
+         (local.set $ptr\n\
+         (local.get $ptr)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (local.set $i\n\
+         (local.get $i)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (local.get $i)\n\
+         (local.get $n)\n\
+         (i32.lt_u)\n\
+         (br_if 0)\n\
+         )\n\
+         (block\n\
+         (loop\n\
+         (local.get $i)\n\
+         (local.get $k)\n\
+         (i32.ge_s)\n\
+         (br_if 1)\n\
+         (local.get $ptr)\n\
+         (i32.const 48)\n\
+         (i32.store8)\n\
+         (local.set $ptr\n\
+         (local.get $ptr)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (local.set $i\n\
+         (local.get $i)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (br 0)\n\
+         )\n\
+         )\n\
+         )\n\
+         (local.get $ptr)\n\
+         (i32.const 10)\n\
+         (i32.store8)\n\
+         (i32.const {text})\n\
+         (local.get $ptr)\n\
+         (i32.const {before_text})\n\
+         (i32.sub)\n\
+         (call $wasi.write)\n\
+         )\n",
+        text = text,
+        after_word = text + 3,
+        after_sign = text + 1,
+        before_text = text - 1,
+        digits = digits,
+    ));
+    code.push_str(&format!(
+        "(func $print_f64.digits (param $bits i64) (result i32 i32)\n\
+         (local $biased i32)\n\
+         (local $frac i64)\n\
+         (local $exp i32)\n\
+         (local $inclusive i32)\n\
+         (local $k i32)\n\
+         (local $n i32)\n\
+         (local $digit i32)\n\
+         (local $down i32)\n\
+         (local $up i32)\n\
+         (local.set $biased\n\
+         (local.get $bits)\n\
+         (i64.const 52)\n\
+         (i64.shr_u)\n\
+         (i32.wrap_i64)\n\
+         (i32.const 0x7ff)\n\
+         (i32.and)\n\
+         )\n\
+         (local.set $frac\n\
+         (local.get $bits)\n\
+         (i64.const 0xfffffffffffff)\n\
+         (i64.and)\n\
+         )\n\
+         (call $big.set\n\
+         (i32.const {minus})\n\
+         (i64.const 1)\n\
+         )\n\
+         (call $big.set\n\
+         (i32.const {plus})\n\
+         (i64.const 1)\n\
+         )\n\
+         (call $big.set\n\
+         (i32.const {s})\n\
+         (i64.const 1)\n\
+         )\n\
+         (local.set $inclusive\n\
+         (local.get $frac)\n\
+         (i32.wrap_i64)\n\
+         (i32.const 1)\n\
+         (i32.and)\n\
+         (i32.eqz)\n\
+         )\n\
+         (local.get $biased)\n\
+         (i32.eqz)\n\
+         (if\n\
+         (then\n\
+         (call $big.set\n\
+         (i32.const {r})\n\
+         (local.get $frac)\n\
+         (i64.const 1)\n\
+         (i64.shl)\n\
+         )\n\
+         (local.set $exp\n\
+         (i32.const -1075)\n\
+         )\n\
+         (local.set $inclusive\n\
+         (i32.const 1)\n\
+         )\n\
+         )\n\
+         (else\n\
+         (local.get $frac)\n\
+         (i64.eqz)\n\
+         (if\n\
+         (then\n\
+         (call $big.set\n\
+         (i32.const {r})\n\
+         (i64.const 0x40000000000000)\n\
+         )\n\
+         (call $big.set\n\
+         (i32.const {plus})\n\
+         (i64.const 2)\n\
+         )\n\
+         (local.set $exp\n\
+         (local.get $biased)\n\
+         (i32.const 1077)\n\
+         (i32.sub)\n\
+         )\n\
+         )\n\
+         (else\n\
+         (call $big.set\n\
+         (i32.const {r})\n\
+         (local.get $frac)\n\
+         (i64.const 0x10000000000000)\n\
+         (i64.or)\n\
+         (i64.const 1)\n\
+         (i64.shl)\n\
+         )\n\
+         (local.set $exp\n\
+         (local.get $biased)\n\
+         (i32.const 1076)\n\
+         (i32.sub)\n\
+         )\n\
+         )\n\
+         )\n\
+         )\n\
+         )\n\
+         (block\n\
+         (loop\n\
+         (local.get $exp)\n\
+         (i32.const 0)\n\
+         (i32.le_s)\n\
+         (br_if 1)\n\
+         (call $big.mul\n\
+         (i32.const {r})\n\
+         (i32.const 2)\n\
+         )\n\
+         (call $big.mul\n\
+         (i32.const {minus})\n\
+         (i32.const 2)\n\
+         )\n\
+         (call $big.mul\n\
+         (i32.const {plus})\n\
+         (i32.const 2)\n\
+         )\n\
+         (local.set $exp\n\
+         (local.get $exp)\n\
+         (i32.const 1)\n\
+         (i32.sub)\n\
+         )\n\
+         (br 0)\n\
+         )\n\
+         )\n\
+         (block\n\
+         (loop\n\
+         (local.get $exp)\n\
+         (i32.const 0)\n\
+         (i32.ge_s)\n\
+         (br_if 1)\n\
+         (call $big.mul\n\
+         (i32.const {s})\n\
+         (i32.const 2)\n\
+         )\n\
+         (local.set $exp\n\
+         (local.get $exp)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (br 0)\n\
+         )\n\
+         )\n\
+         (local.get $inclusive)\n\
+         (call $print_f64.reaches)\n\
+         (if\n\
+         (then\n\
+         (loop\n\
+         (call $big.mul\n\
+         (i32.const {s})\n\
+         (i32.const 10)\n\
+         )\n\
+         (local.set $k\n\
+         (local.get $k)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (local.get $inclusive)\n\
+         (call $print_f64.reaches)\n\
+         (br_if 0)\n\
+         )\n\
+         )\n\
+         (else\n\
+         (block\n\
+         (loop\n\
+         (call $big.add\n\
+         (i32.const {t})\n\
+         (i32.const {r})\n\
+         (i32.const {plus})\n\
+         )\n\
+         (call $big.mul\n\
+         (i32.const {t})\n\
+         (i32.const 10)\n\
+         )\n\
+         (call $big.compare\n\
+         (i32.const {s})\n\
+         (i32.const {t})\n\
+         )\n\
+         (local.get $inclusive)\n\
+         (i32.lt_s)\n\
+         (br_if 1)\n\
+         (call $print_f64.times_ten)\n\
+         (local.set $k\n\
+         (local.get $k)\n\
+         (i32.const 1)\n\
+         (i32.sub)\n\
+         )\n\
+         (br 0)\n\
+         )\n\
+         )\n\
+         )\n\
+         )\n\
+         (loop $digit\n\
+         (call $print_f64.times_ten)\n\
+         (local.set $digit\n\
+         (i32.const 48)\n\
+         )\n\
+         (block\n\
+         (loop\n\
+         (call $big.compare\n\
+         (i32.const {r})\n\
+         (i32.const {s})\n\
+         )\n\
+         (i32.const 0)\n\
+         (i32.lt_s)\n\
+         (br_if 1)\n\
+         (call $big.sub\n\
+         (i32.const {r})\n\
+         (i32.const {s})\n\
+         )\n\
+         (local.set $digit\n\
+         (local.get $digit)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (br 0)\n\
+         )\n\
+         )\n\
+         (local.get $n)\n\
+         (local.get $digit)\n\
+         (i32.store8 offset={digits})\n\
+         (local.set $n\n\
+         (local.get $n)\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         )\n\
+         (local.set $down\n\
+         (call $big.compare\n\
+         (i32.const {r})\n\
+         (i32.const {minus})\n\
+         )\n\
+         (local.get $inclusive)\n\
+         (i32.lt_s)\n\
+         )\n\
+         (local.set $up\n\
+         (local.get $inclusive)\n\
+         (call $print_f64.reaches)\n\
+         )\n\
+         (local.get $down)\n\
+         (local.get $up)\n\
+         (i32.or)\n\
+         (i32.eqz)\n\
+         (br_if $digit)\n\
+         )\n\
+         (call $big.add\n\
+         (i32.const {t})\n\
+         (i32.const {r})\n\
+         (i32.const {r})\n\
+         )\n\
+         (local.get $up)\n\
+         (local.get $down)\n\
+         (i32.eqz)\n\
+         (call $big.compare\n\
+         (i32.const {t})\n\
+         (i32.const {s})\n\
+         )\n\
+         (i32.const 0)\n\
+         (i32.ge_s)\n\
+         (i32.or)\n\
+         (i32.and)\n\
+         (if\n\
+         (then\n\
+         (local.set $digit\n\
+         (local.get $n)\n\
+         )\n\
+         (loop\n\
+         (local.set $digit\n\
+         (local.get $digit)\n\
+         (i32.const 1)\n\
+         (i32.sub)\n\
+         )\n\
+         (local.get $digit)\n\
+         (i32.load8_u offset={digits})\n\
+         (i32.const 57)\n\
+         (i32.eq)\n\
+         (if\n\
+         (then\n\
+         (local.get $digit)\n\
+         (i32.const 48)\n\
+         (i32.store8 offset={digits})\n\
+         (br 1)\n\
+         )\n\
+         )\n\
+         )\n\
+         (local.get $digit)\n\
+         (local.get $digit)\n\
+         (i32.load8_u offset={digits})\n\
+         (i32.const 1)\n\
+         (i32.add)\n\
+         (i32.store8 offset={digits})\n\
+         )\n\
+         )\n\
+         (local.get $n)\n\
+         (local.get $k)\n\
+         )\n\
+         (func $print_f64.reaches (param $inclusive i32) (result i32)\n\
+         (call $big.add\n\
+         (i32.const {t})\n\
+         (i32.const {r})\n\
+         (i32.const {plus})\n\
+         )\n\
+         (call $big.compare\n\
+         (i32.const {s})\n\
+         (i32.const {t})\n\
+         )\n\
+         (local.get $inclusive)\n\
+         (i32.lt_s)\n\
+         )\n\
+         (func $print_f64.times_ten\n\
+         (call $big.mul\n\
+         (i32.const {r})\n\
+         (i32.const 10)\n\
+         )\n\
+         (call $big.mul\n\
+         (i32.const {minus})\n\
+         (i32.const 10)\n\
+         )\n\
+         (call $big.mul\n\
+         (i32.const {plus})\n\
+         (i32.const 10)\n\
+         )\n\
+         )\n",
+        r = r,
+        s = s,
+        plus = plus,
+        minus = minus,
+        t = t,
+        digits = digits,
+    ));
+    code
+}
+
+/// The functions `$print_f64` does its arithmetic with, on unsigned big
+/// numbers of `LIMBS` limbs at the addresses they're given, lowest limb
+/// first. None of them check for overflow, since nothing gets that big.
+///
+/// - `$big.set` sets one to an i64.
+/// - `$big.mul` multiplies one by a small number.
+/// - `$big.add` adds two, putting the sum in a third, which can be one of
+///   them.
+/// - `$big.sub` takes one away from another, which has to be bigger.
+/// - `$big.compare` compares two, returning -1, 0, or 1.
+fn big_functions() -> String {
+    format!(
+        "(func $big.set (param $n i32) (param $value i64)\n\
+         (local $i i32)\n\
+         (loop\n\
+         (local.get $n)\n\
+         (local.get $i)\n\
+         (i32.add)\n\
+         (i32.const 0)\n\
+         (i32.store)\n\
+         (local.set $i\n\
+         (local.get $i)\n\
+         (i32.const 4)\n\
+         (i32.add)\n\
+         )\n\
+         (local.get $i)\n\
+         (i32.const {size})\n\
+         (i32.lt_u)\n\
+         (br_if 0)\n\
+         )\n\
+         (local.get $n)\n\
+         (local.get $value)\n\
+         (i64.store)\n\
+         )\n\
+         (func $big.mul (param $n i32) (param $by i32)\n\
+         (local $i i32)\n\
+         (local $limb i32)\n\
+         (local $carry i64)\n\
+         (loop\n\
+         (local.set $limb\n\
+         (local.get $n)\n\
+         (local.get $i)\n\
+         (i32.add)\n\
+         )\n\
+         (local.set $carry\n\
+         (local.get $limb)\n\
+         (i64.load32_u)\n\
+         (local.get $by)\n\
+         (i64.extend_i32_u)\n\
+         (i64.mul)\n\
+         (local.get $carry)\n\
+         (i64.add)\n\
+         )\n\
+         (local.get $limb)\n\
+         (local.get $carry)\n\
+         (i64.store32)\n\
+         (local.set $carry\n\
+         (local.get $carry)\n\
+         (i64.const 32)\n\
+         (i64.shr_u)\n\
+         )\n\
+         (local.set $i\n\
+         (local.get $i)\n\
+         (i32.const 4)\n\
+         (i32.add)\n\
+         )\n\
+         (local.get $i)\n\
+         (i32.const {size})\n\
+         (i32.lt_u)\n\
+         (br_if 0)\n\
+         )\n\
+         )\n\
+         (func $big.add (param $sum i32) (param $a i32) (param $b i32)\n\
+         (local $i i32)\n\
+         (local $carry i64)\n\
+         (loop\n\
+         (local.set $carry\n\
+         (local.get $a)\n\
+         (local.get $i)\n\
+         (i32.add)\n\
+         (i64.load32_u)\n\
+         (local.get $b)\n\
+         (local.get $i)\n\
+         (i32.add)\n\
+         (i64.load32_u)\n\
+         (i64.add)\n\
+         (local.get $carry)\n\
+         (i64.add)\n\
+         )\n\
+         (local.get $sum)\n\
+         (local.get $i)\n\
+         (i32.add)\n\
+         (local.get $carry)\n\
+         (i64.store32)\n\
+         (local.set $carry\n\
+         (local.get $carry)\n\
+         (i64.const 32)\n\
+         (i64.shr_u)\n\
+         )\n\
+         (local.set $i\n\
+         (local.get $i)\n\
+         (i32.const 4)\n\
+         (i32.add)\n\
+         )\n\
+         (local.get $i)\n\
+         (i32.const {size})\n\
+         (i32.lt_u)\n\
+         (br_if 0)\n\
+         )\n\
+         )\n\
+         (func $big.sub (param $n i32) (param $b i32)\n\
+         (local $i i32)\n\
+         (local $limb i32)\n\
+         (local $difference i64)\n\
+         (loop\n\
+         (local.set $limb\n\
+         (local.get $n)\n\
+         (local.get $i)\n\
+         (i32.add)\n\
+         )\n\
+         (local.set $difference\n\
+         (local.get $limb)\n\
+         (i64.load32_u)\n\
+         (local.get $b)\n\
+         (local.get $i)\n\
+         (i32.add)\n\
+         (i64.load32_u)\n\
+         (i64.sub)\n\
+         (local.get $difference)\n\
+         (i64.const 63)\n\
+         (i64.shr_u)\n\
+         (i64.sub)\n\
+         )\n\
+         (local.get $limb)\n\
+         (local.get $difference)\n\
+         (i64.store32)\n\
+         (local.set $i\n\
+         (local.get $i)\n\
+         (i32.const 4)\n\
+         (i32.add)\n\
+         )\n\
+         (local.get $i)\n\
+         (i32.const {size})\n\
+         (i32.lt_u)\n\
+         (br_if 0)\n\
+         )\n\
+         )\n\
+         (func $big.compare (param $a i32) (param $b i32) (result i32)\n\
+         (local $i i32)\n\
+         (local $x i32)\n\
+         (local $y i32)\n\
+         (local.set $i\n\
+         (i32.const {size})\n\
+         )\n\
+         (loop\n\
+         (local.set $i\n\
+         (local.get $i)\n\
+         (i32.const 4)\n\
+         (i32.sub)\n\
+         )\n\
+         (local.set $x\n\
+         (local.get $a)\n\
+         (local.get $i)\n\
+         (i32.add)\n\
+         (i32.load)\n\
+         )\n\
+         (local.set $y\n\
+         (local.get $b)\n\
+         (local.get $i)\n\
+         (i32.add)\n\
+         (i32.load)\n\
+         )\n\
+         (local.get $x)\n\
+         (local.get $y)\n\
+         (i32.ne)\n\
+         (if\n\
+         (then\n\
+         (i32.const 1)\n\
+         (i32.const -1)\n\
+         (local.get $x)\n\
+         (local.get $y)\n\
+         (i32.gt_u)\n\
+         (select)\n\
+         (return)\n\
+         )\n\
+         )\n\
+         (local.get $i)\n\
+         (br_if 0)\n\
+         )\n\
+         (i32.const 0)\n\
+         )\n",
+        size = BIG_SIZE,
+    )
+}
+
+/// `$print`, which writes an i32 in decimal and a newline.
+pub fn print_i32() -> String {
+    String::from(
+        "(func $print (param $value i32)\n\
+         (local.get $value)\n\
+         (i64.extend_i32_s)\n\
+         (call $print_i64)\n\
+         )\n",
+    )
+}
+
+/// `$print_str`, which writes a string's bytes and a newline.
+pub fn print_str(scratch: u32) -> String {
+    format!(
+        "(func $print_str (param $ptr i32) (param $len i32)\n\
+         (local.get $ptr)\n\
+         (local.get $len)\n\
+         (call $wasi.write)\n\
+         (i32.const {newline})\n\
+         (i32.const 10)\n\
+         (i32.store8)\n\
+         (i32.const {newline})\n\
+         (i32.const 1)\n\
+         (call $wasi.write)\n\
+         )\n",
+        newline = scratch + SCRATCH_SIZE - 1,
+    )
+}
+
+/// `$host.random`, which gets four random bytes and returns them as an i32
+/// without the sign bit. Errors are ignored, like `$wasi.write` ignores
+/// them, leaving whatever bytes were there.
+pub fn random(scratch: u32) -> String {
+    format!(
+        "(func $host.random (result i32)\n\
+         (i32.const {bytes})\n\
+         (i32.const 4)\n\
+         (call $random_get)\n\
+         (drop)\n\
+         (i32.const {bytes})\n\
+         (i32.load)\n\
+         (i32.const 1)\n\
+         (i32.shr_u)\n\
+         )\n",
+        bytes = scratch + 12,
+    )
+}
+
+/// `$host.clock`, which reads the monotonic clock, to the nearest
+/// millisecond, and returns it in milliseconds. Errors are ignored, like
+/// `$host.random` ignores them.
+pub fn clock(scratch: u32) -> String {
+    format!(
+        "(func $host.clock (result i64)\n\
+         (i32.const {monotonic})\n\
+         (i64.const 1000000)\n\
+         (i32.const {time})\n\
+         (call $clock_time_get)\n\
+         (drop)\n\
+         (i32.const {time})\n\
+         (i64.load)\n\
+         (i64.const 1000000)\n\
+         (i64.div_u)\n\
+         )\n",
+        monotonic = MONOTONIC,
+        time = scratch + 16,
+    )
+}
+
+/// `$_start`, which WASI runs. It runs the program and exits with whatever
+/// `main` returns as the status, or 0 if it doesn't return anything.
+pub fn start(main_has_result: bool) -> String {
+    let status = if main_has_result { "" } else { "(i32.const 0)\n" };
+    format!(
+        "(func $_start\n\
+         (call $main)\n\
+         {}\
+         (call $proc_exit)\n\
+         )\n",
+        status
+    )
+}
@@ -0,0 +1,16 @@
+//! With the `wasm` feature, the compiler can be built for wasm32 and called
+//! from JavaScript, to compile programs in the browser without a server.
+
+use wasm_bindgen::prelude::*;
+
+use {compile_to_string, Options};
+
+/// Compiles a program to WAT with the default options. If it doesn't
+/// compile, throws the errors, one to a line, each with where it is.
+#[wasm_bindgen]
+pub fn compile(source: &str) -> Result<String, JsValue> {
+    compile_to_string(source.as_bytes(), &Options::default()).map_err(|errors| {
+        let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        JsValue::from_str(&errors.join("\n"))
+    })
+}
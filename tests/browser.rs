@@ -0,0 +1,47 @@
+//! What --target browser and --html write alongside the module: the loader,
+//! the page, and the module assembled for them to load.
+
+extern crate crenshaw;
+
+mod common;
+
+use crenshaw::browser;
+
+#[test]
+fn the_loader_says_the_module_needs_assembling() {
+    let loader = browser::loader("prog.wasm", "main");
+    assert!(loader.contains("const url = 'prog.wasm';"), "{}", loader);
+    assert!(loader.contains("'could not load ' + url"), "{}", loader);
+    assert!(loader.contains("wat2wasm"), "{}", loader);
+}
+
+#[cfg(feature = "assemble")]
+#[test]
+fn modules_are_assembled_for_the_browser() {
+    let binary = browser::assemble(&common::compile("basics", "--target browser")).unwrap();
+    assert!(binary.starts_with(b"\0asm"));
+}
+
+#[test]
+fn base64_matches_the_rfc() {
+    let cases = [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ];
+    for &(bytes, encoded) in cases.iter() {
+        assert_eq!(browser::base64(bytes.as_bytes()), encoded);
+    }
+}
+
+#[cfg(feature = "assemble")]
+#[test]
+fn pages_have_the_module_in_them() {
+    let binary = browser::assemble(&common::compile("basics", "--target browser")).unwrap();
+    let page = browser::page("basics", &browser::data_url(&binary), "main");
+    assert!(page.contains("'data:application/wasm;base64,AGFzbQ"), "{}", page);
+}
@@ -0,0 +1,80 @@
+//! What the tests share: the programs in tests/programs, and the flags each
+//! one gets compiled with.
+
+#![allow(dead_code)]
+
+use std::fs;
+
+use crenshaw::{compile_to_string, Options};
+
+/// Each program in tests/programs, and the flags it's compiled with, one
+/// set at a time, on top of being compiled without any. Between them, they
+/// cover every flag that changes the code we generate.
+pub const PROGRAMS: &[(&str, &[&str])] = &[
+    (
+        "basics",
+        &[
+            "-O1",
+            "-O2",
+            "--optimize-for size",
+            "--optimize-for speed",
+            "-O2 --optimize-for size",
+            "-O2 --optimize-for speed",
+            "--simd",
+            "--threads",
+            "--tail-calls",
+            "--enable memory64",
+            "--target browser",
+            "--target wasi",
+            "--checked-div",
+            "--metering",
+            "--profile",
+            "--coverage",
+            "--comments",
+            "--export-all",
+            "--no-result",
+            "--entry-name run",
+            "--truthiness nonzero",
+        ],
+    ),
+    (
+        "strings",
+        &["-O2", "--rc", "--target wasi", "--no-bulk-memory", "--no-prelude", "--embed-source"],
+    ),
+    (
+        "records",
+        &["-O2", "--gc", "--rc", "--bounds-check trap", "--bounds-check clamp", "--bounds-check off"],
+    ),
+    ("var", &["-O2", "--rc", "--enable memory64"]),
+];
+
+/// The path to a program in tests/programs, from where the tests run.
+pub fn path(name: &str) -> String {
+    format!("tests/programs/{}.cr", name)
+}
+
+/// The options `flags` would give on the command line, compiling `name`.
+pub fn options(name: &str, flags: &str) -> Options {
+    let mut options = Options::parse(flags.split_whitespace().map(String::from)).unwrap();
+    options.input = Some(path(name));
+    options
+}
+
+/// Compiles a program from tests/programs, which has to compile.
+pub fn compile(name: &str, flags: &str) -> String {
+    let program = fs::read(path(name)).unwrap();
+    match compile_to_string(&program, &options(name, flags)) {
+        Ok(wat) => wat,
+        Err(errors) => {
+            let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+            panic!("{} {} doesn't compile:\n{}", name, flags, errors.join("\n"));
+        }
+    }
+}
+
+/// Each set of flags a program is compiled with, starting with none.
+pub fn flags(sets: &[&'static str]) -> Vec<&'static str> {
+    let mut flags = vec![""];
+    flags.extend_from_slice(sets);
+    flags
+}
@@ -0,0 +1,48 @@
+//! Crash reports, which have to have everything replaying them needs in
+//! them, including the files the input includes and is linked with.
+
+extern crate crenshaw;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use crenshaw::crash::Snapshot;
+use crenshaw::{compile_to_string, Options};
+
+#[test]
+fn reports_keep_every_file() {
+    let snapshot = Snapshot {
+        args: vec![String::from("main.cr"), String::from("more.cr")],
+        source: b"include \"lib.cr\"\nreturn 0\n".to_vec(),
+        files: vec![
+            (String::from("lib.cr"), b"global g\n".to_vec()),
+            (String::from("more.cr"), vec![0xff, b'\n', 0]),
+        ],
+        pass: String::from("codegen"),
+        message: String::from("oops"),
+        ast: None,
+        hir: None,
+    };
+    let path = env::temp_dir().join(format!("crenshaw-{}.crash", process::id()));
+    snapshot.save(&path).unwrap();
+    let loaded = Snapshot::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.args, snapshot.args);
+    assert_eq!(loaded.source, snapshot.source);
+    assert_eq!(loaded.files, snapshot.files);
+}
+
+#[test]
+fn saved_files_are_read_instead_of_the_disk() {
+    let args = ["nowhere/main.cr", "nowhere/more.cr"];
+    let mut options = Options::parse(args.iter().map(|&arg| String::from(arg))).unwrap();
+    options.saved_files = vec![
+        (String::from("nowhere/lib.cr"), b"func seven()\n  return 7\nend\n".to_vec()),
+        (String::from("nowhere/more.cr"), b"func eight()\n  return 8\nend\n".to_vec()),
+    ];
+
+    let wat = compile_to_string(b"include \"lib.cr\"\nprint seven() + eight()\nreturn 0\n", &options).unwrap();
+    assert!(wat.contains("(func $seven") && wat.contains("(func $eight"), "{}", wat);
+}
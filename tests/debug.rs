@@ -0,0 +1,79 @@
+//! --debug, which writes a source map with the module: each statement's
+//! instructions should map back to the line the statement's on, starting at
+//! the statement's first instruction. This needs the `debug` feature.
+
+#![cfg(feature = "debug")]
+
+extern crate crenshaw;
+
+use crenshaw::browser::BASE64_DIGITS;
+use crenshaw::debug;
+use crenshaw::json::Json;
+use crenshaw::{compile_to_string, Options};
+
+const PROGRAM: &str = "func double(n)\n  return n * 2\nend\n\ntotal = 0\nprint total\nreturn double(total)\n";
+
+/// Compiles `PROGRAM` with --debug and assembles it, as if it had been read
+/// from prog.cr. Returns the module and the source map.
+fn assemble() -> (Vec<u8>, Json) {
+    let options = Options::parse(["--debug", "prog.cr"].iter().map(|&arg| String::from(arg))).unwrap();
+    let output = compile_to_string(PROGRAM.as_bytes(), &options).unwrap();
+    let sources = [(String::from("prog.cr"), PROGRAM.as_bytes().to_vec())];
+    let (module, map) = debug::assemble(&output, &sources, "prog.wasm.map").unwrap();
+    (module, Json::parse(&map).expect("the source map is JSON"))
+}
+
+/// Decodes a source map's mappings into the offset each segment starts at,
+/// and the 0-based line it maps to, if it maps to one.
+fn segments(mappings: &str) -> Vec<(i64, Option<i64>)> {
+    let mut segments = Vec::new();
+    let mut last = [0; 4];
+    for segment in mappings.split(',') {
+        let mut fields = Vec::new();
+        let (mut value, mut shift) = (0, 0);
+        for byte in segment.bytes() {
+            let digit = BASE64_DIGITS.iter().position(|&digit| digit == byte).unwrap() as i64;
+            value |= (digit & 31) << shift;
+            shift += 5;
+            if digit & 32 == 0 {
+                fields.push(if value & 1 == 1 { -(value >> 1) } else { value >> 1 });
+                value = 0;
+                shift = 0;
+            }
+        }
+        for (i, field) in fields.iter_mut().enumerate() {
+            *field += last[i];
+            last[i] = *field;
+        }
+        segments.push((fields[0], fields.get(2).cloned()));
+    }
+    segments
+}
+
+#[test]
+fn statements_map_to_their_lines() {
+    let (module, map) = assemble();
+    let mappings = map.get("mappings").and_then(Json::as_str).unwrap();
+
+    let lines: Vec<Option<i64>> = segments(mappings).into_iter().map(|segment| segment.1).collect();
+    assert_eq!(lines, [Some(1), Some(4), Some(5), Some(6)]);
+
+    // Each statement starts with a `local.get` or an `i32.const`.
+    for (offset, _) in segments(mappings) {
+        assert!([0x20, 0x41].contains(&module[offset as usize]), "{:#x} at {}", module[offset as usize], offset);
+    }
+}
+
+#[test]
+fn the_map_has_the_source_in_it() {
+    let (_, map) = assemble();
+    assert_eq!(map.get("version"), Some(&Json::Number(3.0)));
+    assert_eq!(map.get("sources"), Some(&Json::Array(vec![Json::String(String::from("prog.cr"))])));
+    assert_eq!(map.get("sourcesContent"), Some(&Json::Array(vec![Json::String(String::from(PROGRAM))])));
+}
+
+#[test]
+fn the_module_says_where_the_map_is() {
+    let (module, _) = assemble();
+    assert!(module.ends_with(b"\x00\x1f\x10sourceMappingURL\x0dprog.wasm.map"));
+}
@@ -0,0 +1,53 @@
+//! The same program and options always compile to exactly the same output,
+//! whatever order things end up in in the compiler's hash maps, and running
+//! an optimization pass again doesn't change what it made.
+
+extern crate crenshaw;
+
+mod common;
+
+use crenshaw::{compile_to_string, Options};
+
+use common::{compile, flags, PROGRAMS};
+
+#[test]
+fn compiling_twice_gives_the_same_output() {
+    for &(name, sets) in PROGRAMS {
+        for flags in flags(sets) {
+            let first = compile(name, flags);
+            for _ in 0..3 {
+                assert!(compile(name, flags) == first, "{} {} compiled differently", name, flags);
+            }
+        }
+    }
+}
+
+#[test]
+fn optimizing_is_deterministic() {
+    for &(name, _) in PROGRAMS {
+        let first = compile(name, "-O2 --verify-passes");
+        assert!(compile(name, "-O2 --verify-passes") == first, "{} compiled differently", name);
+    }
+}
+
+#[test]
+fn every_pass_is_idempotent() {
+    // -O2 runs fold, deadcode, unreachable, and locals, each of them twice,
+    // and with --optimize-for, inline and unroll too.
+    for &(name, sets) in PROGRAMS {
+        for flags in flags(sets) {
+            for level in &["-O1", "-O2"] {
+                compile(name, &format!("{} {} --verify-passes", flags, level));
+            }
+        }
+    }
+}
+
+#[test]
+fn temporaries_sharing_a_local_stay_shared() {
+    // Both loops' limits go in `temp.0`, which the second run sees assigned
+    // twice.
+    let program = "for i = 1 to 4\n    print i\nend\nfor j = 1 to 3\n    print j\nend\nreturn 0\n";
+    let options = Options::parse(["-O1", "--verify-passes"].iter().map(|&arg| String::from(arg))).unwrap();
+    assert!(compile_to_string(program.as_bytes(), &options).is_ok());
+}
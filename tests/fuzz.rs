@@ -0,0 +1,34 @@
+//! What `compile_bytes` does with input a fuzzer might throw at it: it
+//! never panics, aborts, or exits, however deep or broken the input is.
+
+extern crate crenshaw;
+
+use crenshaw::compile_bytes;
+use crenshaw::parser::MAX_DEPTH;
+
+/// `value` in `depth` pairs of parentheses.
+fn parenthesized(depth: usize, value: &str) -> String {
+    format!("{}{}{}", "(".repeat(depth), value, ")".repeat(depth))
+}
+
+#[test]
+fn nesting_up_to_the_limit_compiles() {
+    // The assignment's expression is a level of its own.
+    let program = format!("x = {}\nreturn x\n", parenthesized(MAX_DEPTH - 1, "1"));
+    assert!(compile_bytes(program.as_bytes()).is_ok());
+}
+
+#[test]
+fn nesting_past_the_limit_is_an_error() {
+    let program = format!("x = {}\nreturn x\n", parenthesized(MAX_DEPTH + 1, "1"));
+    let err = compile_bytes(program.as_bytes()).unwrap_err();
+    assert!(err.to_string().contains("nested too deeply"), "{}", err);
+}
+
+#[test]
+fn each_if_is_one_level() {
+    let ifs = |depth: usize| format!("x = 1\n{}x = 2\n{}return x\n", "if x > 0\n".repeat(depth), "end\n".repeat(depth));
+    assert!(compile_bytes(ifs(MAX_DEPTH - 1).as_bytes()).is_ok());
+    let err = compile_bytes(ifs(MAX_DEPTH).as_bytes()).unwrap_err();
+    assert!(err.to_string().contains("the limit is 1000 levels of blocks"), "{}", err);
+}
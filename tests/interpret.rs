@@ -0,0 +1,140 @@
+//! Programs run with the interpreter, and what they should print and
+//! return.
+
+extern crate crenshaw;
+
+mod common;
+
+use std::fs;
+
+use common::{options, path};
+use crenshaw::{compile_to_string, interpret_captured, Options, Outcome};
+
+/// Runs `program` with `input` for `read`. It has to compile.
+fn run(program: &[u8], options: &Options, input: &str) -> Outcome {
+    match interpret_captured(program, options, input.as_bytes()) {
+        Ok(outcome) => outcome,
+        Err(errors) => panic!("{:?}", errors.iter().map(ToString::to_string).collect::<Vec<_>>()),
+    }
+}
+
+/// Runs a program from tests/programs, and checks that it prints `output`
+/// and returns `result`.
+fn check_program(name: &str, flags: &str, output: &str, result: i32) {
+    let program = fs::read(path(name)).unwrap();
+    let outcome = run(&program, &options(name, flags), "");
+    assert_eq!(String::from_utf8_lossy(&outcome.output), output, "{} {}", name, flags);
+    assert_eq!(outcome.result.ok(), Some(result), "{} {}", name, flags);
+}
+
+/// Runs `program`, compiled with `flags`.
+fn run_source(program: &str, flags: &str, input: &str) -> Outcome {
+    let options = Options::parse(flags.split_whitespace().map(String::from)).unwrap();
+    run(program.as_bytes(), &options, input)
+}
+
+#[test]
+fn basics() {
+    let output = "81\n610\n1973\n9\n2\n2.75\n1.4142135623730951\n1099511627776\n15\n-5\n15\n7\n5050\n39\n1\n";
+    for flags in &["", "-O2", "--checked-div", "--truthiness nonzero"] {
+        check_program("basics", flags, output, 14);
+    }
+}
+
+#[test]
+fn strings() {
+    let output = "cbafedcb\n8\nhello!\n5\nabab\n-1\n0\nhello, world\n";
+    for flags in &["", "-O2", "--rc"] {
+        check_program("strings", flags, output, 12);
+    }
+}
+
+#[test]
+fn records() {
+    let output = "9\n15\n1\n1\n";
+    for flags in &["", "-O2", "--gc", "--rc", "--bounds-check clamp"] {
+        check_program("records", flags, output, 23);
+    }
+}
+
+#[test]
+fn var_parameters() {
+    let output = "101\n5\n3\n3\n12\nhello\nHEY\n3\nhello\n";
+    for flags in &["", "-O2", "--rc", "--enable memory64"] {
+        check_program("var", flags, output, 101);
+    }
+}
+
+#[test]
+fn capturing_functions_have_no_address() {
+    let program = "func apply(f: func(i32): i32, x)\n  return f(x)\nend\n\
+                   func outer(a)\n  b = a + 1\n  func inner(x)\n    return x + b\n  end\n\
+                   return apply(@inner, 2)\nend\n\
+                   return outer(1)\n";
+    let errors = compile_to_string(program.as_bytes(), &Options::default()).unwrap_err();
+    let errors: Vec<_> = errors.iter().map(|error| error.text.as_str()).collect();
+    let error = "`inner` uses variables of `outer`, which it's nested in, so it can't be called through a pointer";
+    assert_eq!(errors, [error]);
+}
+
+#[test]
+fn read_reads_input() {
+    let outcome = run_source("read n\nread m\nprint n * m\nreturn n + m\n", "", "6\n7\n");
+    assert_eq!(outcome.output, b"42\n");
+    assert_eq!(outcome.result.ok(), Some(13));
+}
+
+#[test]
+fn halt_stops_with_its_status() {
+    let outcome = run_source("print 1\nif true\n  halt 3\nend\nprint 2\nreturn 0\n", "", "");
+    assert_eq!(outcome.output, b"1\n");
+    assert_eq!(outcome.result.ok(), Some(3));
+}
+
+#[test]
+fn failed_assertion_traps() {
+    let outcome = run_source("read n\nprint n\nassert n < 5\nreturn 1\n", "", "7\n");
+    assert_eq!(outcome.output, b"7\n");
+    let trap = outcome.result.unwrap_err();
+    assert_eq!(trap.text, "assertion failed");
+    assert_eq!(trap.location.map(|location| (location.line, location.column)), Some((3, 8)));
+}
+
+#[test]
+fn dividing_by_zero_traps() {
+    let outcome = run_source("func f(n)\n  return 10 / n\nend\nprint f(2)\nprint f(0)\n", "", "");
+    assert_eq!(outcome.output, b"5\n");
+    assert_eq!(outcome.result.unwrap_err().text, "integer divide by zero");
+}
+
+#[test]
+fn indexing_past_the_end_traps_or_clamps() {
+    let program = "array a[4]\nfor i = 0 to 3\n  a[i] = i + 1\nend\nprint a[9]\nreturn 0\n";
+    assert!(run_source(program, "--bounds-check trap", "").result.is_err());
+    assert_eq!(run_source(program, "--bounds-check clamp", "").output, b"4\n");
+
+    // GC arrays trap even without bounds checks, like they do in the engine.
+    assert!(run_source(program, "--gc --bounds-check off", "").result.is_err());
+    assert_eq!(run_source(program, "--gc --bounds-check clamp", "").output, b"4\n");
+}
+
+#[test]
+fn names_can_have_underscores_and_builtins_dots() {
+    let program = "func add_one(x_0)\n  return x_0 + 1\nend\n\
+                   _v = i32x4.add(i32x4.splat(2), i32x4.splat(add_one(3)))\n\
+                   print i32x4.extract_lane(i32x4.mul(_v, _v), 2)\n\
+                   p = alloc(16)\natomic_store(p, 40)\nprint atomic_add(p, 2)\nreturn atomic_load(p)\n";
+    let outcome = run_source(program, "--simd --threads", "");
+    assert_eq!(outcome.output, b"36\n40\n");
+    assert_eq!(outcome.result.ok(), Some(42));
+}
+
+#[test]
+fn recursion_goes_as_deep_as_an_engine_lets_it() {
+    let program = "func sum(n)\n  if n == 0\n    return 0\n  end\n  return n + sum(n - 1)\nend\nreturn sum(5000)\n";
+    assert_eq!(run_source(program, "", "").result.ok(), Some(12502500));
+
+    let outcome = run_source("func f(n)\n  return f(n + 1)\nend\nreturn f(0)\n", "", "");
+    assert_eq!(outcome.result.unwrap_err().text, "call stack exhausted");
+}
+
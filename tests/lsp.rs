@@ -0,0 +1,105 @@
+//! Go-to-definition in the language server: the editor opens a file, asks
+//! where the name under the cursor is defined, and should be sent to where
+//! it's named in its definition.
+
+extern crate crenshaw;
+
+use crenshaw::json::{quote, Json};
+use crenshaw::{lsp, Options};
+
+/// Frames a message the way the protocol does.
+fn message(body: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+/// Where `word` is on a line of `text`, as a whole name, counting lines and
+/// characters from 0 the way the protocol does. `nth` picks which time it's
+/// there on the line, from 0.
+fn position(text: &str, line: usize, word: &str, nth: usize) -> (usize, usize) {
+    let source = text.lines().nth(line).unwrap();
+    let bytes = source.as_bytes();
+    let is_name = |at: usize| bytes.get(at).is_some_and(|byte| byte.is_ascii_alphanumeric() || *byte == b'_');
+    let found = source
+        .match_indices(word)
+        .map(|(start, _)| start)
+        .filter(|&start| (start == 0 || !is_name(start - 1)) && !is_name(start + word.len()))
+        .nth(nth)
+        .unwrap_or_else(|| panic!("`{}` isn't on line {}", word, line));
+    (line, found)
+}
+
+/// Opens `text` and asks where the name at `at` is defined. Returns where
+/// the answer starts, if there is one.
+fn definition(text: &str, at: (usize, usize)) -> Option<(usize, usize)> {
+    let uri = quote("file:///tmp/definition.cr");
+    let mut input = String::new();
+    input.push_str(&message(&format!(
+        "{{\"jsonrpc\":\"2.0\",\"method\":\"textDocument/didOpen\",\"params\":{{\"textDocument\":\
+         {{\"uri\":{},\"languageId\":\"crenshaw\",\"version\":1,\"text\":{}}}}}}}",
+        uri,
+        quote(text)
+    )));
+    input.push_str(&message(&format!(
+        "{{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"textDocument/definition\",\"params\":\
+         {{\"textDocument\":{{\"uri\":{}}},\"position\":{{\"line\":{},\"character\":{}}}}}}}",
+        uri, at.0, at.1
+    )));
+    input.push_str(&message("{\"jsonrpc\":\"2.0\",\"method\":\"exit\"}"));
+
+    let mut output = Vec::new();
+    lsp::run(&Options::default(), &mut input.as_bytes(), &mut output);
+
+    // The answer's the last message; the ones before it are diagnostics.
+    let output = String::from_utf8(output).unwrap();
+    let body = &output[output.rfind("\r\n\r\n").unwrap() + 4..];
+    let reply = Json::parse(body).unwrap();
+    let start = reply.get("result")?.get("range")?.get("start")?;
+    Some((start.get("line")?.as_usize()?, start.get("character")?.as_usize()?))
+}
+
+#[test]
+fn parameters_go_to_where_they_are_named() {
+    let text = "func area(width, height)\n  return width * height\nend\nprint area(2, 3)\n";
+    let used = position(text, 1, "height", 0);
+    assert_eq!(definition(text, used), Some(position(text, 0, "height", 0)));
+    let used = position(text, 1, "width", 0);
+    assert_eq!(definition(text, used), Some(position(text, 0, "width", 0)));
+}
+
+#[test]
+fn locals_assigned_in_a_case_are_found() {
+    let text = "read n\ncase n\nwhen 1\n  x = 10\nelse\n  y = 20\nend\nprint x\nprint y\n";
+    assert_eq!(definition(text, position(text, 7, "x", 0)), Some(position(text, 3, "x", 0)));
+    assert_eq!(definition(text, position(text, 8, "y", 0)), Some(position(text, 5, "y", 0)));
+}
+
+#[test]
+fn nested_functions_and_what_they_capture_are_found() {
+    let text = "func outer(n)\n  total = 0\n  func add(k)\n    total = total + k + n\n    return total\n  end\n  \
+                first = add(1)\n  return add(first)\nend\nprint outer(5)\n";
+    assert_eq!(definition(text, position(text, 7, "add", 0)), Some(position(text, 2, "add", 0)));
+    assert_eq!(definition(text, position(text, 3, "k", 0)), Some(position(text, 2, "k", 0)));
+    assert_eq!(definition(text, position(text, 3, "n", 0)), Some(position(text, 0, "n", 0)));
+    assert_eq!(definition(text, position(text, 3, "total", 1)), Some(position(text, 1, "total", 0)));
+}
+
+#[test]
+fn records_and_their_fields_are_found() {
+    let text = "record Point\n  x: i32\n  y: i32\nend\nfunc sum(p: Point)\n  return p.x + p.y\nend\n\
+                p = Point(alloc(sizeof(Point)))\nprint sum(p)\n";
+    assert_eq!(definition(text, position(text, 4, "Point", 0)), Some(position(text, 0, "Point", 0)));
+    assert_eq!(definition(text, position(text, 7, "Point", 0)), Some(position(text, 0, "Point", 0)));
+    assert_eq!(definition(text, position(text, 7, "Point", 1)), Some(position(text, 0, "Point", 0)));
+    assert_eq!(definition(text, position(text, 5, "y", 0)), Some(position(text, 2, "y", 0)));
+    assert_eq!(definition(text, position(text, 5, "p", 0)), Some(position(text, 4, "p", 0)));
+}
+
+#[test]
+fn enums_and_their_variants_are_found() {
+    let text = "enum Shape\n  square\n  circle\nend\nrecord Item\n  shape: Shape\nend\n\
+                item = Item(alloc(sizeof(Item)))\nitem.shape = Shape.circle\nprint 0\n";
+    assert_eq!(definition(text, position(text, 5, "Shape", 0)), Some(position(text, 0, "Shape", 0)));
+    assert_eq!(definition(text, position(text, 8, "Shape", 0)), Some(position(text, 0, "Shape", 0)));
+    assert_eq!(definition(text, position(text, 8, "circle", 0)), Some(position(text, 2, "circle", 0)));
+    assert_eq!(definition(text, position(text, 8, "shape", 0)), Some(position(text, 5, "shape", 0)));
+}
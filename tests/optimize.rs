@@ -0,0 +1,52 @@
+//! What the optimizations that ask the cost model do with it: for size,
+//! they only make the code smaller, and for speed, they make it faster as
+//! long as it doesn't get much bigger.
+
+extern crate crenshaw;
+
+use crenshaw::{compile_to_string, Options};
+
+/// Compiles `program` at -O2, optimizing for `target`.
+fn compile(program: &str, target: &str) -> String {
+    let args = ["-O2", "--verify-passes", "--optimize-for", target];
+    let options = Options::parse(args.iter().map(|&arg| String::from(arg))).unwrap();
+    compile_to_string(program.as_bytes(), &options).unwrap()
+}
+
+#[test]
+fn small_functions_are_inlined() {
+    let program = "func id(n)\n  return n\nend\nfunc square(n)\n  return n * n\nend\nx = 3\n\
+                   print id(x)\nprint square(x)\nreturn 0\n";
+
+    // Calling `id` takes more bytes than reading `x`, but calling `square`
+    // takes fewer than multiplying.
+    let size = compile(program, "size");
+    assert!(!size.contains("(call $id)"), "{}", size);
+    assert!(size.contains("(call $square)"), "{}", size);
+
+    let speed = compile(program, "speed");
+    assert!(!speed.contains("(call $id)") && !speed.contains("(call $square)"), "{}", speed);
+}
+
+#[test]
+fn recursive_functions_arent_inlined() {
+    let program = "func down(n)\n  return n > 0 ? down(n - 1) : 0\nend\nprint down(3)\nreturn 0\n";
+    assert!(compile(program, "speed").contains("(call $down)"));
+}
+
+#[test]
+fn short_loops_are_unrolled_for_speed() {
+    let program = "for i = 1 to 4\n  print i\nend\nprint i\nreturn 0\n";
+    assert!(compile(program, "size").contains("(loop"));
+    assert!(!compile(program, "speed").contains("(loop"));
+
+    // Too many copies of the body would make too much code.
+    let program = "for i = 1 to 1000\n  print i\nend\nreturn 0\n";
+    assert!(compile(program, "speed").contains("(loop"));
+}
+
+#[test]
+fn loops_that_change_their_counter_arent_unrolled() {
+    let program = "for i = 1 to 4\n  print i\n  i = i + 1\nend\nreturn 0\n";
+    assert!(compile(program, "speed").contains("(loop"));
+}
@@ -0,0 +1,30 @@
+//! Errors the parser recovers from, and what it still finds after them.
+
+extern crate crenshaw;
+
+use crenshaw::{compile_to_string, Options};
+
+/// Compiles `program`, which shouldn't compile, and returns the text of
+/// each error.
+fn errors(program: &str) -> Vec<String> {
+    match compile_to_string(program.as_bytes(), &Options::default()) {
+        Ok(_) => panic!("expected errors compiling:\n{}", program),
+        Err(errors) => errors.into_iter().map(|error| error.text).collect(),
+    }
+}
+
+#[test]
+fn unclosed_string_reports_later_errors() {
+    let errors = errors("func main()\n  print \"abc\n  x = 1 +\n  return 0\nend\n");
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert_eq!(errors[0], "string is never closed");
+    assert!(errors[1].starts_with("expected "), "{:?}", errors);
+}
+
+#[test]
+fn unclosed_block_comment_reports_later_errors() {
+    let errors = errors("func main()\n  /* abc\n  x = 1 +\n  return 0\nend\n");
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert_eq!(errors[0], "block comment is never closed");
+    assert!(errors[1].starts_with("expected "), "{:?}", errors);
+}
@@ -0,0 +1,99 @@
+// Runs a program compiled by crenshaw, from the .wasm file next to this, or
+// from the module itself in a data URL. The compiler writes the file too if
+// it was built with the `assemble` feature; otherwise, assemble the WAT it
+// prints, with `wat2wasm prog.wat`.
+(() => {
+  // Strings are read out of the module's memory, which only exists once it's
+  // instantiated.
+  let memory;
+
+  // What `exit` throws to stop the program, since wasm can't be stopped
+  // any other way from outside.
+  class Exit {
+    constructor(status) {
+      this.status = status;
+    }
+  }
+
+  // What `print` and `read` call. The module only imports the ones it uses.
+  const env = {
+    read: () => Number(prompt('read')) | 0,
+    random: () => Math.floor(Math.random() * 2 ** 31),
+    // i64s have to be BigInts.
+    now: () => BigInt(Math.floor(performance.now())),
+    print: value => console.log(value),
+    // i64s arrive as BigInts, which would print with an `n` on the end.
+    print_i64: value => console.log(String(value)),
+    print_f64: value => console.log(value),
+    // With --checked-div, called just before the module traps.
+    divide_by_zero: (line, column) => console.error(`integer divide by zero at line ${line}, column ${column}`),
+    // Called just before the module traps because an `assert` failed.
+    assertion_failed: (line, column) => console.error(`assertion failed at line ${line}, column ${column}`),
+    // Called by `halt`, which stops the program with a status.
+    exit: status => {
+      throw new Exit(status);
+    },
+    // With --metering, called just before the module traps.
+    out_of_fuel: () => console.error('out of fuel'),
+    // With --threads, memory is shared, which TextDecoder won't read from,
+    // so the bytes are copied out of it first.
+    print_str: (ptr, len) => {
+      const bytes = new Uint8Array(memory.buffer, ptr, len).slice();
+      console.log(new TextDecoder().decode(bytes));
+    },
+  };
+
+  const url = 'basics.wasm';
+  fetch(url)
+    .then(response => {
+      if (!response.ok) {
+        const hint = 'assemble the WAT the compiler printed into it, with wat2wasm';
+        throw new Error('could not load ' + url + ' (' + response.status + '): ' + hint);
+      }
+      return response.arrayBuffer();
+    })
+    .then(bytes => WebAssembly.instantiate(bytes, { env }))
+    .then(wasm => {
+      const exports = wasm.instance.exports;
+      memory = exports.memory;
+
+      // Runs the program's `on <name>` handler, if it has one. Try
+      // crenshaw.emit('click', 1) from the console.
+      window.crenshaw = {
+        emit(name, payload = 0) {
+          const event = exports['event:' + name];
+          if (event === undefined) {
+            throw new Error('no handler for event ' + name);
+          }
+          return exports.dispatch(event.value, payload);
+        },
+      };
+
+      // Hosts that asked for --no-result get undefined back. A program
+      // that halts has its status as its result.
+      let result;
+      try {
+        result = exports['main']();
+      } catch (error) {
+        if (!(error instanceof Exit)) {
+          throw error;
+        }
+        result = error.status;
+      }
+      if (result !== undefined) {
+        console.log(result);
+      }
+
+      // With --profile, says which functions and loops were busiest.
+      const counts = Object.keys(exports)
+        .filter(name => name.startsWith('profile:'))
+        .map(name => [name.slice('profile:'.length), exports[name].value >>> 0])
+        .filter(([, count]) => count > 0)
+        .sort((a, b) => b[1] - a[1] || a[0].localeCompare(b[0]));
+      const total = counts.reduce((sum, [, count]) => sum + count, 0);
+      if (counts.length > 0) {
+        const lines = counts.map(([label, count]) => `${count} ${(100 * count / total).toFixed(1)}%  ${label}`);
+        console.info('profile:\n' + lines.join('\n'));
+      }
+    });
+})();
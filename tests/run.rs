@@ -0,0 +1,202 @@
+//! Running the output with wasmtime, which needs the `run` feature: every
+//! program in tests/programs should do exactly what the interpreter does
+//! with it, the host functions should say why a module trapped, and
+//! --verify should say how the two differ when they do.
+
+#![cfg(feature = "run")]
+
+extern crate crenshaw;
+
+mod common;
+
+use std::fs;
+use std::io;
+
+use common::{compile, flags, options, path, PROGRAMS};
+use crenshaw::run::{run, verify, Run, Unverified};
+use crenshaw::{compile_to_string, interpret_captured, Options};
+
+/// Runs a module with `input` for `read`. It has to load.
+fn run_wat(wat: &str, options: &Options, input: &str) -> Run<Vec<u8>> {
+    let input = io::Cursor::new(input.as_bytes().to_vec());
+    run(wat, options, input, Vec::new()).unwrap()
+}
+
+/// Compiles `program` with `flags` and runs it.
+fn run_source(program: &str, flags: &str, input: &str) -> Run<Vec<u8>> {
+    let options = Options::parse(flags.split_whitespace().map(String::from)).unwrap();
+    let wat = compile_to_string(program.as_bytes(), &options).map_err(|_| "doesn't compile").unwrap();
+    run_wat(&wat, &options, input)
+}
+
+#[test]
+fn output_does_what_the_interpreter_does() {
+    for &(name, sets) in PROGRAMS {
+        for flags in flags(sets) {
+            let options = options(name, flags);
+            let ran = run_wat(&compile(name, flags), &options, "");
+            let interpreted = interpret_captured(&fs::read(path(name)).unwrap(), &options, &b""[..]).ok().unwrap();
+
+            assert_eq!(ran.output, interpreted.output, "{} {}", name, flags);
+            if !options.no_result {
+                assert_eq!(ran.result, Ok(interpreted.result.ok()), "{} {}", name, flags);
+            }
+        }
+    }
+}
+
+#[test]
+fn read_reads_input() {
+    let ran = run_source("read n\nread m\nprint n * m\nreturn n + m\n", "", "6\n7\n");
+    assert_eq!(ran.output, b"42\n");
+    assert_eq!(ran.result, Ok(Some(13)));
+    assert_eq!(run_source("read n\nreturn n\n", "", "").result, Err(String::from("there's nothing left to read")));
+}
+
+#[test]
+fn halt_in_the_browser_stops_with_its_status() {
+    let ran = run_source("print 1\nif true\n  halt 3\nend\nprint 2\nreturn 0\n", "--target browser", "");
+    assert_eq!(ran.output, b"1\n");
+    assert_eq!(ran.result, Ok(Some(3)));
+}
+
+#[test]
+fn traps_say_why() {
+    let ran = run_source("read n\nprint n\nassert n < 5\nreturn 1\n", "", "7\n");
+    assert_eq!(ran.output, b"7\n");
+    assert_eq!(ran.result, Err(String::from("assertion failed at line 3, column 8")));
+
+    let program = "func f(n)\n  return 10 / n\nend\nprint f(2)\nprint f(0)\n";
+    let ran = run_source(program, "--checked-div", "");
+    assert_eq!(ran.output, b"5\n");
+    assert_eq!(ran.result, Err(String::from("integer divide by zero at line 2, column 10")));
+
+    let ran = run_source("while true\nend\n", "--metering", "");
+    assert_eq!(ran.result, Err(String::from("out of fuel")));
+}
+
+#[test]
+fn verify_gives_back_what_the_module_did() {
+    let program = "read n\nprint n * 2\nreturn n + 1\n";
+    let wat = compile_to_string(program.as_bytes(), &Options::default()).ok().unwrap();
+    let ran = verify(program.as_bytes(), &wat, &Options::default(), &mut "4\n".as_bytes()).unwrap();
+    assert_eq!(ran.output, b"8\n");
+    assert_eq!(ran.result, Ok(Some(5)));
+}
+
+#[test]
+fn verify_says_how_they_differ() {
+    // The module's from a different program, so it's as if code generation
+    // got it wrong.
+    let program = b"print 1\nprint 2\nreturn 3\n";
+    let wat = compile_to_string(b"print 1\nprint 5\nassert false\nreturn 3\n", &Options::default()).ok().unwrap();
+    let msg = verify(program, &wat, &Options::default(), &mut &b""[..]).err().unwrap();
+    assert_eq!(
+        msg,
+        Unverified::Differs(String::from(
+            "on line 2 of the output, the interpreter printed `2` and the module printed `5`; \
+             the interpreter returned 3 and the module trapped (assertion failed at line 3, column 8)"
+        ))
+    );
+}
+
+#[test]
+fn verify_cant_compare_once_either_runs_out_of_stack() {
+    let program = b"func f(n)\n  return f(n + 1)\nend\nreturn f(0)\n";
+    let wat = compile_to_string(program, &Options::default()).ok().unwrap();
+    assert_eq!(run_wat(&wat, &Options::default(), "").result, Err(String::from("call stack exhausted")));
+    let unverified = verify(program, &wat, &Options::default(), &mut &b""[..]).err().unwrap();
+    assert_eq!(unverified, Unverified::OutOfStack("the interpreter"));
+}
+
+#[test]
+fn wasi_prints_f64s_like_the_host() {
+    // Powers of two, which are the only f64s closer to the one below than
+    // the one above, tenths, which don't have short binary forms, and the
+    // smallest ones, which don't have all their bits.
+    let program = "v = 1.0\nfor i = 1 to 1023\n  v = v * 2.0\n  print v\n  print -v * 3.0\nend\n\
+                   v = 1.0\nfor i = 1 to 1074\n  v = v / 2.0\n  print v\n  print v * 0.75\nend\n\
+                   v = 0.1\nfor i = 1 to 330\n  v = v / 10.0\n  print v\nend\n\
+                   print 0.0\nprint -0.0\nprint 0.0 / 0.0\nprint -1.0 / 0.0\nprint 1.0 / 3.0\nreturn 0\n";
+    let options = Options::parse(["--target", "wasi"].iter().map(|&arg| String::from(arg))).unwrap();
+    let wat = compile_to_string(program.as_bytes(), &options).ok().unwrap();
+    let ran = verify(program.as_bytes(), &wat, &options, &mut &b""[..]).unwrap();
+    let output = String::from_utf8(ran.output).unwrap();
+    assert!(output.ends_with("\n0\n-0\nnan\n-inf\n0.3333333333333333\n"), "{}", output);
+    assert!(output.contains(&format!("\n0.{}5\n", "0".repeat(323))), "{}", output);
+}
+
+#[test]
+fn gc_arrays_trap_without_bounds_checks_in_both() {
+    let program = "array a[4]\nprint a[9]\nreturn 0\n";
+    let options = Options::parse(["--gc", "--bounds-check", "off"].iter().map(|&arg| String::from(arg))).unwrap();
+    let wat = compile_to_string(program.as_bytes(), &options).ok().unwrap();
+    let ran = verify(program.as_bytes(), &wat, &options, &mut &b""[..]).unwrap();
+    assert!(ran.result.is_err());
+}
+
+#[test]
+fn gc_null_records_trap_in_both() {
+    let options = Options::parse(["--gc"].iter().map(|&arg| String::from(arg))).unwrap();
+    let record = "record P\n  x: i32\nend\np = P(0)\n";
+    for access in ["return p.x\n", "p.x = 1\nreturn 0\n"] {
+        let program = format!("{}{}", record, access);
+        let wat = compile_to_string(program.as_bytes(), &options).ok().unwrap();
+        let ran = verify(program.as_bytes(), &wat, &options, &mut &b""[..]).unwrap();
+        assert!(ran.result.is_err(), "{}", program);
+        let interpreted = interpret_captured(program.as_bytes(), &options, &b""[..]).ok().unwrap();
+        assert_eq!(interpreted.result.err().map(|trap| trap.text), Some(String::from("null reference")));
+    }
+}
+
+#[test]
+fn memory64_addresses_dont_wrap_at_4_gib() {
+    // Index 2^30 is 4 GiB past the array, which is past the end of memory
+    // rather than back at the array's start.
+    let options = Options::parse(["--enable", "memory64"].iter().map(|&arg| String::from(arg))).unwrap();
+    for program in ["array a[4]\na[0] = 7\nprint a[1073741824]\nreturn 0\n", "print mem[4294967296i64]\nreturn 0\n"] {
+        let wat = compile_to_string(program.as_bytes(), &options).ok().unwrap();
+        let ran = verify(program.as_bytes(), &wat, &options, &mut &b""[..]).unwrap();
+        assert!(ran.result.is_err(), "{}", program);
+    }
+}
+
+#[test]
+fn records_are_freed_with_rc() {
+    // Each chain is freed once the next one's made, so the heap only ever
+    // has two, where without --rc, it has them all.
+    let program = "record Node\n  next: Node\n  name: str\nend\n\
+                   func chain(n): Node\n  head = Node(0)\n  for i = 1 to n\n    node = Node(alloc(sizeof(Node)))\n\
+                   node.next = head\n    node.name = concat(\"x\", \"y\")\n    head = node\n  end\n  return head\nend\n\
+                   first = alloc(4)\nfor i = 1 to 100\n  c = chain(10)\nend\n\
+                   print c.next.name\nreturn alloc(4) - first\n";
+    let kept = |flags: &str| match run_source(program, flags, "").result {
+        Ok(Some(kept)) => kept,
+        result => panic!("{:?}", result),
+    };
+    assert!(kept("--rc") < 1000, "{}", kept("--rc"));
+    assert!(kept("--rc --enable memory64") < 1000);
+    assert!(kept("") > 10000);
+
+    let options = Options::parse(["--rc"].iter().map(|&arg| String::from(arg))).unwrap();
+    let errors = compile_to_string(b"record P\n  x: i32\nend\np = P(64)\nreturn p.x\n", &options).err().unwrap();
+    assert_eq!(errors[0].text, "with --rc, records are counted, so this has to be `P(alloc(...))` or `P(0)`");
+}
+
+#[test]
+fn programs_can_use_the_names_the_compiler_does() {
+    // The heap's globals, a `var` argument's frame, and the dispatcher.
+    let program = "global heap = 5\nfunc dispatch(var stack)\n  stack += heap\n  return 0\nend\n\
+                   s = concat(\"a\", \"b\")\nframe = len(s)\ndispatch(frame)\nreturn frame\n";
+    for flags in &["", "--rc"] {
+        assert_eq!(run_source(program, flags, "").result, Ok(Some(7)), "{}", flags);
+    }
+}
+
+#[test]
+fn profile_counters_are_read_back() {
+    let options = options("basics", "--profile");
+    let ran = run_wat(&compile("basics", "--profile"), &options, "");
+    assert!(ran.profile.contains(&(String::from("fib"), 1973)), "{:?}", ran.profile);
+    assert!(ran.profile.contains(&(String::from("(main)"), 1)), "{:?}", ran.profile);
+}
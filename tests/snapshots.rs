@@ -0,0 +1,47 @@
+//! The WAT each program in tests/programs compiles to, with each set of
+//! flags, checked against what's in tests/snapshots. When the output is
+//! meant to change, run the tests with UPDATE_SNAPSHOTS=1 to write the new
+//! output over the old, and look over the diff.
+
+extern crate crenshaw;
+
+mod common;
+
+use std::env;
+use std::fs;
+
+use common::{compile, flags, PROGRAMS};
+
+/// Where the snapshot of `name` compiled with `flags` goes: the flags are
+/// in the name, with the dashes in front taken off and spaces made dashes.
+fn snapshot_path(name: &str, flags: &str) -> String {
+    if flags.is_empty() {
+        return format!("tests/snapshots/{}.wat", name);
+    }
+    let flags: Vec<&str> = flags.split_whitespace().map(|flag| flag.trim_start_matches('-')).collect();
+    format!("tests/snapshots/{}.{}.wat", name, flags.join("-"))
+}
+
+#[test]
+fn output_matches_snapshots() {
+    let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+    let mut changed = Vec::new();
+
+    for &(name, sets) in PROGRAMS {
+        for flags in flags(sets) {
+            let wat = compile(name, flags);
+            let path = snapshot_path(name, flags);
+            if update {
+                fs::write(&path, &wat).unwrap();
+            } else if fs::read_to_string(&path).ok().as_ref() != Some(&wat) {
+                changed.push(path);
+            }
+        }
+    }
+
+    assert!(
+        changed.is_empty(),
+        "the output doesn't match these snapshots (run with UPDATE_SNAPSHOTS=1 to update them):\n{}",
+        changed.join("\n")
+    );
+}
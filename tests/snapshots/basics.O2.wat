@@ -0,0 +1,302 @@
+(module
+(import "env" "print" (func $print (param i32)))
+(import "env" "print_i64" (func $print_i64 (param i64)))
+(import "env" "print_f64" (func $print_f64 (param f64)))
+(global $calls (mut i64) (i64.const 0))
+(global $var.stack (mut i32) (i32.const 65576))
+(func $fib (param $n i32) (result i32)
+(global.set $calls
+(global.get $calls)
+(i64.const 1)
+(i64.add)
+)
+(local.get $n)
+(i32.const 2)
+(i32.lt_s)
+(if
+(then
+(local.get $n)
+(return)
+)
+)
+(local.get $n)
+(i32.const 1)
+(i32.sub)
+(call $fib)
+(local.get $n)
+(i32.const 2)
+(i32.sub)
+(call $fib)
+(i32.add)
+(return)
+(i32.const 0)
+(return)
+)
+(func $divmod (param $a i32) (param $b i32) (result i32 i32)
+(local.get $a)
+(local.get $b)
+(i32.div_s)
+(local.get $a)
+(local.get $b)
+(i32.rem_s)
+(return)
+(i32.const 0)
+(i32.const 0)
+(return)
+)
+(func $mean (param $a f64) (param $b f64) (result f64)
+(local.get $a)
+(local.get $b)
+(f64.add)
+(f64.const 2.0)
+(f64.div)
+(return)
+(f64.const 0)
+(return)
+)
+(func $sum (param $n i32) (result i32)
+(local $var.frame i32)
+(local $last i32)
+(local $i i32)
+(local $temp.0 i32)
+(global.get $var.stack)
+(i32.const 48)
+(i32.lt_u)
+(if
+(then
+(unreachable)
+)
+)
+(global.get $var.stack)
+(i32.const 8)
+(i32.sub)
+(global.set $var.stack)
+(global.get $var.stack)
+(local.set $var.frame)
+(local.get $var.frame)
+(i64.const 0)
+(i64.store)
+(local.get $var.frame)
+(i32.const 0)
+(i32.store)
+(local.set $last
+(i32.const 0)
+)
+(local.set $i
+(i32.const 1)
+)
+(local.set $temp.0
+(local.get $n)
+)
+(block
+(loop
+(local.get $i)
+(local.get $temp.0)
+(i32.le_s)
+(i32.eqz)
+(br_if 1)
+(local.set $last
+(local.get $i)
+(local.get $var.frame)
+(call $sum.add)
+)
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(local.get $last)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+(return)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+(i32.const 0)
+(return)
+)
+(func $sum.add (param $k i32) (param $total i32) (result i32)
+(local.get $total)
+(local.get $total)
+(i32.load)
+(local.get $k)
+(i32.add)
+(i32.store)
+(local.get $total)
+(i32.load)
+(return)
+(i32.const 0)
+(return)
+)
+(func $grade (param $n i32) (result i32)
+(local $case.i32 i32)
+(local.get $n)
+(local.set $case.i32)
+(local.get $case.i32)
+(i32.const 1)
+(i32.eq)
+(local.get $case.i32)
+(i32.const 2)
+(i32.eq)
+(i32.or)
+(if
+(then
+(i32.const 10)
+(return)
+)
+(else
+(local.get $case.i32)
+(i32.const 3)
+(i32.eq)
+(if
+(then
+(i32.const 30)
+(return)
+)
+(else
+(i32.const -1)
+(return)
+)
+)
+)
+)
+(i32.const 0)
+(return)
+)
+(func $main (result i32)
+(local $i i32)
+(local $temp.0 i32)
+(local $result.i32.1 i32)
+(local $result.i32.0 i32)
+(local $q i32)
+(local $r i32)
+(local $steps i32)
+(local.set $i
+(i32.const 0)
+)
+(local.set $temp.0
+(i32.const 9)
+)
+(block
+(loop
+(local.get $i)
+(local.get $temp.0)
+(i32.le_s)
+(i32.eqz)
+(br_if 1)
+(local.get $i)
+(i32.const 4)
+(i32.mul)
+(local.get $i)
+(local.get $i)
+(i32.mul)
+(i32.store)
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(i32.const 9)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(call $print)
+(i32.const 15)
+(call $fib)
+(call $print)
+(global.get $calls)
+(call $print_i64)
+(i32.const 47)
+(i32.const 5)
+(call $divmod)
+(local.set $result.i32.1)
+(local.set $result.i32.0)
+(local.set $q
+(local.get $result.i32.0)
+)
+(local.set $r
+(local.get $result.i32.1)
+)
+(local.get $q)
+(call $print)
+(local.get $r)
+(call $print)
+(f64.const 1.5)
+(f64.const 4.0)
+(call $mean)
+(call $print_f64)
+(f64.const 2.0)
+(f64.sqrt)
+(call $print_f64)
+(i64.const 1099511627776)
+(call $print_i64)
+(i32.const 15)
+(call $print)
+(i32.const -5)
+(call $print)
+(i32.const 15)
+(call $print)
+(local.set $i
+(i32.const 0)
+)
+(local.set $steps
+(i32.const 0)
+)
+(block
+(loop
+(local.get $i)
+(i32.const 100)
+(i32.lt_s)
+(i32.eqz)
+(br_if 1)
+(local.set $i
+(local.get $i)
+(i32.const 2)
+(i32.mul)
+(i32.const 1)
+(i32.add)
+)
+(local.set $steps
+(local.get $steps)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(local.get $steps)
+(call $print)
+(i32.const 100)
+(call $sum)
+(call $print)
+(i32.const 2)
+(call $grade)
+(i32.const 3)
+(call $grade)
+(i32.add)
+(i32.const 7)
+(call $grade)
+(i32.add)
+(call $print)
+(i32.const 1)
+(call $print)
+(local.get $steps)
+(i32.const 2)
+(i32.mul)
+(return)
+(i32.const 0)
+(return)
+)
+(memory 2)
+(export "memory" (memory 0))
+(export "main" (func $main))
+)
@@ -0,0 +1,365 @@
+(module
+(import "env" "print" (func $print (param i32)))
+(import "env" "print_i64" (func $print_i64 (param i64)))
+(import "env" "print_f64" (func $print_f64 (param f64)))
+(global $calls (mut i64) (i64.const 0))
+(global $var.stack (mut i32) (i32.const 65576))
+(func $fib (param $n i32) (result i32)
+;; line 9: calls += 1i64
+(global.set $calls
+(global.get $calls)
+(i64.const 1)
+(i64.add)
+)
+;; line 10: if n < 2
+(local.get $n)
+(i32.const 2)
+(i32.lt_s)
+(if
+(then
+;; line 11: return n
+(local.get $n)
+(return)
+)
+)
+;; line 13: return fib(n - 1) + fib(n - 2)
+(local.get $n)
+(i32.const 1)
+(i32.sub)
+(call $fib)
+(local.get $n)
+(i32.const 2)
+(i32.sub)
+(call $fib)
+(i32.add)
+(return)
+(i32.const 0)
+(return)
+)
+(func $divmod (param $a i32) (param $b i32) (result i32 i32)
+;; line 17: return a / b, a % b
+(local.get $a)
+(local.get $b)
+(i32.div_s)
+(local.get $a)
+(local.get $b)
+(i32.rem_s)
+(return)
+(i32.const 0)
+(i32.const 0)
+(return)
+)
+(func $mean (param $a f64) (param $b f64) (result f64)
+;; line 21: return (a + b) / 2.0
+(local.get $a)
+(local.get $b)
+(f64.add)
+(f64.const 2.0)
+(f64.div)
+(return)
+(f64.const 0)
+(return)
+)
+(func $sum (param $n i32) (result i32)
+(local $var.frame i32)
+(local $last i32)
+(local $i i32)
+(local $for.limit.0 i32)
+(global.get $var.stack)
+(i32.const 48)
+(i32.lt_u)
+(if
+(then
+(unreachable)
+)
+)
+(global.get $var.stack)
+(i32.const 8)
+(i32.sub)
+(global.set $var.stack)
+(global.get $var.stack)
+(local.set $var.frame)
+(local.get $var.frame)
+(i64.const 0)
+(i64.store)
+;; line 25: total = 0
+(local.get $var.frame)
+(i32.const 0)
+(i32.store)
+;; line 30: last = 0
+(local.set $last
+(i32.const 0)
+)
+;; line 31: for i = 1 to n
+(local.set $i
+(i32.const 1)
+)
+(local.set $for.limit.0
+(local.get $n)
+)
+(block
+(loop
+(local.get $i)
+(local.get $for.limit.0)
+(i32.le_s)
+(i32.eqz)
+(br_if 1)
+;; line 32: last = add(i)
+(local.set $last
+(local.get $i)
+(local.get $var.frame)
+(call $sum.add)
+)
+;; line 31: for i = 1 to n
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+;; line 34: return last
+(local.get $last)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+(return)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+(i32.const 0)
+(return)
+)
+(func $sum.add (param $k i32) (param $total i32) (result i32)
+;; line 27: total += k
+(local.get $total)
+(local.get $total)
+(i32.load)
+(local.get $k)
+(i32.add)
+(i32.store)
+;; line 28: return total
+(local.get $total)
+(i32.load)
+(return)
+(i32.const 0)
+(return)
+)
+(func $grade (param $n i32) (result i32)
+(local $case.i32 i32)
+;; line 38: case n
+(local.get $n)
+(local.set $case.i32)
+(local.get $case.i32)
+(i32.const 1)
+(i32.eq)
+(local.get $case.i32)
+(i32.const 2)
+(i32.eq)
+(i32.or)
+(if
+(then
+;; line 40: return 10
+(i32.const 10)
+(return)
+)
+(else
+(local.get $case.i32)
+(i32.const 3)
+(i32.eq)
+(if
+(then
+;; line 42: return 30
+(i32.const 30)
+(return)
+)
+(else
+;; line 44: return -1
+(i32.const -1)
+(return)
+)
+)
+)
+)
+(i32.const 0)
+(return)
+)
+(func $main (result i32)
+(local $i i32)
+(local $for.limit.1 i32)
+(local $result.i32.1 i32)
+(local $result.i32.0 i32)
+(local $q i32)
+(local $r i32)
+(local $steps i32)
+;; line 48: for i = 0 to LIMIT - 1
+(local.set $i
+(i32.const 0)
+)
+(local.set $for.limit.1
+(i32.const 10)
+(i32.const 1)
+(i32.sub)
+)
+(block
+(loop
+(local.get $i)
+(local.get $for.limit.1)
+(i32.le_s)
+(i32.eqz)
+(br_if 1)
+;; line 49: squares[i] = i * i
+(local.get $i)
+(i32.const 4)
+(i32.mul)
+(local.get $i)
+(local.get $i)
+(i32.mul)
+(i32.store)
+;; line 48: for i = 0 to LIMIT - 1
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+;; line 51: print squares[LIMIT - 1]
+(i32.const 10)
+(i32.const 1)
+(i32.sub)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(call $print)
+;; line 53: print fib(15)
+(i32.const 15)
+(call $fib)
+(call $print)
+;; line 54: print calls
+(global.get $calls)
+(call $print_i64)
+;; line 56: q, r = divmod(47, 5)
+(i32.const 47)
+(i32.const 5)
+(call $divmod)
+(local.set $result.i32.1)
+(local.set $result.i32.0)
+(local.set $q
+(local.get $result.i32.0)
+)
+(local.set $r
+(local.get $result.i32.1)
+)
+;; line 57: print q
+(local.get $q)
+(call $print)
+;; line 58: print r
+(local.get $r)
+(call $print)
+;; line 60: print mean(1.5, 4.0)
+(f64.const 1.5)
+(f64.const 4.0)
+(call $mean)
+(call $print_f64)
+;; line 61: print sqrt(2.0)
+(f64.const 2.0)
+(f64.sqrt)
+(call $print_f64)
+;; line 62: print 1i64 << 40
+(i64.const 1)
+(i64.const 40)
+(i64.shl)
+(call $print_i64)
+;; line 63: print 0xff & 0b1010 | 0o7
+(i32.const 255)
+(i32.const 10)
+(i32.and)
+(i32.const 7)
+(i32.or)
+(call $print)
+;; line 64: print -17 >> 2
+(i32.const -17)
+(i32.const 2)
+(i32.shr_s)
+(call $print)
+;; line 65: print -17 >>> 28
+(i32.const -17)
+(i32.const 28)
+(i32.shr_u)
+(call $print)
+;; line 67: i = 0
+(local.set $i
+(i32.const 0)
+)
+;; line 68: steps = 0
+(local.set $steps
+(i32.const 0)
+)
+;; line 69: while i < 100
+(block
+(loop
+(local.get $i)
+(i32.const 100)
+(i32.lt_s)
+(i32.eqz)
+(br_if 1)
+;; line 70: i = i * 2 + 1
+(local.set $i
+(local.get $i)
+(i32.const 2)
+(i32.mul)
+(i32.const 1)
+(i32.add)
+)
+;; line 71: steps++
+(local.set $steps
+(local.get $steps)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+;; line 73: print steps
+(local.get $steps)
+(call $print)
+;; line 75: print sum(100)
+(i32.const 100)
+(call $sum)
+(call $print)
+;; line 76: print grade(2) + grade(3) + grade(7)
+(i32.const 2)
+(call $grade)
+(i32.const 3)
+(call $grade)
+(i32.add)
+(i32.const 7)
+(call $grade)
+(i32.add)
+(call $print)
+;; line 77: print LIMIT > 5 ? 1 : 0
+(select
+(i32.const 1)
+(i32.const 0)
+(i32.const 10)
+(i32.const 5)
+(i32.gt_s)
+)
+(call $print)
+;; line 78: return steps * 2
+(local.get $steps)
+(i32.const 2)
+(i32.mul)
+(return)
+(i32.const 0)
+(return)
+)
+(memory 2)
+(export "memory" (memory 0))
+(export "main" (func $main))
+)
@@ -0,0 +1,605 @@
+(module
+(import "env" "print" (func $print (param i32)))
+(import "env" "print_i64" (func $print_i64 (param i64)))
+(import "env" "print_f64" (func $print_f64 (param f64)))
+(global $calls (mut i64) (i64.const 0))
+(global $var.stack (mut i32) (i32.const 65576))
+(func $fib (param $n i32) (result i32)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load)
+(i32.const 1)
+(i32.add)
+(i32.store)
+(global.set $calls
+(global.get $calls)
+(i64.const 1)
+(i64.add)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=4)
+(i32.const 1)
+(i32.add)
+(i32.store offset=4)
+(local.get $n)
+(i32.const 2)
+(i32.lt_s)
+(if
+(then
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=8)
+(i32.const 1)
+(i32.add)
+(i32.store offset=8)
+(local.get $n)
+(return)
+)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=12)
+(i32.const 1)
+(i32.add)
+(i32.store offset=12)
+(local.get $n)
+(i32.const 1)
+(i32.sub)
+(call $fib)
+(local.get $n)
+(i32.const 2)
+(i32.sub)
+(call $fib)
+(i32.add)
+(return)
+(i32.const 0)
+(return)
+)
+(func $divmod (param $a i32) (param $b i32) (result i32 i32)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=16)
+(i32.const 1)
+(i32.add)
+(i32.store offset=16)
+(local.get $a)
+(local.get $b)
+(i32.div_s)
+(local.get $a)
+(local.get $b)
+(i32.rem_s)
+(return)
+(i32.const 0)
+(i32.const 0)
+(return)
+)
+(func $mean (param $a f64) (param $b f64) (result f64)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=20)
+(i32.const 1)
+(i32.add)
+(i32.store offset=20)
+(local.get $a)
+(local.get $b)
+(f64.add)
+(f64.const 2.0)
+(f64.div)
+(return)
+(f64.const 0)
+(return)
+)
+(func $sum (param $n i32) (result i32)
+(local $var.frame i32)
+(local $last i32)
+(local $i i32)
+(local $for.limit.0 i32)
+(global.get $var.stack)
+(i32.const 48)
+(i32.lt_u)
+(if
+(then
+(unreachable)
+)
+)
+(global.get $var.stack)
+(i32.const 8)
+(i32.sub)
+(global.set $var.stack)
+(global.get $var.stack)
+(local.set $var.frame)
+(local.get $var.frame)
+(i64.const 0)
+(i64.store)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=24)
+(i32.const 1)
+(i32.add)
+(i32.store offset=24)
+(local.get $var.frame)
+(i32.const 0)
+(i32.store)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=28)
+(i32.const 1)
+(i32.add)
+(i32.store offset=28)
+(local.set $last
+(i32.const 0)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=32)
+(i32.const 1)
+(i32.add)
+(i32.store offset=32)
+(local.set $i
+(i32.const 1)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=36)
+(i32.const 1)
+(i32.add)
+(i32.store offset=36)
+(local.set $for.limit.0
+(local.get $n)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=40)
+(i32.const 1)
+(i32.add)
+(i32.store offset=40)
+(block
+(loop
+(local.get $i)
+(local.get $for.limit.0)
+(i32.le_s)
+(i32.eqz)
+(br_if 1)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=44)
+(i32.const 1)
+(i32.add)
+(i32.store offset=44)
+(local.set $last
+(local.get $i)
+(local.get $var.frame)
+(call $sum.add)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=48)
+(i32.const 1)
+(i32.add)
+(i32.store offset=48)
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=52)
+(i32.const 1)
+(i32.add)
+(i32.store offset=52)
+(local.get $last)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+(return)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+(i32.const 0)
+(return)
+)
+(func $sum.add (param $k i32) (param $total i32) (result i32)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=56)
+(i32.const 1)
+(i32.add)
+(i32.store offset=56)
+(local.get $total)
+(local.get $total)
+(i32.load)
+(local.get $k)
+(i32.add)
+(i32.store)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=60)
+(i32.const 1)
+(i32.add)
+(i32.store offset=60)
+(local.get $total)
+(i32.load)
+(return)
+(i32.const 0)
+(return)
+)
+(func $grade (param $n i32) (result i32)
+(local $case.i32 i32)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=64)
+(i32.const 1)
+(i32.add)
+(i32.store offset=64)
+(local.get $n)
+(local.set $case.i32)
+(local.get $case.i32)
+(i32.const 1)
+(i32.eq)
+(local.get $case.i32)
+(i32.const 2)
+(i32.eq)
+(i32.or)
+(if
+(then
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=68)
+(i32.const 1)
+(i32.add)
+(i32.store offset=68)
+(i32.const 10)
+(return)
+)
+(else
+(local.get $case.i32)
+(i32.const 3)
+(i32.eq)
+(if
+(then
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=72)
+(i32.const 1)
+(i32.add)
+(i32.store offset=72)
+(i32.const 30)
+(return)
+)
+(else
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=76)
+(i32.const 1)
+(i32.add)
+(i32.store offset=76)
+(i32.const -1)
+(return)
+)
+)
+)
+)
+(i32.const 0)
+(return)
+)
+(func $main (result i32)
+(local $i i32)
+(local $for.limit.1 i32)
+(local $result.i32.1 i32)
+(local $result.i32.0 i32)
+(local $q i32)
+(local $r i32)
+(local $steps i32)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=80)
+(i32.const 1)
+(i32.add)
+(i32.store offset=80)
+(local.set $i
+(i32.const 0)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=84)
+(i32.const 1)
+(i32.add)
+(i32.store offset=84)
+(local.set $for.limit.1
+(i32.const 10)
+(i32.const 1)
+(i32.sub)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=88)
+(i32.const 1)
+(i32.add)
+(i32.store offset=88)
+(block
+(loop
+(local.get $i)
+(local.get $for.limit.1)
+(i32.le_s)
+(i32.eqz)
+(br_if 1)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=92)
+(i32.const 1)
+(i32.add)
+(i32.store offset=92)
+(local.get $i)
+(i32.const 4)
+(i32.mul)
+(local.get $i)
+(local.get $i)
+(i32.mul)
+(i32.store)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=96)
+(i32.const 1)
+(i32.add)
+(i32.store offset=96)
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=100)
+(i32.const 1)
+(i32.add)
+(i32.store offset=100)
+(i32.const 10)
+(i32.const 1)
+(i32.sub)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=104)
+(i32.const 1)
+(i32.add)
+(i32.store offset=104)
+(i32.const 15)
+(call $fib)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=108)
+(i32.const 1)
+(i32.add)
+(i32.store offset=108)
+(global.get $calls)
+(call $print_i64)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=112)
+(i32.const 1)
+(i32.add)
+(i32.store offset=112)
+(i32.const 47)
+(i32.const 5)
+(call $divmod)
+(local.set $result.i32.1)
+(local.set $result.i32.0)
+(local.set $q
+(local.get $result.i32.0)
+)
+(local.set $r
+(local.get $result.i32.1)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=116)
+(i32.const 1)
+(i32.add)
+(i32.store offset=116)
+(local.get $q)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=120)
+(i32.const 1)
+(i32.add)
+(i32.store offset=120)
+(local.get $r)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=124)
+(i32.const 1)
+(i32.add)
+(i32.store offset=124)
+(f64.const 1.5)
+(f64.const 4.0)
+(call $mean)
+(call $print_f64)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=128)
+(i32.const 1)
+(i32.add)
+(i32.store offset=128)
+(f64.const 2.0)
+(f64.sqrt)
+(call $print_f64)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=132)
+(i32.const 1)
+(i32.add)
+(i32.store offset=132)
+(i64.const 1)
+(i64.const 40)
+(i64.shl)
+(call $print_i64)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=136)
+(i32.const 1)
+(i32.add)
+(i32.store offset=136)
+(i32.const 255)
+(i32.const 10)
+(i32.and)
+(i32.const 7)
+(i32.or)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=140)
+(i32.const 1)
+(i32.add)
+(i32.store offset=140)
+(i32.const -17)
+(i32.const 2)
+(i32.shr_s)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=144)
+(i32.const 1)
+(i32.add)
+(i32.store offset=144)
+(i32.const -17)
+(i32.const 28)
+(i32.shr_u)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=148)
+(i32.const 1)
+(i32.add)
+(i32.store offset=148)
+(local.set $i
+(i32.const 0)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=152)
+(i32.const 1)
+(i32.add)
+(i32.store offset=152)
+(local.set $steps
+(i32.const 0)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=156)
+(i32.const 1)
+(i32.add)
+(i32.store offset=156)
+(block
+(loop
+(local.get $i)
+(i32.const 100)
+(i32.lt_s)
+(i32.eqz)
+(br_if 1)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=160)
+(i32.const 1)
+(i32.add)
+(i32.store offset=160)
+(local.set $i
+(local.get $i)
+(i32.const 2)
+(i32.mul)
+(i32.const 1)
+(i32.add)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=164)
+(i32.const 1)
+(i32.add)
+(i32.store offset=164)
+(local.set $steps
+(local.get $steps)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=168)
+(i32.const 1)
+(i32.add)
+(i32.store offset=168)
+(local.get $steps)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=172)
+(i32.const 1)
+(i32.add)
+(i32.store offset=172)
+(i32.const 100)
+(call $sum)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=176)
+(i32.const 1)
+(i32.add)
+(i32.store offset=176)
+(i32.const 2)
+(call $grade)
+(i32.const 3)
+(call $grade)
+(i32.add)
+(i32.const 7)
+(call $grade)
+(i32.add)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=180)
+(i32.const 1)
+(i32.add)
+(i32.store offset=180)
+(select
+(i32.const 1)
+(i32.const 0)
+(i32.const 10)
+(i32.const 5)
+(i32.gt_s)
+)
+(call $print)
+(global.get $coverage.counters)
+(global.get $coverage.counters)
+(i32.load offset=184)
+(i32.const 1)
+(i32.add)
+(i32.store offset=184)
+(local.get $steps)
+(i32.const 2)
+(i32.mul)
+(return)
+(i32.const 0)
+(return)
+)
+(global $coverage.counters i32 (i32.const 65576))
+(memory 2)
+(export "memory" (memory 0))
+(export "main" (func $main))
+)
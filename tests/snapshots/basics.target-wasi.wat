@@ -0,0 +1,1093 @@
+(module
+(import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+(import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+(global $calls (mut i64) (i64.const 0))
+(global $var.stack (mut i32) (i32.const 65576))
+(func $fib (param $n i32) (result i32)
+(global.set $calls
+(global.get $calls)
+(i64.const 1)
+(i64.add)
+)
+(local.get $n)
+(i32.const 2)
+(i32.lt_s)
+(if
+(then
+(local.get $n)
+(return)
+)
+)
+(local.get $n)
+(i32.const 1)
+(i32.sub)
+(call $fib)
+(local.get $n)
+(i32.const 2)
+(i32.sub)
+(call $fib)
+(i32.add)
+(return)
+(i32.const 0)
+(return)
+)
+(func $divmod (param $a i32) (param $b i32) (result i32 i32)
+(local.get $a)
+(local.get $b)
+(i32.div_s)
+(local.get $a)
+(local.get $b)
+(i32.rem_s)
+(return)
+(i32.const 0)
+(i32.const 0)
+(return)
+)
+(func $mean (param $a f64) (param $b f64) (result f64)
+(local.get $a)
+(local.get $b)
+(f64.add)
+(f64.const 2.0)
+(f64.div)
+(return)
+(f64.const 0)
+(return)
+)
+(func $sum (param $n i32) (result i32)
+(local $var.frame i32)
+(local $last i32)
+(local $i i32)
+(local $for.limit.0 i32)
+(global.get $var.stack)
+(i32.const 48)
+(i32.lt_u)
+(if
+(then
+(unreachable)
+)
+)
+(global.get $var.stack)
+(i32.const 8)
+(i32.sub)
+(global.set $var.stack)
+(global.get $var.stack)
+(local.set $var.frame)
+(local.get $var.frame)
+(i64.const 0)
+(i64.store)
+(local.get $var.frame)
+(i32.const 0)
+(i32.store)
+(local.set $last
+(i32.const 0)
+)
+(local.set $i
+(i32.const 1)
+)
+(local.set $for.limit.0
+(local.get $n)
+)
+(block
+(loop
+(local.get $i)
+(local.get $for.limit.0)
+(i32.le_s)
+(i32.eqz)
+(br_if 1)
+(local.set $last
+(local.get $i)
+(local.get $var.frame)
+(call $sum.add)
+)
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(local.get $last)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+(return)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+(i32.const 0)
+(return)
+)
+(func $sum.add (param $k i32) (param $total i32) (result i32)
+(local.get $total)
+(local.get $total)
+(i32.load)
+(local.get $k)
+(i32.add)
+(i32.store)
+(local.get $total)
+(i32.load)
+(return)
+(i32.const 0)
+(return)
+)
+(func $grade (param $n i32) (result i32)
+(local $case.i32 i32)
+(local.get $n)
+(local.set $case.i32)
+(local.get $case.i32)
+(i32.const 1)
+(i32.eq)
+(local.get $case.i32)
+(i32.const 2)
+(i32.eq)
+(i32.or)
+(if
+(then
+(i32.const 10)
+(return)
+)
+(else
+(local.get $case.i32)
+(i32.const 3)
+(i32.eq)
+(if
+(then
+(i32.const 30)
+(return)
+)
+(else
+(i32.const -1)
+(return)
+)
+)
+)
+)
+(i32.const 0)
+(return)
+)
+(func $wasi.write (param $ptr i32) (param $len i32)
+(i32.const 65576)
+(local.get $ptr)
+(i32.store)
+(i32.const 65580)
+(local.get $len)
+(i32.store)
+(i32.const 1)
+(i32.const 65576)
+(i32.const 1)
+(i32.const 65584)
+(call $fd_write)
+(drop)
+)
+(func $print_i64 (param $value i64)
+(local $ptr i32)
+(local $magnitude i64)
+(local.set $ptr
+(i32.const 65611)
+)
+(local.get $ptr)
+(i32.const 10)
+(i32.store8)
+(local.set $magnitude
+(local.get $value)
+)
+(local.get $value)
+(i64.const 0)
+(i64.lt_s)
+(if
+(then
+(local.set $magnitude
+(i64.const 0)
+(local.get $value)
+(i64.sub)
+)
+)
+)
+(loop
+(local.set $ptr
+(local.get $ptr)
+(i32.const 1)
+(i32.sub)
+)
+(local.get $ptr)
+(local.get $magnitude)
+(i64.const 10)
+(i64.rem_u)
+(i32.wrap_i64)
+(i32.const 48)
+(i32.add)
+(i32.store8)
+(local.set $magnitude
+(local.get $magnitude)
+(i64.const 10)
+(i64.div_u)
+)
+(local.get $magnitude)
+(i64.const 0)
+(i64.ne)
+(br_if 0)
+)
+(local.get $value)
+(i64.const 0)
+(i64.lt_s)
+(if
+(then
+(local.set $ptr
+(local.get $ptr)
+(i32.const 1)
+(i32.sub)
+)
+(local.get $ptr)
+(i32.const 45)
+(i32.store8)
+)
+)
+(local.get $ptr)
+(i32.const 65612)
+(local.get $ptr)
+(i32.sub)
+(call $wasi.write)
+)
+(func $print (param $value i32)
+(local.get $value)
+(i64.extend_i32_s)
+(call $print_i64)
+)
+(func $big.set (param $n i32) (param $value i64)
+(local $i i32)
+(loop
+(local.get $n)
+(local.get $i)
+(i32.add)
+(i32.const 0)
+(i32.store)
+(local.set $i
+(local.get $i)
+(i32.const 4)
+(i32.add)
+)
+(local.get $i)
+(i32.const 160)
+(i32.lt_u)
+(br_if 0)
+)
+(local.get $n)
+(local.get $value)
+(i64.store)
+)
+(func $big.mul (param $n i32) (param $by i32)
+(local $i i32)
+(local $limb i32)
+(local $carry i64)
+(loop
+(local.set $limb
+(local.get $n)
+(local.get $i)
+(i32.add)
+)
+(local.set $carry
+(local.get $limb)
+(i64.load32_u)
+(local.get $by)
+(i64.extend_i32_u)
+(i64.mul)
+(local.get $carry)
+(i64.add)
+)
+(local.get $limb)
+(local.get $carry)
+(i64.store32)
+(local.set $carry
+(local.get $carry)
+(i64.const 32)
+(i64.shr_u)
+)
+(local.set $i
+(local.get $i)
+(i32.const 4)
+(i32.add)
+)
+(local.get $i)
+(i32.const 160)
+(i32.lt_u)
+(br_if 0)
+)
+)
+(func $big.add (param $sum i32) (param $a i32) (param $b i32)
+(local $i i32)
+(local $carry i64)
+(loop
+(local.set $carry
+(local.get $a)
+(local.get $i)
+(i32.add)
+(i64.load32_u)
+(local.get $b)
+(local.get $i)
+(i32.add)
+(i64.load32_u)
+(i64.add)
+(local.get $carry)
+(i64.add)
+)
+(local.get $sum)
+(local.get $i)
+(i32.add)
+(local.get $carry)
+(i64.store32)
+(local.set $carry
+(local.get $carry)
+(i64.const 32)
+(i64.shr_u)
+)
+(local.set $i
+(local.get $i)
+(i32.const 4)
+(i32.add)
+)
+(local.get $i)
+(i32.const 160)
+(i32.lt_u)
+(br_if 0)
+)
+)
+(func $big.sub (param $n i32) (param $b i32)
+(local $i i32)
+(local $limb i32)
+(local $difference i64)
+(loop
+(local.set $limb
+(local.get $n)
+(local.get $i)
+(i32.add)
+)
+(local.set $difference
+(local.get $limb)
+(i64.load32_u)
+(local.get $b)
+(local.get $i)
+(i32.add)
+(i64.load32_u)
+(i64.sub)
+(local.get $difference)
+(i64.const 63)
+(i64.shr_u)
+(i64.sub)
+)
+(local.get $limb)
+(local.get $difference)
+(i64.store32)
+(local.set $i
+(local.get $i)
+(i32.const 4)
+(i32.add)
+)
+(local.get $i)
+(i32.const 160)
+(i32.lt_u)
+(br_if 0)
+)
+)
+(func $big.compare (param $a i32) (param $b i32) (result i32)
+(local $i i32)
+(local $x i32)
+(local $y i32)
+(local.set $i
+(i32.const 160)
+)
+(loop
+(local.set $i
+(local.get $i)
+(i32.const 4)
+(i32.sub)
+)
+(local.set $x
+(local.get $a)
+(local.get $i)
+(i32.add)
+(i32.load)
+)
+(local.set $y
+(local.get $b)
+(local.get $i)
+(i32.add)
+(i32.load)
+)
+(local.get $x)
+(local.get $y)
+(i32.ne)
+(if
+(then
+(i32.const 1)
+(i32.const -1)
+(local.get $x)
+(local.get $y)
+(i32.gt_u)
+(select)
+(return)
+)
+)
+(local.get $i)
+(br_if 0)
+)
+(i32.const 0)
+)
+(func $print_f64 (param $value f64)
+(local $ptr i32)
+(local $n i32)
+(local $k i32)
+(local $i i32)
+(local.set $ptr
+(i32.const 66436)
+)
+(block $text
+(local.get $value)
+(local.get $value)
+(f64.ne)
+(if
+(then
+(local.get $ptr)
+(i32.const 0x6e616e)
+(i32.store)
+(local.set $ptr
+(i32.const 66439)
+)
+(br $text)
+)
+)
+(local.get $value)
+(i64.reinterpret_f64)
+(i64.const 0)
+(i64.lt_s)
+(if
+(then
+(local.get $ptr)
+(i32.const 45)
+(i32.store8)
+(local.set $ptr
+(i32.const 66437)
+)
+)
+)
+(local.get $value)
+(f64.abs)
+(f64.const inf)
+(f64.eq)
+(if
+(then
+(local.get $ptr)
+(i32.const 0x666e69)
+(i32.store)
+(local.set $ptr
+(local.get $ptr)
+(i32.const 3)
+(i32.add)
+)
+(br $text)
+)
+)
+(local.get $value)
+(f64.const 0)
+(f64.eq)
+(if
+(then
+(local.get $ptr)
+(i32.const 48)
+(i32.store8)
+(local.set $ptr
+(local.get $ptr)
+(i32.const 1)
+(i32.add)
+)
+(br $text)
+)
+)
+(local.get $value)
+(i64.reinterpret_f64)
+(call $print_f64.digits)
+(local.set $k)
+(local.set $n)
+(local.get $k)
+(i32.const 0)
+(i32.le_s)
+(if
+(then
+(local.get $ptr)
+(i32.const 0x2e30)
+(i32.store16)
+(local.set $ptr
+(local.get $ptr)
+(i32.const 2)
+(i32.add)
+)
+(block
+(loop
+(local.get $k)
+(i32.eqz)
+(br_if 1)
+(local.get $ptr)
+(i32.const 48)
+(i32.store8)
+(local.set $ptr
+(local.get $ptr)
+(i32.const 1)
+(i32.add)
+)
+(local.set $k
+(local.get $k)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+)
+)
+(loop
+(local.get $i)
+(local.get $k)
+(i32.eq)
+(local.get $i)
+(i32.const 0)
+(i32.ne)
+(i32.and)
+(if
+(then
+(local.get $ptr)
+(i32.const 46)
+(i32.store8)
+(local.set $ptr
+(local.get $ptr)
+(i32.const 1)
+(i32.add)
+)
+)
+)
+(local.get $ptr)
+(local.get $i)
+(i32.load8_u offset=66412)
+(i32.store8)
+(local.set $ptr
+(local.get $ptr)
+(i32.const 1)
+(i32.add)
+)
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(local.get $i)
+(local.get $n)
+(i32.lt_u)
+(br_if 0)
+)
+(block
+(loop
+(local.get $i)
+(local.get $k)
+(i32.ge_s)
+(br_if 1)
+(local.get $ptr)
+(i32.const 48)
+(i32.store8)
+(local.set $ptr
+(local.get $ptr)
+(i32.const 1)
+(i32.add)
+)
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+)
+(local.get $ptr)
+(i32.const 10)
+(i32.store8)
+(i32.const 66436)
+(local.get $ptr)
+(i32.const 66435)
+(i32.sub)
+(call $wasi.write)
+)
+(func $print_f64.digits (param $bits i64) (result i32 i32)
+(local $biased i32)
+(local $frac i64)
+(local $exp i32)
+(local $inclusive i32)
+(local $k i32)
+(local $n i32)
+(local $digit i32)
+(local $down i32)
+(local $up i32)
+(local.set $biased
+(local.get $bits)
+(i64.const 52)
+(i64.shr_u)
+(i32.wrap_i64)
+(i32.const 0x7ff)
+(i32.and)
+)
+(local.set $frac
+(local.get $bits)
+(i64.const 0xfffffffffffff)
+(i64.and)
+)
+(call $big.set
+(i32.const 66092)
+(i64.const 1)
+)
+(call $big.set
+(i32.const 65932)
+(i64.const 1)
+)
+(call $big.set
+(i32.const 65772)
+(i64.const 1)
+)
+(local.set $inclusive
+(local.get $frac)
+(i32.wrap_i64)
+(i32.const 1)
+(i32.and)
+(i32.eqz)
+)
+(local.get $biased)
+(i32.eqz)
+(if
+(then
+(call $big.set
+(i32.const 65612)
+(local.get $frac)
+(i64.const 1)
+(i64.shl)
+)
+(local.set $exp
+(i32.const -1075)
+)
+(local.set $inclusive
+(i32.const 1)
+)
+)
+(else
+(local.get $frac)
+(i64.eqz)
+(if
+(then
+(call $big.set
+(i32.const 65612)
+(i64.const 0x40000000000000)
+)
+(call $big.set
+(i32.const 65932)
+(i64.const 2)
+)
+(local.set $exp
+(local.get $biased)
+(i32.const 1077)
+(i32.sub)
+)
+)
+(else
+(call $big.set
+(i32.const 65612)
+(local.get $frac)
+(i64.const 0x10000000000000)
+(i64.or)
+(i64.const 1)
+(i64.shl)
+)
+(local.set $exp
+(local.get $biased)
+(i32.const 1076)
+(i32.sub)
+)
+)
+)
+)
+)
+(block
+(loop
+(local.get $exp)
+(i32.const 0)
+(i32.le_s)
+(br_if 1)
+(call $big.mul
+(i32.const 65612)
+(i32.const 2)
+)
+(call $big.mul
+(i32.const 66092)
+(i32.const 2)
+)
+(call $big.mul
+(i32.const 65932)
+(i32.const 2)
+)
+(local.set $exp
+(local.get $exp)
+(i32.const 1)
+(i32.sub)
+)
+(br 0)
+)
+)
+(block
+(loop
+(local.get $exp)
+(i32.const 0)
+(i32.ge_s)
+(br_if 1)
+(call $big.mul
+(i32.const 65772)
+(i32.const 2)
+)
+(local.set $exp
+(local.get $exp)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(local.get $inclusive)
+(call $print_f64.reaches)
+(if
+(then
+(loop
+(call $big.mul
+(i32.const 65772)
+(i32.const 10)
+)
+(local.set $k
+(local.get $k)
+(i32.const 1)
+(i32.add)
+)
+(local.get $inclusive)
+(call $print_f64.reaches)
+(br_if 0)
+)
+)
+(else
+(block
+(loop
+(call $big.add
+(i32.const 66252)
+(i32.const 65612)
+(i32.const 65932)
+)
+(call $big.mul
+(i32.const 66252)
+(i32.const 10)
+)
+(call $big.compare
+(i32.const 65772)
+(i32.const 66252)
+)
+(local.get $inclusive)
+(i32.lt_s)
+(br_if 1)
+(call $print_f64.times_ten)
+(local.set $k
+(local.get $k)
+(i32.const 1)
+(i32.sub)
+)
+(br 0)
+)
+)
+)
+)
+(loop $digit
+(call $print_f64.times_ten)
+(local.set $digit
+(i32.const 48)
+)
+(block
+(loop
+(call $big.compare
+(i32.const 65612)
+(i32.const 65772)
+)
+(i32.const 0)
+(i32.lt_s)
+(br_if 1)
+(call $big.sub
+(i32.const 65612)
+(i32.const 65772)
+)
+(local.set $digit
+(local.get $digit)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(local.get $n)
+(local.get $digit)
+(i32.store8 offset=66412)
+(local.set $n
+(local.get $n)
+(i32.const 1)
+(i32.add)
+)
+(local.set $down
+(call $big.compare
+(i32.const 65612)
+(i32.const 66092)
+)
+(local.get $inclusive)
+(i32.lt_s)
+)
+(local.set $up
+(local.get $inclusive)
+(call $print_f64.reaches)
+)
+(local.get $down)
+(local.get $up)
+(i32.or)
+(i32.eqz)
+(br_if $digit)
+)
+(call $big.add
+(i32.const 66252)
+(i32.const 65612)
+(i32.const 65612)
+)
+(local.get $up)
+(local.get $down)
+(i32.eqz)
+(call $big.compare
+(i32.const 66252)
+(i32.const 65772)
+)
+(i32.const 0)
+(i32.ge_s)
+(i32.or)
+(i32.and)
+(if
+(then
+(local.set $digit
+(local.get $n)
+)
+(loop
+(local.set $digit
+(local.get $digit)
+(i32.const 1)
+(i32.sub)
+)
+(local.get $digit)
+(i32.load8_u offset=66412)
+(i32.const 57)
+(i32.eq)
+(if
+(then
+(local.get $digit)
+(i32.const 48)
+(i32.store8 offset=66412)
+(br 1)
+)
+)
+)
+(local.get $digit)
+(local.get $digit)
+(i32.load8_u offset=66412)
+(i32.const 1)
+(i32.add)
+(i32.store8 offset=66412)
+)
+)
+(local.get $n)
+(local.get $k)
+)
+(func $print_f64.reaches (param $inclusive i32) (result i32)
+(call $big.add
+(i32.const 66252)
+(i32.const 65612)
+(i32.const 65932)
+)
+(call $big.compare
+(i32.const 65772)
+(i32.const 66252)
+)
+(local.get $inclusive)
+(i32.lt_s)
+)
+(func $print_f64.times_ten
+(call $big.mul
+(i32.const 65612)
+(i32.const 10)
+)
+(call $big.mul
+(i32.const 66092)
+(i32.const 10)
+)
+(call $big.mul
+(i32.const 65932)
+(i32.const 10)
+)
+)
+(func $main (result i32)
+(local $i i32)
+(local $for.limit.1 i32)
+(local $result.i32.1 i32)
+(local $result.i32.0 i32)
+(local $q i32)
+(local $r i32)
+(local $steps i32)
+(local.set $i
+(i32.const 0)
+)
+(local.set $for.limit.1
+(i32.const 10)
+(i32.const 1)
+(i32.sub)
+)
+(block
+(loop
+(local.get $i)
+(local.get $for.limit.1)
+(i32.le_s)
+(i32.eqz)
+(br_if 1)
+(local.get $i)
+(i32.const 4)
+(i32.mul)
+(local.get $i)
+(local.get $i)
+(i32.mul)
+(i32.store)
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(i32.const 10)
+(i32.const 1)
+(i32.sub)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(call $print)
+(i32.const 15)
+(call $fib)
+(call $print)
+(global.get $calls)
+(call $print_i64)
+(i32.const 47)
+(i32.const 5)
+(call $divmod)
+(local.set $result.i32.1)
+(local.set $result.i32.0)
+(local.set $q
+(local.get $result.i32.0)
+)
+(local.set $r
+(local.get $result.i32.1)
+)
+(local.get $q)
+(call $print)
+(local.get $r)
+(call $print)
+(f64.const 1.5)
+(f64.const 4.0)
+(call $mean)
+(call $print_f64)
+(f64.const 2.0)
+(f64.sqrt)
+(call $print_f64)
+(i64.const 1)
+(i64.const 40)
+(i64.shl)
+(call $print_i64)
+(i32.const 255)
+(i32.const 10)
+(i32.and)
+(i32.const 7)
+(i32.or)
+(call $print)
+(i32.const -17)
+(i32.const 2)
+(i32.shr_s)
+(call $print)
+(i32.const -17)
+(i32.const 28)
+(i32.shr_u)
+(call $print)
+(local.set $i
+(i32.const 0)
+)
+(local.set $steps
+(i32.const 0)
+)
+(block
+(loop
+(local.get $i)
+(i32.const 100)
+(i32.lt_s)
+(i32.eqz)
+(br_if 1)
+(local.set $i
+(local.get $i)
+(i32.const 2)
+(i32.mul)
+(i32.const 1)
+(i32.add)
+)
+(local.set $steps
+(local.get $steps)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(local.get $steps)
+(call $print)
+(i32.const 100)
+(call $sum)
+(call $print)
+(i32.const 2)
+(call $grade)
+(i32.const 3)
+(call $grade)
+(i32.add)
+(i32.const 7)
+(call $grade)
+(i32.add)
+(call $print)
+(select
+(i32.const 1)
+(i32.const 0)
+(i32.const 10)
+(i32.const 5)
+(i32.gt_s)
+)
+(call $print)
+(local.get $steps)
+(i32.const 2)
+(i32.mul)
+(return)
+(i32.const 0)
+(return)
+)
+(func $_start
+(call $main)
+(call $proc_exit)
+)
+(memory 2)
+(export "memory" (memory 0))
+(export "_start" (func $_start))
+)
@@ -0,0 +1,254 @@
+(module
+(import "env" "print_f64" (func $print_f64 (param f64)))
+(import "env" "print_i64" (func $print_i64 (param i64)))
+(import "env" "print" (func $print (param i32)))
+(func $area (param $item i32) (result f64)
+(local $case.i32 i32)
+(local.get $item)
+(i32.load)
+(local.set $case.i32)
+(local.get $case.i32)
+(i32.const 0)
+(i32.eq)
+(if
+(then
+(local.get $item)
+(i32.load offset=4)
+(f64.load offset=8)
+(local.get $item)
+(i32.load offset=4)
+(f64.load offset=8)
+(f64.mul)
+(return)
+)
+(else
+(local.get $case.i32)
+(i32.const 1)
+(i32.eq)
+(if
+(then
+(f64.const 3.0)
+(local.get $item)
+(i32.load offset=4)
+(f64.load offset=8)
+(f64.mul)
+(local.get $item)
+(i32.load offset=4)
+(f64.load offset=8)
+(f64.mul)
+(return)
+)
+(else
+(f64.const 0.0)
+(return)
+)
+)
+)
+)
+(f64.const 0)
+(return)
+)
+(func $heap.alloc (param $size i32) (result i32)
+(local $address i32)
+(local $end i32)
+(local $pages i32)
+(local.set $address
+(global.get $heap.top)
+(i32.const 3)
+(i32.add)
+(i32.const -4)
+(i32.and)
+)
+(local.set $end
+(local.get $address)
+(local.get $size)
+(i32.add)
+)
+(local.get $end)
+(local.get $address)
+(i32.lt_u)
+(if
+(then
+(unreachable)
+)
+)
+(local.set $pages
+(local.get $end)
+(i64.extend_i32_u)
+(i64.const 65535)
+(i64.add)
+(i64.const 16)
+(i64.shr_u)
+(i32.wrap_i64)
+)
+(local.get $pages)
+(memory.size)
+(i32.gt_u)
+(if
+(then
+(local.get $pages)
+(memory.size)
+(i32.sub)
+(memory.grow)
+(i32.const -1)
+(i32.eq)
+(if
+(then
+(unreachable)
+)
+)
+)
+)
+(global.set $heap.top
+(local.get $end)
+)
+(local.get $address)
+)
+(func $make (param $shape i32) (param $size i32) (param $next i32) (result i32)
+(local $item i32)
+(local.set $item
+(i32.const 24)
+(call $heap.alloc)
+)
+(local.get $item)
+(local.get $shape)
+(i32.store)
+(local.get $item)
+(i32.const 16)
+(call $heap.alloc)
+(i32.store offset=4)
+(local.get $item)
+(i32.load offset=4)
+(local.get $size)
+(i32.store)
+(local.get $item)
+(i32.load offset=4)
+(f64.const 1.5)
+(f64.store offset=8)
+(local.get $item)
+(i64.const 5)
+(i64.store offset=8)
+(local.get $item)
+(local.get $next)
+(i32.store offset=16)
+(local.get $item)
+(return)
+(i32.const 0)
+(return)
+)
+(func $main (result i32)
+(local $items i32)
+(local $total f64)
+(local $weight i64)
+(local $size i32)
+(local $item i32)
+(i32.const 0)
+(i32.const 4)
+(i32.mul)
+(i32.const 2)
+(i32.store)
+(i32.const 1)
+(i32.const 4)
+(i32.mul)
+(i32.const 3)
+(i32.store)
+(i32.const 2)
+(i32.const 4)
+(i32.mul)
+(i32.const 18)
+(i32.store)
+(local.set $items
+(i32.const 0)
+(i32.const 0)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(i32.const 0)
+(call $make)
+)
+(local.set $items
+(i32.const 1)
+(i32.const 1)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(local.get $items)
+(call $make)
+)
+(local.set $items
+(i32.const 2)
+(i32.const 2)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(local.get $items)
+(call $make)
+)
+(local.set $total
+(f64.const 0.0)
+)
+(local.set $weight
+(i64.const 0)
+)
+(local.set $size
+(i32.const 0)
+)
+(local.set $item
+(local.get $items)
+)
+(block
+(loop
+(local.get $item)
+(i32.const 0)
+(i32.ne)
+(i32.eqz)
+(br_if 1)
+(local.set $total
+(local.get $total)
+(local.get $item)
+(call $area)
+(f64.add)
+)
+(local.set $weight
+(local.get $weight)
+(local.get $item)
+(i64.load offset=8)
+(i64.add)
+)
+(local.set $size
+(local.get $size)
+(local.get $item)
+(i32.load offset=4)
+(i32.load)
+(i32.add)
+)
+(local.set $item
+(local.get $item)
+(i32.load offset=16)
+)
+(br 0)
+)
+)
+(local.get $total)
+(call $print_f64)
+(local.get $weight)
+(call $print_i64)
+(local.get $items)
+(i32.load)
+(i32.const 2)
+(i32.eq)
+(call $print)
+(local.get $items)
+(i32.load offset=16)
+(i32.load)
+(call $print)
+(local.get $size)
+(return)
+(i32.const 0)
+(return)
+)
+(memory 1)
+(export "memory" (memory 0))
+(global $heap.top (mut i32) (i32.const 12))
+(export "main" (func $main))
+)
@@ -0,0 +1,194 @@
+(module
+(import "env" "print_f64" (func $print_f64 (param f64)))
+(import "env" "print_i64" (func $print_i64 (param i64)))
+(import "env" "print" (func $print (param i32)))
+(type $i32.array (array (mut i32)))
+(global $array.sizes (ref $i32.array) (array.new_default $i32.array (i32.const 3)))
+(rec
+(type $record.Point (struct (field $x (mut i32)) (field $y (mut f64))))
+(type $record.Item (struct (field $shape (mut i32)) (field $at (mut (ref null $record.Point))) (field $weight (mut i64)) (field $next (mut (ref null $record.Item)))))
+)
+(func $area (param $item (ref null $record.Item)) (result f64)
+(local $case.i32 i32)
+(local.get $item)
+(struct.get $record.Item $shape)
+(local.set $case.i32)
+(local.get $case.i32)
+(i32.const 0)
+(i32.eq)
+(if
+(then
+(local.get $item)
+(struct.get $record.Item $at)
+(struct.get $record.Point $y)
+(local.get $item)
+(struct.get $record.Item $at)
+(struct.get $record.Point $y)
+(f64.mul)
+(return)
+)
+(else
+(local.get $case.i32)
+(i32.const 1)
+(i32.eq)
+(if
+(then
+(f64.const 3.0)
+(local.get $item)
+(struct.get $record.Item $at)
+(struct.get $record.Point $y)
+(f64.mul)
+(local.get $item)
+(struct.get $record.Item $at)
+(struct.get $record.Point $y)
+(f64.mul)
+(return)
+)
+(else
+(f64.const 0.0)
+(return)
+)
+)
+)
+)
+(f64.const 0)
+(return)
+)
+(func $make (param $shape i32) (param $size i32) (param $next (ref null $record.Item)) (result (ref null $record.Item))
+(local $item (ref null $record.Item))
+(local.set $item
+(struct.new_default $record.Item)
+)
+(local.get $item)
+(local.get $shape)
+(struct.set $record.Item $shape)
+(local.get $item)
+(struct.new_default $record.Point)
+(struct.set $record.Item $at)
+(local.get $item)
+(struct.get $record.Item $at)
+(local.get $size)
+(struct.set $record.Point $x)
+(local.get $item)
+(struct.get $record.Item $at)
+(f64.const 1.5)
+(struct.set $record.Point $y)
+(local.get $item)
+(i64.const 5)
+(struct.set $record.Item $weight)
+(local.get $item)
+(local.get $next)
+(struct.set $record.Item $next)
+(local.get $item)
+(return)
+(ref.null $record.Item)
+(return)
+)
+(func $main (result i32)
+(local $items (ref null $record.Item))
+(local $total f64)
+(local $weight i64)
+(local $size i32)
+(local $item (ref null $record.Item))
+(global.get $array.sizes)
+(i32.const 0)
+(i32.const 2)
+(array.set $i32.array)
+(global.get $array.sizes)
+(i32.const 1)
+(i32.const 3)
+(array.set $i32.array)
+(global.get $array.sizes)
+(i32.const 2)
+(i32.const 18)
+(array.set $i32.array)
+(local.set $items
+(i32.const 0)
+(global.get $array.sizes)
+(i32.const 0)
+(array.get $i32.array)
+(ref.null $record.Item)
+(call $make)
+)
+(local.set $items
+(i32.const 1)
+(global.get $array.sizes)
+(i32.const 1)
+(array.get $i32.array)
+(local.get $items)
+(call $make)
+)
+(local.set $items
+(i32.const 2)
+(global.get $array.sizes)
+(i32.const 2)
+(array.get $i32.array)
+(local.get $items)
+(call $make)
+)
+(local.set $total
+(f64.const 0.0)
+)
+(local.set $weight
+(i64.const 0)
+)
+(local.set $size
+(i32.const 0)
+)
+(local.set $item
+(local.get $items)
+)
+(block
+(loop
+(local.get $item)
+(ref.null $record.Item)
+(ref.eq)
+(i32.eqz)
+(i32.eqz)
+(br_if 1)
+(local.set $total
+(local.get $total)
+(local.get $item)
+(call $area)
+(f64.add)
+)
+(local.set $weight
+(local.get $weight)
+(local.get $item)
+(struct.get $record.Item $weight)
+(i64.add)
+)
+(local.set $size
+(local.get $size)
+(local.get $item)
+(struct.get $record.Item $at)
+(struct.get $record.Point $x)
+(i32.add)
+)
+(local.set $item
+(local.get $item)
+(struct.get $record.Item $next)
+)
+(br 0)
+)
+)
+(local.get $total)
+(call $print_f64)
+(local.get $weight)
+(call $print_i64)
+(local.get $items)
+(struct.get $record.Item $shape)
+(i32.const 2)
+(i32.eq)
+(call $print)
+(local.get $items)
+(struct.get $record.Item $next)
+(struct.get $record.Item $shape)
+(call $print)
+(local.get $size)
+(return)
+(i32.const 0)
+(return)
+)
+(export "main" (func $main))
+)
@@ -0,0 +1,500 @@
+(module
+(import "env" "print_f64" (func $print_f64 (param f64)))
+(import "env" "print_i64" (func $print_i64 (param i64)))
+(import "env" "print" (func $print (param i32)))
+(func $rc.release.Point (param $record i32)
+(local $count i32)
+(local.get $record)
+(global.get $heap.base)
+(i32.le_u)
+(if
+(then
+(return)
+)
+)
+(local.set $count
+(local.get $record)
+(i32.const 4)
+(i32.sub)
+)
+(local.get $count)
+(local.get $count)
+(i32.load)
+(i32.const 1)
+(i32.sub)
+(i32.store)
+(local.get $count)
+(i32.load)
+(i32.eqz)
+(if
+(then
+(local.get $count)
+(global.get $rc.free)
+(i32.store)
+(global.set $rc.free
+(local.get $record)
+)
+)
+)
+)
+(func $rc.release.Item (param $record i32)
+(local $count i32)
+(local.get $record)
+(global.get $heap.base)
+(i32.le_u)
+(if
+(then
+(return)
+)
+)
+(local.set $count
+(local.get $record)
+(i32.const 4)
+(i32.sub)
+)
+(local.get $count)
+(local.get $count)
+(i32.load)
+(i32.const 1)
+(i32.sub)
+(i32.store)
+(local.get $count)
+(i32.load)
+(i32.eqz)
+(if
+(then
+(local.get $record)
+(i32.load offset=4)
+(call $rc.release.Point)
+(local.get $record)
+(i32.load offset=16)
+(call $rc.release.Item)
+(local.get $count)
+(global.get $rc.free)
+(i32.store)
+(global.set $rc.free
+(local.get $record)
+)
+)
+)
+)
+(func $area (param $item i32) (result f64)
+(local $case.i32 i32)
+(block $rc.body (result f64)
+(local.get $item)
+(i32.load)
+(local.set $case.i32)
+(local.get $case.i32)
+(i32.const 0)
+(i32.eq)
+(if
+(then
+(local.get $item)
+(i32.load offset=4)
+(f64.load offset=8)
+(local.get $item)
+(i32.load offset=4)
+(f64.load offset=8)
+(f64.mul)
+(br $rc.body)
+)
+(else
+(local.get $case.i32)
+(i32.const 1)
+(i32.eq)
+(if
+(then
+(f64.const 3.0)
+(local.get $item)
+(i32.load offset=4)
+(f64.load offset=8)
+(f64.mul)
+(local.get $item)
+(i32.load offset=4)
+(f64.load offset=8)
+(f64.mul)
+(br $rc.body)
+)
+(else
+(f64.const 0.0)
+(br $rc.body)
+)
+)
+)
+)
+(f64.const 0)
+)
+(local.get $item)
+(call $rc.release.Item)
+)
+(func $heap.alloc (param $size i32) (result i32)
+(local $address i32)
+(local $end i32)
+(local $pages i32)
+(local.set $address
+(global.get $heap.top)
+(i32.const 3)
+(i32.add)
+(i32.const -4)
+(i32.and)
+)
+(local.set $end
+(local.get $address)
+(local.get $size)
+(i32.add)
+)
+(local.get $end)
+(local.get $address)
+(i32.lt_u)
+(if
+(then
+(unreachable)
+)
+)
+(local.set $pages
+(local.get $end)
+(i64.extend_i32_u)
+(i64.const 65535)
+(i64.add)
+(i64.const 16)
+(i64.shr_u)
+(i32.wrap_i64)
+)
+(local.get $pages)
+(memory.size)
+(i32.gt_u)
+(if
+(then
+(local.get $pages)
+(memory.size)
+(i32.sub)
+(memory.grow)
+(i32.const -1)
+(i32.eq)
+(if
+(then
+(unreachable)
+)
+)
+)
+)
+(global.set $heap.top
+(local.get $end)
+)
+(local.get $address)
+)
+(func $rc.alloc (param $size i32) (result i32)
+(local $string i32)
+(local $previous i32)
+(local.set $string
+(global.get $rc.free)
+)
+(block
+(loop
+(local.get $string)
+(i32.eqz)
+(br_if 1)
+(local.get $string)
+(i32.const 8)
+(i32.sub)
+(i32.load)
+(local.get $size)
+(i32.ge_u)
+(if
+(then
+(local.get $previous)
+(i32.eqz)
+(if
+(then
+(global.set $rc.free
+(local.get $string)
+(i32.const 4)
+(i32.sub)
+(i32.load)
+)
+)
+(else
+(local.get $previous)
+(i32.const 4)
+(i32.sub)
+(local.get $string)
+(i32.const 4)
+(i32.sub)
+(i32.load)
+(i32.store)
+)
+)
+(local.get $string)
+(i32.const 4)
+(i32.sub)
+(i32.const 1)
+(i32.store)
+(local.get $string)
+(return)
+)
+)
+(local.set $previous
+(local.get $string)
+)
+(local.set $string
+(local.get $string)
+(i32.const 4)
+(i32.sub)
+(i32.load)
+)
+(br 0)
+)
+)
+(local.get $size)
+(i32.const 4294967287)
+(i32.gt_u)
+(if
+(then
+(unreachable)
+)
+)
+(local.set $string
+(local.get $size)
+(i32.const 8)
+(i32.add)
+(call $heap.alloc)
+(i32.const 8)
+(i32.add)
+)
+(local.get $string)
+(i32.const 8)
+(i32.sub)
+(local.get $size)
+(i32.store)
+(local.get $string)
+(i32.const 4)
+(i32.sub)
+(i32.const 1)
+(i32.store)
+(local.get $string)
+)
+(func $mem.fill (param $to i32) (param $value i32) (param $len i32) (result i32)
+(local.get $to)
+(local.get $value)
+(local.get $len)
+(memory.fill)
+(local.get $to)
+)
+(func $rc.new (param $size i32) (result i32)
+(local.get $size)
+(call $rc.alloc)
+(i32.const 0)
+(local.get $size)
+(call $mem.fill)
+)
+(func $rc.retain.record (param $record i32) (result i32)
+(local $count i32)
+(local.get $record)
+(global.get $heap.base)
+(i32.gt_u)
+(if
+(then
+(local.set $count
+(local.get $record)
+(i32.const 4)
+(i32.sub)
+)
+(local.get $count)
+(local.get $count)
+(i32.load)
+(i32.const 1)
+(i32.add)
+(i32.store)
+)
+)
+(local.get $record)
+)
+(func $make (param $shape i32) (param $size i32) (param $next i32) (result i32)
+(local $item i32)
+(local $rc.place i32)
+(block $rc.body (result i32)
+(local.set $item
+(i32.const 24)
+(call $rc.new)
+(local.get $item)
+(call $rc.release.Item)
+)
+(local.get $item)
+(local.get $shape)
+(i32.store)
+(local.get $item)
+(local.tee $rc.place)
+(i32.const 16)
+(call $rc.new)
+(local.get $rc.place)
+(i32.load offset=4)
+(call $rc.release.Point)
+(i32.store offset=4)
+(local.get $item)
+(i32.load offset=4)
+(local.get $size)
+(i32.store)
+(local.get $item)
+(i32.load offset=4)
+(f64.const 1.5)
+(f64.store offset=8)
+(local.get $item)
+(i64.const 5)
+(i64.store offset=8)
+(local.get $item)
+(local.tee $rc.place)
+(local.get $next)
+(call $rc.retain.record)
+(local.get $rc.place)
+(i32.load offset=16)
+(call $rc.release.Item)
+(i32.store offset=16)
+(local.get $item)
+(call $rc.retain.record)
+(br $rc.body)
+(i32.const 0)
+)
+(local.get $next)
+(call $rc.release.Item)
+(local.get $item)
+(call $rc.release.Item)
+)
+(func $main (result i32)
+(local $items i32)
+(local $total f64)
+(local $weight i64)
+(local $size i32)
+(local $item i32)
+(block $rc.body (result i32)
+(i32.const 0)
+(i32.const 4)
+(i32.mul)
+(i32.const 2)
+(i32.store)
+(i32.const 1)
+(i32.const 4)
+(i32.mul)
+(i32.const 3)
+(i32.store)
+(i32.const 2)
+(i32.const 4)
+(i32.mul)
+(i32.const 18)
+(i32.store)
+(local.set $items
+(i32.const 0)
+(i32.const 0)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(i32.const 0)
+(call $rc.retain.record)
+(call $make)
+(local.get $items)
+(call $rc.release.Item)
+)
+(local.set $items
+(i32.const 1)
+(i32.const 1)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(local.get $items)
+(call $rc.retain.record)
+(call $make)
+(local.get $items)
+(call $rc.release.Item)
+)
+(local.set $items
+(i32.const 2)
+(i32.const 2)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(local.get $items)
+(call $rc.retain.record)
+(call $make)
+(local.get $items)
+(call $rc.release.Item)
+)
+(local.set $total
+(f64.const 0.0)
+)
+(local.set $weight
+(i64.const 0)
+)
+(local.set $size
+(i32.const 0)
+)
+(local.set $item
+(local.get $items)
+(call $rc.retain.record)
+(local.get $item)
+(call $rc.release.Item)
+)
+(block
+(loop
+(local.get $item)
+(i32.const 0)
+(i32.ne)
+(i32.eqz)
+(br_if 1)
+(local.set $total
+(local.get $total)
+(local.get $item)
+(call $rc.retain.record)
+(call $area)
+(f64.add)
+)
+(local.set $weight
+(local.get $weight)
+(local.get $item)
+(i64.load offset=8)
+(i64.add)
+)
+(local.set $size
+(local.get $size)
+(local.get $item)
+(i32.load offset=4)
+(i32.load)
+(i32.add)
+)
+(local.set $item
+(local.get $item)
+(i32.load offset=16)
+(call $rc.retain.record)
+(local.get $item)
+(call $rc.release.Item)
+)
+(br 0)
+)
+)
+(local.get $total)
+(call $print_f64)
+(local.get $weight)
+(call $print_i64)
+(local.get $items)
+(i32.load)
+(i32.const 2)
+(i32.eq)
+(call $print)
+(local.get $items)
+(i32.load offset=16)
+(i32.load)
+(call $print)
+(local.get $size)
+(br $rc.body)
+(i32.const 0)
+)
+(local.get $items)
+(call $rc.release.Item)
+(local.get $item)
+(call $rc.release.Item)
+)
+(memory 1)
+(export "memory" (memory 0))
+(global $heap.top (mut i32) (i32.const 12))
+(global $heap.base i32 (i32.const 12))
+(global $rc.free (mut i32) (i32.const 0))
+(export "main" (func $main))
+)
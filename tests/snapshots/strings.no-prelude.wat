@@ -0,0 +1,387 @@
+(module
+(import "env" "print_str" (func $print_str (param i32 i32)))
+(import "env" "print" (func $print (param i32)))
+(global $greeting (mut i64) (i64.const 21474836480))
+(func $heap.alloc (param $size i32) (result i32)
+(local $address i32)
+(local $end i32)
+(local $pages i32)
+(local.set $address
+(global.get $heap.top)
+(i32.const 3)
+(i32.add)
+(i32.const -4)
+(i32.and)
+)
+(local.set $end
+(local.get $address)
+(local.get $size)
+(i32.add)
+)
+(local.get $end)
+(local.get $address)
+(i32.lt_u)
+(if
+(then
+(unreachable)
+)
+)
+(local.set $pages
+(local.get $end)
+(i64.extend_i32_u)
+(i64.const 65535)
+(i64.add)
+(i64.const 16)
+(i64.shr_u)
+(i32.wrap_i64)
+)
+(local.get $pages)
+(memory.size)
+(i32.gt_u)
+(if
+(then
+(local.get $pages)
+(memory.size)
+(i32.sub)
+(memory.grow)
+(i32.const -1)
+(i32.eq)
+(if
+(then
+(unreachable)
+)
+)
+)
+)
+(global.set $heap.top
+(local.get $end)
+)
+(local.get $address)
+)
+(func $mem.copy (param $to i32) (param $from i32) (param $len i32)
+(local.get $to)
+(local.get $from)
+(local.get $len)
+(memory.copy)
+)
+(func $str.concat (param $left i64) (param $right i64) (result i64)
+(local $left.len i32)
+(local $len i32)
+(local $address i32)
+(local.set $left.len
+(local.get $left)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+)
+(local.set $len
+(local.get $left.len)
+(local.get $right)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(i32.add)
+)
+(local.set $address
+(local.get $len)
+(call $heap.alloc)
+)
+(local.get $address)
+(local.get $left)
+(i32.wrap_i64)
+(local.get $left.len)
+(call $mem.copy)
+(local.get $address)
+(local.get $left.len)
+(i32.add)
+(local.get $right)
+(i32.wrap_i64)
+(local.get $len)
+(local.get $left.len)
+(i32.sub)
+(call $mem.copy)
+(local.get $len)
+(i64.extend_i32_u)
+(i64.const 32)
+(i64.shl)
+(local.get $address)
+(i64.extend_i32_u)
+(i64.or)
+)
+(func $twice (param $s i64) (result i64)
+(local.get $s)
+(local.get $s)
+(call $str.concat)
+(return)
+(i64.const 0)
+(return)
+)
+(func $shout (param $s i64) (result i64 i32)
+(local.get $s)
+(i64.const 4294967301)
+(call $str.concat)
+(local.get $s)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(return)
+(i64.const 0)
+(i32.const 0)
+(return)
+)
+(func $str.substr (param $string i64) (param $start i32) (param $len i32) (result i64)
+(local.get $start)
+(local.get $len)
+(i32.or)
+(i32.const 0)
+(i32.lt_s)
+(local.get $start)
+(local.get $len)
+(i32.add)
+(local.get $string)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(i32.gt_u)
+(i32.or)
+(if
+(then
+(unreachable)
+)
+)
+(local.get $len)
+(i64.extend_i32_u)
+(i64.const 32)
+(i64.shl)
+(local.get $string)
+(i32.wrap_i64)
+(local.get $start)
+(i32.add)
+(i64.extend_i32_u)
+(i64.or)
+)
+(func $str.compare (param $left i64) (param $right i64) (result i32)
+(local $left.len i32)
+(local $right.len i32)
+(local $index i32)
+(local $left.byte i32)
+(local $right.byte i32)
+(local.set $left.len
+(local.get $left)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+)
+(local.set $right.len
+(local.get $right)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+)
+(block
+(loop
+(local.get $index)
+(local.get $left.len)
+(i32.eq)
+(br_if 1)
+(local.get $index)
+(local.get $right.len)
+(i32.eq)
+(br_if 1)
+(local.set $left.byte
+(local.get $left)
+(i32.wrap_i64)
+(local.get $index)
+(i32.add)
+(i32.load8_u)
+)
+(local.set $right.byte
+(local.get $right)
+(i32.wrap_i64)
+(local.get $index)
+(i32.add)
+(i32.load8_u)
+)
+(local.get $left.byte)
+(local.get $right.byte)
+(i32.ne)
+(if
+(then
+(local.get $left.byte)
+(local.get $right.byte)
+(i32.gt_u)
+(local.get $left.byte)
+(local.get $right.byte)
+(i32.lt_u)
+(i32.sub)
+(return)
+)
+)
+(local.set $index
+(local.get $index)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(local.get $left.len)
+(local.get $right.len)
+(i32.gt_u)
+(local.get $left.len)
+(local.get $right.len)
+(i32.lt_u)
+(i32.sub)
+)
+(func $main (result i32)
+(local $s i64)
+(local $i i32)
+(local $for.limit.0 i32)
+(local $print.str i64)
+(local $result.i32.1 i32)
+(local $result.str.0 i64)
+(local $loud i64)
+(local $n i32)
+(local.set $s
+(i64.const 6)
+)
+(local.set $i
+(i32.const 1)
+)
+(local.set $for.limit.0
+(i32.const 50)
+)
+(block
+(loop
+(local.get $i)
+(local.get $for.limit.0)
+(i32.le_s)
+(i32.eqz)
+(br_if 1)
+(local.set $s
+(i64.const 25769803782)
+(local.get $i)
+(i32.const 6)
+(i32.rem_s)
+(i32.const 1)
+(call $str.substr)
+(local.get $s)
+(call $str.concat)
+)
+(local.get $s)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(i32.const 8)
+(i32.gt_s)
+(if
+(then
+(local.set $s
+(local.get $s)
+(i32.const 0)
+(i32.const 8)
+(call $str.substr)
+)
+)
+)
+(local.set $i
+(local.get $i)
+(i32.const 1)
+(i32.add)
+)
+(br 0)
+)
+)
+(local.get $s)
+(local.tee $print.str)
+(i32.wrap_i64)
+(local.get $print.str)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(call $print_str)
+(local.get $s)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(call $print)
+(global.get $greeting)
+(call $shout)
+(local.set $result.i32.1)
+(local.set $result.str.0)
+(local.set $loud
+(local.get $result.str.0)
+)
+(local.set $n
+(local.get $result.i32.1)
+)
+(local.get $loud)
+(local.tee $print.str)
+(i32.wrap_i64)
+(local.get $print.str)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(call $print_str)
+(local.get $n)
+(call $print)
+(i64.const 8589934604)
+(call $twice)
+(local.tee $print.str)
+(i32.wrap_i64)
+(local.get $print.str)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(call $print_str)
+(i64.const 12884901902)
+(i64.const 12884901905)
+(call $str.compare)
+(call $print)
+(i64.const 4294967316)
+(call $twice)
+(i64.const 8589934613)
+(call $str.compare)
+(call $print)
+(global.get $greeting)
+(call $twice)
+(drop)
+(global.get $greeting)
+(call $shout)
+(drop)
+(drop)
+(global.set $greeting
+(global.get $greeting)
+(i64.const 30064771095)
+(call $str.concat)
+)
+(global.get $greeting)
+(local.tee $print.str)
+(i32.wrap_i64)
+(local.get $print.str)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(call $print_str)
+(global.get $greeting)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(return)
+(i32.const 0)
+(return)
+)
+(memory 1)
+(data (i32.const 0) "hello")
+(data (i32.const 5) "!")
+(data (i32.const 6) "abcdef")
+(data (i32.const 12) "ab")
+(data (i32.const 14) "abc")
+(data (i32.const 17) "abd")
+(data (i32.const 20) "x")
+(data (i32.const 21) "xx")
+(data (i32.const 23) ", world")
+(export "memory" (memory 0))
+(global $heap.top (mut i32) (i32.const 30))
+(export "main" (func $main))
+)
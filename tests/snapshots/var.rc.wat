@@ -0,0 +1,328 @@
+(module
+(import "env" "print_str" (func $print_str (param i32 i32)))
+(import "env" "print" (func $print (param i32)))
+(global $g i32 (i32.const 12))
+(global $greeting i32 (i32.const 24))
+(global $var.stack (mut i32) (i32.const 65576))
+(func $inc (param $a i32) (result i32)
+(block $rc.body (result i32)
+(local.get $a)
+(local.get $a)
+(i32.load)
+(i32.const 1)
+(i32.add)
+(i32.store)
+(global.get $g)
+(global.get $g)
+(i32.load)
+(i32.const 100)
+(i32.add)
+(i32.store)
+(i32.const 0)
+(br $rc.body)
+(i32.const 0)
+)
+)
+(func $both (param $a i32) (param $b i32) (result i32)
+(block $rc.body (result i32)
+(local.get $a)
+(i32.const 5)
+(i32.store)
+(local.get $b)
+(i32.load)
+(br $rc.body)
+(i32.const 0)
+)
+)
+(func $bump (param $n i32) (result i32)
+(block $rc.body (result i32)
+(local.get $n)
+(local.get $n)
+(i32.load)
+(i32.const 1)
+(i32.add)
+(i32.store)
+(i32.const 0)
+(br $rc.body)
+(i32.const 0)
+)
+)
+(func $bumps (param $n i32) (result i32)
+(block $rc.body (result i32)
+(local.get $n)
+(call $bump)
+(drop)
+(local.get $n)
+(call $bump)
+(drop)
+(local.get $n)
+(i32.load)
+(br $rc.body)
+(i32.const 0)
+)
+)
+(func $rc.retain (param $string i64) (result i64)
+(local $count i32)
+(local.get $string)
+(i32.wrap_i64)
+(global.get $heap.base)
+(i32.gt_u)
+(if
+(then
+(local.set $count
+(local.get $string)
+(i32.wrap_i64)
+(i32.const 4)
+(i32.sub)
+)
+(local.get $count)
+(local.get $count)
+(i32.load)
+(i32.const 1)
+(i32.add)
+(i32.store)
+)
+)
+(local.get $string)
+)
+(func $rc.release (param $string i64)
+(local $count i32)
+(local.get $string)
+(i32.wrap_i64)
+(global.get $heap.base)
+(i32.le_u)
+(if
+(then
+(return)
+)
+)
+(local.set $count
+(local.get $string)
+(i32.wrap_i64)
+(i32.const 4)
+(i32.sub)
+)
+(local.get $count)
+(local.get $count)
+(i32.load)
+(i32.const 1)
+(i32.sub)
+(i32.store)
+(local.get $count)
+(i32.load)
+(i32.eqz)
+(if
+(then
+(local.get $count)
+(global.get $rc.free)
+(i32.store)
+(global.set $rc.free
+(local.get $string)
+(i32.wrap_i64)
+)
+)
+)
+)
+(func $rename (param $s i32) (param $name i64) (result i32)
+(block $rc.body (result i32)
+(local.get $s)
+(local.get $name)
+(call $rc.retain)
+(local.get $s)
+(i64.load)
+(call $rc.release)
+(i64.store)
+(i32.const 0)
+(br $rc.body)
+(i32.const 0)
+)
+(local.get $name)
+(call $rc.release)
+)
+(func $shout (param $s i64) (result i32)
+(local $var.frame i32)
+(local $print.str i64)
+(block $rc.body (result i32)
+(global.get $var.stack)
+(i32.const 48)
+(i32.lt_u)
+(if
+(then
+(unreachable)
+)
+)
+(global.get $var.stack)
+(i32.const 8)
+(i32.sub)
+(global.set $var.stack)
+(global.get $var.stack)
+(local.set $var.frame)
+(local.get $var.frame)
+(local.get $s)
+(i64.store)
+(local.get $var.frame)
+(i64.const 12884967464)
+(call $rename)
+(drop)
+(local.get $var.frame)
+(i64.load)
+(local.tee $print.str)
+(i32.wrap_i64)
+(local.get $print.str)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(call $print_str)
+(local.get $var.frame)
+(i64.load)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(br $rc.body)
+(i32.const 0)
+)
+(local.get $var.frame)
+(i64.load)
+(call $rc.release)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+)
+(func $outer (result i32)
+(local $var.frame i32)
+(block $rc.body (result i32)
+(global.get $var.stack)
+(i32.const 48)
+(i32.lt_u)
+(if
+(then
+(unreachable)
+)
+)
+(global.get $var.stack)
+(i32.const 8)
+(i32.sub)
+(global.set $var.stack)
+(global.get $var.stack)
+(local.set $var.frame)
+(local.get $var.frame)
+(i64.const 0)
+(i64.store)
+(local.get $var.frame)
+(i32.const 1)
+(i32.store)
+(local.get $var.frame)
+(local.get $var.frame)
+(call $outer.inner)
+(drop)
+(local.get $var.frame)
+(i32.load)
+(br $rc.body)
+(i32.const 0)
+)
+(global.get $var.stack)
+(i32.const 8)
+(i32.add)
+(global.set $var.stack)
+)
+(func $outer.inner (param $a i32) (param $x i32) (result i32)
+(block $rc.body (result i32)
+(local.get $a)
+(local.get $a)
+(i32.load)
+(i32.const 1)
+(i32.add)
+(i32.store)
+(local.get $x)
+(local.get $x)
+(i32.load)
+(i32.const 10)
+(i32.add)
+(i32.store)
+(i32.const 0)
+(br $rc.body)
+(i32.const 0)
+)
+)
+(func $main (result i32)
+(local $print.str i64)
+(block $rc.body (result i32)
+(global.get $g)
+(call $inc)
+(drop)
+(global.get $g)
+(i32.load)
+(call $print)
+(i32.const 32)
+(i32.const 1)
+(i32.store)
+(i32.const 32)
+(i32.const 32)
+(call $both)
+(call $print)
+(i32.const 1)
+(i32.const 4)
+(i32.mul)
+(i32.const 0)
+(i32.add)
+(call $bump)
+(drop)
+(i32.const 1)
+(i32.const 4)
+(i32.mul)
+(i32.const 0)
+(i32.add)
+(call $bumps)
+(call $print)
+(i32.const 1)
+(i32.const 4)
+(i32.mul)
+(i32.load)
+(call $print)
+(call $outer)
+(call $print)
+(global.get $greeting)
+(i64.const 21474902059)
+(call $rename)
+(drop)
+(global.get $greeting)
+(i64.load)
+(local.tee $print.str)
+(i32.wrap_i64)
+(local.get $print.str)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(call $print_str)
+(global.get $greeting)
+(i64.load)
+(call $rc.retain)
+(call $shout)
+(call $print)
+(global.get $greeting)
+(i64.load)
+(local.tee $print.str)
+(i32.wrap_i64)
+(local.get $print.str)
+(i64.const 32)
+(i64.shr_u)
+(i32.wrap_i64)
+(call $print_str)
+(global.get $g)
+(i32.load)
+(br $rc.body)
+(i32.const 0)
+)
+)
+(memory 2)
+(data (i32.const 20) "hi")
+(data (i32.const 65576) "HEY")
+(data (i32.const 65579) "hello")
+(data (i32.const 12) "\00\00\00\00")
+(data (i32.const 24) "\14\00\00\00\02\00\00\00")
+(export "memory" (memory 0))
+(global $heap.base i32 (i32.const 65584))
+(global $rc.free (mut i32) (i32.const 0))
+(export "main" (func $main))
+)
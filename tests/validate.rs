@@ -0,0 +1,39 @@
+//! The validator, which needs the `check` feature, and the output of every
+//! program in tests/programs going through it.
+
+#![cfg(feature = "check")]
+
+extern crate crenshaw;
+
+mod common;
+
+use common::{compile, flags, PROGRAMS};
+use crenshaw::validate::validate;
+
+#[test]
+fn output_is_valid() {
+    for &(name, sets) in PROGRAMS {
+        for flags in flags(sets) {
+            if let Err(msg) = validate(&compile(name, flags)) {
+                panic!("{} {} compiled to an invalid module: {}", name, flags, msg);
+            }
+        }
+    }
+}
+
+#[test]
+fn more_than_one_memory_is_valid() {
+    assert_eq!(validate("(module (memory 1) (memory 1))"), Ok(()));
+}
+
+#[test]
+fn errors_say_which_function() {
+    let msg = validate("(module (func $f (result i32) (i64.const 1)) (func $g))").unwrap_err();
+    assert!(msg.starts_with("in `$f`: type mismatch"), "{}", msg);
+}
+
+#[test]
+fn syntax_errors_say_where() {
+    let msg = validate("(module (func $f (i32.bogus)))").unwrap_err();
+    assert!(msg.contains("unknown operator"), "{}", msg);
+}
@@ -0,0 +1,52 @@
+//! Warnings, and where they point. `--Werror` makes them errors, which is
+//! how they come back from `compile_to_string`.
+
+extern crate crenshaw;
+
+use crenshaw::{compile_to_string, Options};
+
+/// Compiles `program` with every warning an error, and returns each one's
+/// text, with the line and column it points at.
+fn warnings(program: &str) -> Vec<(String, usize, usize)> {
+    let options = Options::parse(Some(String::from("--Werror")).into_iter()).unwrap();
+    match compile_to_string(program.as_bytes(), &options) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors
+            .into_iter()
+            .map(|error| {
+                let location = error.location.expect("warnings point somewhere");
+                (error.text, location.line, location.column)
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn unreachable_code_points_at_the_statement() {
+    let warnings = warnings("print 1\nreturn 0\nprint 2\n");
+    let text = "this never runs, because of a `return` before it [--Werror=unreachable-code]";
+    assert_eq!(warnings, [(String::from(text), 3, 1)]);
+}
+
+#[test]
+fn shadowing_and_unused_locals_point_at_the_name() {
+    let warnings = warnings("x = 1\nprint x\nbegin\n    x: i64 = 2\nend\nreturn 0\n");
+    let shadows = "`x` shadows the `x` first assigned on line 1, column 1 [--Werror=shadowing]";
+    let unused = "`x` is never read [--Werror=unused-variable]";
+    assert_eq!(warnings, [(String::from(shadows), 4, 5), (String::from(unused), 4, 5)]);
+}
+
+#[test]
+fn reads_in_expressions_with_errors_still_count() {
+    for program in [
+        "y = 2\nprint (y > 0) + 1\nreturn 0\n",
+        "y = 2\nprint y ? 1 : 2.0\nreturn 0\n",
+        "y = 2\nprint -\"s\" + y\nreturn 0\n",
+        "y = 2\nassert y + 1\nreturn 0\n",
+        "y = 2\nq, r = y\nprint q + r\nreturn 0\n",
+    ] {
+        let warnings = warnings(program);
+        assert!(!warnings.is_empty(), "{}", program);
+        assert!(warnings.iter().all(|warning| !warning.0.contains("never read")), "{:?}", warnings);
+    }
+}